@@ -0,0 +1,80 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+use clap::Clap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::Instant;
+
+use pravega_video::event_serde::{EventWithHeader, EventWriter};
+use pravega_video::timestamp::PravegaTimestamp;
+
+#[derive(Clap)]
+struct Opts {
+    /// Number of events to write
+    #[clap(long, default_value = "10000000")]
+    events: u64,
+    /// Payload size of each event. The default is the size of an MPEG TS packet.
+    #[clap(long, default_value = "188")]
+    payload_size: usize,
+    /// Size of the BufWriter that wraps the output, as used by pravegasink
+    #[clap(long, default_value = "131072")]
+    buffer_size: usize,
+    /// Write to this file instead of discarding the output
+    #[clap(long)]
+    output: Option<String>,
+}
+
+/// Measure the throughput of EventWriter with small events.
+/// Both cases copy the header and payload into one buffer so that each event is written with a single write_all.
+/// The "allocate" case allocates a new buffer for each event. The "reuse" case is EventWriter, which reuses its buffer.
+fn main() {
+    let opts: Opts = Opts::parse();
+    let payload = vec![0x47; opts.payload_size];
+    println!("events={}, payload_size={}, buffer_size={}", opts.events, opts.payload_size, opts.buffer_size);
+    let allocate = benchmark("allocate", &opts, &payload, write_event_with_allocation);
+    let mut event_writer = EventWriter::new();
+    let reuse = benchmark("reuse", &opts, &payload, |event, writer| event_writer.write(event, writer));
+    println!("speedup from reusing the buffer: {:.2}x", reuse / allocate);
+}
+
+/// Write all events and return the number of events per second.
+fn benchmark<F>(name: &str, opts: &Opts, payload: &[u8], mut write_event: F) -> f64
+where
+    F: FnMut(&EventWithHeader, &mut BufWriter<Box<dyn Write>>) -> std::io::Result<()>,
+{
+    let output: Box<dyn Write> = match &opts.output {
+        Some(path) => Box::new(File::create(path).expect("create output file")),
+        None => Box::new(std::io::sink()),
+    };
+    let mut writer = BufWriter::with_capacity(opts.buffer_size, output);
+    let start = Instant::now();
+    for i in 0..opts.events {
+        let event = EventWithHeader::new(payload, PravegaTimestamp::from_nanoseconds(Some(i + 1)), false, false, false);
+        write_event(&event, &mut writer).expect("write event");
+    }
+    writer.flush().expect("flush");
+    let elapsed = start.elapsed().as_secs_f64();
+    let events_per_sec = opts.events as f64 / elapsed;
+    let bytes = opts.events * (payload.len() as u64 + 20);
+    println!("{:>8}: {:.3} sec, {:.0} events/sec, {:.1} MB/sec",
+        name, elapsed, events_per_sec, bytes as f64 / elapsed / 1e6);
+    events_per_sec
+}
+
+/// Write an event from a buffer that is allocated for each event.
+fn write_event_with_allocation<W: Write>(event: &EventWithHeader, writer: &mut W) -> std::io::Result<()> {
+    let header = EventWriter::new().encode_header(event)?;
+    let payload_length = event.payload.len();
+    let mut bytes_to_write: Vec<u8> = vec![0; payload_length + 20];
+    bytes_to_write[0..20].copy_from_slice(&header[..]);
+    bytes_to_write[20..20+payload_length].copy_from_slice(event.payload);
+    writer.write_all(&bytes_to_write)
+}
@@ -0,0 +1,116 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+use clap::Clap;
+use serde_json::json;
+
+use pravega_client_shared::{Scope, Stream, ScopedStream};
+use pravega_video::storage::create_storage_backend;
+use pravega_video::stream_verifier::{StreamVerifierConfig, VerificationReport, verify_stream_with_storage};
+use pravega_video::timestamp::TimeDelta;
+
+#[derive(Clap)]
+struct Opts {
+    /// Pravega controller in format "127.0.0.1:9090", or a URI such as "file:///path" for other storage backends
+    #[clap(short, long, default_value = "127.0.0.1:9090")]
+    controller: String,
+    /// Pravega scope
+    #[clap(long)]
+    scope: String,
+    /// Pravega stream
+    #[clap(long)]
+    stream: String,
+    /// Pravega keycloak file
+    #[clap(long, default_value = "", setting(clap::ArgSettings::AllowEmptyValues))]
+    keycloak_file: String,
+    /// Report gaps in the media longer than this many milliseconds
+    #[clap(long, default_value = "1000")]
+    gap_threshold_ms: u64,
+    /// Maximum number of issues to report
+    #[clap(long, default_value = "1000")]
+    max_issues: usize,
+    /// Print the report as JSON
+    #[clap(long)]
+    json: bool,
+    /// Exit with a non-zero code if there are warnings
+    #[clap(long)]
+    fail_on_warning: bool,
+}
+
+/// Verify the integrity of a video stream and its index.
+/// Exits with 0 if the stream is valid, 1 if problems were found, or 2 if the stream could not be read.
+fn main() {
+    env_logger::init();
+    let opts: Opts = Opts::parse();
+    let keycloak_file = if opts.keycloak_file.is_empty() {
+        None
+    } else {
+        Some(opts.keycloak_file)
+    };
+    let storage = match create_storage_backend(opts.controller, keycloak_file) {
+        Ok(storage) => storage,
+        Err(e) => {
+            eprintln!("Unable to create storage backend: {}", e);
+            std::process::exit(2);
+        },
+    };
+    let scoped_stream = ScopedStream {
+        scope: Scope::from(opts.scope),
+        stream: Stream::from(opts.stream),
+    };
+    let config = StreamVerifierConfig {
+        gap_threshold: TimeDelta(Some(opts.gap_threshold_ms as i128 * 1_000_000)),
+        max_issues: opts.max_issues,
+    };
+    let report = match verify_stream_with_storage(storage.as_ref(), &scoped_stream, &config) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Unable to verify stream {}: {}", scoped_stream, e);
+            std::process::exit(2);
+        },
+    };
+    if opts.json {
+        println!("{}", serde_json::to_string_pretty(&report_to_json(&scoped_stream, &report)).unwrap());
+    } else {
+        println!("stream:          {}", scoped_stream);
+        println!("{}", report);
+    }
+    if !report.is_ok() || (opts.fail_on_warning && report.warning_count > 0) {
+        std::process::exit(1);
+    }
+}
+
+fn report_to_json(scoped_stream: &ScopedStream, report: &VerificationReport) -> serde_json::Value {
+    let issues: Vec<_> = report.issues.iter().map(|issue| json!({
+        "severity": issue.severity.to_string(),
+        "kind": format!("{:?}", issue.kind),
+        "stream": issue.stream.to_string(),
+        "offset": issue.offset,
+        "message": issue.message,
+    })).collect();
+    json!({
+        "stream": scoped_stream.to_string(),
+        "ok": report.is_ok(),
+        "data_head": report.data_head,
+        "data_tail": report.data_tail,
+        "index_head": report.index_head,
+        "index_tail": report.index_tail,
+        "index_version": report.index_version.map(|v| format!("{:?}", v)),
+        "event_count": report.event_count,
+        "index_record_count": report.index_record_count,
+        "first_timestamp": report.first_timestamp.nanoseconds(),
+        "last_timestamp": report.last_timestamp.nanoseconds(),
+        "discontinuity_count": report.discontinuity_count,
+        "gap_count": report.gap_count,
+        "error_count": report.error_count,
+        "warning_count": report.warning_count,
+        "issues": issues,
+    })
+}
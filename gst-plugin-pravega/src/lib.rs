@@ -9,13 +9,10 @@
 //
 
 mod counting_reader;
-mod counting_writer;
-mod numeric;
 mod fragmp4pay;
 mod pravegasink;
 mod pravegasrc;
 mod pravegatc;
-mod seekable_byte_stream_writer;
 mod seekable_take;
 mod timestampcvt;
 pub mod utils;
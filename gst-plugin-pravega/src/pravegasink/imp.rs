@@ -18,30 +18,21 @@ use gst::subclass::prelude::*;
 use gst::{gst_debug, gst_error, gst_fixme, gst_info, gst_log, gst_trace, gst_memdump};
 use gst_base::subclass::prelude::*;
 
-use std::cmp;
-use std::convert::TryInto;
-use std::io::{BufWriter, Seek, SeekFrom, Write};
-use std::sync::Mutex;
+use std::convert::{TryFrom, TryInto};
+use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
 use std::time::Duration;
 use std::sync::mpsc::{self, Sender, Receiver, RecvTimeoutError};
 
 use once_cell::sync::Lazy;
 
-use pravega_client::client_factory::ClientFactoryAsync;
-use pravega_client::byte::ByteWriter;
-use pravega_client_shared::{Scope, Stream, StreamConfiguration, ScopedStream, Scaling, ScaleType};
-use pravega_video::event_serde::{EventWithHeader, EventWriter};
-use pravega_video::index::{IndexRecord, IndexRecordWriter, IndexSearcher, SearchMethod, get_index_stream_name};
+use pravega_client_shared::{Scope, Stream, ScopedStream};
+use pravega_video::index::{IndexSearcher, IndexVersion, SearchMethod, get_index_stream_name};
+use pravega_video::storage::{StorageBackend, StreamReader, StreamWriter, create_storage_backend};
 use pravega_video::timestamp::{PravegaTimestamp, SECOND};
 use pravega_video::utils;
-use pravega_video::utils::SyncByteReader;
+use pravega_video::video_stream_writer::{IndexDecision, VideoFrame, VideoStreamWriter, VideoStreamWriterConfig};
 
-use tokio::runtime::Runtime;
-
-use crate::counting_writer::CountingWriter;
-use crate::numeric::u64_to_i64_saturating_sub;
-use crate::seekable_byte_stream_writer::SeekableByteWriter;
 
 const PROPERTY_NAME_STREAM: &str = "stream";
 const PROPERTY_NAME_CONTROLLER: &str = "controller";
@@ -50,6 +41,7 @@ const PROPERTY_NAME_BUFFER_SIZE: &str = "buffer-size";
 const PROPERTY_NAME_TIMESTAMP_MODE: &str = "timestamp-mode";
 const PROPERTY_NAME_INDEX_MIN_SEC: &str = "index-min-sec";
 const PROPERTY_NAME_INDEX_MAX_SEC: &str = "index-max-sec";
+const PROPERTY_NAME_INDEX_VERSION: &str = "index-version";
 const PROPERTY_NAME_ALLOW_CREATE_SCOPE: &str = "allow-create-scope";
 const PROPERTY_NAME_KEYCLOAK_FILE: &str = "keycloak-file";
 const PROPERTY_NAME_RETENTION_TYPE: &str = "retention-type";
@@ -137,26 +129,40 @@ struct RetentionMaintainer {
     element: super::PravegaSink,
     interval_seconds: u64,
     retention_policy: RetentionPolicy,
-    factory: ClientFactoryAsync,
-    index_searcher: IndexSearcher<SyncByteReader>,
-    index_writer: ByteWriter,
-    data_writer: ByteWriter,
+    index_searcher: IndexSearcher<Box<dyn StreamReader>>,
+    index_writer: Box<dyn StreamWriter>,
+    data_writer: Box<dyn StreamWriter>,
 }
 
 impl RetentionMaintainer {
-    fn new(element: super::PravegaSink, interval_seconds: u64, retention_policy: RetentionPolicy, factory: ClientFactoryAsync, index_scoped_stream: ScopedStream, data_scoped_stream: ScopedStream) -> Self {
-        let index_reader = factory.runtime_handle().block_on(factory.create_byte_reader(index_scoped_stream.clone()));
-        let index_writer = factory.runtime_handle().block_on(factory.create_byte_writer(index_scoped_stream));
-        let data_writer = factory.runtime_handle().block_on(factory.create_byte_writer(data_scoped_stream));
-        let index_searcher = IndexSearcher::new(SyncByteReader::new(index_reader, factory.runtime_handle()));
-        Self {
+    fn new(element: super::PravegaSink, interval_seconds: u64, retention_policy: RetentionPolicy, storage: Arc<dyn StorageBackend>,
+            index_scoped_stream: ScopedStream, data_scoped_stream: ScopedStream) -> std::io::Result<Self> {
+        let index_reader = storage.create_reader(&index_scoped_stream)?;
+        let index_writer = storage.create_writer(&index_scoped_stream)?;
+        let data_writer = storage.create_writer(&data_scoped_stream)?;
+        let index_searcher = IndexSearcher::new(index_reader);
+        Ok(Self {
             element,
             interval_seconds,
             retention_policy,
-            factory,
             index_searcher,
             index_writer,
             data_writer,
+        })
+    }
+
+    /// Truncate the index and then the data stream.
+    fn truncate(&mut self, index_offset: u64, data_offset: u64) {
+        match self.index_writer.truncate_data_before(index_offset) {
+            Ok(()) => gst_info!(CAT, obj: &self.element, "Index truncated at offset {}", index_offset),
+            Err(error) => {
+                gst_error!(CAT, obj: &self.element, "Failed to truncate index at offset {}: {}", index_offset, error);
+                return;
+            },
+        }
+        match self.data_writer.truncate_data_before(data_offset) {
+            Ok(()) => gst_info!(CAT, obj: &self.element, "Data truncated at offset {}", data_offset),
+            Err(error) => gst_error!(CAT, obj: &self.element, "Failed to truncate data at offset {}: {}", data_offset, error),
         }
     }
 
@@ -186,11 +192,7 @@ impl RetentionMaintainer {
 
                     let search_result = self.index_searcher.search_timestamp_and_return_index_offset(truncate_at_timestamp, SearchMethod::Before);
                     if let Ok(result) = search_result {
-                        let runtime = self.factory.runtime_handle();
-                        runtime.block_on(self.index_writer.truncate_data_before(result.1 as i64)).unwrap();
-                        gst_info!(CAT, obj: &self.element, "Index truncated at offset {}", result.1);
-                        runtime.block_on(self.data_writer.truncate_data_before(result.0.offset as i64)).unwrap();
-                        gst_info!(CAT, obj: &self.element, "Data truncated at offset {}", result.0.offset);
+                        self.truncate(result.1, result.0.offset);
                     }
                 }
 
@@ -199,11 +201,7 @@ impl RetentionMaintainer {
 
                     let search_result = self.index_searcher.search_size_and_return_index_offset(bytes, SearchMethod::Before);
                     if let Ok(result) = search_result {
-                        let runtime = self.factory.runtime_handle();
-                        runtime.block_on(self.index_writer.truncate_data_before(result.1 as i64)).unwrap();
-                        gst_info!(CAT, obj: &self.element, "Index truncated at offset {}", result.1);
-                        runtime.block_on(self.data_writer.truncate_data_before(result.0.offset as i64)).unwrap();
-                        gst_info!(CAT, obj: &self.element, "Data truncated at offset {}", result.0.offset);
+                        self.truncate(result.1, result.0.offset);
                     }
                 }
 
@@ -225,6 +223,7 @@ const DEFAULT_BUFFER_SIZE: usize = 128*1024;
 const DEFAULT_TIMESTAMP_MODE: TimestampMode = TimestampMode::Tai;
 const DEFAULT_INDEX_MIN_SEC: f64 = 0.5;
 const DEFAULT_INDEX_MAX_SEC: f64 = 10.0;
+const DEFAULT_INDEX_VERSION: u32 = 1;
 const DEFAULT_RETENTION_TYPE: RetentionType = RetentionType::None;
 const DEFAULT_RETENTION_MAINTENANCE_INTERVAL_SECONDS: u64 = 15 * 60;
 
@@ -238,6 +237,7 @@ struct Settings {
     timestamp_mode: TimestampMode,
     index_min_nanos: u64,
     index_max_nanos: u64,
+    index_version: IndexVersion,
     allow_create_scope: bool,
     keycloak_file: Option<String>,
     retention_type: RetentionType,
@@ -257,6 +257,7 @@ impl Default for Settings {
             timestamp_mode: DEFAULT_TIMESTAMP_MODE,
             index_min_nanos: (DEFAULT_INDEX_MIN_SEC * 1e9) as u64,
             index_max_nanos: (DEFAULT_INDEX_MAX_SEC * 1e9) as u64,
+            index_version: IndexVersion::try_from(DEFAULT_INDEX_VERSION).unwrap(),
             allow_create_scope: true,
             keycloak_file: utils::default_keycloak_file(),
             retention_type: DEFAULT_RETENTION_TYPE,
@@ -270,18 +271,7 @@ impl Default for Settings {
 enum State {
     Stopped,
     Started {
-        runtime: Runtime,
-        writer: CountingWriter<BufWriter<SeekableByteWriter>>,
-        index_writer: SeekableByteWriter,
-        // First received PTS that is not None.
-        first_valid_time: PravegaTimestamp,
-        // PTS of last written index record.
-        last_index_time: PravegaTimestamp,
-        // The timestamp that will be written to the index upon end-of-stream.
-        final_timestamp: PravegaTimestamp,
-        // The offset that will be written to the index upon end-of-stream.
-        final_offset: Option<u64>,
-        buffers_written: u64,
+        writer: VideoStreamWriter,
         retention_thread_stop_tx: Sender<()>,
         retention_thread_handle: Option<JoinHandle<()>>,
     },
@@ -381,6 +371,7 @@ impl ObjectImpl for PravegaSink {
                 PROPERTY_NAME_CONTROLLER,
                 "Controller",
                 format!("Pravega controller. \
+                    Use file:///path to store streams in a local directory or memory://name to store streams in memory. \
                     If not specified, this will use the value of the environment variable {}. \
                     If that is empty, it will use the default of {}.",
                     utils::ENV_PRAVEGA_CONTROLLER_URI, utils::DEFAULT_PRAVEGA_CONTROLLER_URI).as_str(),
@@ -429,6 +420,17 @@ impl ObjectImpl for PravegaSink {
                 DEFAULT_INDEX_MAX_SEC.try_into().unwrap(),
                 glib::ParamFlags::WRITABLE,
             ),
+            glib::ParamSpec::new_uint(
+                PROPERTY_NAME_INDEX_VERSION,
+                "Index version",
+                "The version of the index format to write. \
+                Version 2 also stores the duration and length of each segment and a session id. \
+                If the index stream already has records, the version of the existing records will be used.",
+                1,
+                2,
+                DEFAULT_INDEX_VERSION,
+                glib::ParamFlags::WRITABLE,
+            ),
             glib::ParamSpec::new_boolean(
                 PROPERTY_NAME_ALLOW_CREATE_SCOPE,
                 "Allow create scope",
@@ -583,6 +585,18 @@ impl ObjectImpl for PravegaSink {
                     gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_INDEX_MAX_SEC, err);
                 }
             },
+            PROPERTY_NAME_INDEX_VERSION => {
+                let res = match value.get::<u32>() {
+                    Ok(index_version) => {
+                        let mut settings = self.settings.lock().unwrap();
+                        IndexVersion::try_from(index_version).map(|v| settings.index_version = v)
+                    },
+                    Err(_) => unreachable!("type checked upstream"),
+                };
+                if let Err(err) = res {
+                    gst_error!(CAT, obj: obj, "Failed to set property `{}`: {}", PROPERTY_NAME_INDEX_VERSION, err);
+                }
+            },
             PROPERTY_NAME_ALLOW_CREATE_SCOPE => {
                 let res: Result<(), glib::Error> = match value.get::<bool>() {
                     Ok(allow_create_scope) => {
@@ -744,105 +758,51 @@ impl BaseSinkImpl for PravegaSink {
             gst_info!(CAT, obj: element, "start: controller={}", controller);
             let keycloak_file = settings.keycloak_file.clone();
             gst_info!(CAT, obj: element, "start: keycloak_file={:?}", keycloak_file);
-            let config = utils::create_client_config(controller, keycloak_file).map_err(|error| {
-                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create Pravega client config: {}", error])
-            })?;
-            gst_trace!(CAT, obj: element, "start: config={:?}", config);
-            gst_info!(CAT, obj: element, "start: controller_uri={}:{}", config.controller_uri.domain_name(), config.controller_uri.port());
-            gst_info!(CAT, obj: element, "start: is_tls_enabled={}", config.is_tls_enabled);
-            gst_info!(CAT, obj: element, "start: is_auth_enabled={}", config.is_auth_enabled);
-
-            let runtime = Runtime::new().unwrap();
-            let client_factory = ClientFactoryAsync::new(config, runtime.handle().to_owned());
-            let controller_client = client_factory.controller_client();
-
-            // Create scope.
-            gst_info!(CAT, obj: element, "start: allow_create_scope={}", settings.allow_create_scope);
-            if settings.allow_create_scope {
-                // This is expected to fail in some environments, even if the scope already exists.
-                // We will log the error and continue.
-                let _ = runtime.block_on(controller_client.create_scope(&scope)).map_err(|error| {
-                    gst_debug!(CAT, obj: element, "Failed to create Pravega scope. This is normal if the scope already exists: {:?}", error);
-                });
-            }
-
-            // Create data stream.
-            let stream_config = StreamConfiguration {
-                scoped_stream: ScopedStream {
-                    scope: scope.clone(),
-                    stream: stream.clone(),
-                },
-                scaling: Scaling {
-                    scale_type: ScaleType::FixedNumSegments,
-                    min_num_segments: 1,
-                    ..Default::default()
-                },
-                retention: Default::default(),
-                tags: utils::get_video_tags(),
-            };
-            runtime.block_on(controller_client.create_stream(&stream_config)).map_err(|error| {
-                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create Pravega data stream: {:?}", error])
-            })?;
-
-            // Create index stream.
-            let index_stream_config = StreamConfiguration {
-                scoped_stream: ScopedStream {
-                    scope: scope.clone(),
-                    stream: index_stream.clone(),
-                },
-                scaling: Scaling {
-                    scale_type: ScaleType::FixedNumSegments,
-                    min_num_segments: 1,
-                    ..Default::default()
-                },
-                retention: Default::default(),
-                tags: None,
-            };
-            runtime.block_on(controller_client.create_stream(&index_stream_config)).map_err(|error| {
-                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create Pravega index stream: {:?}", error])
+            // The controller may also be file:///path or memory://name to store streams without Pravega.
+            let storage = create_storage_backend(controller, keycloak_file).map_err(|error| {
+                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create storage backend: {}", error])
             })?;
 
             let scoped_stream = ScopedStream {
                 scope: scope.clone(),
                 stream: stream.clone(),
             };
-            let writer = runtime.block_on(client_factory.create_byte_writer(scoped_stream.clone()));
-            let mut seekable_writer = SeekableByteWriter::new(writer, runtime.handle().to_owned());
-            gst_info!(CAT, obj: element, "start: Opened Pravega writer for data");
-            seekable_writer.seek_to_tail();
-
             let index_scoped_stream = ScopedStream {
                 scope: scope.clone(),
                 stream: index_stream.clone(),
             };
-            let index_writer = runtime.block_on(client_factory.create_byte_writer(index_scoped_stream.clone()));
-            let mut index_writer = SeekableByteWriter::new(index_writer, runtime.handle().to_owned());
-            gst_info!(CAT, obj: element, "start: Opened Pravega writer for index");
-            index_writer.seek_to_tail();
 
+            // Create the scope and streams if needed and open the writers.
+            // All records in an index must have the same version.
+            // If the index already has records, the writer will continue to use the existing version.
+            gst_info!(CAT, obj: element, "start: allow_create_scope={}", settings.allow_create_scope);
             gst_info!(CAT, obj: element, "start: Buffer size is {}", settings.buffer_size);
-            let buf_writer = BufWriter::with_capacity(settings.buffer_size, seekable_writer);
-            let counting_writer = CountingWriter::new(buf_writer).unwrap();
+            let writer_config = VideoStreamWriterConfig {
+                buffer_size: settings.buffer_size,
+                index_min_nanos: settings.index_min_nanos,
+                index_max_nanos: settings.index_max_nanos,
+                index_version: settings.index_version,
+                allow_create_scope: settings.allow_create_scope,
+            };
+            let writer = VideoStreamWriter::open_with_storage(storage.clone(), scoped_stream.clone(), writer_config).map_err(|error| {
+                gst::error_msg!(gst::ResourceError::Settings, ["Failed to open video stream writer: {}", error])
+            })?;
+            gst_info!(CAT, obj: element, "start: Opened writers; index_version={:?}", writer.index_version());
 
             let retention_policy = RetentionPolicy::new(settings.retention_type, settings.retention_days, settings.retention_bytes).map_err(|error| {
                 gst::error_msg!(gst::ResourceError::Settings, ["Failed to create retention policy: {}", error])
             })?;
             gst_info!(CAT, obj: element, "start: retention_policy={:?}", retention_policy);
 
-            let retention_maintainer = RetentionMaintainer::new(element.clone(), settings.retention_maintenance_interval_seconds, retention_policy, client_factory.clone(),
-                index_scoped_stream, scoped_stream);
+            let retention_maintainer = RetentionMaintainer::new(element.clone(), settings.retention_maintenance_interval_seconds, retention_policy, storage,
+                index_scoped_stream, scoped_stream).map_err(|error| {
+                gst::error_msg!(gst::ResourceError::OpenReadWrite, ["Failed to open streams for retention maintenance: {}", error])
+            })?;
             let (retention_thread_stop_tx, retention_thread_stop_rx) = mpsc::channel();
             let retention_thread_handle = retention_maintainer.run(retention_thread_stop_rx);
 
             *state = State::Started {
-                runtime,
-                writer: counting_writer,
-                index_writer,
-                first_valid_time: PravegaTimestamp::NONE,
-                last_index_time: PravegaTimestamp::NONE,
-                final_timestamp: PravegaTimestamp::NONE,
-                final_offset: None,
-                buffers_written: 0,
+                writer,
                 retention_thread_stop_tx,
                 retention_thread_handle,
             };
@@ -861,29 +821,11 @@ impl BaseSinkImpl for PravegaSink {
         gst_trace!(CAT, obj: element, "render: BEGIN: Rendering {:?}", buffer);
         let result = (|| {
             let mut state = self.state.lock().unwrap();
-            let (writer,
-                index_writer,
-                first_valid_time,
-                last_index_time,
-                final_timestamp,
-                final_offset,
-                buffers_written) = match *state {
+            let writer = match *state {
                 State::Started {
                     ref mut writer,
-                    ref mut index_writer,
-                    ref mut first_valid_time,
-                    ref mut last_index_time,
-                    ref mut final_timestamp,
-                    ref mut final_offset,
-                    ref mut buffers_written,
                     ..
-                } => (writer,
-                    index_writer,
-                    first_valid_time,
-                    last_index_time,
-                    final_timestamp,
-                    final_offset,
-                    buffers_written),
+                } => writer,
                 State::Stopped => {
                     gst::element_error!(element, gst::CoreError::Failed, ["Not started yet"]);
                     return Err(gst::FlowError::Error);
@@ -899,9 +841,9 @@ impl BaseSinkImpl for PravegaSink {
             })?;
             let payload = map.as_ref();
 
-            let (timestamp_mode, index_min_nanos, index_max_nanos) = {
+            let timestamp_mode = {
                 let settings = self.settings.lock().unwrap();
-                (settings.timestamp_mode, settings.index_min_nanos, settings.index_max_nanos)
+                settings.timestamp_mode
             };
 
             let timestamp = match timestamp_mode {
@@ -922,198 +864,65 @@ impl BaseSinkImpl for PravegaSink {
                 }
             };
 
-            if first_valid_time.is_none() {
-                *first_valid_time = timestamp;
-            }
-
-            // Get the writer offset before writing. This offset will be used in the index.
-            let writer_offset = writer.seek(SeekFrom::Current(0)).unwrap();
-
             gst_log!(CAT, obj: element, "render: timestamp={:?}, pts={}, base_time={}, duration={}, size={}, writer_offset={}",
-                timestamp, pts, element.base_time(), buffer.duration(), buffer.size(), writer_offset);
+                timestamp, pts, element.base_time(), duration, buffer.size(), writer.offset());
 
-            // We only want to include key frames (non-delta units) in the index.
-            // However, if no key frame has been received in a while, force an index record.
-            // This is required for nvv4l2h264enc because it identifies all buffers as DELTA_UNIT.
+            // The writer determines when to write index records, based on the key frame flag, timestamp, and index-min-sec and index-max-sec.
+            // Upstream can indicate a discontinuity (or resync) in the buffer.
+            // Flush after writing if the buffer contains the SYNC_AFTER flag. This is normally not used.
             let buffer_flags = buffer.flags();
-            let is_delta_unit = buffer_flags.contains(gst::BufferFlags::DELTA_UNIT);
-            let random_access = !is_delta_unit;
-            let include_in_index = match timestamp.nanoseconds() {
-                Some(timestamp) => {
-                    match last_index_time.nanoseconds() {
-                        Some(last_index_time) => {
-                            let interval_sec = u64_to_i64_saturating_sub(timestamp, last_index_time) as f64 * 1e-9;
-                            if is_delta_unit {
-                                // We are at a delta frame.
-                                if timestamp > last_index_time + index_max_nanos {
-                                    gst_fixme!(CAT, obj: element,
-                                        "render: Forcing index record at delta unit because no key frame has been received for {} sec", interval_sec);
-                                    true
-                                } else {
-                                    false
-                                }
-                            } else {
-                                // We are at a key frame.
-                                if timestamp < last_index_time + index_min_nanos {
-                                    gst_debug!(CAT, obj: element,
-                                        "render: Skipping creation of index record because an index record was created {} sec ago", interval_sec);
-                                    false
-                                } else {
-                                    gst_debug!(CAT, obj: element,
-                                        "render: Creating index record at key frame; last index record was created {} sec ago", interval_sec);
-                                    true
-                                }
-                            }
-                        },
-                        None => {
-                            // An index record has not been written by this element yet.
-                            // The timestamp is valid.
-                            if random_access {
-                                true
-                            } else {
-                                // We are at a delta frame.
-                                // Do not write an index record. unless no index record has been written for a while.
-                                match first_valid_time.nanoseconds() {
-                                    Some(first_valid_time) => {
-                                        if timestamp > first_valid_time + index_max_nanos {
-                                            let interval_sec = u64_to_i64_saturating_sub(timestamp, first_valid_time) as f64 * 1e-9;
-                                            gst_fixme!(CAT, obj: element,
-                                                "render: Forcing first index record at delta unit because no key frame has been received for {} sec", interval_sec);
-                                            true
-                                        } else {
-                                            false
-                                        }
-                                    },
-                                    None => {
-                                        // Should be unreachable.
-                                        false
-                                    },
-                                }
-                            }
-                        },
-                    }
-                },
-                None => {
-                    // Buffer has an invalid timestamp. Never index.
-                    false
-                },
+            let frame = VideoFrame {
+                payload,
+                timestamp,
+                duration: duration.nanoseconds(),
+                random_access: !buffer_flags.contains(gst::BufferFlags::DELTA_UNIT),
+                discontinuity: buffer_flags.contains(gst::BufferFlags::DISCONT) || buffer_flags.contains(gst::BufferFlags::RESYNC),
+                sync_after: buffer_flags.contains(gst::BufferFlags::SYNC_AFTER),
             };
+            gst_memdump!(CAT, obj: element, "render: writing frame={:?}", frame);
+            let result = writer.write(&frame).map_err(|err| {
+                gst::element_error!(
+                    element,
+                    gst::ResourceError::Write,
+                    ["Failed to write buffer: {}", err]
+                );
+                gst::FlowError::Error
+            })?;
 
-            // Per the index constraints defined in index.rs, if we are writing an index record now,
-            // we must flush any data writes prior to this buffer, so that reads do not block waiting on this writer.
-            let flush = include_in_index;
-            if flush {
-                writer.flush().map_err(|error| {
-                    gst::element_error!(element, gst::CoreError::Failed, ["Failed to flush Pravega data stream: {}", error]);
-                    gst::FlowError::Error
-                })?;
-            }
-
-            // In order to detect any stalls writing the index stream, flush the index stream.
-            // This will wait for all previous index records to be durably persisted.
-            if flush {
-                index_writer.flush().map_err(|error| {
-                    gst::element_error!(element, gst::CoreError::Failed, ["Failed to flush Pravega index stream: {}", error]);
-                    gst::FlowError::Error
-                })?;
+            match result.index_decision {
+                IndexDecision::KeyFrame { interval_nanos: Some(interval_nanos) } => {
+                    gst_debug!(CAT, obj: element,
+                        "render: Creating index record at key frame; last index record was created {} sec ago", interval_nanos as f64 * 1e-9);
+                },
+                IndexDecision::SkipKeyFrame { interval_nanos } => {
+                    gst_debug!(CAT, obj: element,
+                        "render: Skipping creation of index record because an index record was created {} sec ago", interval_nanos as f64 * 1e-9);
+                },
+                IndexDecision::ForceDeltaUnit { interval_nanos, first: false } => {
+                    gst_fixme!(CAT, obj: element,
+                        "render: Forcing index record at delta unit because no key frame has been received for {} sec", interval_nanos as f64 * 1e-9);
+                },
+                IndexDecision::ForceDeltaUnit { interval_nanos, first: true } => {
+                    gst_fixme!(CAT, obj: element,
+                        "render: Forcing first index record at delta unit because no key frame has been received for {} sec", interval_nanos as f64 * 1e-9);
+                },
+                _ => {},
             }
-
-            // Record a discontinuity if any of the following are true:
-            //   1) upstream has indicated a discontinuity (or resync) in the buffer
-            //   3) this will be the first buffer written to the data stream from this instance
-            //   2) this will be the first index record written from this instance
-            let discontinuity =
-                   buffer_flags.contains(gst::BufferFlags::DISCONT)
-                || buffer_flags.contains(gst::BufferFlags::RESYNC)
-                || *buffers_written == 0
-                || (include_in_index && last_index_time.nanoseconds().is_none());
-            if discontinuity {
+            if result.discontinuity {
                 gst_debug!(CAT, obj: element, "render: Recording discontinuity");
             }
-
-            // Write index record.
-            // We write the index record before the buffer so that any readers blocked on reading the
-            // index will unblock as soon as possible.
-            if include_in_index {
-                let index_record = IndexRecord::new(timestamp, writer_offset,
-                    random_access, discontinuity);
-                let mut index_record_writer = IndexRecordWriter::new();
-                index_record_writer.write(&index_record, index_writer).map_err(|err| {
-                    gst::element_error!(
-                        element,
-                        gst::ResourceError::Write,
-                        ["Failed to write index: {}", err]
-                    );
-                    gst::FlowError::Error
-                })?;
+            if let Some(index_record) = result.index_record {
                 gst_debug!(CAT, obj: element, "render: Wrote index record {:?}", index_record);
-                *last_index_time = timestamp;
             }
-
-            // Write buffer to Pravega byte stream.
-            // If buffer is greater than ~8 MiB, it will be fragmented into multiple atomic writes, each with an EventHeader.
-            // Once fragmented, buffers will not be reassembled by pravegasrc.
-            // However, demuxers such as qtdemux can correctly handled fragmented buffers.
-            // In the event of an ungraceful pravegasink termination before all fragments are written,
-            // it will mark the first buffer after starting as a discontinuity,
-            // allowing elements downstream from pravegasrc to reinitialize.
-            let mut pos_to_write = 0;
-            loop {
-                let length_to_write = usize::min(payload.len() - pos_to_write, EventWithHeader::max_payload_size());
-                if length_to_write == 0 { break };
-                let event = if pos_to_write == 0 {
-                    EventWithHeader::new(&payload[pos_to_write..pos_to_write+length_to_write],
-                        timestamp, include_in_index, random_access, discontinuity)
-                } else {
-                    gst_debug!(CAT, obj: element, "render: buffer exceeds atomic write size and has been fragmented; writing additional payload of {} bytes", length_to_write);
-                    // Additional writes must not be indexed and must not be marked as a discontinuity as that would reset the demuxer.
-                    EventWithHeader::new(&payload[pos_to_write..pos_to_write+length_to_write],
-                        timestamp, false, false, false)
-                };
-                gst_memdump!(CAT, obj: element, "render: writing event={:?}", event);
-                let mut event_writer = EventWriter::new();
-                event_writer.write(&event, writer).map_err(|err| {
-                    gst::element_error!(
-                        element,
-                        gst::ResourceError::Write,
-                        ["Failed to write buffer: {}", err]
-                    );
-                    gst::FlowError::Error
-                })?;
-                pos_to_write += length_to_write;
+            if result.fragments > 1 {
+                gst_debug!(CAT, obj: element, "render: buffer exceeds atomic write size and has been fragmented into {} events", result.fragments);
             }
-            *buffers_written += 1;
-
-            // Get the writer offset after writing.
-            let writer_offset_end = writer.seek(SeekFrom::Current(0)).unwrap();
             gst_trace!(CAT, obj: element, "render: wrote {} bytes from offset {} to {}",
-                writer_offset_end - writer_offset, writer_offset, writer_offset_end);
-
-            // Flush after writing if the buffer contains the SYNC_AFTER flag. This is normally not used.
-            let sync_after = buffer_flags.contains(gst::BufferFlags::SYNC_AFTER);
-            if sync_after {
-                writer.flush().map_err(|error| {
-                    gst::element_error!(element, gst::CoreError::Failed, ["Failed to flush Pravega data stream: {}", error]);
-                    gst::FlowError::Error
-                })?;
-                index_writer.flush().map_err(|error| {
-                    gst::element_error!(element, gst::CoreError::Failed, ["Failed to flush Pravega index stream: {}", error]);
-                    gst::FlowError::Error
-                })?;
+                result.end_offset - result.offset, result.offset, result.end_offset);
+            if frame.sync_after {
                 gst_debug!(CAT, obj: element, "render: Streams flushed because SYNC_AFTER flag was set");
             }
 
-            // Maintain values that may be written to the index on end-of-stream.
-            // Per the index constraints defined in index.rs, the timestamp in the index record must
-            // be strictly greater than the timestamp in the data stream.
-            if timestamp.is_some() {
-                // If duration of the buffer is reported as 0, we record it as if it had a 1 nanosecond duration.
-                let duration = cmp::max(1, duration.nanoseconds().unwrap_or_default());
-                *final_timestamp = PravegaTimestamp::from_nanoseconds(
-                    timestamp.nanoseconds().map(|t| t + duration));
-            }
-            *final_offset = Some(writer_offset_end);
-
             Ok(gst::FlowSuccess::Ok)
         })();
         gst_trace!(CAT, obj: element, "render: END: result={:?}", result);
@@ -1129,27 +938,15 @@ impl BaseSinkImpl for PravegaSink {
             };
 
             let mut state = self.state.lock().unwrap();
-            let (runtime,
-                writer,
-                index_writer,
-                final_timestamp,
-                final_offset,
+            let (writer,
                 retention_thread_stop_tx,
                 retention_thread_handle) = match *state {
                 State::Started {
-                    ref runtime,
                     ref mut writer,
-                    ref mut index_writer,
-                    ref mut final_timestamp,
-                    ref mut final_offset,
                     ref mut retention_thread_stop_tx,
                     ref mut retention_thread_handle,
                     ..
-                } => (runtime,
-                    writer,
-                    index_writer,
-                    final_timestamp,
-                    final_offset,
+                } => (writer,
                     retention_thread_stop_tx,
                     retention_thread_handle),
                 State::Stopped => {
@@ -1160,37 +957,20 @@ impl BaseSinkImpl for PravegaSink {
                 }
             };
 
-            writer.flush().map_err(|error| {
-                gst::error_msg!(gst::ResourceError::Write, ["Failed to flush Pravega data stream: {}", error])
-            })?;
-
-            // Write final index record.
+            // Flush data and write the final index record.
             // The timestamp will be the the buffer timestamp + duration of the final buffer.
             // The offset will be current write position.
-            if let Some(final_offset) = *final_offset {
-                if final_timestamp.is_some() {
-                    let index_record = IndexRecord::new(*final_timestamp, final_offset,
-                        false, false);
-                    let mut index_record_writer = IndexRecordWriter::new();
-                    index_record_writer.write(&index_record, index_writer).map_err(|error| {
-                        gst::error_msg!(gst::ResourceError::Write, ["Failed to write Pravega index stream: {}", error])
-                    })?;
-                    gst_info!(CAT, obj: element, "stop: Wrote final index record {:?}", index_record);
-                }
-            }
-
-            index_writer.flush().map_err(|error| {
-                gst::error_msg!(gst::ResourceError::Write, ["Failed to flush Pravega index stream: {}", error])
+            let index_record = writer.close().map_err(|error| {
+                gst::error_msg!(gst::ResourceError::Write, ["Failed to close Pravega streams: {}", error])
             })?;
+            if let Some(index_record) = index_record {
+                gst_info!(CAT, obj: element, "stop: Wrote final index record {:?}", index_record);
+            }
 
             if seal {
                 gst_info!(CAT, obj: element, "stop: Sealing streams");
-                let writer = writer.get_mut().get_mut().get_mut();
-                runtime.block_on(writer.seal()).map_err(|error| {
-                    gst::error_msg!(gst::ResourceError::Write, ["Failed to seal Pravega data stream: {}", error])
-                })?;
-                index_writer.seal().map_err(|error| {
-                    gst::error_msg!(gst::ResourceError::Write, ["Failed to seal Pravega index stream: {}", error])
+                writer.seal().map_err(|error| {
+                    gst::error_msg!(gst::ResourceError::Write, ["Failed to seal Pravega streams: {}", error])
                 })?;
                 gst_info!(CAT, obj: element, "stop: Streams sealed");
             }
@@ -20,20 +20,23 @@ use gst::{gst_debug, gst_error, gst_info, gst_log, gst_trace, gst_memdump};
 use gst_base::prelude::*;
 use gst_base::subclass::prelude::*;
 
+use std::collections::HashMap;
 use std::convert::{TryInto, TryFrom};
 use std::io::{BufReader, ErrorKind, Seek, SeekFrom};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use std::u8;
 
 use once_cell::sync::Lazy;
 
-use pravega_client::client_factory::ClientFactory;
-use pravega_client_shared::{Scope, Stream, StreamConfiguration, ScopedStream, Scaling, ScaleType};
+use pravega_client_shared::{Scope, Stream, ScopedStream};
 use pravega_video::event_serde::EventReader;
-use pravega_video::index::{IndexSearcher, get_index_stream_name};
+use pravega_video::index::get_index_stream_name;
+use pravega_video::index_cache::{CachedIndex, IndexCache};
+use pravega_video::storage::{StorageBackend, StreamReader, create_storage_backend};
 use pravega_video::timestamp::PravegaTimestamp;
 use pravega_video::utils;
-use pravega_video::utils::{CurrentHead, SyncByteReader};
+use pravega_video::utils::CurrentHead;
 use crate::counting_reader::CountingReader;
 use crate::seekable_take::SeekableTake;
 use crate::utils::{clocktime_to_pravega, pravega_to_clocktime};
@@ -50,6 +53,17 @@ const PROPERTY_NAME_END_UTC: &str = "end-utc";
 const PROPERTY_NAME_ALLOW_CREATE_SCOPE: &str = "allow-create-scope";
 const PROPERTY_NAME_KEYCLOAK_FILE: &str = "keycloak-file";
 
+/// The maximum number of streams in each index cache.
+const INDEX_CACHE_STREAMS: usize = 100;
+/// The maximum number of index records cached for each stream.
+const INDEX_CACHE_RECORDS: usize = 10000;
+
+/// Index caches shared by all pravegasrc elements in this process, by controller and keycloak file.
+/// Elements that read the same stream, such as those in repeated export or thumbnail pipelines,
+/// will read its index only once, after which only new records are read.
+static INDEX_CACHES: Lazy<Mutex<HashMap<(String, Option<String>), IndexCache<Box<dyn StreamReader>>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::GEnum)]
 #[repr(u32)]
 #[genum(type_name = "GstStartMode")]
@@ -161,10 +175,10 @@ impl Default for Settings {
 enum State {
     Stopped,
     Started {
-        reader: Arc<Mutex<CountingReader<BufReader<SeekableTake<SyncByteReader>>>>>,
-        index_searcher: Arc<Mutex<IndexSearcher<SyncByteReader>>>,
-        // save client facotry to keep the tokio runtime
-        client_factory: ClientFactory,
+        reader: Arc<Mutex<CountingReader<BufReader<SeekableTake<Box<dyn StreamReader>>>>>>,
+        index_searcher: Arc<tokio::sync::Mutex<CachedIndex<Box<dyn StreamReader>>>>,
+        // The storage backend may own the Tokio runtime used by the readers.
+        storage: Arc<dyn StorageBackend>,
     },
 }
 
@@ -262,6 +276,7 @@ impl ObjectImpl for PravegaSrc {
                 PROPERTY_NAME_CONTROLLER,
                 "Controller",
                 format!("Pravega controller. \
+                    Use file:///path to store streams in a local directory or memory://name to store streams in memory. \
                     If not specified, this will use the value of the environment variable {}. \
                     If that is empty, it will use the default of {}.",
                     utils::ENV_PRAVEGA_CONTROLLER_URI, utils::DEFAULT_PRAVEGA_CONTROLLER_URI).as_str(),
@@ -572,80 +587,56 @@ impl BaseSrcImpl for PravegaSrc {
             gst_info!(CAT, obj: element, "start: controller={}", controller);
             let keycloak_file = settings.keycloak_file.clone();
             gst_info!(CAT, obj: element, "start: keycloak_file={:?}", keycloak_file);
-            let config = utils::create_client_config(controller, keycloak_file).map_err(|error| {
-                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create Pravega client config: {}", error])
+            // The controller may also be file:///path or memory://name to read streams without Pravega.
+            let storage = create_storage_backend(controller.clone(), keycloak_file.clone()).map_err(|error| {
+                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create storage backend: {}", error])
             })?;
-            gst_trace!(CAT, obj: element, "start: config={:?}", config);
-            gst_info!(CAT, obj: element, "start: controller_uri={}:{}", config.controller_uri.domain_name(), config.controller_uri.port());
-            gst_info!(CAT, obj: element, "start: is_tls_enabled={}", config.is_tls_enabled);
-            gst_info!(CAT, obj: element, "start: is_auth_enabled={}", config.is_auth_enabled);
-
-            let client_factory = ClientFactory::new(config);
-            let controller_client = client_factory.controller_client();
-            let runtime = client_factory.runtime();
 
             // Create scope.
             gst_info!(CAT, obj: element, "start: allow_create_scope={}", settings.allow_create_scope);
             if settings.allow_create_scope {
                 // This is expected to fail in some environments, even if the scope already exists.
                 // We will log the error and continue.
-                let _ = runtime.block_on(controller_client.create_scope(&scope)).map_err(|error| {
-                    gst_debug!(CAT, obj: element, "Failed to create Pravega scope. This is normal if the scope already exists: {:?}", error);
+                let _ = storage.create_scope(&scope).map_err(|error| {
+                    gst_debug!(CAT, obj: element, "Failed to create scope. This is normal if the scope already exists: {}", error);
                 });
             }
 
             // Create data stream.
-            let stream_config = StreamConfiguration {
-                scoped_stream: ScopedStream {
-                    scope: scope.clone(),
-                    stream: stream.clone(),
-                },
-                scaling: Scaling {
-                    scale_type: ScaleType::FixedNumSegments,
-                    min_num_segments: 1,
-                    ..Default::default()
-                },
-                retention: Default::default(),
-                tags: utils::get_video_tags(),
-            };
-            runtime.block_on(controller_client.create_stream(&stream_config)).map_err(|error| {
-                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create Pravega data stream: {:?}", error])
-            })?;
-
-            // Create index stream.
-            let index_stream_config = StreamConfiguration {
-                scoped_stream: ScopedStream {
-                    scope: scope.clone(),
-                    stream: index_stream.clone(),
-                },
-                scaling: Scaling {
-                    scale_type: ScaleType::FixedNumSegments,
-                    min_num_segments: 1,
-                    ..Default::default()
-                },
-                retention: Default::default(),
-                tags: None,
-            };
-            runtime.block_on(controller_client.create_stream(&index_stream_config)).map_err(|error| {
-                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create Pravega index stream: {:?}", error])
-            })?;
-
             let scoped_stream = ScopedStream {
                 scope: scope.clone(),
                 stream: stream.clone(),
             };
-            let reader = runtime.block_on(client_factory.create_byte_reader(scoped_stream));
-            let mut reader = SyncByteReader::new(reader, client_factory.runtime_handle());
-            gst_info!(CAT, obj: element, "start: Opened Pravega reader for data");
+            storage.create_stream(&scoped_stream, utils::get_video_tags()).map_err(|error| {
+                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create data stream: {}", error])
+            })?;
 
+            // Create index stream.
             let index_scoped_stream = ScopedStream {
                 scope: scope.clone(),
                 stream: index_stream.clone(),
             };
-            let index_reader = runtime.block_on(client_factory.create_byte_reader(index_scoped_stream));
-            gst_info!(CAT, obj: element, "start: Opened Pravega reader for index");
+            storage.create_stream(&index_scoped_stream, None).map_err(|error| {
+                gst::error_msg!(gst::ResourceError::Settings, ["Failed to create index stream: {}", error])
+            })?;
 
-            let mut index_searcher = IndexSearcher::new(SyncByteReader::new(index_reader, client_factory.runtime_handle()));
+            let mut reader = storage.create_reader(&scoped_stream).map_err(|error| {
+                gst::error_msg!(gst::ResourceError::OpenRead, ["Failed to open reader for data stream: {}", error])
+            })?;
+            gst_info!(CAT, obj: element, "start: Opened reader for data");
+
+            // The index cache is shared with other elements that use the same controller.
+            let index_cache = INDEX_CACHES.lock().unwrap()
+                .entry((controller, keycloak_file))
+                .or_insert_with(|| {
+                    IndexCache::with_storage_backend(storage.clone(), Duration::from_secs(0), INDEX_CACHE_STREAMS)
+                        .with_max_records(INDEX_CACHE_RECORDS)
+                })
+                .clone();
+            let index_searcher = index_cache.get(&scoped_stream).map_err(|error| {
+                gst::error_msg!(gst::ResourceError::OpenRead, ["Failed to open reader for index stream: {}", error])
+            })?;
+            gst_info!(CAT, obj: element, "start: Opened reader for index");
 
             // TODO: Run below based on CAT threshold.
             // gst_debug!(CAT, obj: element, "index_records={:?}", index_searcher.get_index_records());
@@ -660,14 +651,14 @@ impl BaseSrcImpl for PravegaSrc {
                 },
                 EndMode::LatestIndexed => {
                     // Determine Pravega stream offset for this timestamp by searching the index.
-                    let index_record = index_searcher.get_last_record().unwrap();
+                    let index_record = index_searcher.blocking_lock().get_last_record().unwrap();
                     gst_info!(CAT, obj: element, "start: end index_record={:?}", index_record);
                     index_record.offset
                 },
                 EndMode::Timestamp => {
                     let end_timestamp = PravegaTimestamp::from_nanoseconds(Some(settings.end_timestamp));
                     // Determine Pravega stream offset for this timestamp by searching the index.
-                    let index_record = index_searcher.search_timestamp_after(end_timestamp).unwrap();
+                    let index_record = index_searcher.blocking_lock().search_timestamp_after(end_timestamp).unwrap();
                     gst_info!(CAT, obj: element, "start: end index_record={:?}", index_record);
                     index_record.offset
                 },
@@ -680,8 +671,8 @@ impl BaseSrcImpl for PravegaSrc {
 
             *state = State::Started {
                 reader: Arc::new(Mutex::new(counting_reader)),
-                index_searcher: Arc::new(Mutex::new(index_searcher)),
-                client_factory,
+                index_searcher,
+                storage,
             };
             gst_info!(CAT, obj: element, "start: Started");
             Ok(())
@@ -752,7 +743,7 @@ impl BaseSrcImpl for PravegaSrc {
             let index_searcher = index_searcher.clone();
             drop(state);
             let mut reader = reader.lock().unwrap();
-            let mut index_searcher = index_searcher.lock().unwrap();
+            let mut index_searcher = index_searcher.blocking_lock();
 
             let segment = segment.downcast_mut::<gst::format::Time>().unwrap();
 
@@ -847,7 +838,7 @@ impl BaseSrcImpl for PravegaSrc {
                         };
                         let index_searcher = index_searcher.clone();
                         drop(state);
-                        let mut index_searcher = index_searcher.lock().unwrap();
+                        let mut index_searcher = index_searcher.blocking_lock();
 
                         let start = match index_searcher.get_first_record() {
                             Ok(start) => start,
@@ -21,6 +21,8 @@ mod rtsp_camera_simulator;
 mod rtsp_tests;
 mod truncation_tests;
 mod utils;
+mod video_server;
+mod video_server_tests;
 
 use lazy_static::lazy_static;
 use pravega_client_config::ClientConfig;
@@ -0,0 +1,123 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Runs Pravega Video Server for integration tests.
+
+use lazy_static::lazy_static;
+use std::net::{SocketAddr, TcpStream};
+use std::process::{Child, Command};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+#[allow(unused_imports)]
+use tracing::{error, info, warn};
+
+const VIDEO_SERVER_DIR: &str = "../pravega-video-server";
+const VIDEO_SERVER_BIND_ADDRESS: &str = "127.0.0.1";
+/// The port of the server shared by all tests.
+pub const DEFAULT_VIDEO_SERVER_PORT: u16 = 3030;
+const VIDEO_SERVER_START_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A Pravega Video Server process.
+/// The process is killed when this is dropped.
+#[derive(Debug)]
+pub struct VideoServer {
+    process: Child,
+    address: SocketAddr,
+}
+
+impl VideoServer {
+    /// Build and start Pravega Video Server, then wait until it accepts connections.
+    pub fn start(controller_uri: &str, port: u16) -> Self {
+        info!("Building Pravega Video Server");
+        let status = Command::new("cargo")
+            .arg("build")
+            .arg("--release")
+            .arg("--manifest-path").arg(format!("{}/Cargo.toml", VIDEO_SERVER_DIR))
+            .status()
+            .expect("failed to execute cargo");
+        assert!(status.success(), "failed to build Pravega Video Server");
+        info!("Starting Pravega Video Server with controller {}", controller_uri);
+        let process = Command::new(format!("{}/target/release/pravega-video-server", VIDEO_SERVER_DIR))
+            .arg("--pravega-controller-uri").arg(controller_uri)
+            .arg("--resource-dir").arg(format!("{}/resources", VIDEO_SERVER_DIR))
+            .arg("--bind-address").arg(VIDEO_SERVER_BIND_ADDRESS)
+            .arg("--port").arg(port.to_string())
+            .spawn()
+            .expect("failed to start Pravega Video Server");
+        let address = SocketAddr::new(VIDEO_SERVER_BIND_ADDRESS.parse().unwrap(), port);
+        let mut video_server = VideoServer { process, address };
+        video_server.wait_for_start();
+        video_server
+    }
+
+    fn wait_for_start(&mut self) {
+        let start = Instant::now();
+        while TcpStream::connect_timeout(&self.address, Duration::from_secs(1)).is_err() {
+            if let Some(status) = self.process.try_wait().unwrap() {
+                panic!("Pravega Video Server exited with {}", status);
+            }
+            if start.elapsed() > VIDEO_SERVER_START_TIMEOUT {
+                panic!("timeout {:?} exceeded waiting for Pravega Video Server", VIDEO_SERVER_START_TIMEOUT);
+            }
+            std::thread::sleep(Duration::from_millis(200));
+        }
+        info!("Pravega Video Server is running.");
+    }
+
+    pub fn uri(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    pub fn stop(&mut self) -> Result<(), std::io::Error> {
+        self.process.kill()?;
+        self.process.wait()?;
+        Ok(())
+    }
+}
+
+impl Drop for VideoServer {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+lazy_static! {
+    static ref VIDEO_SERVER: Mutex<Option<VideoServer>> = Mutex::new(None);
+}
+
+fn lock_video_server() -> MutexGuard<'static, Option<VideoServer>> {
+    // A test that panicked while holding the lock does not leave the server in a bad state.
+    VIDEO_SERVER.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Returns the URI of the video server shared by all tests.
+/// If the environment variable PRAVEGA_VIDEO_SERVER_URI is set, it will be used.
+/// Otherwise, it will start Pravega Video Server with the controller if it is not already running.
+pub fn get_video_server_uri(controller_uri: &str) -> String {
+    match std::env::var("PRAVEGA_VIDEO_SERVER_URI") {
+        Ok(video_server_uri) => {
+            info!("Using external Pravega Video Server {}", video_server_uri);
+            video_server_uri
+        },
+        Err(_) => lock_video_server()
+            .get_or_insert_with(|| VideoServer::start(controller_uri, DEFAULT_VIDEO_SERVER_PORT))
+            .uri(),
+    }
+}
+
+/// If Pravega Video Server was started, it will be stopped when this process exits.
+/// The shutdown function must not use println or tracing or a panic will occur.
+#[cfg(test)]
+#[ctor::dtor]
+unsafe fn shutdown_video_server() {
+    if let Some(mut video_server) = lock_video_server().take() {
+        let _ = video_server.stop();
+    }
+}
@@ -0,0 +1,471 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+#[cfg(test)]
+mod test {
+    use pravega_client::client_factory::ClientFactory;
+    use pravega_client_shared::{Scope, Stream, ScopedStream};
+    use pravega_video::event_serde::{EventWithHeader, EventWriter};
+    use pravega_video::index::{IndexRecord, IndexRecordWriter, get_index_stream_name};
+    use pravega_video::timestamp::{PravegaTimestamp, MSECOND, SECOND};
+    use pravega_video::utils::SyncByteWriter;
+    use reqwest::StatusCode;
+    use rstest::rstest;
+    use std::convert::TryFrom;
+    use std::io::{ErrorKind, Read, Write};
+    use std::net::TcpStream;
+    use std::time::Duration;
+    #[allow(unused_imports)]
+    use tracing::{error, info, debug};
+    use uuid::Uuid;
+    use crate::*;
+    use crate::utils::*;
+    use crate::video_server::*;
+
+    /// A controller port that nothing listens on.
+    const UNAVAILABLE_CONTROLLER_URI: &str = "tcp://127.0.0.1:9099";
+    /// The port of the video server that uses the unavailable controller.
+    const UNAVAILABLE_VIDEO_SERVER_PORT: u16 = 3031;
+
+    /// Write 5 seconds of fragmented MP4 video to a stream.
+    fn video_server_test_data_gen(test_config: &TestConfig, stream_name: &str) {
+        gst_init();
+        let first_timestamp = PravegaTimestamp::try_from(Some("2001-02-03T04:00:00.000Z".to_owned())).unwrap();
+        let fps = 30;
+        let container_format = ContainerFormat::Mp4(Mp4MuxConfigBuilder::default().fragment_duration(100 * MSECOND).build().unwrap());
+        let pipeline_description = format!(
+            "videotestsrc name=src timestamp-offset={timestamp_offset} num-buffers={num_buffers} \
+            ! video/x-raw,width=320,height=180,framerate={fps}/1 \
+            ! videoconvert \
+            ! x264enc key-int-max={fps} bitrate=100 \
+            ! {container_pipeline} \
+            ! pravegasink {pravega_plugin_properties} \
+              seal=true timestamp-mode=tai sync=false",
+            pravega_plugin_properties = test_config.pravega_plugin_properties(stream_name),
+            timestamp_offset = first_timestamp.nanoseconds().unwrap(),
+            num_buffers = 5 * fps,
+            fps = fps,
+            container_pipeline = container_format.pipeline(),
+        );
+        launch_pipeline(&pipeline_description).unwrap();
+    }
+
+    /// Create a stream with an empty index.
+    fn video_server_empty_stream_gen(test_config: &TestConfig, stream_name: &str) {
+        gst_init();
+        let pipeline_description = format!(
+            "fakesrc num-buffers=0 \
+            ! pravegasink {pravega_plugin_properties} sync=false",
+            pravega_plugin_properties = test_config.pravega_plugin_properties(stream_name),
+        );
+        launch_pipeline(&pipeline_description).unwrap();
+    }
+
+    /// Create a stream with an event for each payload, an index record at the beginning of each event,
+    /// and an index record at the end of the data.
+    /// Returns the offsets of the index records.
+    fn video_server_events_gen(test_config: &TestConfig, stream_name: &str, events: &[(PravegaTimestamp, Vec<u8>)],
+            end_timestamp: PravegaTimestamp) -> Vec<u64> {
+        video_server_empty_stream_gen(test_config, stream_name);
+        let client_factory = ClientFactory::new(test_config.client_config.clone());
+        let runtime = client_factory.runtime();
+        let scope = Scope::from(test_config.scope.clone());
+        let scoped_stream = ScopedStream {
+            scope: scope.clone(),
+            stream: Stream::from(stream_name.to_owned()),
+        };
+        let index_scoped_stream = ScopedStream {
+            scope,
+            stream: Stream::from(get_index_stream_name(stream_name)),
+        };
+        let mut writer = SyncByteWriter::new(
+            runtime.block_on(client_factory.create_byte_writer(scoped_stream)), client_factory.runtime_handle());
+        let mut index_writer = SyncByteWriter::new(
+            runtime.block_on(client_factory.create_byte_writer(index_scoped_stream)), client_factory.runtime_handle());
+        let mut event_writer = EventWriter::new();
+        let mut index_records = Vec::new();
+        let mut offset = 0;
+        for (timestamp, payload) in events {
+            let event = EventWithHeader::new(payload, *timestamp, true, true, false);
+            event_writer.write(&event, &mut writer).unwrap();
+            index_records.push(IndexRecord::new(*timestamp, offset, true, false));
+            offset += event.encoded_length() as u64;
+        }
+        index_records.push(IndexRecord::new(end_timestamp, offset, true, false));
+        writer.flush().unwrap();
+        let mut index_record_writer = IndexRecordWriter::new();
+        for index_record in index_records.iter() {
+            index_record_writer.write(index_record, &mut index_writer).unwrap();
+        }
+        index_writer.flush().unwrap();
+        index_records.iter().map(|index_record| index_record.offset).collect()
+    }
+
+    fn http_client() -> reqwest::blocking::Client {
+        reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(120))
+            .build()
+            .unwrap()
+    }
+
+    /// Request a URI and assert that the response is a problem with the status and type.
+    fn assert_problem(uri: &str, expected_status: StatusCode, expected_type: &str) {
+        info!("GET {}", uri);
+        let response = http_client().get(uri).send().unwrap();
+        assert_problem_response(response, expected_status, expected_type);
+    }
+
+    fn assert_problem_response(response: reqwest::blocking::Response, expected_status: StatusCode, expected_type: &str) {
+        let status = response.status();
+        let content_type = response.headers().get("content-type").map(|value| value.to_str().unwrap().to_owned());
+        let body = response.text().unwrap();
+        info!("status={}, body={}", status, body);
+        assert_eq!(status, expected_status);
+        assert_eq!(content_type.as_deref(), Some("application/problem+json"));
+        let problem: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(problem["type"], expected_type);
+        assert_eq!(problem["status"], expected_status.as_u16());
+    }
+
+    #[rstest]
+    #[case("m3u8")]
+    #[case("mpd")]
+    #[case("timeline?begin=2001-02-03T04:00:00Z&end=2001-02-03T05:00:00Z")]
+    fn test_video_server_scope_not_found(#[case] resource: &str) {
+        let test_config = &get_test_config();
+        let video_server_uri = get_video_server_uri(&test_config.client_config.controller_uri.0);
+        let scope = format!("test-missing-{}", Uuid::new_v4());
+        assert_problem(&format!("{}/scopes/{}/streams", video_server_uri, scope),
+            StatusCode::NOT_FOUND, "scope-not-found");
+        assert_problem(&format!("{}/scopes/{}/streams/missing/{}", video_server_uri, scope, resource),
+            StatusCode::NOT_FOUND, "scope-not-found");
+    }
+
+    #[rstest]
+    #[case("m3u8")]
+    #[case("mpd")]
+    #[case("media?begin=0&end=0")]
+    #[case("timeline?begin=2001-02-03T04:00:00Z&end=2001-02-03T05:00:00Z")]
+    fn test_video_server_stream_not_found(#[case] resource: &str) {
+        let test_config = &get_test_config();
+        // Create the scope.
+        let stream_name = &format!("test-video-server-{}-{}", test_config.test_id, Uuid::new_v4())[..];
+        video_server_empty_stream_gen(test_config, stream_name);
+        let video_server_uri = get_video_server_uri(&test_config.client_config.controller_uri.0);
+        assert_problem(&format!("{}/scopes/{}/streams/test-missing-{}/{}", video_server_uri, test_config.scope, Uuid::new_v4(), resource),
+            StatusCode::NOT_FOUND, "stream-not-found");
+    }
+
+    #[rstest]
+    #[case("m3u8")]
+    #[case("mpd")]
+    #[case("media?begin=0&end=0")]
+    #[case("timeline?begin=2001-02-03T04:00:00Z&end=2001-02-03T05:00:00Z")]
+    fn test_video_server_empty_index(#[case] resource: &str) {
+        let test_config = &get_test_config();
+        let stream_name = &format!("test-video-server-{}-{}", test_config.test_id, Uuid::new_v4())[..];
+        video_server_empty_stream_gen(test_config, stream_name);
+        let video_server_uri = get_video_server_uri(&test_config.client_config.controller_uri.0);
+        assert_problem(&format!("{}/scopes/{}/streams/{}/{}", video_server_uri, test_config.scope, stream_name, resource),
+            StatusCode::NOT_FOUND, "empty-index");
+    }
+
+    #[rstest]
+    #[case("m3u8?begin=2001-02-03T04:00:05Z&end=2001-02-03T04:00:00Z", StatusCode::BAD_REQUEST, "invalid-time-range")]
+    #[case("mpd?begin=2001-02-03T04:00:05Z&end=2001-02-03T04:00:00Z", StatusCode::BAD_REQUEST, "invalid-time-range")]
+    #[case("timeline?begin=2001-02-03T04:00:05Z&end=2001-02-03T04:00:00Z", StatusCode::BAD_REQUEST, "invalid-time-range")]
+    #[case("export.mp4?begin=2001-02-03T04:00:00Z&end=2001-02-03T06:00:00Z", StatusCode::BAD_REQUEST, "invalid-time-range")]
+    #[case("m3u8?begin=yesterday", StatusCode::BAD_REQUEST, "bad-request")]
+    #[case("media?begin=0", StatusCode::BAD_REQUEST, "bad-request")]
+    fn test_video_server_bad_request(#[case] resource: &str, #[case] expected_status: StatusCode, #[case] expected_type: &str) {
+        let test_config = &get_test_config();
+        let stream_name = &format!("test-video-server-{}-{}", test_config.test_id, Uuid::new_v4())[..];
+        video_server_test_data_gen(test_config, stream_name);
+        let video_server_uri = get_video_server_uri(&test_config.client_config.controller_uri.0);
+        assert_problem(&format!("{}/scopes/{}/streams/{}/{}", video_server_uri, test_config.scope, stream_name, resource),
+            expected_status, expected_type);
+    }
+
+    /// Media segments must begin and end at the offset of an index record.
+    #[test]
+    fn test_video_server_not_event_boundary() {
+        let test_config = &get_test_config();
+        let stream_name = &format!("test-video-server-{}-{}", test_config.test_id, Uuid::new_v4())[..];
+        video_server_test_data_gen(test_config, stream_name);
+        let video_server_uri = get_video_server_uri(&test_config.client_config.controller_uri.0);
+        let stream_uri = format!("{}/scopes/{}/streams/{}", video_server_uri, test_config.scope, stream_name);
+        let playlist = http_client().get(&format!("{}/m3u8", stream_uri)).send().unwrap().error_for_status().unwrap().text().unwrap();
+        debug!("playlist={}", playlist);
+        // Get the byte offsets of the first media segment.
+        let media_uri = playlist.lines().find(|line| line.starts_with("media?")).unwrap();
+        let (begin, end) = parse_media_uri(media_uri);
+        info!("begin={}, end={}", begin, end);
+        assert!(begin < end);
+        let response = http_client().get(&format!("{}/media?begin={}&end={}", stream_uri, begin, end)).send().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.bytes().unwrap().len() as u64, end - begin);
+        assert_problem(&format!("{}/media?begin={}&end={}", stream_uri, begin + 1, end),
+            StatusCode::CONFLICT, "not-event-boundary");
+        assert_problem(&format!("{}/media?begin={}&end={}", stream_uri, begin, end - 1),
+            StatusCode::CONFLICT, "not-event-boundary");
+        assert_problem(&format!("{}/init?begin={}", stream_uri, begin + 1),
+            StatusCode::CONFLICT, "not-event-boundary");
+    }
+
+    /// Parse the byte offsets from a media URI such as "media?begin=0&end=204".
+    fn parse_media_uri(media_uri: &str) -> (u64, u64) {
+        let query = media_uri.split(|c| c == '?' || c == '&').collect::<Vec<_>>();
+        let offset = |name: &str| query.iter().find_map(|param| param.strip_prefix(name)).unwrap().parse::<u64>().unwrap();
+        (offset("begin="), offset("end="))
+    }
+
+    /// Each byte range of a payload resource in a byte range playlist has the same bytes as the media segment.
+    #[test]
+    fn test_video_server_byte_range_playlist() {
+        let test_config = &get_test_config();
+        let stream_name = &format!("test-video-server-{}-{}", test_config.test_id, Uuid::new_v4())[..];
+        video_server_test_data_gen(test_config, stream_name);
+        let video_server_uri = get_video_server_uri(&test_config.client_config.controller_uri.0);
+        let stream_uri = format!("{}/scopes/{}/streams/{}", video_server_uri, test_config.scope, stream_name);
+        let playlist = http_client().get(&format!("{}/m3u8", stream_uri)).send().unwrap().error_for_status().unwrap().text().unwrap();
+        let byte_range_playlist = http_client().get(&format!("{}/m3u8?byte_range=true", stream_uri)).send().unwrap()
+            .error_for_status().unwrap().text().unwrap();
+        debug!("byte_range_playlist={}", byte_range_playlist);
+        let media_uris: Vec<_> = playlist.lines().filter(|line| line.starts_with("media?")).collect();
+        let byte_ranges: Vec<_> = byte_range_playlist.lines()
+            .filter_map(|line| line.strip_prefix("#EXT-X-BYTERANGE:"))
+            .zip(byte_range_playlist.lines().filter(|line| line.starts_with("payload/")))
+            .collect();
+        assert!(!media_uris.is_empty());
+        assert_eq!(byte_ranges.len(), media_uris.len());
+        for (media_uri, (byte_range, payload_uri)) in media_uris.iter().zip(byte_ranges.iter()) {
+            let (begin, end) = parse_media_uri(media_uri);
+            let (length, offset) = byte_range.split_once('@').unwrap();
+            let (length, offset) = (length.parse::<u64>().unwrap(), offset.parse::<u64>().unwrap());
+            info!("media_uri={}, payload_uri={}, byte_range={}", media_uri, payload_uri, byte_range);
+            // The payload resource includes the initialization segments.
+            let media = http_client().get(&format!("{}/media?begin={}&end={}", stream_uri, begin, end)).send().unwrap()
+                .error_for_status().unwrap().bytes().unwrap();
+            assert_eq!(media.len() as u64, end - begin);
+            let response = http_client().get(&format!("{}/{}", stream_uri, payload_uri))
+                .header("range", format!("bytes={}-{}", offset, offset + length - 1))
+                .send().unwrap();
+            assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+            assert_eq!(response.bytes().unwrap(), media);
+        }
+        // The anchor must be the first index record of a window.
+        let (begin, _) = parse_media_uri(media_uris[1]);
+        assert_problem(&format!("{}/payload/{}", stream_uri, begin), StatusCode::NOT_FOUND, "not-found");
+    }
+
+    /// Media segments and payload ranges larger than a chunk are streamed with exactly the bytes of the payloads.
+    /// A segment that the client stops reading is not cached.
+    #[test]
+    fn test_video_server_streamed_media_segment() {
+        let test_config = &get_test_config();
+        let stream_name = &format!("test-video-server-{}-{}", test_config.test_id, Uuid::new_v4())[..];
+        let first_timestamp = PravegaTimestamp::try_from(Some("2001-02-03T04:00:00.000Z".to_owned())).unwrap();
+        // 20 events of 10,000 bytes are streamed in several chunks of 64 KiB.
+        let payload_size = 10_000;
+        let events: Vec<_> = (0..20u64)
+            .map(|i| (first_timestamp + i * SECOND,
+                (0..payload_size).map(|j| ((i * payload_size + j) % 251) as u8).collect::<Vec<u8>>()))
+            .collect();
+        let payloads: Vec<u8> = events.iter().flat_map(|(_, payload)| payload.iter().cloned()).collect();
+        let offsets = video_server_events_gen(test_config, stream_name, &events, first_timestamp + 20 * SECOND);
+        let video_server_uri = get_video_server_uri(&test_config.client_config.controller_uri.0);
+        let stream_uri = format!("{}/scopes/{}/streams/{}", video_server_uri, test_config.scope, stream_name);
+        let payload_offset = |event_number: usize| event_number * payload_size as usize;
+
+        // The entire stream.
+        let response = http_client().get(&format!("{}/media?begin={}&end={}", stream_uri, offsets[0], offsets[20])).send().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get("content-length").is_none());
+        assert!(response.bytes().unwrap()[..] == payloads[..]);
+
+        // Stop reading after the first bytes. Reading must stop and the partial segment must not be cached.
+        let media_uri = format!("{}/media?begin={}&end={}", stream_uri, offsets[3], offsets[17]);
+        let mut response = http_client().get(&media_uri).send().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut first_bytes = vec![0; 1000];
+        response.read_exact(&mut first_bytes).unwrap();
+        assert!(first_bytes[..] == payloads[payload_offset(3)..payload_offset(3) + 1000]);
+        drop(response);
+        // The complete segment is read and then returned from the cache.
+        for _ in 0..2 {
+            let response = http_client().get(&media_uri).send().unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(response.bytes().unwrap()[..] == payloads[payload_offset(3)..payload_offset(17)]);
+        }
+
+        // A byte range of the payload resource skips the beginning of the first event and stops within the last event.
+        let (begin, end) = (12_345, 150_000);
+        let response = http_client().get(&format!("{}/payload/{}", stream_uri, offsets[0]))
+            .header("range", format!("bytes={}-{}", begin, end - 1))
+            .send().unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers().get("content-length").unwrap().to_str().unwrap(), (end - begin).to_string());
+        assert!(response.bytes().unwrap()[..] == payloads[begin..end]);
+    }
+
+    /// Read the messages of a WebSocket until none are received for the idle timeout.
+    fn read_websocket_messages(uri: &str, idle_timeout: Duration) -> Vec<tungstenite::Message> {
+        let url = url::Url::parse(&uri.replacen("http", "ws", 1)).unwrap();
+        info!("WebSocket {}", url);
+        let stream = TcpStream::connect((url.host_str().unwrap(), url.port_or_known_default().unwrap())).unwrap();
+        stream.set_read_timeout(Some(idle_timeout)).unwrap();
+        let (mut socket, _) = tungstenite::client(url, stream).unwrap();
+        let mut messages = Vec::new();
+        loop {
+            match socket.read_message() {
+                Ok(message) if message.is_text() || message.is_binary() => messages.push(message),
+                Ok(_) => {},
+                Err(tungstenite::Error::Io(err)) if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut => break,
+                Err(err) => panic!("Unable to read WebSocket message: {}", err),
+            }
+        }
+        messages
+    }
+
+    /// A WebSocket sends the MIME type, then the initialization segment, then only media fragments.
+    #[test]
+    fn test_video_server_websocket() {
+        let test_config = &get_test_config();
+        let stream_name = &format!("test-video-server-{}-{}", test_config.test_id, Uuid::new_v4())[..];
+        video_server_test_data_gen(test_config, stream_name);
+        let video_server_uri = get_video_server_uri(&test_config.client_config.controller_uri.0);
+        let stream_uri = format!("{}/scopes/{}/streams/{}", video_server_uri, test_config.scope, stream_name);
+        // The WebSocket begins at the first key frame, which has the first initialization segment.
+        let playlist = http_client().get(&format!("{}/m3u8?version=7", stream_uri)).send().unwrap().error_for_status().unwrap().text().unwrap();
+        let init_uri = playlist.lines().find_map(|line| line.strip_prefix("#EXT-X-MAP:URI=\"")).unwrap().trim_end_matches('"');
+        let init_segment = http_client().get(&format!("{}/{}", stream_uri, init_uri)).send().unwrap().error_for_status().unwrap().bytes().unwrap();
+        let messages = read_websocket_messages(&format!("{}/websocket?begin=2001-02-03T04:00:00Z", stream_uri), Duration::from_secs(10));
+        info!("Received {} messages", messages.len());
+        assert!(messages.len() >= 3);
+        let init_message: serde_json::Value = serde_json::from_str(messages[0].to_text().unwrap()).unwrap();
+        assert!(init_message["mimeType"].as_str().unwrap().starts_with("video/mp4; codecs=\"avc1."));
+        assert!(messages[1].is_binary());
+        assert!(messages[1].clone().into_data()[..] == init_segment[..]);
+        // The initialization segment does not change, so it is not sent again.
+        assert!(messages[2..].iter().all(|message| message.is_binary()));
+        let media: Vec<u8> = messages[2..].iter().flat_map(|message| message.clone().into_data()).collect();
+        assert_eq!(&media[4..8], b"moof");
+        assert!(media.windows(4).any(|box_type| box_type == b"mdat"));
+        assert!(!media.windows(4).any(|box_type| box_type == b"ftyp"));
+    }
+
+    /// A file downloaded from one stream can be ingested into another stream at a different time.
+    #[test]
+    fn test_video_server_ingest() {
+        let test_config = &get_test_config();
+        let stream_name = &format!("test-video-server-{}-{}", test_config.test_id, Uuid::new_v4())[..];
+        video_server_test_data_gen(test_config, stream_name);
+        let video_server_uri = get_video_server_uri(&test_config.client_config.controller_uri.0);
+        let stream_uri = format!("{}/scopes/{}/streams/{}", video_server_uri, test_config.scope, stream_name);
+
+        // Download the stream as a fragmented MP4 file with a single initialization segment.
+        let playlist = http_client().get(&format!("{}/m3u8?version=7", stream_uri)).send().unwrap().error_for_status().unwrap().text().unwrap();
+        let media_uris: Vec<_> = playlist.lines().filter(|line| line.starts_with("media?")).collect();
+        let (begin, _) = parse_media_uri(media_uris.first().unwrap());
+        let (_, end) = parse_media_uri(media_uris.last().unwrap());
+        let mut file = http_client().get(&format!("{}/init?begin={}", stream_uri, begin))
+            .send().unwrap().error_for_status().unwrap().bytes().unwrap().to_vec();
+        file.extend_from_slice(&http_client().get(&format!("{}/media?begin={}&end={}&strip_init=true", stream_uri, begin, end))
+            .send().unwrap().error_for_status().unwrap().bytes().unwrap());
+        info!("Downloaded {} bytes", file.len());
+
+        // Ingest the file into a new stream one hour later.
+        let ingest_stream_name = &format!("test-video-server-ingest-{}-{}", test_config.test_id, Uuid::new_v4())[..];
+        let ingest_stream_uri = format!("{}/scopes/{}/streams/{}", video_server_uri, test_config.scope, ingest_stream_name);
+        let response = http_client().post(&format!("{}/ingest?start-utc=2001-02-03T05:00:00Z", ingest_stream_uri))
+            .body(file.clone())
+            .send().unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let location = response.headers().get("location").unwrap().to_str().unwrap().to_owned();
+        let status: serde_json::Value = serde_json::from_str(&response.text().unwrap()).unwrap();
+        assert_eq!(status["bytesReceived"].as_u64().unwrap(), file.len() as u64);
+        let start = std::time::Instant::now();
+        let status = loop {
+            let status = http_client().get(&format!("{}{}", video_server_uri, location))
+                .send().unwrap().error_for_status().unwrap().text().unwrap();
+            let status: serde_json::Value = serde_json::from_str(&status).unwrap();
+            debug!("status={}", status);
+            match status["state"].as_str().unwrap() {
+                "completed" | "failed" => break status,
+                _ => {},
+            }
+            assert!(start.elapsed() < Duration::from_secs(120), "Timeout waiting for ingest to complete");
+            std::thread::sleep(Duration::from_millis(500));
+        };
+        info!("status={}", status);
+        assert_eq!(status["state"], "completed");
+        assert_eq!(status["progress"].as_f64().unwrap(), 1.0);
+        // The first frame is at start-utc, but the first fragment may begin at a later presentation time.
+        let time = |name: &str| chrono::DateTime::parse_from_rfc3339(status[name].as_str().unwrap()).unwrap();
+        let start_utc = chrono::DateTime::parse_from_rfc3339("2001-02-03T05:00:00Z").unwrap();
+        assert!(time("beginTime") >= start_utc && time("beginTime") < start_utc + chrono::Duration::seconds(1),
+            "beginTime={}", time("beginTime"));
+        // The last fragment begins within the 5 seconds of video.
+        let duration = time("endTime") - time("beginTime");
+        assert!(duration >= chrono::Duration::seconds(2) && duration <= chrono::Duration::seconds(5), "duration={}", duration);
+
+        // The ingested stream can be played at the new time.
+        let playlist = http_client().get(&format!("{}/m3u8?begin=2001-02-03T05:00:00Z&end=2001-02-03T05:01:00Z", ingest_stream_uri))
+            .send().unwrap().error_for_status().unwrap().text().unwrap();
+        debug!("playlist={}", playlist);
+        assert!(playlist.contains("#EXT-X-PROGRAM-DATE-TIME:2001-02-03T05:00:0"));
+        assert!(playlist.matches("#EXTINF:").count() >= 3);
+    }
+
+    /// An index record without a timestamp, before or after a record with a timestamp, is a discontinuity.
+    #[rstest]
+    #[case(3)]
+    #[case(7)]
+    fn test_video_server_missing_timestamp(#[case] version: u32) {
+        let test_config = &get_test_config();
+        let stream_name = &format!("test-video-server-{}-{}", test_config.test_id, Uuid::new_v4())[..];
+        let first_timestamp = PravegaTimestamp::try_from(Some("2001-02-03T04:00:00.000Z".to_owned())).unwrap();
+        let events = vec![
+            (first_timestamp, vec![1; 100]),
+            (first_timestamp + SECOND, vec![2; 100]),
+            (PravegaTimestamp::none(), vec![3; 100]),
+            (first_timestamp + 3 * SECOND, vec![4; 100]),
+        ];
+        let offsets = video_server_events_gen(test_config, stream_name, &events, first_timestamp + 4 * SECOND);
+        let video_server_uri = get_video_server_uri(&test_config.client_config.controller_uri.0);
+        let stream_uri = format!("{}/scopes/{}/streams/{}", video_server_uri, test_config.scope, stream_name);
+        let playlist = http_client().get(&format!("{}/m3u8?version={}", stream_uri, version))
+            .send().unwrap().error_for_status().unwrap().text().unwrap();
+        debug!("playlist={}", playlist);
+        // The segments on either side of the record without a timestamp are played.
+        assert!(playlist.contains(&format!("media?begin={}&end={}\n", offsets[0], offsets[1])));
+        assert!(playlist.contains(&format!("media?begin={}&end={}\n", offsets[3], offsets[4])));
+        // The record without a timestamp cannot begin or end a segment.
+        assert!(!playlist.contains(&format!("&end={}\n", offsets[2])));
+        assert!(!playlist.contains(&format!("media?begin={}&end={}\n", offsets[2], offsets[3])));
+        if version >= 7 {
+            assert_eq!(playlist.matches("#EXT-X-GAP").count(), 2);
+        } else {
+            assert_eq!(playlist.matches("/static/gap-").count(), 2);
+        }
+    }
+
+    /// When Pravega cannot be reached, requests fail with 503 instead of dropping the connection.
+    /// This starts a separate video server that uses a controller that is not running.
+    #[test]
+    fn test_video_server_pravega_unavailable() {
+        let unavailable_video_server = VideoServer::start(UNAVAILABLE_CONTROLLER_URI, UNAVAILABLE_VIDEO_SERVER_PORT);
+        let video_server_uri = unavailable_video_server.uri();
+        assert_problem(&format!("{}/scopes", video_server_uri),
+            StatusCode::SERVICE_UNAVAILABLE, "pravega-unavailable");
+        assert_problem(&format!("{}/scopes/test/streams/test/m3u8", video_server_uri),
+            StatusCode::SERVICE_UNAVAILABLE, "pravega-unavailable");
+    }
+}
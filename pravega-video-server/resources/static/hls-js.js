@@ -29,13 +29,17 @@ function load_video() {
     if (end != "") {
         query = query + ((query == "") ? "?" : "&") + "end=" + new Date(end).toISOString();
     }
+    if (begin == "" && end == "") {
+        // Play the live edge of the stream with Low-Latency HLS.
+        query = query + ((query == "") ? "?" : "&") + "low_latency=true";
+    }
 
     var manifestUri = "/scopes/" + scope + "/streams/" + stream + "/m3u8" + query;
     console.log(manifestUri);
 
     if (Hls.isSupported()) {
         video = document.getElementById('video');
-        var hls = new Hls();
+        var hls = new Hls({lowLatencyMode: true});
         hls.on(Hls.Events.FRAG_CHANGED, function(event, data) {
             // Each time we get a new fragment, revise playStartMillisSinceEpoch.
             playStartMillisSinceEpoch = data.frag.programDateTime - data.frag.startPTS * 1000.0;
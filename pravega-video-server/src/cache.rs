@@ -0,0 +1,242 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// In-process caches shared by all requests.
+//
+// The bytes in a range of a data stream never change, so media segments can be cached until the
+// stream is truncated past them. Live playlists change as the stream is written, so they are cached
+// only briefly. This avoids reading the same data from Pravega when many clients watch the same stream.
+
+use hyper::body::Bytes;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Identifies a media segment of a stream.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SegmentKey<S> {
+    pub stream: S,
+    /// Begin byte offset
+    pub begin: u64,
+    /// End byte offset (exclusive)
+    pub end: u64,
+    /// True if ftyp and moov boxes were removed.
+    pub strip_init: bool,
+}
+
+struct SegmentEntry {
+    data: Bytes,
+    /// The position of the entry in the LRU order.
+    tick: u64,
+}
+
+struct SegmentCacheInner<S> {
+    entries: HashMap<SegmentKey<S>, SegmentEntry>,
+    /// Keys ordered from least to most recently used.
+    lru: BTreeMap<u64, SegmentKey<S>>,
+    next_tick: u64,
+    /// Total bytes of all entries.
+    size: usize,
+    /// The lowest offset that is known to be valid for each stream.
+    heads: HashMap<S, u64>,
+}
+
+/// A least-recently-used cache of media segment bodies, bounded by the total number of bytes.
+#[derive(Clone)]
+pub struct SegmentCache<S> {
+    capacity: usize,
+    inner: Arc<Mutex<SegmentCacheInner<S>>>,
+}
+
+impl<S: Clone + Eq + Hash> SegmentCache<S> {
+    /// Creates a cache that holds at most capacity bytes. A capacity of 0 disables the cache.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Arc::new(Mutex::new(SegmentCacheInner {
+                entries: HashMap::new(),
+                lru: BTreeMap::new(),
+                next_tick: 0,
+                size: 0,
+                heads: HashMap::new(),
+            })),
+        }
+    }
+
+    /// The largest segment that will be cached.
+    /// This prevents a single large segment from evicting many smaller ones.
+    pub fn max_entry_size(&self) -> usize {
+        self.capacity / 8
+    }
+
+    pub fn get(&self, key: &SegmentKey<S>) -> Option<Bytes> {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+        let entry = inner.entries.get_mut(key)?;
+        inner.lru.remove(&entry.tick);
+        entry.tick = inner.next_tick;
+        inner.next_tick += 1;
+        inner.lru.insert(entry.tick, key.clone());
+        Some(entry.data.clone())
+    }
+
+    /// Inserts a segment, evicting the least recently used segments to make room.
+    pub fn insert(&self, key: SegmentKey<S>, data: Bytes) {
+        if data.len() > self.max_entry_size() {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        // The stream may have been truncated while the segment was read.
+        if matches!(inner.heads.get(&key.stream), Some(head) if key.begin < *head) {
+            return;
+        }
+        inner.remove(&key);
+        while inner.size + data.len() > self.capacity {
+            let oldest = match inner.lru.keys().next() {
+                Some(tick) => inner.lru[tick].clone(),
+                None => break,
+            };
+            inner.remove(&oldest);
+        }
+        let tick = inner.next_tick;
+        inner.next_tick += 1;
+        inner.size += data.len();
+        inner.lru.insert(tick, key.clone());
+        inner.entries.insert(key, SegmentEntry { data, tick });
+    }
+
+    /// Removes all segments of the stream that begin before the offset, which is the head of the truncated stream.
+    pub fn invalidate_before(&self, stream: &S, head: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.heads.get(stream) {
+            Some(previous_head) if *previous_head >= head => return,
+            _ => {},
+        }
+        inner.heads.insert(stream.clone(), head);
+        let keys: Vec<SegmentKey<S>> = inner.entries.keys()
+            .filter(|key| key.stream == *stream && key.begin < head)
+            .cloned()
+            .collect();
+        for key in keys {
+            inner.remove(&key);
+        }
+    }
+
+    /// Total bytes of all cached segments.
+    #[cfg(test)]
+    fn size(&self) -> usize {
+        self.inner.lock().unwrap().size
+    }
+}
+
+impl<S: Clone + Eq + Hash> SegmentCacheInner<S> {
+    fn remove(&mut self, key: &SegmentKey<S>) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.lru.remove(&entry.tick);
+            self.size -= entry.data.len();
+        }
+    }
+}
+
+/// A cache of values that expire after a fixed duration.
+#[derive(Clone)]
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    entries: Arc<Mutex<HashMap<K, (Instant, V)>>>,
+}
+
+impl<K: Eq + Hash, V: Clone> TtlCache<K, V> {
+    /// Creates a cache of values that expire after the duration. A duration of 0 disables the cache.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        match self.entries.lock().unwrap().get(key) {
+            Some((inserted, value)) if inserted.elapsed() < self.ttl => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    /// Inserts a value and removes all expired values.
+    pub fn insert(&self, key: K, value: V) {
+        if self.ttl == Duration::from_secs(0) {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        let ttl = self.ttl;
+        entries.retain(|_, (inserted, _)| inserted.elapsed() < ttl);
+        entries.insert(key, (Instant::now(), value));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(stream: &str, begin: u64) -> SegmentKey<String> {
+        SegmentKey { stream: stream.to_owned(), begin, end: begin + 100, strip_init: false }
+    }
+
+    #[test]
+    fn test_segment_cache_lru() {
+        let cache = SegmentCache::new(800);
+        cache.insert(key("s1", 0), Bytes::from(vec![0; 100]));
+        cache.insert(key("s1", 100), Bytes::from(vec![1; 100]));
+        // Segments larger than an eighth of the capacity are not cached.
+        cache.insert(key("s1", 200), Bytes::from(vec![2; 101]));
+        assert_eq!(cache.size(), 200);
+        assert!(cache.get(&key("s1", 200)).is_none());
+        // Using the first segment makes the second the least recently used.
+        assert_eq!(cache.get(&key("s1", 0)), Some(Bytes::from(vec![0; 100])));
+        for begin in 3..10 {
+            cache.insert(key("s2", begin * 100), Bytes::from(vec![3; 100]));
+        }
+        assert_eq!(cache.size(), 800);
+        assert!(cache.get(&key("s1", 100)).is_none());
+        assert!(cache.get(&key("s1", 0)).is_some());
+        cache.insert(key("s2", 1000), Bytes::from(vec![3; 100]));
+        assert_eq!(cache.size(), 800);
+        assert!(cache.get(&key("s2", 300)).is_none());
+        assert!(cache.get(&key("s1", 0)).is_some());
+        assert!(cache.get(&key("s2", 400)).is_some());
+    }
+
+    #[test]
+    fn test_segment_cache_invalidate_before() {
+        let cache = SegmentCache::new(8000);
+        cache.insert(key("s1", 0), Bytes::from(vec![0; 100]));
+        cache.insert(key("s1", 100), Bytes::from(vec![1; 100]));
+        cache.insert(key("s2", 0), Bytes::from(vec![2; 100]));
+        cache.invalidate_before(&"s1".to_owned(), 100);
+        assert!(cache.get(&key("s1", 0)).is_none());
+        assert!(cache.get(&key("s1", 100)).is_some());
+        assert!(cache.get(&key("s2", 0)).is_some());
+        assert_eq!(cache.size(), 200);
+        // A segment that was read before the stream was truncated is not inserted.
+        cache.insert(key("s1", 0), Bytes::from(vec![0; 100]));
+        assert!(cache.get(&key("s1", 0)).is_none());
+    }
+
+    #[test]
+    fn test_ttl_cache() {
+        let cache = TtlCache::new(Duration::from_secs(60));
+        cache.insert("a", 1);
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"b"), None);
+        let cache = TtlCache::new(Duration::from_secs(0));
+        cache.insert("a", 1);
+        assert_eq!(cache.get(&"a"), None);
+    }
+}
@@ -0,0 +1,221 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Generation of MPEG-DASH Media Presentation Descriptions (MPD).
+//
+// Each pair of consecutive index records defines a segment, just as in HLS playlists.
+// Segments are listed with SegmentList and their times with SegmentTimeline, using the timestamps in the index.
+// Each discontinuity begins a new Period.
+
+use pravega_video::index::IndexRecord;
+use pravega_video::timestamp::PravegaTimestamp;
+use tracing::warn;
+
+/// Units per second of times in SegmentTimeline.
+pub const TIMESCALE: u64 = 90_000;
+
+#[derive(Debug, Clone)]
+pub struct MpdConfig {
+    /// If true, produce a dynamic (live) MPD that clients will reload. Otherwise, produce a static MPD.
+    pub dynamic: bool,
+    /// If true, segments are fragmented MP4 and each Period references an initialization segment.
+    /// Otherwise, segments are MPEG transport streams.
+    pub is_mp4: bool,
+    /// The codecs attribute of the Representation, if known.
+    pub codecs: Option<String>,
+    /// The time at which the MPD was generated.
+    pub publish_time: PravegaTimestamp,
+}
+
+#[derive(Debug)]
+struct Segment {
+    begin_offset: u64,
+    end_offset: u64,
+    /// Unix time in nanoseconds.
+    time_nanos: u64,
+    duration_nanos: u64,
+}
+
+fn to_timescale(nanos: u64) -> u64 {
+    (nanos as u128 * TIMESCALE as u128 / 1_000_000_000) as u64
+}
+
+/// Format a duration in the xs:duration format, such as "PT12.345S".
+fn format_duration(seconds: f64) -> String {
+    format!("PT{:.3}S", seconds)
+}
+
+/// Build an MPD from consecutive index records.
+/// Returns None if there are no complete segments.
+pub fn build_mpd(index_records: &[IndexRecord], config: &MpdConfig) -> Option<String> {
+    // Initial value for target duration. This will be updated with an exponential moving average.
+    let mut target_duration_seconds = 10.0;
+    let mut periods: Vec<Vec<Segment>> = vec![Vec::new()];
+    for pair in index_records.windows(2) {
+        let (prev_index_record, index_record) = (&pair[0], &pair[1]);
+        // Discontinuities are detected in the same way as for HLS playlists.
+        // Any recorded content that falls in a gap may be corrupt so it is not included.
+        let timestamps = (prev_index_record.timestamp.to_unix_nanoseconds(), index_record.timestamp.to_unix_nanoseconds());
+        let (prev_time_nanos, time_nanos) = match timestamps {
+            (Some(prev_time_nanos), Some(time_nanos)) if time_nanos >= prev_time_nanos => (prev_time_nanos, time_nanos),
+            _ => {
+                warn!("Detected discontinuity from {:?} to {:?}", prev_index_record, index_record);
+                periods.push(Vec::new());
+                continue;
+            },
+        };
+        let duration_seconds = (time_nanos - prev_time_nanos) as f64 * 1e-9;
+        if duration_seconds > target_duration_seconds + 1.0 {
+            warn!("Detected discontinuity; {:.3} second gap from {} to {}", duration_seconds, prev_index_record.timestamp, index_record.timestamp);
+            periods.push(Vec::new());
+            continue;
+        }
+        let ema_alpha = 0.1;
+        target_duration_seconds = ema_alpha * duration_seconds + (1.0 - ema_alpha) * target_duration_seconds;
+        // Version 2 index records store the actual duration of the preceding segment.
+        let duration_nanos = match index_record.segment_duration.nanoseconds() {
+            Some(segment_duration) if segment_duration > 0 => segment_duration as u64,
+            _ => time_nanos - prev_time_nanos,
+        };
+        periods.last_mut().unwrap().push(Segment {
+            begin_offset: prev_index_record.offset,
+            end_offset: index_record.offset,
+            time_nanos: prev_time_nanos,
+            duration_nanos,
+        });
+    }
+    periods.retain(|segments| !segments.is_empty());
+    let first_time_nanos = periods.first()?.first()?.time_nanos;
+    let last_segment = periods.last()?.last()?;
+    let end_time_nanos = last_segment.time_nanos + last_segment.duration_nanos;
+    let duration_seconds = (end_time_nanos - first_time_nanos) as f64 * 1e-9;
+    let target_duration_seconds = target_duration_seconds.round().max(1.0);
+
+    // Dynamic MPDs use the Unix epoch as the availability start time so that Period start times
+    // do not change when the stream is truncated.
+    let presentation_start_nanos = if config.dynamic { 0 } else { first_time_nanos };
+
+    let (profiles, mime_type) = if config.is_mp4 {
+        ("urn:mpeg:dash:profile:isoff-main:2011", "video/mp4")
+    } else {
+        ("urn:mpeg:dash:profile:mp2t-main:2011", "video/mp2t")
+    };
+    let media_uri_suffix = if config.is_mp4 { "&amp;strip_init=true" } else { "" };
+
+    let mut mpd = String::new();
+    mpd.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    if config.dynamic {
+        mpd.push_str(&format!(concat!(
+            "<MPD xmlns=\"urn:mpeg:dash:schema:mpd:2011\" profiles=\"{}\" type=\"dynamic\"",
+            " availabilityStartTime=\"1970-01-01T00:00:00Z\" publishTime=\"{}\" minimumUpdatePeriod=\"{}\"",
+            " timeShiftBufferDepth=\"{}\" suggestedPresentationDelay=\"{}\" minBufferTime=\"{}\">\n"),
+            profiles,
+            config.publish_time.to_iso_8601().unwrap_or_default(),
+            format_duration(target_duration_seconds),
+            format_duration(duration_seconds),
+            format_duration(3.0 * target_duration_seconds),
+            format_duration(target_duration_seconds)));
+    } else {
+        mpd.push_str(&format!(concat!(
+            "<MPD xmlns=\"urn:mpeg:dash:schema:mpd:2011\" profiles=\"{}\" type=\"static\"",
+            " mediaPresentationDuration=\"{}\" minBufferTime=\"{}\">\n"),
+            profiles,
+            format_duration(duration_seconds),
+            format_duration(target_duration_seconds)));
+    }
+    for segments in periods.iter() {
+        let first_segment = segments.first().unwrap();
+        let last_segment = segments.last().unwrap();
+        let period_duration_nanos = last_segment.time_nanos + last_segment.duration_nanos - first_segment.time_nanos;
+        let period_bytes = last_segment.end_offset - first_segment.begin_offset;
+        let bandwidth = (period_bytes as f64 * 8.0 / (period_duration_nanos.max(1) as f64 * 1e-9)).round() as u64;
+        // The Period id is the data stream offset so that it does not change when a dynamic MPD is reloaded.
+        mpd.push_str(&format!("  <Period id=\"{}\" start=\"{}\">\n",
+            first_segment.begin_offset, format_duration((first_segment.time_nanos - presentation_start_nanos) as f64 * 1e-9)));
+        mpd.push_str(&format!("    <AdaptationSet mimeType=\"{}\" segmentAlignment=\"true\">\n", mime_type));
+        match &config.codecs {
+            Some(codecs) => mpd.push_str(&format!("      <Representation id=\"0\" bandwidth=\"{}\" codecs=\"{}\">\n", bandwidth, codecs)),
+            None => mpd.push_str(&format!("      <Representation id=\"0\" bandwidth=\"{}\">\n", bandwidth)),
+        }
+        mpd.push_str(&format!("        <SegmentList timescale=\"{}\" presentationTimeOffset=\"{}\">\n",
+            TIMESCALE, to_timescale(first_segment.time_nanos)));
+        if config.is_mp4 {
+            mpd.push_str(&format!("          <Initialization sourceURL=\"init?begin={}\"/>\n", first_segment.begin_offset));
+        }
+        mpd.push_str("          <SegmentTimeline>\n");
+        for segment in segments.iter() {
+            let t = to_timescale(segment.time_nanos);
+            let d = to_timescale(segment.time_nanos + segment.duration_nanos) - t;
+            mpd.push_str(&format!("            <S t=\"{}\" d=\"{}\"/>\n", t, d));
+        }
+        mpd.push_str("          </SegmentTimeline>\n");
+        for segment in segments.iter() {
+            mpd.push_str(&format!("          <SegmentURL media=\"media?begin={}&amp;end={}{}\"/>\n",
+                segment.begin_offset, segment.end_offset, media_uri_suffix));
+        }
+        mpd.push_str("        </SegmentList>\n");
+        mpd.push_str("      </Representation>\n");
+        mpd.push_str("    </AdaptationSet>\n");
+        mpd.push_str("  </Period>\n");
+    }
+    mpd.push_str("</MPD>\n");
+    Some(mpd)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SECOND: u64 = 1_000_000_000;
+    const UNIX_SECONDS: u64 = 1_600_000_000;
+
+    fn make_records(times_seconds: &[u64]) -> Vec<IndexRecord> {
+        times_seconds.iter().enumerate().map(|(i, t)| {
+            let timestamp = PravegaTimestamp::from_unix_nanoseconds(Some((UNIX_SECONDS + t) * SECOND));
+            IndexRecord::new(timestamp, 1000 * i as u64, true, i == 0)
+        }).collect()
+    }
+
+    fn config(dynamic: bool) -> MpdConfig {
+        MpdConfig {
+            dynamic,
+            is_mp4: true,
+            codecs: Some("avc1.64001F".to_owned()),
+            publish_time: PravegaTimestamp::from_unix_nanoseconds(Some((UNIX_SECONDS + 100) * SECOND)),
+        }
+    }
+
+    #[test]
+    fn test_build_mpd_static() {
+        // A gap of 60 seconds after the third segment.
+        let records = make_records(&[0, 2, 4, 6, 66, 68, 70]);
+        let mpd = build_mpd(&records, &config(false)).unwrap();
+        assert!(mpd.contains("type=\"static\" mediaPresentationDuration=\"PT70.000S\""));
+        assert_eq!(mpd.matches("<Period ").count(), 2);
+        assert!(mpd.contains("<Period id=\"0\" start=\"PT0.000S\">"));
+        assert!(mpd.contains("<Period id=\"4000\" start=\"PT66.000S\">"));
+        assert!(mpd.contains("<Initialization sourceURL=\"init?begin=4000\"/>"));
+        assert!(mpd.contains(&format!("<S t=\"{}\" d=\"180000\"/>", UNIX_SECONDS * TIMESCALE)));
+        assert!(mpd.contains("<SegmentURL media=\"media?begin=1000&amp;end=2000&amp;strip_init=true\"/>"));
+        assert!(!mpd.contains("media?begin=3000&amp;end=4000"));
+        assert!(mpd.contains("codecs=\"avc1.64001F\""));
+        assert!(mpd.contains("bandwidth=\"4000\""));
+    }
+
+    #[test]
+    fn test_build_mpd_dynamic() {
+        let records = make_records(&[0, 2, 4]);
+        let mpd = build_mpd(&records, &config(true)).unwrap();
+        assert!(mpd.contains("type=\"dynamic\" availabilityStartTime=\"1970-01-01T00:00:00Z\""));
+        assert!(mpd.contains(&format!("<Period id=\"0\" start=\"PT{}.000S\">", UNIX_SECONDS)));
+        assert!(mpd.contains("timeShiftBufferDepth=\"PT4.000S\""));
+        assert!(build_mpd(&records[..1], &config(true)).is_none());
+    }
+}
@@ -0,0 +1,252 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Errors returned by the API.
+//
+// Errors are returned as JSON problem details (RFC 7807) with the content type application/problem+json.
+// The type member is a short string that identifies the kind of error so that clients do not need to parse the detail.
+
+use serde_derive::Serialize;
+use std::fmt;
+use std::io::ErrorKind;
+#[allow(unused_imports)]
+use tracing::{debug, error, info, warn, trace};
+use warp::Reply;
+use warp::http::StatusCode;
+
+#[derive(Debug, Clone)]
+pub enum ApiError {
+    /// The scope does not exist.
+    ScopeNotFound(String),
+    /// The stream does not exist. The value is the scoped stream, such as "my_scope/my_stream".
+    StreamNotFound(String),
+    /// The index of the stream has no records so there is no video.
+    EmptyIndex(String),
+    /// The requested resource does not exist, such as a time range without video.
+    NotFound(String),
+    /// The begin and end timestamps are not a valid time range.
+    InvalidTimeRange(String),
+    /// Any other problem with the request.
+    BadRequest(String),
+    /// The request has no credentials or they are not valid.
+    /// The challenge is the value of the WWW-Authenticate header.
+    Unauthorized { challenge: String, detail: String },
+    /// The credentials do not allow access to the scope or stream, or a signed URL is not valid.
+    Forbidden(String),
+    MethodNotAllowed,
+    /// An uploaded file is larger than the server allows.
+    PayloadTooLarge(String),
+    /// A byte offset in the request is not the offset of an index record.
+    /// Media segments must begin and end at event boundaries.
+    NotEventBoundary(u64),
+    /// The Pravega controller or segment store could not be reached or returned an error.
+    PravegaUnavailable(String),
+    /// A feature is not available or is at its concurrency limit.
+    ServiceUnavailable(String),
+    Internal(String),
+}
+
+#[derive(Debug, Serialize)]
+struct Problem {
+    #[serde(rename = "type")]
+    problem_type: &'static str,
+    title: &'static str,
+    status: u16,
+    detail: String,
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::ScopeNotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::StreamNotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::EmptyIndex(_) => StatusCode::NOT_FOUND,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::InvalidTimeRange(_) => StatusCode::BAD_REQUEST,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized { .. } => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::NotEventBoundary(_) => StatusCode::CONFLICT,
+            ApiError::PravegaUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The type member of the problem details.
+    pub fn problem_type(&self) -> &'static str {
+        match self {
+            ApiError::ScopeNotFound(_) => "scope-not-found",
+            ApiError::StreamNotFound(_) => "stream-not-found",
+            ApiError::EmptyIndex(_) => "empty-index",
+            ApiError::NotFound(_) => "not-found",
+            ApiError::InvalidTimeRange(_) => "invalid-time-range",
+            ApiError::BadRequest(_) => "bad-request",
+            ApiError::Unauthorized { .. } => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::MethodNotAllowed => "method-not-allowed",
+            ApiError::PayloadTooLarge(_) => "payload-too-large",
+            ApiError::NotEventBoundary(_) => "not-event-boundary",
+            ApiError::PravegaUnavailable(_) => "pravega-unavailable",
+            ApiError::ServiceUnavailable(_) => "service-unavailable",
+            ApiError::Internal(_) => "internal-error",
+        }
+    }
+
+    /// Convert an error from reading the index of a stream.
+    /// An index without records is reported as EmptyIndex.
+    pub fn from_index_error(scoped_stream: &impl fmt::Display, err: std::io::Error) -> Self {
+        match err.kind() {
+            ErrorKind::UnexpectedEof => ApiError::EmptyIndex(scoped_stream.to_string()),
+            _ => err.into(),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::ScopeNotFound(scope) => write!(f, "Scope {} does not exist", scope),
+            ApiError::StreamNotFound(stream) => write!(f, "Stream {} does not exist", stream),
+            ApiError::EmptyIndex(stream) => write!(f, "Stream {} has no index records", stream),
+            ApiError::NotEventBoundary(offset) => write!(f, "Offset {} is not the offset of an index record", offset),
+            ApiError::MethodNotAllowed => write!(f, "Method not allowed"),
+            ApiError::Unauthorized { detail, .. } => write!(f, "{}", detail),
+            ApiError::NotFound(message)
+            | ApiError::InvalidTimeRange(message)
+            | ApiError::BadRequest(message)
+            | ApiError::Forbidden(message)
+            | ApiError::PayloadTooLarge(message)
+            | ApiError::PravegaUnavailable(message)
+            | ApiError::ServiceUnavailable(message)
+            | ApiError::Internal(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// Filters such as authorization reject requests with an ApiError.
+impl warp::reject::Reject for ApiError {}
+
+impl warp::Reply for ApiError {
+    fn into_response(self) -> warp::reply::Response {
+        let status = self.status();
+        if status.is_server_error() {
+            warn!("{}: {}", self.problem_type(), self);
+        } else {
+            debug!("{}: {}", self.problem_type(), self);
+        }
+        let problem = Problem {
+            problem_type: self.problem_type(),
+            title: status.canonical_reason().unwrap_or_default(),
+            status: status.as_u16(),
+            detail: self.to_string(),
+        };
+        let reply = warp::reply::json(&problem);
+        let reply = warp::reply::with_header(reply, "content-type", "application/problem+json");
+        let mut response = warp::reply::with_status(reply, status).into_response();
+        if let ApiError::Unauthorized { challenge, .. } = &self {
+            if let Ok(challenge) = warp::http::HeaderValue::from_str(challenge) {
+                response.headers_mut().insert("www-authenticate", challenge);
+            }
+        }
+        response
+    }
+}
+
+/// Errors from the Pravega client are reported as std::io::Error.
+/// Invalid data in a stream is an internal error. Anything else is assumed to be a problem communicating with Pravega.
+impl From<std::io::Error> for ApiError {
+    fn from(err: std::io::Error) -> Self {
+        match err.kind() {
+            ErrorKind::InvalidData | ErrorKind::InvalidInput => ApiError::Internal(err.to_string()),
+            ErrorKind::NotFound => ApiError::NotFound(err.to_string()),
+            ErrorKind::PermissionDenied => ApiError::Forbidden(err.to_string()),
+            _ => ApiError::PravegaUnavailable(err.to_string()),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        ApiError::Internal(err.to_string())
+    }
+}
+
+impl From<warp::http::Error> for ApiError {
+    fn from(err: warp::http::Error) -> Self {
+        ApiError::Internal(err.to_string())
+    }
+}
+
+/// Convert the result of a request into a response.
+pub fn into_response<T: warp::Reply>(result: Result<T, ApiError>) -> warp::reply::Response {
+    match result {
+        Ok(reply) => reply.into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Convert rejections from filters, such as an invalid query string, into problem details.
+/// Other rejections are handled by Warp.
+pub async fn handle_rejection(rejection: warp::Rejection) -> Result<warp::reply::Response, warp::Rejection> {
+    let err = if let Some(err) = rejection.find::<ApiError>() {
+        err.clone()
+    } else if let Some(err) = rejection.find::<warp::reject::InvalidQuery>() {
+        ApiError::BadRequest(err.to_string())
+    } else if let Some(err) = rejection.find::<warp::body::BodyDeserializeError>() {
+        ApiError::BadRequest(err.to_string())
+    } else if rejection.find::<warp::reject::MethodNotAllowed>().is_some() {
+        ApiError::MethodNotAllowed
+    } else {
+        return Err(rejection);
+    };
+    Ok(err.into_response())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_problem_response() {
+        let response = ApiError::StreamNotFound("my_scope/my_stream".to_owned()).into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()["content-type"], "application/problem+json");
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], &br#"{"type":"stream-not-found","title":"Not Found","status":404,"detail":"Stream my_scope/my_stream does not exist"}"#[..]);
+    }
+
+    #[test]
+    fn test_from_index_error() {
+        let err = std::io::Error::new(ErrorKind::UnexpectedEof, "Index has no records");
+        let err = ApiError::from_index_error(&"my_scope/my_stream", err);
+        assert_eq!(err.status(), StatusCode::NOT_FOUND);
+        assert_eq!(err.problem_type(), "empty-index");
+        let err = ApiError::from(std::io::Error::new(ErrorKind::ConnectionRefused, "Connection refused"));
+        assert_eq!(err.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let err = ApiError::from(std::io::Error::new(ErrorKind::NotFound, "No such file"));
+        assert_eq!(err.status(), StatusCode::NOT_FOUND);
+        let err = ApiError::from(std::io::Error::new(ErrorKind::PermissionDenied, "Permission denied"));
+        assert_eq!(err.status(), StatusCode::FORBIDDEN);
+        assert_eq!(ApiError::NotEventBoundary(5).status(), StatusCode::CONFLICT);
+    }
+
+    #[test]
+    fn test_unauthorized_challenge() {
+        let err = ApiError::Unauthorized { challenge: "Basic realm=\"test\"".to_owned(), detail: "Credentials are required".to_owned() };
+        let response = err.into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()["www-authenticate"], "Basic realm=\"test\"");
+    }
+}
@@ -0,0 +1,265 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Export of a time range of a video stream as a single MP4 file.
+//
+// A GStreamer pipeline reads the stream with pravegasrc, demuxes it with parsebin, and remuxes the
+// elementary streams with mp4mux. There is no decoding or encoding.
+// pravegasrc starts at the random-access point on or immediately before the begin timestamp and stops at the
+// random-access point on or immediately after the end timestamp, so the clip begins and ends at key frames.
+// mp4mux produces a regular (non-fragmented) MP4 file with the moov box at the beginning, so that players can
+// start playing it while it is downloaded. This requires the entire file to be muxed first, so it is written to
+// a temporary file which is sent to the client when the pipeline completes.
+
+use anyhow::anyhow;
+use futures::Stream;
+use gst::prelude::*;
+use hyper::body::Bytes;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+#[allow(unused_imports)]
+use tracing::{debug, error, info, warn, trace};
+use super::gst_util;
+
+/// Maximum number of chunks of the MP4 file that will be buffered if the client is slow.
+const CHANNEL_CAPACITY: usize = 16;
+/// The MP4 file is sent to the client in chunks of this size.
+const CHUNK_SIZE: usize = 256 * 1024;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone)]
+struct ExportSettings {
+    /// Pravega controller in format "tcp://127.0.0.1:9090"
+    controller: String,
+    keycloak_file: String,
+    /// Stream in format "my_scope/my_stream"
+    stream: String,
+    /// Begin timestamp in RFC 3339 format
+    begin_utc: String,
+    /// End timestamp in RFC 3339 format
+    end_utc: String,
+    /// The time of the first frame, in ISO 8601 format. This will be written to the MP4 metadata.
+    recording_start: Option<String>,
+    /// The temporary file that receives the MP4 file. It is removed when the export finishes.
+    path: PathBuf,
+}
+
+/// Sets the flag when dropped. This stops the pipeline if the client disconnects before the export completes.
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Starts exports and limits the number of concurrent exports.
+#[derive(Clone)]
+pub struct Exporter {
+    controller: String,
+    keycloak_file: String,
+    semaphore: Arc<Semaphore>,
+    next_id: Arc<AtomicU64>,
+    max_duration: Duration,
+}
+
+impl Exporter {
+    pub fn new(controller: String, keycloak_file: String, max_concurrent_exports: usize, max_duration: Duration) -> Self {
+        Self {
+            controller,
+            keycloak_file,
+            semaphore: Arc::new(Semaphore::new(max_concurrent_exports)),
+            next_id: Arc::new(AtomicU64::new(1)),
+            max_duration,
+        }
+    }
+
+    /// The maximum duration of the time range of an export.
+    pub fn max_duration(&self) -> Duration {
+        self.max_duration
+    }
+
+    /// Start exporting a time range of a stream as an MP4 file.
+    /// Returns a stream of chunks of the MP4 file, or None if the maximum number of exports are in progress.
+    /// The first chunk is available when the entire file has been muxed.
+    pub fn start(
+        &self,
+        stream: String,
+        begin_utc: String,
+        end_utc: String,
+        recording_start: Option<String>,
+    ) -> anyhow::Result<Option<impl Stream<Item = Result<Bytes, std::io::Error>>>> {
+        let permit = match self.semaphore.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => return Ok(None),
+        };
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let settings = ExportSettings {
+            controller: self.controller.clone(),
+            keycloak_file: self.keycloak_file.clone(),
+            stream,
+            begin_utc,
+            end_utc,
+            recording_start,
+            path: std::env::temp_dir().join(format!("pravega-video-server-export-{}-{}.mp4", std::process::id(), id)),
+        };
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let receiver = start_export(settings, cancelled.clone())?;
+        // The permit is released and the export is cancelled when the stream is dropped.
+        let state = (receiver, permit, CancelOnDrop(cancelled));
+        let stream = futures::stream::unfold(state, |(mut receiver, permit, cancel): (_, OwnedSemaphorePermit, _)| async move {
+            receiver.recv().await.map(|chunk| (chunk, (receiver, permit, cancel)))
+        });
+        Ok(Some(stream))
+    }
+}
+
+/// Initialize GStreamer. This must be called before exporting.
+pub fn init() -> anyhow::Result<()> {
+    gst_util::init(&["pravegasrc", "parsebin", "queue", "mp4mux", "filesink"])
+}
+
+/// Start a pipeline that exports a time range of a stream as an MP4 file.
+/// Returns a channel that will receive the MP4 file in chunks when the pipeline completes.
+/// The channel is closed when the entire file has been sent. If cancelled is set, the pipeline will stop.
+fn start_export(settings: ExportSettings, cancelled: Arc<AtomicBool>) -> anyhow::Result<mpsc::Receiver<Result<Bytes, std::io::Error>>> {
+    info!("start_export: settings={:?}", settings);
+    let pipeline_description =
+        "pravegasrc name=src start-mode=timestamp end-mode=timestamp allow-create-scope=false".to_owned()
+        + " ! parsebin name=parsebin"
+        + " mp4mux name=mux faststart=true"
+        + " ! filesink name=sink";
+    info!("start_export: Launch Pipeline: {}", pipeline_description);
+    let pipeline = gst::parse_launch(&pipeline_description)?;
+    let pipeline = pipeline.dynamic_cast::<gst::Pipeline>().unwrap();
+
+    let pravegasrc = pipeline.by_name("src").unwrap();
+    pravegasrc.set_property("controller", &settings.controller)?;
+    pravegasrc.set_property("keycloak-file", &settings.keycloak_file)?;
+    pravegasrc.set_property("stream", &settings.stream)?;
+    pravegasrc.set_property("start-utc", &settings.begin_utc)?;
+    pravegasrc.set_property("end-utc", &settings.end_utc)?;
+    let filesink = pipeline.by_name("sink").unwrap();
+    filesink.set_property("location", &settings.path.to_string_lossy().to_string())?;
+
+    let mux = pipeline.by_name("mux").unwrap();
+    if let Some(recording_start) = &settings.recording_start {
+        // mp4mux writes the date and time tag to the MP4 metadata.
+        let date_time = gst::DateTime::from_iso8601_string(recording_start)?;
+        let tag_setter = mux.dynamic_cast_ref::<gst::TagSetter>().unwrap();
+        tag_setter.add::<gst::tags::DateTime>(&date_time, gst::TagMergeMode::Replace);
+    }
+
+    // Each elementary stream found by parsebin is linked to a new pad of mp4mux.
+    gst_util::connect_pad_added(&pipeline, "parsebin", |pipeline, parsebin, src_pad| {
+        let media_type = gst_util::media_type(src_pad);
+        info!("start_export: pad added: media_type={:?}", media_type);
+        let mux_pad_template = match media_type {
+            Some(media_type) if media_type.starts_with("video/") => "video_%u",
+            Some(media_type) if media_type.starts_with("audio/") => "audio_%u",
+            _ => {
+                // Streams such as KLV metadata cannot be written to MP4.
+                warn!("start_export: Ignoring stream from pad {}", src_pad.name());
+                return;
+            },
+        };
+        let link_to_mux = || -> anyhow::Result<()> {
+            let mux = pipeline.by_name("mux").unwrap();
+            let queue = gst::ElementFactory::make("queue", None)?;
+            pipeline.add(&queue)?;
+            // This requests a new pad from mp4mux.
+            queue.link_pads(Some("src"), &mux, Some(mux_pad_template))?;
+            let sink_pad = queue.static_pad("sink").unwrap();
+            src_pad.link(&sink_pad)?;
+            queue.sync_state_with_parent()?;
+            Ok(())
+        };
+        if let Err(err) = link_to_mux() {
+            gst::element_error!(
+                parsebin,
+                gst::LibraryError::Failed,
+                ("Failed to link stream to mp4mux"),
+                ["{}", err]
+            );
+        }
+    });
+
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    if let Err(err) = pipeline.set_state(gst::State::Playing) {
+        let _ = pipeline.set_state(gst::State::Null);
+        let _ = std::fs::remove_file(&settings.path);
+        return Err(err.into());
+    }
+
+    // Wait for the pipeline to finish and send the file in a separate thread because these block.
+    // Sending blocks when the channel is full so a slow client will not cause the file to be buffered in memory.
+    std::thread::spawn(move || {
+        let result = wait_for_eos(&pipeline, &cancelled);
+        if let Err(err) = pipeline.set_state(gst::State::Null) {
+            error!("start_export: Unable to set the pipeline to the Null state: {}", err);
+        }
+        let result = result.and_then(|()| {
+            info!("start_export: End-Of-Stream reached");
+            send_file(&settings.path, &sender)
+        });
+        if let Err(err) = result {
+            let message = err.to_string();
+            // If the client disconnected, this send will fail. This is expected.
+            if sender.blocking_send(Err(std::io::Error::new(std::io::ErrorKind::Other, message.clone()))).is_ok() {
+                error!("start_export: {}", message);
+            }
+        }
+        if let Err(err) = std::fs::remove_file(&settings.path) {
+            debug!("start_export: Unable to remove {}: {}", settings.path.display(), err);
+        }
+        info!("start_export: END");
+    });
+
+    Ok(receiver)
+}
+
+/// Wait until the pipeline reaches End-Of-Stream or posts an error. This blocks.
+/// Returns an error if cancelled is set before then.
+fn wait_for_eos(pipeline: &gst::Pipeline, cancelled: &AtomicBool) -> anyhow::Result<()> {
+    let bus = pipeline.bus().unwrap();
+    loop {
+        if cancelled.load(Ordering::Relaxed) {
+            return Err(anyhow!("Stopping pipeline because the client disconnected"));
+        }
+        if let Some(msg) = bus.pop_filtered(&[gst::MessageType::Eos, gst::MessageType::Error]) {
+            return match gst_util::message_error(&msg) {
+                Some(err) => Err(err),
+                None => Ok(()),
+            };
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Send a file to the channel in chunks. This blocks.
+fn send_file(path: &Path, sender: &mpsc::Sender<Result<Bytes, std::io::Error>>) -> anyhow::Result<()> {
+    let mut file = File::open(path)?;
+    loop {
+        let mut chunk = vec![0; CHUNK_SIZE];
+        let length = file.read(&mut chunk)?;
+        if length == 0 {
+            return Ok(());
+        }
+        chunk.truncate(length);
+        if sender.blocking_send(Ok(Bytes::from(chunk))).is_err() {
+            return Err(anyhow!("Stopping because the client disconnected"));
+        }
+    }
+}
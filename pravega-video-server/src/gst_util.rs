@@ -0,0 +1,146 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Helpers shared by the GStreamer pipelines used for export, thumbnails, ingest, and WebRTC playback.
+
+use anyhow::anyhow;
+use gst::prelude::*;
+#[allow(unused_imports)]
+use tracing::{debug, error, info, warn, trace};
+
+/// Initialize GStreamer and ensure that the required elements are available,
+/// so that errors are reported when the server starts.
+pub fn init(factory_names: &[&str]) -> anyhow::Result<()> {
+    gst::init()?;
+    for factory_name in factory_names {
+        if gst::ElementFactory::find(factory_name).is_none() {
+            return Err(anyhow!("GStreamer element {} not found", factory_name));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the media type of the current caps of a pad, such as "video/x-h264".
+pub fn media_type(pad: &gst::Pad) -> Option<String> {
+    pad.current_caps().and_then(|caps| {
+        caps.structure(0).map(|s| s.name().to_owned())
+    })
+}
+
+/// Call f with the pipeline, the element, and the new pad whenever the named element adds a pad.
+pub fn connect_pad_added<F>(pipeline: &gst::Pipeline, element_name: &str, f: F)
+where
+    F: Fn(&gst::Pipeline, &gst::Element, &gst::Pad) + Send + Sync + 'static,
+{
+    // See the note in pravega-video-player.rs about using a weak reference to the pipeline.
+    let pipeline_weak = pipeline.downgrade();
+    let element = pipeline.by_name(element_name).unwrap();
+    element.connect_pad_added(move |element, src_pad| {
+        if let Some(pipeline) = pipeline_weak.upgrade() {
+            f(&pipeline, element, src_pad);
+        }
+    });
+}
+
+/// Link the H.264 video stream found by a demuxer such as parsebin to the sink pad of the named element.
+/// Only the first H.264 stream is linked. Other video streams cause an error and all other streams are ignored.
+/// If the demuxer finds no H.264 stream, an error is posted.
+/// The context is used in log messages.
+pub fn link_h264_video(pipeline: &gst::Pipeline, demuxer_name: &str, element_name: &str, context: String) {
+    let element_name = element_name.to_owned();
+    let pad_added_context = context.clone();
+    let pad_added_element_name = element_name.clone();
+    connect_pad_added(pipeline, demuxer_name, move |pipeline, demuxer, src_pad| {
+        let context = &pad_added_context;
+        let media_type = media_type(src_pad);
+        info!("{}: pad added: media_type={:?}", context, media_type);
+        match media_type {
+            Some(media_type) if media_type == "video/x-h264" => {},
+            Some(media_type) if media_type.starts_with("video/") => {
+                gst::element_error!(
+                    demuxer,
+                    gst::StreamError::Format,
+                    ("Only H.264 video is supported"),
+                    ["Unsupported video stream {}", media_type]
+                );
+                return;
+            },
+            _ => {
+                debug!("{}: Ignoring stream from pad {}", context, src_pad.name());
+                return;
+            },
+        }
+        let sink_pad = pipeline.by_name(&pad_added_element_name).unwrap().static_pad("sink").unwrap();
+        if sink_pad.is_linked() {
+            warn!("{}: Ignoring additional video stream from pad {}", context, src_pad.name());
+            return;
+        }
+        if let Err(err) = src_pad.link(&sink_pad) {
+            gst::element_error!(
+                demuxer,
+                gst::LibraryError::Failed,
+                ("Failed to link video stream"),
+                ["{:?}", err]
+            );
+        }
+    });
+    let pipeline_weak = pipeline.downgrade();
+    let demuxer = pipeline.by_name(demuxer_name).unwrap();
+    demuxer.connect_no_more_pads(move |demuxer| {
+        let pipeline = match pipeline_weak.upgrade() {
+            Some(pipeline) => pipeline,
+            None => return,
+        };
+        if !pipeline.by_name(&element_name).unwrap().static_pad("sink").unwrap().is_linked() {
+            warn!("{}: No H.264 stream was found", context);
+            gst::element_error!(
+                demuxer,
+                gst::StreamError::Format,
+                ("The stream does not contain H.264 video"),
+                ["No H.264 stream was found by {}", demuxer.name()]
+            );
+        }
+    });
+}
+
+/// Returns the error in an error message from a pipeline bus.
+pub fn message_error(msg: &gst::Message) -> Option<anyhow::Error> {
+    match msg.view() {
+        gst::MessageView::Error(err) => Some(anyhow!("Error from {:?}: {} ({:?})",
+            err.src().map(|s| s.path_string()), err.error(), err.debug())),
+        _ => None,
+    }
+}
+
+/// Wait until the pipeline reaches End-Of-Stream or posts an error.
+/// If stop_message is specified, also stop waiting when an application message with this name is posted.
+/// This blocks.
+pub fn wait_for_eos(bus: &gst::Bus, stop_message: Option<&str>) -> anyhow::Result<()> {
+    for msg in bus.iter_timed(gst::CLOCK_TIME_NONE) {
+        match msg.view() {
+            gst::MessageView::Eos(..) => {
+                debug!("wait_for_eos: End-Of-Stream reached");
+                return Ok(());
+            },
+            gst::MessageView::Error(..) => {
+                return Err(message_error(&msg).unwrap());
+            },
+            gst::MessageView::Application(app) => {
+                let name = app.structure().map(|s| s.name().to_owned());
+                if name.is_some() && name.as_deref() == stop_message {
+                    debug!("wait_for_eos: Received {:?}", name);
+                    return Ok(());
+                }
+            },
+            _ => (),
+        }
+    }
+    Err(anyhow!("The pipeline stopped unexpectedly"))
+}
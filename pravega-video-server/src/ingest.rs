@@ -0,0 +1,280 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Ingest of uploaded MP4 or MPEG TS files into a video stream.
+//
+// The uploaded file is written to a temporary file, then a GStreamer pipeline demuxes it with parsebin,
+// converts the timestamps so that the first frame is at the requested time with timestampcvt,
+// remuxes the H.264 video with mp4mux and fragmp4pay, and writes it with pravegasink.
+// There is no decoding or encoding. Audio and any other streams in the file are not written.
+// Clients poll the status of the ingest job for progress.
+
+use gst::prelude::*;
+use pravega_video::timestamp::PravegaTimestamp;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+#[allow(unused_imports)]
+use tracing::{debug, error, info, warn, trace};
+use super::gst_util;
+
+/// The status of this many of the most recent ingest jobs is retained.
+const MAX_JOBS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IngestState {
+    /// The file is being uploaded.
+    Receiving,
+    /// The file is being written to the stream.
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestStatus {
+    pub id: u64,
+    pub scope_name: String,
+    pub stream_name: String,
+    pub state: IngestState,
+    pub bytes_received: u64,
+    /// The fraction of the file that has been read by the pipeline, from 0 to 1.
+    pub progress: f64,
+    /// The timestamp of the first fragment written to the stream, in ISO 8601 format.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub begin_time: Option<String>,
+    /// The timestamp of the last fragment written to the stream, in ISO 8601 format.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Starts ingest jobs, limits the number of concurrent jobs, and retains their status.
+#[derive(Clone)]
+pub struct Ingester {
+    controller: String,
+    keycloak_file: String,
+    /// The maximum size of an uploaded file, in bytes.
+    max_file_size: u64,
+    semaphore: Arc<Semaphore>,
+    next_id: Arc<AtomicU64>,
+    jobs: Arc<Mutex<BTreeMap<u64, Arc<Mutex<IngestStatus>>>>>,
+}
+
+impl Ingester {
+    pub fn new(controller: String, keycloak_file: String, max_concurrent_ingests: usize, max_file_size: u64) -> Self {
+        Self {
+            controller,
+            keycloak_file,
+            max_file_size,
+            semaphore: Arc::new(Semaphore::new(max_concurrent_ingests)),
+            next_id: Arc::new(AtomicU64::new(1)),
+            jobs: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    pub fn max_file_size(&self) -> u64 {
+        self.max_file_size
+    }
+
+    /// Create a job that will write an uploaded file to the stream.
+    /// Returns None if the maximum number of ingests are in progress.
+    pub fn create_job(&self, scope_name: String, stream_name: String) -> Option<IngestJob> {
+        let permit = self.semaphore.clone().try_acquire_owned().ok()?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let status = Arc::new(Mutex::new(IngestStatus {
+            id,
+            scope_name,
+            stream_name,
+            state: IngestState::Receiving,
+            bytes_received: 0,
+            progress: 0.0,
+            begin_time: None,
+            end_time: None,
+            error: None,
+        }));
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.insert(id, status.clone());
+        // Jobs in progress hold a permit so there are few of them. Only finished jobs are removed.
+        while jobs.len() > MAX_JOBS {
+            let finished = jobs.iter()
+                .find(|(_, status)| matches!(status.lock().unwrap().state, IngestState::Completed | IngestState::Failed))
+                .map(|(id, _)| *id);
+            match finished {
+                Some(id) => jobs.remove(&id),
+                None => break,
+            };
+        }
+        let path = std::env::temp_dir().join(format!("pravega-video-server-ingest-{}-{}", std::process::id(), id));
+        Some(IngestJob {
+            controller: self.controller.clone(),
+            keycloak_file: self.keycloak_file.clone(),
+            status,
+            path,
+            permit,
+        })
+    }
+
+    pub fn status(&self, id: u64) -> Option<IngestStatus> {
+        self.jobs.lock().unwrap().get(&id).map(|status| status.lock().unwrap().clone())
+    }
+}
+
+/// An ingest job. The uploaded file is written to path before the job is started.
+/// If the job is dropped before it is started, such as when the client disconnects during the upload,
+/// it is marked as failed. The temporary file is removed when the job is dropped.
+pub struct IngestJob {
+    controller: String,
+    keycloak_file: String,
+    status: Arc<Mutex<IngestStatus>>,
+    /// The temporary file that receives the upload. It is removed when the job finishes.
+    pub path: PathBuf,
+    /// Released when the job finishes.
+    permit: OwnedSemaphorePermit,
+}
+
+impl IngestJob {
+    pub fn set_bytes_received(&self, bytes_received: u64) {
+        self.status.lock().unwrap().bytes_received = bytes_received;
+    }
+
+    /// Mark the job as failed, such as when the upload was too large.
+    pub fn fail(self, error: String) {
+        warn!("ingest: id={}: {}", self.status.lock().unwrap().id, error);
+        set_failed(&self.status, error);
+    }
+
+    /// Start the pipeline that writes the uploaded file to the stream.
+    /// The first frame will have the timestamp start_utc, in RFC 3339 format.
+    /// This returns the status immediately. The pipeline runs in a separate thread and updates the status.
+    pub fn start(self, start_utc: String) -> IngestStatus {
+        let status = {
+            let mut status = self.status.lock().unwrap();
+            status.state = IngestState::Running;
+            status.clone()
+        };
+        std::thread::spawn(move || {
+            match self.run(&start_utc) {
+                Ok(()) => {
+                    let mut status = self.status.lock().unwrap();
+                    info!("ingest: Completed: status={:?}", status);
+                    status.state = IngestState::Completed;
+                    status.progress = 1.0;
+                },
+                Err(err) => {
+                    error!("ingest: Failed: id={}: {:#}", self.status.lock().unwrap().id, err);
+                    set_failed(&self.status, format!("{:#}", err));
+                },
+            }
+        });
+        status
+    }
+
+    /// Run the pipeline until the entire file has been written. This blocks.
+    fn run(&self, start_utc: &str) -> anyhow::Result<()> {
+        let (stream, file_size) = {
+            let status = self.status.lock().unwrap();
+            (format!("{}/{}", status.scope_name, status.stream_name), status.bytes_received)
+        };
+        info!("ingest: stream={}, start_utc={}, path={}", stream, start_utc, self.path.display());
+        let pipeline_description =
+            "filesrc name=src ! parsebin name=parsebin".to_owned()
+            + " queue name=queue ! timestampcvt name=timestampcvt input-timestamp-mode=start-at-fixed-time"
+            + " mp4mux name=mux streamable=true fragment-duration=1 ! fragmp4pay"
+            + " ! pravegasink name=sink allow-create-scope=false timestamp-mode=tai sync=false";
+        info!("ingest: Launch Pipeline: {}", pipeline_description);
+        let pipeline = gst::parse_launch(&pipeline_description)?;
+        let pipeline = pipeline.dynamic_cast::<gst::Pipeline>().unwrap();
+
+        let filesrc = pipeline.by_name("src").unwrap();
+        filesrc.set_property("location", &self.path.to_string_lossy().to_string())?;
+        let timestampcvt = pipeline.by_name("timestampcvt").unwrap();
+        timestampcvt.set_property("start-utc", &start_utc)?;
+        // This requests a video pad from mp4mux.
+        let mux = pipeline.by_name("mux").unwrap();
+        timestampcvt.link_pads(Some("src"), &mux, Some("video_%u"))?;
+        let pravegasink = pipeline.by_name("sink").unwrap();
+        pravegasink.set_property("controller", &self.controller)?;
+        pravegasink.set_property("keycloak-file", &self.keycloak_file)?;
+        pravegasink.set_property("stream", &stream)?;
+
+        // The H.264 stream from parsebin is linked to the queue. Other streams are ignored.
+        gst_util::link_h264_video(&pipeline, "parsebin", "queue", "ingest".to_owned());
+
+        // Progress is the fraction of the file that has been read.
+        // Since each byte is read about once, this works even if the file is not read in order.
+        let status = self.status.clone();
+        let bytes_read = AtomicU64::new(0);
+        filesrc.static_pad("src").unwrap().add_probe(gst::PadProbeType::BUFFER, move |_, probe_info| {
+            if let Some(gst::PadProbeData::Buffer(ref buffer)) = probe_info.data {
+                let bytes_read = bytes_read.fetch_add(buffer.size() as u64, Ordering::Relaxed) + buffer.size() as u64;
+                if file_size > 0 {
+                    status.lock().unwrap().progress = (bytes_read as f64 / file_size as f64).min(0.99);
+                }
+            }
+            gst::PadProbeReturn::Ok
+        });
+
+        // The time range is determined from the timestamps of the fragments written to the stream.
+        let status = self.status.clone();
+        pravegasink.static_pad("sink").unwrap().add_probe(gst::PadProbeType::BUFFER, move |_, probe_info| {
+            if let Some(gst::PadProbeData::Buffer(ref buffer)) = probe_info.data {
+                if let Some(time) = PravegaTimestamp::from_nanoseconds(buffer.pts().nanoseconds()).to_iso_8601() {
+                    let mut status = status.lock().unwrap();
+                    if status.begin_time.is_none() {
+                        status.begin_time = Some(time.clone());
+                    }
+                    status.end_time = Some(time);
+                }
+            }
+            gst::PadProbeReturn::Ok
+        });
+
+        pipeline.set_state(gst::State::Playing)?;
+        let result = gst_util::wait_for_eos(&pipeline.bus().unwrap(), None);
+        pipeline.set_state(gst::State::Null)?;
+        result
+    }
+}
+
+impl Drop for IngestJob {
+    fn drop(&mut self) {
+        {
+            let mut status = self.status.lock().unwrap();
+            if status.state == IngestState::Receiving {
+                warn!("ingest: id={}: The upload was interrupted after {} bytes", status.id, status.bytes_received);
+                status.state = IngestState::Failed;
+                status.error = Some("The upload was interrupted".to_owned());
+            }
+        }
+        match std::fs::remove_file(&self.path) {
+            Ok(()) => {},
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {},
+            Err(err) => warn!("ingest: Unable to remove {}: {}", self.path.display(), err),
+        }
+    }
+}
+
+fn set_failed(status: &Mutex<IngestStatus>, error: String) {
+    let mut status = status.lock().unwrap();
+    status.state = IngestState::Failed;
+    status.error = Some(error);
+}
+
+/// Initialize GStreamer. This must be called before ingesting.
+pub fn init() -> anyhow::Result<()> {
+    gst_util::init(&["filesrc", "parsebin", "queue", "timestampcvt", "mp4mux", "fragmp4pay", "pravegasink"])
+}
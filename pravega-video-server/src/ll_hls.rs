@@ -0,0 +1,299 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Generation of Low-Latency HLS playlists.
+//
+// Each index record begins a partial segment (EXT-X-PART) that ends at the next index record.
+// A fixed number of consecutive index records are grouped into each media segment,
+// so the Media Sequence Number of a segment is the index record number divided by the number of parts per segment.
+// This keeps Media Sequence Numbers stable between playlist reloads, even after truncation.
+// To achieve low latency, the index must be fine-grained. For example, pravegasink with index-min-sec=0
+// and an encoder that produces a key frame every 0.5 seconds will produce 0.5 second parts.
+
+use pravega_video::index::IndexRecord;
+use pravega_video::timestamp::PravegaTimestamp;
+use tracing::debug;
+
+/// The number of complete segments in a live playlist.
+pub const WINDOW_SEGMENTS: u64 = 6;
+/// Parts are listed only for this many complete segments at the end of the playlist.
+const PART_SEGMENTS: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct LowLatencyPlaylistConfig {
+    pub version: u32,
+    pub parts_per_segment: u64,
+    /// If true, EXT-X-MAP will reference the initialization segment of fragmented MP4.
+    pub use_init_map: bool,
+}
+
+/// A Low-Latency HLS playlist and the position of its live edge.
+#[derive(Debug, Clone)]
+pub struct LowLatencyPlaylist {
+    pub playlist: String,
+    /// The Media Sequence Number of the segment that is in progress.
+    /// All segments before this are complete.
+    pub last_msn: u64,
+    /// The number of parts of the segment that is in progress.
+    pub last_part_count: u64,
+    pub target_duration_seconds: f64,
+}
+
+impl LowLatencyPlaylist {
+    /// Returns true if the playlist contains the part requested with _HLS_msn and _HLS_part.
+    /// If part is None, the segment must be complete.
+    pub fn contains(&self, msn: u64, part: Option<u64>) -> bool {
+        match part {
+            _ if msn < self.last_msn => true,
+            Some(part) if msn == self.last_msn => part < self.last_part_count,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Part {
+    begin_offset: u64,
+    end_offset: u64,
+    timestamp: PravegaTimestamp,
+    duration_seconds: f64,
+    independent: bool,
+}
+
+#[derive(Debug)]
+struct Segment {
+    msn: u64,
+    parts: Vec<Part>,
+    discontinuity: bool,
+    /// The data offset at the end of the segment, if complete.
+    end_offset: Option<u64>,
+}
+
+/// Returns the record number of the first index record that should be included in a live playlist.
+pub fn first_record_number(first_record_number: u64, last_record_number: u64, parts_per_segment: u64) -> u64 {
+    let last_msn = last_record_number / parts_per_segment;
+    let first_msn = last_msn.saturating_sub(WINDOW_SEGMENTS);
+    first_record_number.max(first_msn * parts_per_segment)
+}
+
+/// Build a Low-Latency HLS playlist from consecutive index records and their offsets in the index.
+/// The last index record begins the part that is in progress, which is identified with EXT-X-PRELOAD-HINT.
+/// Returns None if there are no records.
+pub fn build_playlist(index_records: &[(IndexRecord, u64)], record_size: u64, config: &LowLatencyPlaylistConfig) -> Option<LowLatencyPlaylist> {
+    let (last_index_record, last_index_offset) = index_records.last()?;
+    let parts_per_segment = config.parts_per_segment.max(1);
+    let last_msn = last_index_offset / record_size / parts_per_segment;
+
+    // Exponential moving average of the part duration, used to detect gaps.
+    let mut part_duration_seconds = 1.0;
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut next_part_discont = false;
+    for pair in index_records.windows(2) {
+        let (index_record, index_offset) = pair[0];
+        let (next_index_record, _) = pair[1];
+        let msn = index_offset / record_size / parts_per_segment;
+        if segments.last().map(|s| s.msn) != Some(msn) {
+            if let Some(segment) = segments.last_mut() {
+                segment.end_offset = Some(index_record.offset);
+            }
+            segments.push(Segment { msn, parts: Vec::new(), discontinuity: false, end_offset: None });
+        }
+        let segment = segments.last_mut().unwrap();
+
+        // If the next index record indicates a discontinuity, then assume there is a gap in the data
+        // between this record and the next one. Any recorded content that falls in this gap may be corrupt
+        // so we will not include this part. Parts earlier in the same segment are also removed so that the
+        // discontinuity can be placed at the beginning of the segment.
+        let duration_seconds = match (index_record.timestamp.nanoseconds(), next_index_record.timestamp.nanoseconds()) {
+            (Some(t1), Some(t2)) if t2 >= t1 => Some((t2 - t1) as f64 * 1e-9),
+            _ => None,
+        };
+        let discont = next_index_record.discontinuity || match duration_seconds {
+            Some(duration_seconds) => duration_seconds > f64::max(1.0, 3.0 * part_duration_seconds),
+            None => true,
+        };
+        if discont {
+            debug!("Detected discontinuity between {:?} and {:?}", index_record, next_index_record);
+            next_part_discont = true;
+            continue;
+        }
+        let duration_seconds = duration_seconds.unwrap();
+        let ema_alpha = 0.1;
+        part_duration_seconds = ema_alpha * duration_seconds + (1.0 - ema_alpha) * part_duration_seconds;
+        // Version 2 index records store the actual duration of the preceding part.
+        let duration_seconds = match next_index_record.segment_duration.nanoseconds() {
+            Some(segment_duration) if segment_duration > 0 => segment_duration as f64 * 1e-9,
+            _ => duration_seconds,
+        };
+        if next_part_discont {
+            segment.parts.clear();
+            segment.discontinuity = true;
+            next_part_discont = false;
+        }
+        segment.parts.push(Part {
+            begin_offset: index_record.offset,
+            end_offset: next_index_record.offset,
+            timestamp: index_record.timestamp,
+            duration_seconds,
+            independent: index_record.random_access,
+        });
+    }
+    match segments.last_mut() {
+        Some(segment) if segment.msn == last_msn => {},
+        last_segment => {
+            if let Some(segment) = last_segment {
+                segment.end_offset = Some(last_index_record.offset);
+            }
+            segments.push(Segment { msn: last_msn, parts: Vec::new(), discontinuity: next_part_discont, end_offset: None });
+        },
+    }
+
+    // Each part duration must not exceed the part target duration.
+    let max_part_duration_seconds = segments.iter().flat_map(|s| s.parts.iter()).map(|p| p.duration_seconds).fold(0.0, f64::max);
+    let part_target_seconds = if max_part_duration_seconds > 0.0 { (max_part_duration_seconds * 1000.0).ceil() / 1000.0 } else { 1.0 };
+    // Each rounded segment duration must not exceed the target duration, including the segment in progress.
+    let gap_duration_seconds = part_target_seconds * parts_per_segment as f64;
+    let max_segment_duration_seconds = segments.iter()
+        .map(|s| s.parts.iter().map(|p| p.duration_seconds).sum::<f64>())
+        .fold(gap_duration_seconds, f64::max);
+    let target_duration_seconds = max_segment_duration_seconds.round().max(1.0);
+
+    let media_uri_suffix = if config.use_init_map { "&strip_init=true" } else { "" };
+    let mut playlist = String::new();
+    playlist.push_str(&format!("#EXTM3U\n#EXT-X-VERSION:{}\n", config.version));
+    playlist.push_str(&format!("#EXT-X-TARGETDURATION:{}\n", target_duration_seconds));
+    playlist.push_str(&format!("#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK={:.3}\n", 3.0 * part_target_seconds));
+    playlist.push_str(&format!("#EXT-X-PART-INF:PART-TARGET={:.3}\n", part_target_seconds));
+    playlist.push_str(&format!("#EXT-X-MEDIA-SEQUENCE:{}\n", segments[0].msn));
+    let first_segment_with_parts = segments.len().saturating_sub(PART_SEGMENTS + 1);
+    let mut need_map = true;
+    for (i, segment) in segments.iter().enumerate() {
+        if segment.discontinuity && i > 0 {
+            playlist.push_str("#EXT-X-DISCONTINUITY\n");
+            need_map = true;
+        }
+        if let Some(first_part) = segment.parts.first() {
+            if config.use_init_map && need_map {
+                playlist.push_str(&format!("#EXT-X-MAP:URI=\"init?begin={}\"\n", first_part.begin_offset));
+                need_map = false;
+            }
+            if let Some(timestamp) = first_part.timestamp.to_iso_8601() {
+                playlist.push_str(&format!("#EXT-X-PROGRAM-DATE-TIME:{}\n", timestamp));
+            }
+        }
+        if i >= first_segment_with_parts {
+            for part in segment.parts.iter() {
+                playlist.push_str(&format!("#EXT-X-PART:DURATION={:.3},URI=\"media?begin={}&end={}{}\"{}\n",
+                    part.duration_seconds, part.begin_offset, part.end_offset, media_uri_suffix,
+                    if part.independent { ",INDEPENDENT=YES" } else { "" }));
+            }
+        }
+        if let Some(end_offset) = segment.end_offset {
+            match (segment.parts.first(), segment.parts.last()) {
+                (Some(first_part), Some(last_part)) => {
+                    let duration_seconds: f64 = segment.parts.iter().map(|p| p.duration_seconds).sum();
+                    playlist.push_str(&format!("#EXTINF:{},\n", duration_seconds));
+                    playlist.push_str(&format!("media?begin={}&end={}{}\n", first_part.begin_offset, last_part.end_offset, media_uri_suffix));
+                },
+                _ => {
+                    // All parts of this segment were removed because of a discontinuity.
+                    // The segment is still required so that Media Sequence Numbers remain consecutive.
+                    playlist.push_str(&format!("#EXTINF:{},\n", gap_duration_seconds));
+                    playlist.push_str("#EXT-X-GAP\n");
+                    playlist.push_str(&format!("media?begin={}&end={}\n", end_offset, end_offset));
+                },
+            }
+        }
+    }
+    playlist.push_str(&format!("#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"part?begin={}{}\"\n", last_index_record.offset, media_uri_suffix));
+
+    let last_segment = segments.last().unwrap();
+    Some(LowLatencyPlaylist {
+        playlist,
+        last_msn: last_segment.msn,
+        last_part_count: last_segment.parts.len() as u64,
+        target_duration_seconds,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SECOND: u64 = 1_000_000_000;
+    const RECORD_SIZE: u64 = IndexRecord::RECORD_SIZE as u64;
+
+    /// Make index records every 0.5 seconds, beginning at the given record number.
+    fn make_records(first_record_number: u64, num_records: u64) -> Vec<(IndexRecord, u64)> {
+        (first_record_number..first_record_number + num_records).map(|n| {
+            let timestamp = PravegaTimestamp::from_nanoseconds(Some(1_600_000_000 * SECOND + n * SECOND / 2));
+            (IndexRecord::new(timestamp, n * 1000, true, n == 0), n * RECORD_SIZE)
+        }).collect()
+    }
+
+    fn config() -> LowLatencyPlaylistConfig {
+        LowLatencyPlaylistConfig {
+            version: 7,
+            parts_per_segment: 4,
+            use_init_map: false,
+        }
+    }
+
+    #[test]
+    fn test_first_record_number() {
+        assert_eq!(first_record_number(0, 3, 4), 0);
+        assert_eq!(first_record_number(0, 100, 4), 76);
+        assert_eq!(first_record_number(90, 100, 4), 90);
+    }
+
+    #[test]
+    fn test_build_playlist() {
+        // Records 10 to 21 are segment 2 (partial), 3, 4 (complete), and 5 (2 parts in progress).
+        let records = make_records(10, 12);
+        let result = build_playlist(&records, RECORD_SIZE, &config()).unwrap();
+        assert_eq!(result.last_msn, 5);
+        assert_eq!(result.last_part_count, 1);
+        assert_eq!(result.target_duration_seconds, 2.0);
+        assert!(result.contains(4, None));
+        assert!(result.contains(5, Some(0)));
+        assert!(!result.contains(5, Some(1)));
+        assert!(!result.contains(5, None));
+        let playlist = result.playlist;
+        assert!(playlist.contains("#EXT-X-MEDIA-SEQUENCE:2\n"));
+        assert!(playlist.contains("#EXT-X-PART-INF:PART-TARGET=0.500\n"));
+        assert!(playlist.contains("#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK=1.500\n"));
+        assert!(playlist.contains("#EXTINF:1,\nmedia?begin=10000&end=12000\n"));
+        assert!(playlist.contains("#EXTINF:2,\nmedia?begin=12000&end=16000\n"));
+        assert!(playlist.contains("#EXTINF:2,\nmedia?begin=16000&end=20000\n"));
+        assert!(playlist.contains("#EXT-X-PART:DURATION=0.500,URI=\"media?begin=20000&end=21000\",INDEPENDENT=YES\n"));
+        assert!(playlist.ends_with("#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"part?begin=21000\"\n"));
+        assert!(!playlist.contains("#EXT-X-DISCONTINUITY"));
+    }
+
+    #[test]
+    fn test_build_playlist_discontinuity() {
+        // A new session begins at record 14 after a gap of 60 seconds.
+        let mut records = make_records(8, 10);
+        for (index_record, _) in records.iter_mut().skip(6) {
+            index_record.timestamp = index_record.timestamp + pravega_video::timestamp::TimeDelta(Some(60 * SECOND as i128));
+        }
+        records[6].0.discontinuity = true;
+        let config = LowLatencyPlaylistConfig { use_init_map: true, ..config() };
+        let result = build_playlist(&records, RECORD_SIZE, &config).unwrap();
+        let playlist = result.playlist;
+        // Segment 3 has a gap between records 13 and 14 so the parts before record 14 are removed.
+        assert!(playlist.contains("#EXTINF:2,\nmedia?begin=8000&end=12000&strip_init=true\n"));
+        assert!(playlist.contains("#EXT-X-DISCONTINUITY\n#EXT-X-MAP:URI=\"init?begin=14000\"\n"));
+        assert!(playlist.contains("#EXTINF:1,\nmedia?begin=14000&end=16000&strip_init=true\n"));
+        assert_eq!(playlist.matches("#EXT-X-MAP").count(), 2);
+        assert_eq!(result.last_msn, 4);
+        assert_eq!(result.last_part_count, 1);
+    }
+}
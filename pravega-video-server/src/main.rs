@@ -11,14 +11,33 @@
 use clap::Clap;
 use pravega_client::client_factory::ClientFactoryAsync;
 use pravega_video::utils;
-use std::path::Path;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use tokio::runtime::Runtime;
 use tracing_subscriber::fmt::format::FmtSpan;
 #[allow(unused_imports)]
-use tracing::{error, info, info_span, warn, trace, event, Level, span};
+use tracing::{debug, error, info, info_span, warn, trace, event};
 use warp::Filter;
 use warp::http::header::{HeaderMap, HeaderValue};
 
+mod auth;
+mod cache;
+mod dash;
+mod error;
+mod export;
+mod gst_util;
+mod ingest;
+mod ll_hls;
+mod metrics;
+mod mp4;
+mod payload;
+mod sprite;
+mod stream_info;
+mod thumbnail;
+mod timeline;
+mod tls;
+mod whep;
+
 /// Serve HTTP Live Streaming (HLS) from a Pravega Video Stream.
 /// Point your browser to: http://localhost:3030/player?scope=examples&stream=hlsav4
 #[derive(Clap, Debug)]
@@ -32,6 +51,69 @@ struct Opts {
     /// Directory containing static files and templates.
     #[clap(long, env = "PRAVEGA_VIDEO_SERVER_RESOURCE_DIR", default_value = "./resources")]
     resource_dir: String,
+    /// The default HLS version of playlists. Version 7 and higher use EXT-X-MAP for fragmented MP4 and EXT-X-GAP for gaps.
+    /// Version 3 repeats the initialization segment in every media segment and uses static/gap-5s.mp4 for gaps,
+    /// which may be needed by older versions of hls.js. This can be overridden with the version query parameter.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_HLS_VERSION", default_value = "7")]
+    hls_version: u32,
+    /// The number of index records (partial segments) in each segment of Low-Latency HLS playlists.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_LL_HLS_PARTS_PER_SEGMENT", default_value = "4")]
+    ll_hls_parts_per_segment: u64,
+    /// If true, playlists will use EXT-X-BYTERANGE to identify segments as byte ranges of the payload resource of the stream.
+    /// This can be overridden with the byte_range query parameter.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_HLS_BYTE_RANGE")]
+    hls_byte_range: bool,
+    /// The maximum number of MP4 exports that can run at the same time. Each export runs a GStreamer pipeline.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_MAX_CONCURRENT_EXPORTS", default_value = "4")]
+    max_concurrent_exports: usize,
+    /// The maximum duration of an MP4 export, in minutes. Longer time ranges will be rejected.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_MAX_EXPORT_DURATION_MINUTES", default_value = "60")]
+    max_export_duration_minutes: u64,
+    /// The maximum number of thumbnails and sprite sheets that can be decoded at the same time.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_MAX_CONCURRENT_THUMBNAILS", default_value = "4")]
+    max_concurrent_thumbnails: usize,
+    /// The maximum number of uploaded files that can be written to streams at the same time.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_MAX_CONCURRENT_INGESTS", default_value = "2")]
+    max_concurrent_ingests: usize,
+    /// The maximum size of an uploaded file, in MiB.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_MAX_INGEST_SIZE_MB", default_value = "4096")]
+    max_ingest_size_mb: u64,
+    /// The maximum number of WebRTC playback sessions that can run at the same time. Each session runs a GStreamer pipeline.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_MAX_CONCURRENT_WHEP_SESSIONS", default_value = "8")]
+    max_concurrent_whep_sessions: usize,
+    /// The maximum size of media segments cached in memory, in MiB. Set to 0 to disable the cache.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_SEGMENT_CACHE_SIZE_MB", default_value = "256")]
+    segment_cache_size_mb: usize,
+    /// How long live playlists are cached, in milliseconds. Set to 0 to disable the cache.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_PLAYLIST_CACHE_TTL_MS", default_value = "500")]
+    playlist_cache_ttl_ms: u64,
+    /// The maximum number of streams whose index records are cached in memory.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_INDEX_CACHE_STREAMS", default_value = "1000")]
+    index_cache_streams: usize,
+    /// The maximum number of index records cached in memory for each stream.
+    /// Searches for older records read the index stream.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_INDEX_CACHE_RECORDS", default_value = "10000")]
+    index_cache_records: usize,
+    /// The IP address to listen on.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_BIND_ADDRESS", default_value = "0.0.0.0")]
+    bind_address: IpAddr,
+    /// The port to listen on. This is the HTTPS port if a TLS certificate is specified.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_PORT", default_value = "3030")]
+    port: u16,
+    /// PEM file with the TLS certificate chain. If specified, the server will use HTTPS.
+    /// The certificate and key are reloaded when either file changes.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_TLS_CERT_FILE", requires = "tls-key-file")]
+    tls_cert_file: Option<PathBuf>,
+    /// PEM file with the PKCS#8 or RSA private key of the TLS certificate.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_TLS_KEY_FILE", requires = "tls-cert-file")]
+    tls_key_file: Option<PathBuf>,
+    /// If specified with a TLS certificate, HTTP requests to this port will be redirected to HTTPS.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_HTTP_REDIRECT_PORT")]
+    http_redirect_port: Option<u16>,
+    /// JSON file with API keys, users, JWT settings, and authorization rules.
+    /// If not specified, authentication is disabled and all scopes and streams can be accessed.
+    #[clap(long, env = "PRAVEGA_VIDEO_SERVER_AUTH_CONFIG_FILE")]
+    auth_config_file: Option<PathBuf>,
 }
 
 fn main() {
@@ -47,7 +129,71 @@ fn main() {
     info!("opts={:?}", opts);
 
     let static_dir_name = format!("{}/static", opts.resource_dir);
+    let hls_version = opts.hls_version;
+    let ll_hls_parts_per_segment = opts.ll_hls_parts_per_segment;
+    let hls_byte_range = opts.hls_byte_range;
+    let cache_config = models::CacheConfig {
+        segment_cache_size: opts.segment_cache_size_mb * 1024 * 1024,
+        playlist_cache_ttl: std::time::Duration::from_millis(opts.playlist_cache_ttl_ms),
+        index_cache_streams: opts.index_cache_streams,
+        index_cache_records: opts.index_cache_records,
+    };
+    let addr = SocketAddr::new(opts.bind_address, opts.port);
+    let http_redirect_port = opts.http_redirect_port;
+    let tls_config = match (opts.tls_cert_file.clone(), opts.tls_key_file.clone()) {
+        (Some(cert_file), Some(key_file)) => Some(tls::TlsConfig { cert_file, key_file }),
+        _ => None,
+    };
     ensure_extra_files(opts.resource_dir.clone());
+    let auth = match &opts.auth_config_file {
+        Some(auth_config_file) => match auth::Auth::load(auth_config_file) {
+            Ok(auth) => auth,
+            Err(err) => {
+                error!("Unable to load auth configuration: {:#}", err);
+                std::process::exit(1);
+            },
+        },
+        None => {
+            warn!("Authentication is disabled because no auth configuration file was specified");
+            auth::Auth::disabled()
+        },
+    };
+
+    // Export requires GStreamer and the pravegasrc element. Other requests do not.
+    let exporter = match export::init() {
+        Ok(()) => Some(export::Exporter::new(
+            opts.pravega_controller_uri.clone(), opts.keycloak_service_account_file.clone(), opts.max_concurrent_exports,
+            std::time::Duration::from_secs(opts.max_export_duration_minutes * 60))),
+        Err(err) => {
+            warn!("Export to MP4 will not be available: {}", err);
+            None
+        },
+    };
+    let thumbnailer = match thumbnail::init() {
+        Ok(()) => Some(thumbnail::Thumbnailer::new(
+            opts.pravega_controller_uri.clone(), opts.keycloak_service_account_file.clone(), opts.max_concurrent_thumbnails)),
+        Err(err) => {
+            warn!("Thumbnails will not be available: {}", err);
+            None
+        },
+    };
+    let ingester = match ingest::init() {
+        Ok(()) => Some(ingest::Ingester::new(
+            opts.pravega_controller_uri.clone(), opts.keycloak_service_account_file.clone(), opts.max_concurrent_ingests,
+            opts.max_ingest_size_mb * 1024 * 1024)),
+        Err(err) => {
+            warn!("Ingest of uploaded files will not be available: {}", err);
+            None
+        },
+    };
+    let whep_server = match whep::init() {
+        Ok(()) => Some(whep::WhepServer::new(
+            opts.pravega_controller_uri.clone(), opts.keycloak_service_account_file.clone(), opts.max_concurrent_whep_sessions)),
+        Err(err) => {
+            warn!("WebRTC playback will not be available: {}", err);
+            None
+        },
+    };
 
     // Use the Tokio runtime. It will also be used by Warp.
     let runtime  = Runtime::new().unwrap();
@@ -56,8 +202,12 @@ fn main() {
     let client_factory_db = client_factory.clone();
 
     runtime.block_on(async {
-        let db = models::new(client_factory_db);
-        let api = filters::get_all_filters(db);
+        auth.start().await;
+        let metrics = metrics::Metrics::new();
+        let db = models::new(client_factory_db, hls_version, ll_hls_parts_per_segment, hls_byte_range, cache_config, exporter, thumbnailer,
+            ingester, whep_server, metrics.clone());
+        let api = filters::get_all_filters(db.clone(), auth.clone());
+        let signed_api = filters::get_signed_stream_filters(db, auth.clone());
         let ui = ui::get_all_filters();
         let static_dir = warp::path("static").and(warp::fs::dir(static_dir_name));
         // let redirect = warp::path::end().map(|| {
@@ -67,11 +217,35 @@ fn main() {
         let mut headers = HeaderMap::new();
         headers.insert("access-control-allow-origin", HeaderValue::from_static("*"));
         let routes = api
+            .or(signed_api)
             .or(ui)
             .or(static_dir)
+            .or(metrics::get_metrics(metrics.clone(), auth.clone()))
+            .recover(error::handle_rejection);
+        let routes = metrics.instrument(routes)
             .with(warp::reply::with::headers(headers))
             .with(warp::trace::request());
-        warp::serve(routes).run(([0, 0, 0, 0], 3030)).await;
+        match tls_config {
+            Some(tls_config) => {
+                if let Some(http_redirect_port) = http_redirect_port {
+                    let redirect_addr = SocketAddr::new(addr.ip(), http_redirect_port);
+                    info!("Redirecting HTTP on {} to HTTPS", redirect_addr);
+                    let redirect = tls::https_redirect(addr.port()).with(warp::trace::request());
+                    tokio::spawn(warp::serve(redirect).run(redirect_addr));
+                }
+                if let Err(err) = tls::serve(routes, addr, tls_config).await {
+                    error!("Unable to serve HTTPS: {:#}", err);
+                    std::process::exit(1);
+                }
+            },
+            None => {
+                if http_redirect_port.is_some() {
+                    warn!("HTTP redirect port is ignored because TLS is not enabled");
+                }
+                info!("Listening for HTTP on {}", addr);
+                warp::serve(routes).run(addr).await;
+            },
+        }
     })
 }
 
@@ -86,61 +260,376 @@ fn ensure_extra_files(resource_dir: String) {
 }
 
 mod filters {
+    use super::auth::{self, Auth, GetSignedUrlOptions};
     use super::handlers;
-    use super::models::{Db, GetMediaSegmentOptions, GetM3u8PlaylistOptions};
+    use super::models::{Db, GetExportOptions, GetInitSegmentOptions, GetMediaSegmentOptions, GetM3u8PlaylistOptions, GetMpdOptions, GetPartOptions,
+        GetSpriteOptions, GetThumbnailOptions, GetThumbnailTrackOptions, GetTimelineOptions, GetTimelinesRequest, GetWebSocketOptions, PostIngestOptions,
+        PostWhepOptions};
+    use super::thumbnail::ImageFormat;
     use warp::Filter;
 
     pub fn get_all_filters(
         db: Db,
+        auth: Auth,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        get_stream_filters(db.clone(), auth.clone())
+            .or(get_signed_url(auth.clone()))
+            .or(post_ingest(db.clone(), auth.clone()))
+            .or(get_ingest(db.clone(), auth.clone()))
+            .or(get_timelines(db.clone(), auth.clone()))
+            .or(list_video_streams(db.clone(), auth.clone()))
+            .or(list_scopes(db, auth))
+    }
+
+    /// The resources of a single stream.
+    pub fn get_stream_filters(
+        db: Db,
+        auth: Auth,
+    ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+        get_playlist_filters(db.clone(), auth.clone())
+            .or(get_export(db.clone(), auth.clone()))
+            .or(get_thumbnail(db.clone(), auth.clone()))
+            .or(get_thumbnail_track(db.clone(), auth.clone()))
+            .or(get_sprite(db.clone(), auth.clone()))
+            .or(get_timeline(db.clone(), auth.clone()))
+            .or(get_websocket(db.clone(), auth.clone()))
+            .or(post_whep(db.clone(), auth.clone()))
+            .or(delete_whep(db.clone(), auth.clone()))
+            .or(get_stream_info(db, auth))
+    }
+
+    /// The playlists and segments of a single stream. These can also be accessed with a signed URL.
+    pub fn get_playlist_filters(
+        db: Db,
+        auth: Auth,
+    ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+        get_media_segment(db.clone(), auth.clone())
+            .or(get_init_segment(db.clone(), auth.clone()))
+            .or(get_part(db.clone(), auth.clone()))
+            .or(get_payload(db.clone(), auth.clone()))
+            .or(get_m3u8_playlist(db.clone(), auth.clone()))
+            .or(get_mpd(db, auth))
+    }
+
+    /// GET /signed/1618840800.c2lnbmF0dXJl/scopes/my_scope/streams/my_stream/m3u8
+    /// The playlists and segments of a single stream with a signed URL from get_signed_url.
+    /// The signature is verified by the authorization of each playlist filter.
+    pub fn get_signed_stream_filters(
+        db: Db,
+        auth: Auth,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path("signed")
+            .and(warp::path::param::<String>())
+            .map(|_signature: String| ())
+            .untuple_one()
+            .and(get_playlist_filters(db, auth))
+    }
+
+    /// GET /scopes/my_scope/streams/my_stream/signed-url?ttl=300
+    /// Returns a URL that grants access to the stream without credentials until it expires.
+    pub fn get_signed_url(
+        auth: Auth,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("scopes" / String / "streams" / String / "signed-url")
+            .and(warp::get())
+            .and(auth::authorize(auth))
+            .and(warp::query::<GetSignedUrlOptions>())
+            .and_then(handlers::get_signed_url)
+    }
+
+    /// POST /scopes/my_scope/streams/my_stream/ingest?start-utc=2021-04-19T14:02:00Z
+    /// Uploads an MP4 or MPEG TS file and writes its H.264 video to the stream. Audio is not written.
+    /// Returns the status of the ingest job.
+    /// This is not available with a signed URL.
+    pub fn post_ingest(
+        db: Db,
+        auth: Auth,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("scopes" / String / "streams" / String / "ingest")
+            .and(warp::post())
+            .and(auth::require(auth))
+            .and(warp::query::<PostIngestOptions>())
+            .and(warp::body::stream())
+            .and(with_db(db))
+            .and_then(handlers::post_ingest)
+    }
+
+    /// GET /ingests/1
+    /// Returns the status of an ingest job, including its progress and the time range written to the stream.
+    pub fn get_ingest(
+        db: Db,
+        auth: Auth,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-        get_media_segment(db.clone())
-            .or(get_m3u8_playlist(db.clone()))
-            .or(list_video_streams(db.clone()))
-            .or(list_scopes(db.clone()))
+        warp::path!("ingests" / u64)
+            .and(warp::get())
+            .and(auth::authorize(auth))
+            .and(with_db(db))
+            .and_then(handlers::get_ingest)
     }
 
     /// GET /scopes/my_scope/streams/my_stream/media?begin=0&end=204
     /// Returns a media segment consisting of fragmented MP4 or MPEG TS.
     pub fn get_media_segment(
         db: Db,
+        auth: Auth,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
         warp::path!("scopes" / String / "streams" / String / "media" )
             .and(warp::get())
+            .and(auth::require(auth))
             .and(warp::query::<GetMediaSegmentOptions>())
+            .and(warp::header::optional::<String>("if-none-match"))
             .and(with_db(db))
             .and_then(handlers::get_media_segment)
     }
 
+    /// GET /scopes/my_scope/streams/my_stream/payload/0
+    /// Returns the payloads of all events after the anchor offset. The Range header is supported.
+    pub fn get_payload(
+        db: Db,
+        auth: Auth,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("scopes" / String / "streams" / String / "payload" / u64 )
+            .and(warp::get())
+            .and(auth::require(auth))
+            .and(warp::header::optional::<String>("range"))
+            .and(with_db(db))
+            .and_then(handlers::get_payload)
+    }
+
+    /// GET /scopes/my_scope/streams/my_stream/init?begin=0
+    /// Returns the initialization segment (ftyp and moov boxes) of the fragmented MP4 at the byte offset.
+    pub fn get_init_segment(
+        db: Db,
+        auth: Auth,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("scopes" / String / "streams" / String / "init" )
+            .and(warp::get())
+            .and(auth::require(auth))
+            .and(warp::query::<GetInitSegmentOptions>())
+            .and(with_db(db))
+            .and_then(handlers::get_init_segment)
+    }
+
+    /// GET /scopes/my_scope/streams/my_stream/part?begin=12345
+    /// Returns the partial segment that begins at the byte offset, waiting until it has been written.
+    /// This is used for EXT-X-PRELOAD-HINT in Low-Latency HLS playlists.
+    pub fn get_part(
+        db: Db,
+        auth: Auth,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("scopes" / String / "streams" / String / "part" )
+            .and(warp::get())
+            .and(auth::require(auth))
+            .and(warp::query::<GetPartOptions>())
+            .and(with_db(db))
+            .and_then(handlers::get_part)
+    }
+
+    /// GET /scopes/my_scope/streams/my_stream/websocket?begin=2021-04-19T00:00:00Z
+    /// Upgrades to a WebSocket that sends the fragmented MP4 of the stream as binary messages for Media Source Extensions.
+    /// Without begin, this begins at the latest key frame and continues as the stream is written.
+    pub fn get_websocket(
+        db: Db,
+        auth: Auth,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("scopes" / String / "streams" / String / "websocket" )
+            .and(warp::get())
+            .and(auth::require(auth))
+            .and(warp::query::<GetWebSocketOptions>())
+            .and(warp::ws())
+            .and(with_db(db))
+            .and_then(handlers::get_websocket)
+    }
+
+    /// POST /scopes/my_scope/streams/my_stream/whep?begin=2021-04-19T14:02:00Z
+    /// Accepts an SDP offer and returns an SDP answer for WebRTC playback of the stream.
+    /// The location of the session is returned in the location header.
+    /// This is not available with a signed URL.
+    pub fn post_whep(
+        db: Db,
+        auth: Auth,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("scopes" / String / "streams" / String / "whep")
+            .and(warp::post())
+            .and(auth::require(auth))
+            .and(warp::query::<PostWhepOptions>())
+            .and(warp::body::content_length_limit(64 * 1024))
+            .and(warp::body::bytes())
+            .and(with_db(db))
+            .and_then(handlers::post_whep)
+    }
+
+    /// DELETE /scopes/my_scope/streams/my_stream/whep/1
+    /// Stops a WebRTC playback session.
+    pub fn delete_whep(
+        db: Db,
+        auth: Auth,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("scopes" / String / "streams" / String / "whep" / u64)
+            .and(warp::delete())
+            .and(auth::require(auth))
+            .and(with_db(db))
+            .and_then(handlers::delete_whep)
+    }
+
     /// GET /scopes/my_scope/streams/my_stream/m3u8?begin=2021-04-19T00:00:00Z&end=2021-04-20T00:00:00Z
     pub fn get_m3u8_playlist(
         db: Db,
+        auth: Auth,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
         warp::path!("scopes" / String / "streams" / String / "m3u8" )
             .and(warp::get())
+            .and(auth::require(auth))
             .and(warp::query::<GetM3u8PlaylistOptions>())
             .and(with_db(db))
             .and_then(handlers::get_m3u8_playlist)
             .with(warp::compression::gzip())
     }
 
+    /// GET /scopes/my_scope/streams/my_stream/mpd?begin=2021-04-19T00:00:00Z&end=2021-04-20T00:00:00Z
+    pub fn get_mpd(
+        db: Db,
+        auth: Auth,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("scopes" / String / "streams" / String / "mpd" )
+            .and(warp::get())
+            .and(auth::require(auth))
+            .and(warp::query::<GetMpdOptions>())
+            .and(with_db(db))
+            .and_then(handlers::get_mpd)
+            .with(warp::compression::gzip())
+    }
+
+    /// GET /scopes/my_scope/streams/my_stream/export.mp4?begin=2021-04-19T14:02:00Z&end=2021-04-19T14:09:00Z
+    /// Returns a single MP4 file containing the time range.
+    pub fn get_export(
+        db: Db,
+        auth: Auth,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("scopes" / String / "streams" / String / "export.mp4" )
+            .and(warp::get())
+            .and(auth::require(auth))
+            .and(warp::query::<GetExportOptions>())
+            .and(with_db(db))
+            .and_then(handlers::get_export)
+    }
+
+    /// GET /scopes/my_scope/streams/my_stream/thumbnail.jpg?timestamp=2021-04-19T14:02:00Z&width=320
+    /// GET /scopes/my_scope/streams/my_stream/thumbnail.png?timestamp=2021-04-19T14:02:00Z&width=320
+    /// Returns the key frame on or immediately before the timestamp as an image.
+    pub fn get_thumbnail(
+        db: Db,
+        auth: Auth,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        let jpeg = warp::path!("scopes" / String / "streams" / String / "thumbnail.jpg")
+            .map(|scope_name, stream_name| (scope_name, stream_name, ImageFormat::Jpeg));
+        let png = warp::path!("scopes" / String / "streams" / String / "thumbnail.png")
+            .map(|scope_name, stream_name| (scope_name, stream_name, ImageFormat::Png));
+        jpeg.or(png).unify().untuple_one()
+            .and(warp::get())
+            .and(auth::require(auth))
+            .and(warp::query::<GetThumbnailOptions>())
+            .and(with_db(db))
+            .and_then(handlers::get_thumbnail)
+    }
+
+    /// GET /scopes/my_scope/streams/my_stream/thumbnails.vtt?begin=2021-04-19T14:00:00Z&end=2021-04-19T15:00:00Z&interval=10
+    /// Returns a WebVTT thumbnail track that references sprite sheets.
+    pub fn get_thumbnail_track(
+        db: Db,
+        auth: Auth,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("scopes" / String / "streams" / String / "thumbnails.vtt")
+            .and(warp::get())
+            .and(auth::require(auth))
+            .and(warp::query::<GetThumbnailTrackOptions>())
+            .and(with_db(db))
+            .and_then(handlers::get_thumbnail_track)
+    }
+
+    /// GET /scopes/my_scope/streams/my_stream/sprite.jpg?begin=2021-04-19T14:00:00Z&end=2021-04-19T15:00:00Z&interval=10&sheet=0
+    /// Returns a sprite sheet of a thumbnail track.
+    pub fn get_sprite(
+        db: Db,
+        auth: Auth,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("scopes" / String / "streams" / String / "sprite.jpg")
+            .and(warp::get())
+            .and(auth::require(auth))
+            .and(warp::query::<GetSpriteOptions>())
+            .and(with_db(db))
+            .and_then(handlers::get_sprite)
+    }
+
+    /// GET /scopes/my_scope/streams/my_stream/timeline?begin=2021-04-01T00:00:00Z&end=2021-05-01T00:00:00Z&resolution=60
+    /// Returns the recorded intervals, gaps, and discontinuities of the time range as JSON.
+    pub fn get_timeline(
+        db: Db,
+        auth: Auth,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("scopes" / String / "streams" / String / "timeline")
+            .and(warp::get())
+            .and(auth::require(auth))
+            .and(warp::query::<GetTimelineOptions>())
+            .and(with_db(db))
+            .and_then(handlers::get_timeline)
+            .with(warp::compression::gzip())
+    }
+
+    /// POST /timelines
+    /// Returns the timelines of multiple streams. The body is JSON with the streams and the same options as get_timeline.
+    pub fn get_timelines(
+        db: Db,
+        auth: Auth,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("timelines")
+            .and(warp::post())
+            .and(auth::authorize(auth))
+            .and(warp::body::content_length_limit(MAX_TIMELINES_REQUEST_LENGTH))
+            .and(warp::body::json::<GetTimelinesRequest>())
+            .and(with_db(db))
+            .and_then(handlers::get_timelines)
+            .with(warp::compression::gzip())
+    }
+
+    /// Maximum length of the body of a request for timelines.
+    const MAX_TIMELINES_REQUEST_LENGTH: u64 = 1024 * 1024;
+
     /// List scopes this player has access to
     /// GET /scopes
     pub fn list_scopes(
         db: Db,
+        auth: Auth,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
         warp::path!("scopes")
             .and(warp::get())
+            .and(auth::authorize(auth))
             .and(with_db(db))
             .and_then(handlers::list_scopes)
     }
 
+    /// Get the time range, size, and other information about a stream
+    /// GET /scopes/my_scope/streams/my_stream
+    pub fn get_stream_info(
+        db: Db,
+        auth: Auth,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("scopes" / String / "streams" / String)
+            .and(warp::get())
+            .and(auth::require(auth))
+            .and(with_db(db))
+            .and_then(handlers::get_stream_info)
+    }
+
     /// List streams within the given scope
     /// GET /scopes/my_scope/streams
     pub fn list_video_streams(
         db: Db,
+        auth: Auth,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
         warp::path!("scopes" / String / "streams")
             .and(warp::get())
+            .and(auth::authorize(auth))
             .and(with_db(db))
             .and_then(handlers::list_video_streams)
     }
@@ -191,16 +680,91 @@ mod ui {
 
 mod handlers {
     use std::convert::Infallible;
-    use super::models::{Db, GetMediaSegmentOptions, GetM3u8PlaylistOptions};
+    use super::auth::{Access, GetSignedUrlOptions};
+    use super::error;
+    use super::models::{Db, GetExportOptions, GetInitSegmentOptions, GetMediaSegmentOptions, GetM3u8PlaylistOptions, GetMpdOptions, GetPartOptions,
+        GetSpriteOptions, GetThumbnailOptions, GetThumbnailTrackOptions, GetTimelineOptions, GetTimelinesRequest, GetWebSocketOptions, PostIngestOptions,
+        PostWhepOptions};
+    use super::thumbnail::ImageFormat;
     use super::*;
 
     pub async fn get_media_segment(
         scope_name: String,
         stream_name: String,
         opts: GetMediaSegmentOptions,
+        if_none_match: Option<String>,
+        db: Db,
+    ) -> Result<impl warp::Reply, Infallible> {
+        // Media segments never change so the client's cached copy can always be used if the ETag matches.
+        let etag = opts.etag();
+        if let Some(if_none_match) = if_none_match {
+            if if_none_match.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*") {
+                info!("get_media_segment: Not modified: etag={}", etag);
+                let response = warp::http::Response::builder()
+                    .status(warp::http::StatusCode::NOT_MODIFIED)
+                    .header("etag", etag)
+                    .body(hyper::Body::empty())
+                    .map_err(error::ApiError::from);
+                return Ok(error::into_response(response));
+            }
+        }
+        Ok(error::into_response(db.get_media_segment(scope_name, stream_name, opts).await))
+    }
+
+    pub async fn get_payload(
+        scope_name: String,
+        stream_name: String,
+        anchor: u64,
+        range: Option<String>,
+        db: Db,
+    ) -> Result<impl warp::Reply, Infallible> {
+        Ok(error::into_response(db.get_payload(scope_name, stream_name, anchor, range).await))
+    }
+
+    pub async fn get_init_segment(
+        scope_name: String,
+        stream_name: String,
+        opts: GetInitSegmentOptions,
+        db: Db,
+    ) -> Result<impl warp::Reply, Infallible> {
+        let init_segment = db.get_init_segment(scope_name, stream_name, opts).await;
+        let response = init_segment.map(|init_segment| warp::reply::with_header(init_segment, "content-type", "video/mp4"));
+        Ok(error::into_response(response))
+    }
+
+    pub async fn get_part(
+        scope_name: String,
+        stream_name: String,
+        opts: GetPartOptions,
+        db: Db,
+    ) -> Result<impl warp::Reply, Infallible> {
+        let end = db.clone().wait_for_part_end(scope_name.clone(), stream_name.clone(), opts.begin).await;
+        let response = match end {
+            Ok(end) => {
+                let opts = GetMediaSegmentOptions {
+                    begin: opts.begin,
+                    end,
+                    strip_init: opts.strip_init,
+                };
+                db.get_media_segment(scope_name, stream_name, opts).await
+            },
+            Err(err) => Err(err),
+        };
+        Ok(error::into_response(response))
+    }
+
+    pub async fn get_websocket(
+        scope_name: String,
+        stream_name: String,
+        opts: GetWebSocketOptions,
+        ws: warp::ws::Ws,
         db: Db,
     ) -> Result<impl warp::Reply, Infallible> {
-        db.get_media_segment(scope_name, stream_name, opts).await
+        let start = db.get_websocket_start(scope_name, stream_name, opts).await;
+        let response = start.map(|(scoped_stream, begin_index_record)| {
+            ws.on_upgrade(move |websocket| db.send_websocket(websocket, scoped_stream, begin_index_record))
+        });
+        Ok(error::into_response(response))
     }
 
     pub async fn get_m3u8_playlist(
@@ -209,52 +773,322 @@ mod handlers {
         opts: GetM3u8PlaylistOptions,
         db: Db,
     ) -> Result<impl warp::Reply, Infallible> {
-        let playlist = db.get_m3u8_playlist(scope_name, stream_name, opts).await.unwrap();
-        Ok(warp::reply::with_header(playlist, "content-type", "application/x-mpegURL"))
+        let playlist = db.get_m3u8_playlist(scope_name, stream_name, opts).await;
+        let response = playlist.map(|playlist| warp::reply::with_header(playlist, "content-type", "application/x-mpegURL"));
+        Ok(error::into_response(response))
+    }
+
+    pub async fn get_mpd(
+        scope_name: String,
+        stream_name: String,
+        opts: GetMpdOptions,
+        db: Db,
+    ) -> Result<impl warp::Reply, Infallible> {
+        let mpd = db.get_mpd(scope_name, stream_name, opts).await;
+        let response = mpd.map(|mpd| warp::reply::with_header(mpd, "content-type", "application/dash+xml"));
+        Ok(error::into_response(response))
+    }
+
+    pub async fn get_export(
+        scope_name: String,
+        stream_name: String,
+        opts: GetExportOptions,
+        db: Db,
+    ) -> Result<impl warp::Reply, Infallible> {
+        Ok(error::into_response(db.get_export(scope_name, stream_name, opts).await))
+    }
+
+    pub async fn post_ingest(
+        scope_name: String,
+        stream_name: String,
+        opts: PostIngestOptions,
+        body: impl futures::Stream<Item = Result<impl hyper::body::Buf, warp::Error>>,
+        db: Db,
+    ) -> Result<impl warp::Reply, Infallible> {
+        let status = db.post_ingest(scope_name, stream_name, opts, body).await;
+        let response = status.map(|status| {
+            let location = format!("/ingests/{}", status.id);
+            let reply = warp::reply::with_status(warp::reply::json(&status), warp::http::StatusCode::ACCEPTED);
+            warp::reply::with_header(reply, "location", location)
+        });
+        Ok(error::into_response(response))
+    }
+
+    pub async fn get_ingest(
+        id: u64,
+        access: Access,
+        db: Db,
+    ) -> Result<impl warp::Reply, Infallible> {
+        let status = db.get_ingest(id, &access);
+        Ok(error::into_response(status.map(|status| warp::reply::json(&status))))
+    }
+
+    pub async fn post_whep(
+        scope_name: String,
+        stream_name: String,
+        opts: PostWhepOptions,
+        body: hyper::body::Bytes,
+        db: Db,
+    ) -> Result<impl warp::Reply, Infallible> {
+        let location_prefix = format!("/scopes/{}/streams/{}/whep", scope_name, stream_name);
+        let answer = db.post_whep(scope_name, stream_name, opts, body).await;
+        let response = answer.map(|answer| {
+            let location = format!("{}/{}", location_prefix, answer.id);
+            let reply = warp::reply::with_status(answer.sdp, warp::http::StatusCode::CREATED);
+            let reply = warp::reply::with_header(reply, "content-type", "application/sdp");
+            warp::reply::with_header(reply, "location", location)
+        });
+        Ok(error::into_response(response))
+    }
+
+    pub async fn delete_whep(
+        scope_name: String,
+        stream_name: String,
+        id: u64,
+        db: Db,
+    ) -> Result<impl warp::Reply, Infallible> {
+        Ok(error::into_response(db.delete_whep(&scope_name, &stream_name, id).map(|()| warp::reply())))
+    }
+
+    pub async fn get_thumbnail(
+        scope_name: String,
+        stream_name: String,
+        format: ImageFormat,
+        opts: GetThumbnailOptions,
+        db: Db,
+    ) -> Result<impl warp::Reply, Infallible> {
+        Ok(error::into_response(db.get_thumbnail(scope_name, stream_name, format, opts).await))
+    }
+
+    pub async fn get_thumbnail_track(
+        scope_name: String,
+        stream_name: String,
+        opts: GetThumbnailTrackOptions,
+        db: Db,
+    ) -> Result<impl warp::Reply, Infallible> {
+        Ok(error::into_response(db.get_thumbnail_track(scope_name, stream_name, opts).await))
+    }
+
+    pub async fn get_sprite(
+        scope_name: String,
+        stream_name: String,
+        opts: GetSpriteOptions,
+        db: Db,
+    ) -> Result<impl warp::Reply, Infallible> {
+        Ok(error::into_response(db.get_sprite(scope_name, stream_name, opts).await))
+    }
+
+    pub async fn get_timeline(
+        scope_name: String,
+        stream_name: String,
+        opts: GetTimelineOptions,
+        db: Db,
+    ) -> Result<impl warp::Reply, Infallible> {
+        let timeline = db.get_timeline(scope_name, stream_name, opts).await;
+        Ok(error::into_response(timeline.map(|timeline| warp::reply::json(&timeline))))
+    }
+
+    pub async fn get_timelines(
+        access: Access,
+        request: GetTimelinesRequest,
+        db: Db,
+    ) -> Result<impl warp::Reply, Infallible> {
+        // Timelines are returned only if all of the streams can be accessed.
+        let allowed = request.streams.iter()
+            .try_for_each(|stream| access.check_stream(&stream.scope_name, &stream.stream_name));
+        let timelines = match allowed {
+            Ok(()) => db.get_timelines(request).await,
+            Err(err) => Err(err),
+        };
+        Ok(error::into_response(timelines.map(|timelines| warp::reply::json(&timelines))))
+    }
+
+    pub async fn get_signed_url(
+        scope_name: String,
+        stream_name: String,
+        access: Access,
+        opts: GetSignedUrlOptions,
+    ) -> Result<impl warp::Reply, Infallible> {
+        info!("get_signed_url: scope_name={}, stream_name={}, ttl={:?}", scope_name, stream_name, opts.ttl);
+        let signed_url = access.sign(&scope_name, &stream_name, opts);
+        Ok(error::into_response(signed_url.map(|signed_url| warp::reply::json(&signed_url))))
     }
 
     pub async fn list_scopes(
+        access: Access,
         db: Db,
     ) -> Result<impl warp::Reply, Infallible> {
         info!("list_scopes");
-        let streams = db.list_scopes().await.unwrap();
-        Ok(warp::reply::json(&streams))
+        let scopes = db.list_scopes().await.map(|mut scopes| {
+            scopes.scopes.retain(|scope| access.can_access_scope(&scope.scope_name));
+            scopes
+        });
+        Ok(error::into_response(scopes.map(|scopes| warp::reply::json(&scopes))))
+    }
+
+    pub async fn get_stream_info(
+        scope_name: String,
+        stream_name: String,
+        db: Db,
+    ) -> Result<impl warp::Reply, Infallible> {
+        info!("get_stream_info: scope_name={}, stream_name={}", scope_name, stream_name);
+        let stream_info = db.get_stream_info(scope_name, stream_name).await;
+        Ok(error::into_response(stream_info.map(|stream_info| warp::reply::json(&stream_info))))
     }
 
     pub async fn list_video_streams(
         scope_name: String,
+        access: Access,
         db: Db,
     ) -> Result<impl warp::Reply, Infallible> {
         info!("list_video_streams: scope_name={}", scope_name);
-        let streams = db.list_video_streams(scope_name).await.unwrap();
-        Ok(warp::reply::json(&streams))
+        let streams = db.list_video_streams(scope_name).await.map(|mut streams| {
+            streams.streams.retain(|stream| access.can_access_stream(&stream.scope_name, &stream.stream_name));
+            streams
+        });
+        Ok(error::into_response(streams.map(|streams| warp::reply::json(&streams))))
     }
 }
 
 mod models {
-    use anyhow;
     use chrono::{DateTime, Utc};
-    use futures::{StreamExt, future};
-    use hyper::body::{Body, Bytes};
+    use futures::{SinkExt, StreamExt, future};
+    use futures::stream::SplitSink;
+    use hyper::body::{Body, Buf, Bytes};
     use pravega_client::client_factory::ClientFactoryAsync;
     use pravega_client_shared::{Scope, ScopedStream, Stream};
     use pravega_controller_client::paginator::{list_streams_for_tag, list_scopes};
-    use pravega_video::{event_serde::{EventReader}, index::IndexSearcher};
-    use pravega_video::index::{IndexRecord, IndexRecordReader, SearchMethod, get_index_stream_name};
+    use pravega_video::event_serde::EventReader;
+    use pravega_video::index::{IndexRecord, SearchMethod, get_index_stream_name};
+    use pravega_video::index_cache::IndexCache;
     use pravega_video::timestamp::PravegaTimestamp;
-    use pravega_video::utils::SyncByteReader;
+    use pravega_video::utils::{AsyncByteReader, current_head_async};
     use serde_derive::{Deserialize, Serialize};
-    use std::convert::Infallible;
-    use std::io::{ErrorKind, Read, Seek, SeekFrom};
+    use std::collections::HashMap;
+    use std::io::{ErrorKind, SeekFrom};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, Take};
+    use warp::ws::{Message, WebSocket};
     use super::*;
+    use super::auth::Access;
+    use super::cache::{SegmentCache, SegmentKey, TtlCache};
+    use super::dash::MpdConfig;
+    use super::error::ApiError;
+    use super::export::Exporter;
+    use super::ingest::{IngestJob, IngestStatus, Ingester};
+    use super::metrics::Metrics;
+    use super::sprite::{self, SpriteLayout};
+    use super::stream_info;
+    use super::thumbnail::{ImageFormat, KeyFrame, Thumbnailer};
+    use super::whep::{self, WhepAnswer, WhepServer};
+    use super::timeline::{self, DiscontinuityReason, Interval};
+    use super::ll_hls::{LowLatencyPlaylistConfig, LowLatencyPlaylist};
+    use super::mp4::{InitSegmentExtractor, InitSegmentFilter, MseSegment, MseSegmenter};
+    use super::payload::{self, PayloadMap, RangeRequest};
+
+    /// How often the index is read while waiting for a blocking playlist reload or a preload hint.
+    const LL_HLS_POLL_INTERVAL: Duration = Duration::from_millis(50);
+    /// Requests for a part in a preload hint will wait at most this long for the part to be written.
+    const LL_HLS_PART_TIMEOUT: Duration = Duration::from_secs(10);
+    /// How often the data stream is checked for new events while a WebSocket client is at the tail.
+    const WEBSOCKET_POLL_INTERVAL: Duration = Duration::from_millis(50);
+    /// WebSocket clients are sent media at most this far ahead of real time
+    /// so that historical playback does not exceed the buffer of the player.
+    const WEBSOCKET_MAX_LEAD: Duration = Duration::from_secs(5);
+    /// Streams that exist are checked with the controller again after this long,
+    /// so that deleted streams are eventually reported as not found.
+    const KNOWN_STREAMS_TTL: Duration = Duration::from_secs(60);
+
+    /// The maximum number of payload maps. The least recently used map is evicted when there are more.
+    /// Each map has at most payload::WINDOW_RECORDS + 1 points.
+    const PAYLOAD_MAPS_CAPACITY: usize = 1000;
+
+    struct PayloadMapEntry {
+        map: Arc<tokio::sync::Mutex<PayloadMap>>,
+        last_used: Instant,
+    }
+
+    /// Payload maps for each stream and anchor.
+    type PayloadMaps = HashMap<(ScopedStream, u64), PayloadMapEntry>;
 
     #[derive(Clone)]
     pub struct Db {
         pub client_factory: ClientFactoryAsync,
+        /// Index records shared by all requests.
+        pub index_cache: IndexCache<AsyncByteReader>,
+        /// The default HLS version of playlists.
+        pub hls_version: u32,
+        /// The number of index records in each segment of Low-Latency HLS playlists.
+        pub ll_hls_parts_per_segment: u64,
+        /// The default for whether playlists use EXT-X-BYTERANGE.
+        pub hls_byte_range: bool,
+        /// Streams that are known to exist. Streams are checked with the controller at most once per KNOWN_STREAMS_TTL.
+        known_streams: TtlCache<ScopedStream, ()>,
+        /// Whether each stream contains fragmented MP4, determined from the first event.
+        mp4_streams: Arc<Mutex<HashMap<ScopedStream, bool>>>,
+        /// Payload maps for each stream and anchor. These are extended as the stream grows.
+        payload_maps: Arc<Mutex<PayloadMaps>>,
+        /// Media segments shared by all clients. Segments are removed when the stream is truncated.
+        segment_cache: SegmentCache<ScopedStream>,
+        /// Live playlists, keyed by the request.
+        playlist_cache: TtlCache<String, String>,
+        /// None if export is not available because GStreamer could not be initialized.
+        exporter: Option<Exporter>,
+        /// None if thumbnails are not available because GStreamer could not be initialized.
+        thumbnailer: Option<Thumbnailer>,
+        /// None if ingest is not available because GStreamer could not be initialized.
+        ingester: Option<Ingester>,
+        /// None if WebRTC playback is not available because GStreamer could not be initialized.
+        whep_server: Option<WhepServer>,
+        metrics: Metrics,
+    }
+
+    #[derive(Debug)]
+    pub struct CacheConfig {
+        /// The maximum total bytes of cached media segments.
+        pub segment_cache_size: usize,
+        /// How long live playlists are cached.
+        pub playlist_cache_ttl: Duration,
+        /// The maximum number of streams whose index records are cached.
+        pub index_cache_streams: usize,
+        /// The maximum number of index records cached for each stream.
+        pub index_cache_records: usize,
     }
 
-    pub fn new(client_factory: ClientFactoryAsync) -> Db {
-        Db { client_factory }
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        client_factory: ClientFactoryAsync,
+        hls_version: u32,
+        ll_hls_parts_per_segment: u64,
+        hls_byte_range: bool,
+        cache_config: CacheConfig,
+        exporter: Option<Exporter>,
+        thumbnailer: Option<Thumbnailer>,
+        ingester: Option<Ingester>,
+        whep_server: Option<WhepServer>,
+        metrics: Metrics,
+    ) -> Db {
+        // Concurrent playlist requests for the same stream will read the index at most once per interval.
+        let index_cache = IndexCache::with_client_factory_async(client_factory.clone(), Duration::from_millis(500),
+            cache_config.index_cache_streams).with_max_records(cache_config.index_cache_records);
+        Db {
+            client_factory,
+            index_cache,
+            hls_version,
+            ll_hls_parts_per_segment,
+            hls_byte_range,
+            known_streams: TtlCache::new(KNOWN_STREAMS_TTL),
+            mp4_streams: Arc::new(Mutex::new(HashMap::new())),
+            payload_maps: Arc::new(Mutex::new(HashMap::new())),
+            segment_cache: SegmentCache::new(cache_config.segment_cache_size),
+            playlist_cache: TtlCache::new(cache_config.playlist_cache_ttl),
+            exporter,
+            thumbnailer,
+            ingester,
+            whep_server,
+            metrics,
+        }
     }
 
     // The query parameters for get_media_segment.
@@ -264,6 +1098,50 @@ mod models {
         pub begin: u64,
         /// End byte offset (exclusive)
         pub end: u64,
+        /// If true, remove ftyp and moov boxes from fragmented MP4.
+        /// This is used when the initialization segment is provided by EXT-X-MAP.
+        pub strip_init: Option<bool>,
+    }
+
+    impl GetMediaSegmentOptions {
+        /// The entity tag of the media segment.
+        /// The bytes in a range of a data stream never change so this depends only on the options.
+        pub fn etag(&self) -> String {
+            let suffix = if self.strip_init.unwrap_or_default() { "-s" } else { "" };
+            format!("\"{}-{}{}\"", self.begin, self.end, suffix)
+        }
+    }
+
+    // The query parameters for get_init_segment.
+    #[derive(Debug, Deserialize)]
+    pub struct GetInitSegmentOptions {
+        /// Byte offset of a fragment that begins with an initialization segment
+        pub begin: u64,
+    }
+
+    // The query parameters for get_part.
+    #[derive(Debug, Deserialize)]
+    pub struct GetPartOptions {
+        /// Begin byte offset, which must be the offset of an index record
+        pub begin: u64,
+        /// If true, remove ftyp and moov boxes from fragmented MP4.
+        pub strip_init: Option<bool>,
+    }
+
+    // The query parameters for get_websocket.
+    #[derive(Debug, Deserialize)]
+    pub struct GetWebSocketOptions {
+        /// If set, playback begins at the key frame on or before this time.
+        /// Otherwise, playback begins at the latest key frame.
+        pub begin: Option<DateTime<Utc>>,
+    }
+
+    // The query parameters for post_whep.
+    #[derive(Debug, Deserialize)]
+    pub struct PostWhepOptions {
+        /// If set, playback begins at the key frame on or before this time.
+        /// Otherwise, playback begins at the latest key frame.
+        pub begin: Option<DateTime<Utc>>,
     }
 
     // The query parameters for get_m3u8_playlist.
@@ -271,23 +1149,197 @@ mod models {
     pub struct GetM3u8PlaylistOptions {
         pub begin: Option<DateTime<Utc>>,
         pub end: Option<DateTime<Utc>>,
+        /// HLS version. If not specified, the default for the server is used.
+        pub version: Option<u32>,
+        /// If true, return a Low-Latency HLS playlist of the live edge of the stream.
+        /// This is ignored if end is specified or the version is less than 7.
+        pub low_latency: Option<bool>,
+        /// Blocking playlist reload: wait until the playlist contains this Media Sequence Number.
+        #[serde(rename = "_HLS_msn")]
+        pub hls_msn: Option<u64>,
+        /// Blocking playlist reload: wait until the playlist contains this part of the segment identified by _HLS_msn.
+        #[serde(rename = "_HLS_part")]
+        pub hls_part: Option<u64>,
+        /// If true, identify segments with EXT-X-BYTERANGE as byte ranges of the payload resource.
+        /// If not specified, the default for the server is used. This is ignored for Low-Latency HLS playlists.
+        pub byte_range: Option<bool>,
     }
 
-    #[derive(Debug, Deserialize, Serialize, Clone)]
-    pub struct ListScopesResult {
-        pub scopes: Vec<ListScopesRecord>,
+    // The query parameters for get_mpd.
+    #[derive(Debug, Deserialize)]
+    pub struct GetMpdOptions {
+        pub begin: Option<DateTime<Utc>>,
+        pub end: Option<DateTime<Utc>>,
     }
 
-    #[derive(Debug, Deserialize, Serialize, Clone)]
-    pub struct ListStreamsResult {
-        pub streams: Vec<ListStreamsRecord>,
+    // The query parameters for get_export.
+    #[derive(Debug, Deserialize)]
+    pub struct GetExportOptions {
+        pub begin: DateTime<Utc>,
+        pub end: DateTime<Utc>,
     }
 
+    // The query parameters for post_ingest.
+    #[derive(Debug, Deserialize)]
+    pub struct PostIngestOptions {
+        /// The timestamp of the first frame of the uploaded file.
+        #[serde(rename = "start-utc")]
+        pub start_utc: DateTime<Utc>,
+    }
 
-    #[derive(Debug, Deserialize, Serialize, Clone)]
-    pub struct ListScopesRecord {
-        #[serde(rename = "scopeName")]
-        pub scope_name: String
+    // The query parameters for get_thumbnail.
+    #[derive(Debug, Deserialize)]
+    pub struct GetThumbnailOptions {
+        pub timestamp: DateTime<Utc>,
+        /// If only one of width or height is specified, the aspect ratio will be preserved.
+        pub width: Option<u32>,
+        pub height: Option<u32>,
+    }
+
+    // The query parameters for get_thumbnail_track.
+    #[derive(Debug, Deserialize)]
+    pub struct GetThumbnailTrackOptions {
+        pub begin: DateTime<Utc>,
+        pub end: DateTime<Utc>,
+        /// Seconds between thumbnails. The default is 10.
+        pub interval: Option<f64>,
+        /// Width of each thumbnail. The default is 160.
+        pub width: Option<u32>,
+        /// Height of each thumbnail. The default is 90.
+        pub height: Option<u32>,
+    }
+
+    // The query parameters for get_sprite. These are the same as get_thumbnail_track with the addition of the sheet number.
+    #[derive(Debug, Deserialize)]
+    pub struct GetSpriteOptions {
+        pub begin: DateTime<Utc>,
+        pub end: DateTime<Utc>,
+        pub interval: Option<f64>,
+        pub width: Option<u32>,
+        pub height: Option<u32>,
+        pub sheet: usize,
+    }
+
+    // The query parameters for get_timeline.
+    #[derive(Debug, Deserialize)]
+    pub struct GetTimelineOptions {
+        pub begin: DateTime<Utc>,
+        pub end: DateTime<Utc>,
+        /// Recorded intervals separated by less than this many seconds are merged.
+        /// The default and minimum is the duration divided by MAX_TIMELINE_INTERVALS.
+        pub resolution: Option<f64>,
+    }
+
+    // The body of get_timelines.
+    #[derive(Debug, Deserialize)]
+    pub struct GetTimelinesRequest {
+        pub streams: Vec<ListStreamsRecord>,
+        pub begin: DateTime<Utc>,
+        pub end: DateTime<Utc>,
+        pub resolution: Option<f64>,
+    }
+
+    /// The tiles of a thumbnail track.
+    struct ThumbnailTrack {
+        begin_timestamp: PravegaTimestamp,
+        duration_nanos: u64,
+        interval_nanos: u64,
+        tile_width: u32,
+        tile_height: u32,
+    }
+
+    impl ThumbnailTrack {
+        fn new(
+            begin: DateTime<Utc>,
+            end: DateTime<Utc>,
+            interval: Option<f64>,
+            width: Option<u32>,
+            height: Option<u32>,
+        ) -> Result<Self, ApiError> {
+            let interval_seconds = interval.unwrap_or(10.0);
+            if interval_seconds.is_nan() || interval_seconds < 0.001 {
+                return Err(ApiError::BadRequest("interval must be at least 0.001".to_owned()));
+            }
+            let (tile_width, tile_height) = (width.unwrap_or(160), height.unwrap_or(90));
+            if tile_width == 0 || tile_height == 0 || tile_width > 1920 || tile_height > 1080 {
+                return Err(ApiError::BadRequest("width and height must be between 1 and 1920x1080".to_owned()));
+            }
+            let begin_timestamp = PravegaTimestamp::from(Some(begin));
+            let end_timestamp = PravegaTimestamp::from(Some(end));
+            let duration_nanos = match (begin_timestamp.nanoseconds(), end_timestamp.nanoseconds()) {
+                (Some(begin_nanos), Some(end_nanos)) if end_nanos > begin_nanos => end_nanos - begin_nanos,
+                _ => return Err(ApiError::InvalidTimeRange("begin must be before end".to_owned())),
+            };
+            let track = ThumbnailTrack {
+                begin_timestamp,
+                duration_nanos,
+                interval_nanos: (interval_seconds * 1e9) as u64,
+                tile_width,
+                tile_height,
+            };
+            if track.tile_count() > MAX_THUMBNAIL_TRACK_TILES {
+                return Err(ApiError::BadRequest(format!("The thumbnail track would have more than {} thumbnails; increase the interval",
+                    MAX_THUMBNAIL_TRACK_TILES)));
+            }
+            Ok(track)
+        }
+
+        fn tile_count(&self) -> usize {
+            sprite::tile_count(self.duration_nanos, self.interval_nanos)
+        }
+    }
+
+    /// The time range and resolution of a timeline.
+    struct TimelineRange {
+        begin_timestamp: PravegaTimestamp,
+        end_timestamp: PravegaTimestamp,
+        resolution_nanos: u64,
+    }
+
+    impl TimelineRange {
+        fn new(begin: DateTime<Utc>, end: DateTime<Utc>, resolution: Option<f64>) -> Result<Self, ApiError> {
+            let resolution_seconds = resolution.unwrap_or(0.0);
+            if resolution_seconds.is_nan() || resolution_seconds < 0.0 {
+                return Err(ApiError::BadRequest("resolution must not be negative".to_owned()));
+            }
+            let begin_timestamp = PravegaTimestamp::from(Some(begin));
+            let end_timestamp = PravegaTimestamp::from(Some(end));
+            let duration_nanos = match (begin_timestamp.nanoseconds(), end_timestamp.nanoseconds()) {
+                (Some(begin_nanos), Some(end_nanos)) if end_nanos > begin_nanos => end_nanos - begin_nanos,
+                _ => return Err(ApiError::InvalidTimeRange("begin must be before end".to_owned())),
+            };
+            // This bounds the number of intervals, gaps, and discontinuities.
+            let min_resolution_nanos = duration_nanos / MAX_TIMELINE_INTERVALS;
+            let resolution_nanos = ((resolution_seconds * 1e9) as u64).max(min_resolution_nanos);
+            Ok(TimelineRange { begin_timestamp, end_timestamp, resolution_nanos })
+        }
+    }
+
+    /// Index records between two timestamps.
+    struct IndexRange {
+        index_records: Vec<(IndexRecord, u64)>,
+        /// The index offset of the first record.
+        index_begin_offset: u64,
+        record_size: u64,
+        /// True if future appends to the index will not change the records in the range.
+        have_all_data: bool,
+    }
+
+    #[derive(Debug, Deserialize, Serialize, Clone)]
+    pub struct ListScopesResult {
+        pub scopes: Vec<ListScopesRecord>,
+    }
+
+    #[derive(Debug, Deserialize, Serialize, Clone)]
+    pub struct ListStreamsResult {
+        pub streams: Vec<ListStreamsRecord>,
+    }
+
+
+    #[derive(Debug, Deserialize, Serialize, Clone)]
+    pub struct ListScopesRecord {
+        #[serde(rename = "scopeName")]
+        pub scope_name: String
     }
 
     #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -298,258 +1350,1634 @@ mod models {
         pub stream_name: String,
     }
 
+    #[derive(Debug, Serialize, Clone)]
+    #[serde(rename_all = "camelCase")]
+    pub struct ByteStreamInfo {
+        pub head_offset: u64,
+        pub tail_offset: u64,
+    }
+
+    #[derive(Debug, Serialize, Clone)]
+    #[serde(rename_all = "camelCase")]
+    pub struct StreamInfo {
+        pub scope_name: String,
+        pub stream_name: String,
+        /// Timestamps are None if the index is empty.
+        pub first_timestamp: Option<String>,
+        pub last_timestamp: Option<String>,
+        pub duration_seconds: Option<f64>,
+        pub data: ByteStreamInfo,
+        pub index: ByteStreamInfo,
+        /// Bytes between the head and tail of the data and index streams.
+        pub retained_bytes: u64,
+        pub index_record_count: usize,
+        /// Bits per second, averaged over the entire stream including gaps.
+        pub average_bitrate: Option<f64>,
+        pub key_frame_interval_seconds: Option<f64>,
+        /// None if the controller could not be queried.
+        pub sealed: Option<bool>,
+        /// The content type of media segments, determined from the first event.
+        pub content_type: Option<String>,
+        /// The codecs parameter (RFC 6381) from the initialization segment of fragmented MP4.
+        pub codecs: Option<String>,
+    }
+
+    #[derive(Debug, Serialize, Clone)]
+    pub struct TimelineInterval {
+        pub begin: String,
+        pub end: String,
+    }
+
+    #[derive(Debug, Serialize, Clone)]
+    pub struct TimelineDiscontinuity {
+        pub timestamp: String,
+        pub reason: DiscontinuityReason,
+    }
+
+    #[derive(Debug, Serialize, Clone)]
+    pub struct TimelineResult {
+        #[serde(rename = "scopeName")]
+        pub scope_name: String,
+        #[serde(rename = "streamName")]
+        pub stream_name: String,
+        pub begin: String,
+        pub end: String,
+        #[serde(rename = "resolutionSeconds")]
+        pub resolution_seconds: f64,
+        pub intervals: Vec<TimelineInterval>,
+        pub gaps: Vec<TimelineInterval>,
+        pub discontinuities: Vec<TimelineDiscontinuity>,
+        /// If the timeline of this stream could not be determined, the other lists are empty.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub error: Option<String>,
+    }
+
+    #[derive(Debug, Serialize, Clone)]
+    pub struct TimelinesResult {
+        pub timelines: Vec<TimelineResult>,
+    }
+
+    /// The text message sent before each initialization segment on a WebSocket.
+    /// The MIME type can be passed to MediaSource.addSourceBuffer.
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct WebSocketInitMessage {
+        mime_type: String,
+    }
+
+    /// Maximum number of bytes of event payloads that will be combined into a single chunk of a media segment.
+    /// MPEG TS is written with one 188-byte packet per event so sending each payload separately would be inefficient.
+    const MEDIA_CHUNK_SIZE: usize = 64 * 1024;
+
+    /// Maximum number of events to read when searching for an initialization segment.
+    const MAX_INIT_SEGMENT_EVENTS: usize = 16;
+
+    /// A thumbnail will not be produced from a key frame this long before the requested time,
+    /// since there is probably a gap in the recording.
+    const MAX_KEY_FRAME_AGE: Duration = Duration::from_secs(30);
+
+    /// Maximum number of thumbnails in a thumbnail track.
+    const MAX_THUMBNAIL_TRACK_TILES: usize = 1000;
+
+    /// A timeline will have at most approximately this many intervals.
+    const MAX_TIMELINE_INTERVALS: u64 = 1000;
+
+    /// Maximum number of streams in a request for timelines.
+    const MAX_TIMELINES_STREAMS: usize = 100;
+
+    /// Writes an uploaded file to the temporary file of the ingest job.
+    async fn receive_file<S, B>(job: &IngestJob, body: S, max_file_size: u64) -> Result<(), ApiError>
+    where
+        S: futures::Stream<Item = Result<B, warp::Error>>,
+        B: Buf,
+    {
+        let file_error = |err: std::io::Error| ApiError::Internal(format!("Unable to write {}: {}", job.path.display(), err));
+        let mut file = tokio::fs::File::create(&job.path).await.map_err(file_error)?;
+        futures::pin_mut!(body);
+        let mut bytes_received = 0;
+        while let Some(buf) = body.next().await {
+            let mut buf = buf.map_err(|err| ApiError::BadRequest(format!("Unable to receive the file: {}", err)))?;
+            while buf.has_remaining() {
+                let chunk = buf.chunk();
+                let len = chunk.len();
+                bytes_received += len as u64;
+                if bytes_received > max_file_size {
+                    return Err(ApiError::PayloadTooLarge(format!("The file is larger than {} bytes", max_file_size)));
+                }
+                file.write_all(chunk).await.map_err(file_error)?;
+                buf.advance(len);
+            }
+            job.set_bytes_received(bytes_received);
+        }
+        file.flush().await.map_err(file_error)?;
+        if bytes_received == 0 {
+            return Err(ApiError::BadRequest("The request has no file".to_owned()));
+        }
+        info!("receive_file: Received {} bytes", bytes_received);
+        Ok(())
+    }
+
+    /// Returns true if the name can be used for a new Pravega stream.
+    fn is_valid_stream_name(name: &str) -> bool {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+    }
+
+    /// Returns true if a playlist with the end timestamp may change as the stream is written.
+    fn is_live(end: Option<DateTime<Utc>>) -> bool {
+        match end {
+            Some(end) => end > Utc::now(),
+            None => true,
+        }
+    }
+
+    /// Number of timelines in a request for timelines that are built at the same time.
+    const TIMELINES_CONCURRENCY: usize = 8;
+
+    /// Reads the payloads of the events in a byte range of a data stream.
+    struct EventPayloadReader {
+        reader: Take<AsyncByteReader>,
+        /// Reused for each event.
+        read_buffer: Vec<u8>,
+        /// The data stream offset of the next event.
+        offset: u64,
+    }
+
+    impl EventPayloadReader {
+        async fn open(client_factory: &ClientFactoryAsync, scoped_stream: ScopedStream, begin: u64, limit: u64) -> Result<Self, std::io::Error> {
+            let reader = client_factory.create_byte_reader(scoped_stream).await;
+            let mut reader = AsyncByteReader::new(reader);
+            reader.seek(SeekFrom::Start(begin)).await?;
+            Ok(Self {
+                reader: reader.take(limit),
+                read_buffer: Vec::new(),
+                offset: begin,
+            })
+        }
+
+        /// The data stream offset of the next event.
+        fn offset(&self) -> u64 {
+            self.offset
+        }
+
+        /// Extend the range to the current tail of the data stream.
+        /// This also discards any partial event that was read at the previous tail.
+        /// Returns true if there is new data to read.
+        async fn extend_to_tail(&mut self) -> Result<bool, std::io::Error> {
+            let reader = self.reader.get_mut();
+            let tail_offset = reader.seek(SeekFrom::End(0)).await?;
+            reader.seek(SeekFrom::Start(self.offset)).await?;
+            let limit = tail_offset.saturating_sub(self.offset);
+            self.reader.set_limit(limit);
+            Ok(limit > 0)
+        }
+
+        /// Read the next event and return its payload.
+        /// Returns None if there are no more events in the range.
+        async fn read_payload(&mut self) -> Result<Option<&[u8]>, std::io::Error> {
+            let reader = &mut self.reader;
+            let mut event_reader = EventReader::new();
+            let required_buffer_length =
+                match event_reader.read_required_buffer_length_async(reader).await {
+                    Ok(n) => n,
+                    Err(e) if e.kind() == ErrorKind::UnexpectedEof && reader.limit() == 0 => return Ok(None),
+                    Err(e) => return Err(e),
+            };
+            self.read_buffer.resize(required_buffer_length, 0);
+            let event = match event_reader.read_event_async(reader, &mut self.read_buffer[..]).await {
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof && reader.limit() == 0 => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            trace!("event={:?}", event.header);
+            self.offset += event.encoded_length() as u64;
+            Ok(Some(event.payload))
+        }
+    }
+
+    /// Reads the events in a byte range of a data stream and produces chunks of the media segment.
+    struct MediaSegmentReader {
+        reader: EventPayloadReader,
+        /// If set, the initialization segment will be removed from fragmented MP4.
+        init_filter: Option<InitSegmentFilter>,
+        /// The number of payload bytes to skip before the first byte sent.
+        skip: u64,
+        /// If set, the number of payload bytes remaining to be sent.
+        remaining: Option<u64>,
+        chunk_count: u64,
+        finished: bool,
+        /// Observes the time to read each chunk from Pravega.
+        read_duration: prometheus::Histogram,
+        /// If set, the chunks are collected and inserted into the segment cache when the segment has been completely read.
+        cache_insert: Option<SegmentCacheInsert>,
+    }
+
+    struct SegmentCacheInsert {
+        cache: SegmentCache<ScopedStream>,
+        key: SegmentKey<ScopedStream>,
+        data: Vec<u8>,
+    }
+
+    impl MediaSegmentReader {
+        /// Returns the next chunk containing the payloads of one or more events,
+        /// or None if the end of the range has been reached.
+        async fn next_chunk(mut self) -> Result<Option<(Bytes, Self)>, std::io::Error> {
+            let timer = self.read_duration.start_timer();
+            let mut chunk: Vec<u8> = Vec::new();
+            while !self.finished && chunk.len() < MEDIA_CHUNK_SIZE {
+                match self.reader.read_payload().await? {
+                    Some(payload) => {
+                        let skip = self.skip.min(payload.len() as u64);
+                        self.skip -= skip;
+                        let payload = &payload[skip as usize..];
+                        let payload = match self.remaining {
+                            Some(remaining) => {
+                                let len = remaining.min(payload.len() as u64);
+                                self.remaining = Some(remaining - len);
+                                &payload[..len as usize]
+                            },
+                            None => payload,
+                        };
+                        match &mut self.init_filter {
+                            Some(init_filter) => init_filter.filter(payload, &mut chunk)?,
+                            None => chunk.extend_from_slice(payload),
+                        }
+                        if self.remaining == Some(0) {
+                            trace!("Reached requested length");
+                            self.finished = true;
+                            info!("get_media_segment: Sent {} chunks", self.chunk_count + if chunk.is_empty() { 0 } else { 1 });
+                        }
+                    },
+                    None => {
+                        trace!("Reached requested end");
+                        self.finished = true;
+                        info!("get_media_segment: Sent {} chunks", self.chunk_count + if chunk.is_empty() { 0 } else { 1 });
+                    },
+                }
+            }
+            if let Some(cache_insert) = &mut self.cache_insert {
+                cache_insert.data.extend_from_slice(&chunk);
+                // Stop collecting a segment that is too large to be cached.
+                if cache_insert.data.len() > cache_insert.cache.max_entry_size() {
+                    self.cache_insert = None;
+                }
+            }
+            if self.finished {
+                if let Some(cache_insert) = self.cache_insert.take() {
+                    cache_insert.cache.insert(cache_insert.key, Bytes::from(cache_insert.data));
+                }
+            }
+            if chunk.is_empty() {
+                timer.stop_and_discard();
+                return Ok(None);
+            }
+            timer.observe_duration();
+            self.chunk_count += 1;
+            Ok(Some((Bytes::from(chunk), self)))
+        }
+    }
+
+    impl Drop for MediaSegmentReader {
+        fn drop(&mut self) {
+            if !self.finished {
+                info!("get_media_segment: Stopped reading after {} chunks because the client disconnected or an error occurred",
+                    self.chunk_count);
+            }
+        }
+    }
+
     impl Db {
+        /// Returns the scoped stream if it exists.
+        /// If it does not, the error identifies whether the scope or only the stream does not exist.
+        async fn get_scoped_stream(&self, scope_name: String, stream_name: String) -> Result<ScopedStream, ApiError> {
+            let scoped_stream = ScopedStream {
+                scope: Scope::from(scope_name),
+                stream: Stream::from(stream_name),
+            };
+            if self.known_streams.get(&scoped_stream).is_some() {
+                return Ok(scoped_stream);
+            }
+            let controller_client = self.client_factory.controller_client();
+            let stream_exists = controller_client.check_stream_exists(&scoped_stream).await
+                .map_err(|err| ApiError::PravegaUnavailable(format!("Unable to check whether stream {} exists: {:?}", scoped_stream, err)))?;
+            if !stream_exists {
+                let scope_exists = controller_client.check_scope_exists(&scoped_stream.scope).await
+                    .map_err(|err| ApiError::PravegaUnavailable(format!("Unable to check whether scope {} exists: {:?}", scoped_stream.scope.name, err)))?;
+                return Err(if scope_exists {
+                    ApiError::StreamNotFound(scoped_stream.to_string())
+                } else {
+                    ApiError::ScopeNotFound(scoped_stream.scope.name)
+                });
+            }
+            self.known_streams.insert(scoped_stream.clone(), ());
+            Ok(scoped_stream)
+        }
+
+        /// Returns an error if the byte offset is not the offset of an index record.
+        /// Index records are always at event boundaries so a range between them contains only complete events.
+        async fn check_event_boundary(&self, scoped_stream: &ScopedStream, offset: u64) -> Result<(), ApiError> {
+            let _timer = self.metrics.index_search_duration("event_boundary").start_timer();
+            let index_error = |err| ApiError::from_index_error(scoped_stream, err);
+            let cached_index = self.index_cache.get(scoped_stream).map_err(index_error)?;
+            let mut cached_index = cached_index.lock().await;
+            let first_index_record = cached_index.get_first_record_async().await.map_err(index_error)?;
+            if offset == first_index_record.offset {
+                return Ok(());
+            }
+            if offset > first_index_record.offset {
+                let is_record = |next_index_record: Option<(IndexRecord, u64)>|
+                    matches!(next_index_record, Some((index_record, _)) if index_record.offset == offset);
+                if is_record(cached_index.search_offset_after_async(offset - 1).await.map_err(index_error)?) {
+                    return Ok(());
+                }
+                // The index record may have been written since the cached index was last refreshed.
+                cached_index.refresh_now_async().await.map_err(index_error)?;
+                if is_record(cached_index.search_offset_after_async(offset - 1).await.map_err(index_error)?) {
+                    return Ok(());
+                }
+            }
+            Err(ApiError::NotEventBoundary(offset))
+        }
+
         pub async fn get_media_segment(
             self,
             scope_name: String,
             stream_name: String,
             opts: GetMediaSegmentOptions,
-        ) -> Result<impl warp::Reply, Infallible> {
+        ) -> Result<warp::reply::Response, ApiError> {
             info!("get_media_segment: scope_name={}, stream_name={}, begin={}, end={}", scope_name, stream_name, opts.begin, opts.end);
-            assert!(opts.begin <= opts.end);
+            if opts.begin > opts.end {
+                return Err(ApiError::BadRequest("begin must not be after end".to_owned()));
+            }
+            let scoped_stream = self.get_scoped_stream(scope_name, stream_name).await?;
+            let key = SegmentKey {
+                stream: scoped_stream.clone(),
+                begin: opts.begin,
+                end: opts.end,
+                strip_init: opts.strip_init.unwrap_or_default(),
+            };
+            // Cached segments are valid until the stream is truncated past them.
+            let head_offset = async {
+                let cached_index = self.index_cache.get(&scoped_stream)?;
+                let mut cached_index = cached_index.lock().await;
+                cached_index.get_first_record_async().await
+            }.await.map_err(|err| ApiError::from_index_error(&scoped_stream, err))?.offset;
+            self.segment_cache.invalidate_before(&scoped_stream, head_offset);
+            let cached_segment = self.segment_cache.get(&key);
+            self.metrics.cache_request("segment", cached_segment.is_some());
+            let body = match cached_segment {
+                Some(data) => {
+                    info!("get_media_segment: Found in cache");
+                    Body::from(data)
+                },
+                None => {
+                    self.check_event_boundary(&scoped_stream, opts.begin).await?;
+                    self.check_event_boundary(&scoped_stream, opts.end).await?;
+                    let timer = self.metrics.pravega_read_duration("open").start_timer();
+                    let reader = EventPayloadReader::open(&self.client_factory, scoped_stream, opts.begin, opts.end - opts.begin).await?;
+                    timer.observe_duration();
+                    info!("get_media_segment: Opened Pravega reader");
+                    let segment_reader = MediaSegmentReader {
+                        reader,
+                        init_filter: if key.strip_init { Some(InitSegmentFilter::new()) } else { None },
+                        skip: 0,
+                        remaining: None,
+                        chunk_count: 0,
+                        finished: false,
+                        read_duration: self.metrics.pravega_read_duration("chunk"),
+                        cache_insert: Some(SegmentCacheInsert {
+                            cache: self.segment_cache.clone(),
+                            key,
+                            data: Vec::new(),
+                        }),
+                    };
+                    // The body is produced as events are read. Hyper polls the stream only when the client
+                    // is ready for more data, so a slow client will slow down reading from Pravega.
+                    // If the client disconnects, the stream is dropped and reading stops.
+                    let stream = futures::stream::try_unfold(segment_reader, MediaSegmentReader::next_chunk);
+                    Body::wrap_stream(stream)
+                },
+            };
+            // TODO: Get content type from Pravega stream tag. For now "video/mp4" appears to work for MP4 and MPEG TS.
+            // let content_type = "video/MP2T";
+            let content_type = "video/mp4";
+            let response = warp::http::Response::builder()
+                .header("content-type", content_type)
+                .header("etag", opts.etag())
+                // The bytes in a range of a data stream never change.
+                .header("cache-control", "public, max-age=31536000, immutable")
+                .body(body)?;
+            Ok(response)
+        }
 
-            // TODO: Provide chunks to the HTTP client as a stream instead of buffering the entire response.
+        /// Returns a copy of the payload map of the window of index records that contains the index offset.
+        /// The anchor is the first index record in the window that has not been truncated.
+        /// The map is extended to the first index record of the next window or, if there is none, the last index record.
+        async fn get_payload_map(&self, scoped_stream: &ScopedStream, index_offset: u64) -> Result<PayloadMap, std::io::Error> {
+            let (head_offset, index_records) = {
+                let cached_index = self.index_cache.get(scoped_stream)?;
+                let mut cached_index = cached_index.lock().await;
+                let record_size = cached_index.record_size_async().await?;
+                let window_size = payload::WINDOW_RECORDS * record_size;
+                let window_begin = index_offset / window_size * window_size;
+                let first_index_record = cached_index.get_first_record_async().await?;
+                let index_records = cached_index.get_index_records_in_range_async(
+                    window_begin, window_begin + window_size + record_size).await?;
+                (first_index_record.offset, index_records)
+            };
+            let anchor = match index_records.first() {
+                Some((index_record, _)) => index_record.offset,
+                None => return Err(std::io::Error::new(ErrorKind::NotFound, "Index records have been truncated")),
+            };
+            let payload_map = {
+                let mut payload_maps = self.payload_maps.lock().unwrap();
+                // Anchors that have been truncated will not be requested again.
+                payload_maps.retain(|(s, a), _| s != scoped_stream || *a >= head_offset);
+                let entry = payload_maps.entry((scoped_stream.clone(), anchor))
+                    .or_insert_with(|| PayloadMapEntry {
+                        map: Arc::new(tokio::sync::Mutex::new(PayloadMap::new(anchor))),
+                        last_used: Instant::now(),
+                    });
+                entry.last_used = Instant::now();
+                let payload_map = entry.map.clone();
+                while payload_maps.len() > PAYLOAD_MAPS_CAPACITY {
+                    let least_recently_used = payload_maps.iter()
+                        .min_by_key(|(_, entry)| entry.last_used)
+                        .map(|(key, _)| key.clone());
+                    if let Some(key) = least_recently_used {
+                        payload_maps.remove(&key);
+                    }
+                }
+                payload_map
+            };
+            let mut locked_map = payload_map.lock().await;
+            let (last_data_offset, last_payload_offset) = locked_map.last_point();
+            let offsets: Vec<u64> = index_records.iter()
+                .map(|(index_record, _)| index_record.offset)
+                .filter(|offset| *offset > last_data_offset)
+                .collect();
+            let mut next_offsets = offsets.into_iter().peekable();
+            let end_offset = match index_records.last() {
+                Some((last_index_record, _)) if last_index_record.offset > last_data_offset => last_index_record.offset,
+                _ => return Ok(locked_map.clone()),
+            };
+            debug!("get_payload_map: Reading events from {} to {}", last_data_offset, end_offset);
+            let mut reader = EventPayloadReader::open(&self.client_factory, scoped_stream.clone(), last_data_offset, end_offset - last_data_offset).await?;
+            let mut payload_offset = last_payload_offset;
+            while let Some(payload) = reader.read_payload().await? {
+                payload_offset += payload.len() as u64;
+                let data_offset = reader.offset();
+                // Index records should always be at event boundaries. Any that are not cannot be mapped.
+                while let Some(offset) = next_offsets.next_if(|offset| *offset <= data_offset) {
+                    if offset == data_offset {
+                        locked_map.add_point(data_offset, payload_offset);
+                    } else {
+                        warn!("get_payload_map: Index record at offset {} is not at an event boundary", offset);
+                    }
+                }
+            }
+            Ok(locked_map.clone())
+        }
 
-            // Use spawn_blocking to allow Pravega non-async methods to block this thread.
-            // See https://stackoverflow.com/a/65452213/5890553.
+        /// Returns the payloads of the events from the anchor to the last index record.
+        /// A single byte range in the Range header will be returned with 206 Partial Content.
+        pub async fn get_payload(
+            self,
+            scope_name: String,
+            stream_name: String,
+            anchor: u64,
+            range: Option<String>,
+        ) -> Result<warp::reply::Response, ApiError> {
+            info!("get_payload: scope_name={}, stream_name={}, anchor={}, range={:?}", scope_name, stream_name, anchor, range);
+            let scoped_stream = self.get_scoped_stream(scope_name, stream_name).await?;
+            let index_error = |err| ApiError::from_index_error(&scoped_stream, err);
+            // The anchor must be the offset of the first index record in a window that has not been truncated.
+            let anchor_index_record = async {
+                let cached_index = self.index_cache.get(&scoped_stream)?;
+                let mut cached_index = cached_index.lock().await;
+                let first_index_record = cached_index.search_timestamp_and_return_index_offset_async(
+                    PravegaTimestamp::MIN, SearchMethod::Before).await?;
+                if anchor <= first_index_record.0.offset {
+                    Ok(Some(first_index_record))
+                } else {
+                    cached_index.search_offset_after_async(anchor - 1).await
+                }
+            }.await.map_err(index_error)?;
+            let payload_map = match anchor_index_record {
+                Some((index_record, index_offset)) if index_record.offset == anchor =>
+                    self.get_payload_map(&scoped_stream, index_offset).await.map_err(index_error)?,
+                _ => return Err(ApiError::NotFound("Anchor is not the offset of an index record".to_owned())),
+            };
+            if payload_map.anchor() != anchor {
+                return Err(ApiError::NotFound("Anchor is not the first index record of a payload window".to_owned()));
+            }
+            let (_, length) = payload_map.last_point();
+
+            let range = match range {
+                Some(range) => payload::parse_range(&range, length),
+                None => RangeRequest::Ignored,
+            };
+            debug!("get_payload: length={}, range={:?}", length, range);
+            let (begin, end) = match range {
+                RangeRequest::Satisfiable(begin, end) => (begin, end),
+                RangeRequest::NotSatisfiable => {
+                    let response = warp::http::Response::builder()
+                        .status(warp::http::StatusCode::RANGE_NOT_SATISFIABLE)
+                        .header("content-range", format!("bytes */{}", length))
+                        .body(Body::empty())?;
+                    return Ok(response);
+                },
+                RangeRequest::Ignored => (0, length),
+            };
+            let (begin_data_offset, begin_payload_offset) = payload_map.point_before(begin);
+            let (end_data_offset, _) = payload_map.point_at_or_after(end).unwrap_or_else(|| payload_map.last_point());
+
+            let reader = EventPayloadReader::open(&self.client_factory, scoped_stream, begin_data_offset, end_data_offset - begin_data_offset).await?;
+            let segment_reader = MediaSegmentReader {
+                reader,
+                init_filter: None,
+                skip: begin - begin_payload_offset,
+                remaining: Some(end - begin),
+                chunk_count: 0,
+                finished: end == begin,
+                read_duration: self.metrics.pravega_read_duration("chunk"),
+                cache_insert: None,
+            };
+            let stream = futures::stream::try_unfold(segment_reader, MediaSegmentReader::next_chunk);
+            let builder = warp::http::Response::builder()
+                .header("content-type", "video/mp4")
+                .header("content-length", end - begin)
+                .header("accept-ranges", "bytes");
+            // The length of the resource grows as events are written but the bytes in a range never change.
+            let builder = match range {
+                RangeRequest::Satisfiable(..) => builder
+                    .status(warp::http::StatusCode::PARTIAL_CONTENT)
+                    .header("content-range", format!("bytes {}-{}/*", begin, end - 1))
+                    .header("cache-control", "public, max-age=31536000, immutable"),
+                _ => builder
+                    .header("cache-control", "no-cache"),
+            };
+            Ok(builder.body(Body::wrap_stream(stream))?)
+        }
 
-            let chunks = self.client_factory.runtime_handle().spawn_blocking(move || {
-                let span = span!(Level::INFO, "get_media_segment: SPAWNED THREAD");
-                span.in_scope(|| {
-                    info!("BEGIN");
-                    let client_factory = self.client_factory;
-                    let scoped_stream = ScopedStream {
-                        scope: Scope::from(scope_name),
-                        stream: Stream::from(stream_name),
-                    };
-                    let reader = client_factory.runtime_handle().block_on(client_factory.create_byte_reader(scoped_stream));
-                    let mut reader = SyncByteReader::new(reader, client_factory.runtime_handle());
-                    info!("Opened Pravega reader");
-
-                    reader.seek(SeekFrom::Start(opts.begin)).unwrap();
-                    let limit = opts.end - opts.begin;
-                    let mut reader = reader.take(limit);
-
-                    let mut chunks: Vec<Result<Bytes, std::io::Error>> = Vec::new();
-
-                    loop {
-                        let mut event_reader = EventReader::new();
-                        let required_buffer_length =
-                            match event_reader.read_required_buffer_length(&mut reader) {
-                                Ok(n) => n,
-                                Err(e) if e.kind() == ErrorKind::UnexpectedEof && reader.limit() == 0 => {
-                                    trace!("Reached requested end");
-                                    break;
-                                },
-                                Err(e) => return Err(e),
+        /// Returns the initialization segment (ftyp and moov boxes) at the beginning of the fragment at a byte offset.
+        /// Returns NotFound if the fragment does not begin with an initialization segment.
+        pub async fn get_init_segment(
+            self,
+            scope_name: String,
+            stream_name: String,
+            opts: GetInitSegmentOptions,
+        ) -> Result<Vec<u8>, ApiError> {
+            info!("get_init_segment: scope_name={}, stream_name={}, begin={}", scope_name, stream_name, opts.begin);
+            let scoped_stream = self.get_scoped_stream(scope_name, stream_name).await?;
+            self.check_event_boundary(&scoped_stream, opts.begin).await?;
+            let init_segment = self.read_init_segment(scoped_stream, opts.begin).await?;
+            init_segment.ok_or_else(|| ApiError::NotFound("Initialization segment not found".to_owned()))
+        }
+
+        async fn read_init_segment(&self, scoped_stream: ScopedStream, begin: u64) -> Result<Option<Vec<u8>>, std::io::Error> {
+            let mut reader = EventPayloadReader::open(&self.client_factory, scoped_stream, begin, u64::MAX).await?;
+            let mut extractor = InitSegmentExtractor::new();
+            for _ in 0..MAX_INIT_SEGMENT_EVENTS {
+                match reader.read_payload().await? {
+                    Some(payload) => if extractor.push(payload)? { break },
+                    None => break,
+                }
+            }
+            Ok(extractor.into_init_segment())
+        }
+
+        /// Returns true if the stream contains fragmented MP4 instead of MPEG TS.
+        /// This is determined from the event at the byte offset and cached for the stream.
+        async fn is_mp4_stream(&self, scoped_stream: &ScopedStream, offset: u64) -> Result<bool, std::io::Error> {
+            if let Some(is_mp4) = self.mp4_streams.lock().unwrap().get(scoped_stream) {
+                return Ok(*is_mp4);
+            }
+            let mut reader = EventPayloadReader::open(&self.client_factory, scoped_stream.clone(), offset, u64::MAX).await?;
+            let is_mp4 = match reader.read_payload().await? {
+                Some(payload) => mp4::is_mp4(payload),
+                None => return Ok(false),
+            };
+            info!("is_mp4_stream: scoped_stream={}, is_mp4={}", scoped_stream, is_mp4);
+            self.mp4_streams.lock().unwrap().insert(scoped_stream.clone(), is_mp4);
+            Ok(is_mp4)
+        }
+
+        /// Returns the index record of the key frame where WebSocket playback will begin.
+        /// This is called before the connection is upgraded so that errors are returned as HTTP responses.
+        pub async fn get_websocket_start(
+            &self,
+            scope_name: String,
+            stream_name: String,
+            opts: GetWebSocketOptions,
+        ) -> Result<(ScopedStream, IndexRecord), ApiError> {
+            info!("get_websocket_start: scope_name={}, stream_name={}, opts={:?}", scope_name, stream_name, opts);
+            let scoped_stream = self.get_scoped_stream(scope_name, stream_name).await?;
+            let timestamp = PravegaTimestamp::from(opts.begin).or(PravegaTimestamp::MAX);
+            let index_record = async {
+                let _timer = self.metrics.index_search_duration("websocket_start").start_timer();
+                let cached_index = self.index_cache.get(&scoped_stream)?;
+                let mut cached_index = cached_index.lock().await;
+                let (index_record, _) = cached_index.search_timestamp_and_return_index_offset_async(timestamp, SearchMethod::Before).await?;
+                Ok(index_record)
+            }.await.map_err(|err| ApiError::from_index_error(&scoped_stream, err))?;
+            if !self.is_mp4_stream(&scoped_stream, index_record.offset).await? {
+                return Err(ApiError::BadRequest("WebSocket playback requires a stream of fragmented MP4".to_owned()));
+            }
+            Ok((scoped_stream, index_record))
+        }
+
+        /// Sends the fragmented MP4 of the stream to a Media Source Extensions player until the client disconnects.
+        /// Messages from the client are ignored.
+        pub async fn send_websocket(self, websocket: WebSocket, scoped_stream: ScopedStream, begin_index_record: IndexRecord) {
+            info!("send_websocket: scoped_stream={}, begin_index_record={:?}", scoped_stream, begin_index_record);
+            let websocket_connections = self.metrics.websocket_connections();
+            websocket_connections.inc();
+            let (sink, mut stream) = websocket.split();
+            // The stream must be polled for the close message to be answered.
+            let receive = async {
+                while let Some(Ok(message)) = stream.next().await {
+                    if message.is_close() {
+                        break;
+                    }
+                }
+            };
+            tokio::select! {
+                result = self.send_websocket_fragments(scoped_stream.clone(), begin_index_record, sink) => match result {
+                    Ok(()) => {},
+                    Err(err) => warn!("send_websocket: scoped_stream={}: {:?}", scoped_stream, err),
+                },
+                _ = receive => info!("send_websocket: scoped_stream={}: Client disconnected", scoped_stream),
+            }
+            websocket_connections.dec();
+        }
+
+        /// Returns the first index record after the offset, if it has been indexed.
+        async fn search_index_record_after(&self, scoped_stream: &ScopedStream, offset: u64) -> Result<Option<IndexRecord>, std::io::Error> {
+            let cached_index = self.index_cache.get(scoped_stream)?;
+            let mut cached_index = cached_index.lock().await;
+            let next_index_record = cached_index.search_offset_after_async(offset).await?;
+            Ok(next_index_record.map(|(index_record, _)| index_record))
+        }
+
+        /// Sends the initialization segments and media fragments, beginning at the key frame of the index record.
+        /// Each new initialization segment is preceded by a text message with its MIME type.
+        /// Media is sent at most WEBSOCKET_MAX_LEAD ahead of real time so that historical video plays at normal speed.
+        async fn send_websocket_fragments(
+            &self,
+            scoped_stream: ScopedStream,
+            begin_index_record: IndexRecord,
+            mut sink: SplitSink<WebSocket, Message>,
+        ) -> anyhow::Result<()> {
+            let mut reader = EventPayloadReader::open(&self.client_factory, scoped_stream.clone(), begin_index_record.offset, 0).await?;
+            let mut segmenter = MseSegmenter::new();
+            let mut segments = Vec::new();
+            let started = Instant::now();
+            // The offset of the last key frame used for pacing and the next key frame, if it has been indexed.
+            let mut paced_offset = begin_index_record.offset;
+            let mut next_index_record = None;
+            loop {
+                if !reader.extend_to_tail().await? {
+                    tokio::time::sleep(WEBSOCKET_POLL_INTERVAL).await;
+                    continue;
+                }
+                if next_index_record.is_none() {
+                    next_index_record = self.search_index_record_after(&scoped_stream, paced_offset).await?;
+                }
+                loop {
+                    if let Some(index_record) = next_index_record.as_ref().filter(|r| reader.offset() >= r.offset) {
+                        let media_time = match (begin_index_record.timestamp.nanoseconds(), index_record.timestamp.nanoseconds()) {
+                            (Some(begin_nanos), Some(nanos)) => Duration::from_nanos(nanos.saturating_sub(begin_nanos)),
+                            _ => Duration::from_secs(0),
                         };
-                        let mut read_buffer: Vec<u8> = vec![0; required_buffer_length];
-                        let event = match event_reader.read_event(&mut reader, &mut read_buffer[..]) {
-                            Ok(n) => n,
-                            Err(e) if e.kind() == ErrorKind::UnexpectedEof && reader.limit() == 0 => {
-                                trace!("Reached requested end");
-                                break;
+                        if let Some(wait) = media_time.checked_sub(started.elapsed() + WEBSOCKET_MAX_LEAD) {
+                            tokio::time::sleep(wait).await;
+                        }
+                        paced_offset = index_record.offset;
+                        next_index_record = self.search_index_record_after(&scoped_stream, paced_offset).await?;
+                    }
+                    match reader.read_payload().await? {
+                        Some(payload) => segmenter.push(payload, &mut segments)?,
+                        None => break,
+                    }
+                    for segment in segments.drain(..) {
+                        match segment {
+                            MseSegment::Init(init_segment) => {
+                                let mime_type = match mp4::codecs(&init_segment) {
+                                    Some(codecs) => format!("video/mp4; codecs=\"{}\"", codecs),
+                                    None => "video/mp4".to_owned(),
+                                };
+                                let message = serde_json::to_string(&WebSocketInitMessage { mime_type })?;
+                                sink.send(Message::text(message)).await?;
+                                sink.send(Message::binary(init_segment)).await?;
                             },
-                            Err(e) => return Err(e),
-                        };
-                        trace!("event={:?}", event);
-                        chunks.push(Ok(Bytes::copy_from_slice(&event.payload)));
+                            MseSegment::Media(media) => sink.send(Message::binary(media)).await?,
+                        }
                     }
-                    info!("Created {} chunks", chunks.len());
-                    assert!(reader.limit() == 0);
-                    info!("END");
-                    Ok(chunks)
-                })
-            })
-            .await
-            .unwrap()
-            .unwrap();
+                }
+            }
+        }
 
-            trace!("get_media_segment: spawn_blocking done");
-            let stream = futures_util::stream::iter(chunks);
-            let body = Body::wrap_stream(stream);
-            // TODO: Get content type from Pravega stream tag. For now "video/mp4" appears to work for MP4 and MPEG TS.
-            // let content_type = "video/MP2T";
-            let content_type = "video/mp4";
-            Ok(warp::reply::with_header(warp::reply::Response::new(body), "content-type", content_type))
+        /// Returns the index records that cover the time range.
+        async fn get_index_range(
+            &self,
+            scoped_stream: &ScopedStream,
+            begin_timestamp: PravegaTimestamp,
+            end_timestamp: PravegaTimestamp,
+        ) -> Result<IndexRange, ApiError> {
+            let _timer = self.metrics.index_search_duration("time_range").start_timer();
+            self.read_index_range(scoped_stream, begin_timestamp, end_timestamp).await
+                .map_err(|err| ApiError::from_index_error(scoped_stream, err))
         }
 
+        async fn read_index_range(
+            &self,
+            scoped_stream: &ScopedStream,
+            begin_timestamp: PravegaTimestamp,
+            end_timestamp: PravegaTimestamp,
+        ) -> Result<IndexRange, std::io::Error> {
+            let cached_index = self.index_cache.get(scoped_stream)?;
+            let mut cached_index = cached_index.lock().await;
+            info!("Opened cached index");
+
+            let begin_index_record = cached_index.search_timestamp_and_return_index_offset_async(
+                begin_timestamp, SearchMethod::After).await?;
+            let end_index_record = cached_index.search_timestamp_and_return_index_offset_async(
+                end_timestamp, SearchMethod::After).await?;
+            // Determine whether we can possibly get more data in the future.
+            // If the caller specified an end time and we already have an index record beyond this, then
+            // future appends will not affect our result.
+            // TODO: We can also guarantee this if the stream has been sealed.
+            let have_all_data = end_index_record.0.timestamp >= end_timestamp;
+            info!("begin_index_record={:?}, end_index_record={:?}, have_all_data={}",
+                    begin_index_record, end_index_record, have_all_data);
+            let record_size = cached_index.record_size_async().await?;
+
+            // Determine begin and end offsets of the index.
+            let index_begin_offset = begin_index_record.1;
+            let index_end_offset = end_index_record.1 + record_size;
+            info!("index_begin_offset={}, index_end_offset={}", index_begin_offset, index_end_offset);
+            let index_records = cached_index.get_index_records_in_range_async(index_begin_offset, index_end_offset).await?;
+            Ok(IndexRange { index_records, index_begin_offset, record_size, have_all_data })
+        }
+
+        /// Wait until the part that begins at the byte offset has been written and return the byte offset at its end.
+        /// Returns NotFound if the part is not written within LL_HLS_PART_TIMEOUT.
+        pub async fn wait_for_part_end(
+            self,
+            scope_name: String,
+            stream_name: String,
+            begin: u64,
+        ) -> Result<u64, ApiError> {
+            info!("wait_for_part_end: scope_name={}, stream_name={}, begin={}", scope_name, stream_name, begin);
+            let scoped_stream = self.get_scoped_stream(scope_name, stream_name).await?;
+            self.check_event_boundary(&scoped_stream, begin).await?;
+            let index_error = |err| ApiError::from_index_error(&scoped_stream, err);
+            let start = Instant::now();
+            let cached_index = self.index_cache.get(&scoped_stream).map_err(index_error)?;
+            loop {
+                let next_index_record = cached_index.lock().await.search_offset_after_async(begin).await.map_err(index_error)?;
+                if let Some((next_index_record, _)) = next_index_record {
+                    return Ok(next_index_record.offset);
+                }
+                if start.elapsed() >= LL_HLS_PART_TIMEOUT {
+                    warn!("wait_for_part_end: Timed out waiting for part at offset {}", begin);
+                    return Err(ApiError::NotFound("Part not available".to_owned()));
+                }
+                tokio::time::sleep(LL_HLS_POLL_INTERVAL).await;
+                cached_index.lock().await.refresh_now_async().await.map_err(index_error)?;
+            }
+        }
+
+        /// Returns a Low-Latency HLS playlist of the live edge of the stream.
+        /// If _HLS_msn is specified, this waits for up to three target durations until the playlist contains the requested part.
+        async fn get_ll_hls_playlist(
+            &self,
+            scoped_stream: &ScopedStream,
+            version: u32,
+            opts: &GetM3u8PlaylistOptions,
+        ) -> Result<String, ApiError> {
+            let index_error = |err| ApiError::from_index_error(scoped_stream, err);
+            let start = Instant::now();
+            let cached_index = self.index_cache.get(scoped_stream).map_err(index_error)?;
+            loop {
+                let timer = self.metrics.index_search_duration("live_edge").start_timer();
+                let mut locked_index = cached_index.lock().await;
+                let record_size = locked_index.record_size_async().await.map_err(index_error)?;
+                let (_, first_index_offset) = locked_index.search_timestamp_and_return_index_offset_async(
+                    PravegaTimestamp::MIN, SearchMethod::Before).await.map_err(index_error)?;
+                let (_, last_index_offset) = locked_index.search_timestamp_and_return_index_offset_async(
+                    PravegaTimestamp::MAX, SearchMethod::Before).await.map_err(index_error)?;
+                let first_record_number = ll_hls::first_record_number(
+                    first_index_offset / record_size, last_index_offset / record_size, self.ll_hls_parts_per_segment);
+                let index_records = locked_index.get_index_records_in_range_async(
+                    first_record_number * record_size, last_index_offset + record_size).await.map_err(index_error)?;
+                drop(locked_index);
+                timer.observe_duration();
+
+                let use_init_map = match index_records.first() {
+                    Some((first_index_record, _)) => self.is_mp4_stream(scoped_stream, first_index_record.offset).await?,
+                    None => false,
+                };
+                let config = LowLatencyPlaylistConfig {
+                    version,
+                    parts_per_segment: self.ll_hls_parts_per_segment,
+                    use_init_map,
+                };
+                let playlist: LowLatencyPlaylist = match ll_hls::build_playlist(&index_records, record_size, &config) {
+                    Some(playlist) => playlist,
+                    None => return Err(ApiError::ServiceUnavailable("Low-Latency HLS playlist is not available".to_owned())),
+                };
+                let msn = match opts.hls_msn {
+                    Some(msn) => msn,
+                    None => return Ok(playlist.playlist),
+                };
+                if playlist.contains(msn, opts.hls_part) {
+                    debug!("get_ll_hls_playlist: msn={}, part={:?}, waited {:?}", msn, opts.hls_part, start.elapsed());
+                    return Ok(playlist.playlist);
+                }
+                if msn > playlist.last_msn + 2 {
+                    return Err(ApiError::BadRequest(format!(
+                        "_HLS_msn={} is more than two segments after the last segment {}", msn, playlist.last_msn)));
+                }
+                if start.elapsed().as_secs_f64() >= 3.0 * playlist.target_duration_seconds {
+                    warn!("get_ll_hls_playlist: Timed out waiting for msn={}, part={:?}", msn, opts.hls_part);
+                    return Err(ApiError::ServiceUnavailable("Blocking playlist reload timed out".to_owned()));
+                }
+                tokio::time::sleep(LL_HLS_POLL_INTERVAL).await;
+                cached_index.lock().await.refresh_now_async().await.map_err(index_error)?;
+            }
+        }
+
+        /// Returns the HLS playlist.
+        /// Live playlists are cached briefly since every client of a live stream requests the same playlist.
         pub async fn get_m3u8_playlist(
             self,
             scope_name: String,
             stream_name: String,
             opts: GetM3u8PlaylistOptions,
-        ) -> anyhow::Result<String> {
+        ) -> Result<String, ApiError> {
+            let key = format!("{}/{}/m3u8?{:?}", scope_name, stream_name, opts);
+            let live = is_live(opts.end);
+            if live {
+                let cached_playlist = self.playlist_cache.get(&key);
+                self.metrics.cache_request("playlist", cached_playlist.is_some());
+                if let Some(playlist) = cached_playlist {
+                    info!("get_m3u8_playlist: Found in cache: scope_name={}, stream_name={}", scope_name, stream_name);
+                    if opts.end.is_none() {
+                        self.metrics.live_playlist_requested(format!("{}/{}", scope_name, stream_name));
+                    }
+                    return Ok(playlist);
+                }
+            }
+            let playlist = self.clone().build_m3u8_playlist(scope_name, stream_name, opts).await?;
+            if live {
+                self.playlist_cache.insert(key, playlist.clone());
+            }
+            Ok(playlist)
+        }
 
-            info!("get_m3u8_playlist: BEGIN: scope_name={}, stream_name={}, begin={:?}, end={:?}", scope_name, stream_name, opts.begin, opts.end);
+        async fn build_m3u8_playlist(
+            self,
+            scope_name: String,
+            stream_name: String,
+            opts: GetM3u8PlaylistOptions,
+        ) -> Result<String, ApiError> {
+
+            info!("get_m3u8_playlist: BEGIN: scope_name={}, stream_name={}, begin={:?}, end={:?}, version={:?}, low_latency={:?}, _HLS_msn={:?}, _HLS_part={:?}",
+                scope_name, stream_name, opts.begin, opts.end, opts.version, opts.low_latency, opts.hls_msn, opts.hls_part);
+            let version = opts.version.unwrap_or(self.hls_version);
+            // Version 7 allows EXT-X-MAP for fragmented MP4 and EXT-X-GAP.
+            let use_gap_tag = version >= 7;
+
+            let low_latency = (opts.low_latency.unwrap_or_default() || opts.hls_msn.is_some()) && opts.end.is_none() && use_gap_tag;
+            if low_latency {
+                let scoped_stream = self.get_scoped_stream(scope_name, stream_name).await?;
+                self.metrics.live_playlist_requested(format!("{}/{}", scoped_stream.scope.name, scoped_stream.stream.name));
+                let playlist = self.get_ll_hls_playlist(&scoped_stream, version, &opts).await?;
+                trace!("get_m3u8_playlist: playlist={}", playlist);
+                info!("get_m3u8_playlist: END");
+                return Ok(playlist);
+            }
 
-            let index_stream_name = get_index_stream_name(&stream_name);
             let begin_timestamp = PravegaTimestamp::from(opts.begin).or(PravegaTimestamp::MIN);
             let end_timestamp = PravegaTimestamp::from(opts.end).or(PravegaTimestamp::MAX);
             info!("get_m3u8_playlist: begin_timestamp={}, end_timestamp={}", begin_timestamp, end_timestamp);
-            assert!(begin_timestamp <= end_timestamp);
-
-            // Use spawn_blocking to allow Pravega non-async methods to block this thread.
-            // See https://stackoverflow.com/a/65452213/5890553.
-
-            let playlist = tokio::task::spawn_blocking(move || {
-                let span = span!(Level::INFO, "get_m3u8_playlist: SPAWNED THREAD");
-                span.in_scope(|| {
-                    info!("BEGIN");
-                    let client_factory = self.client_factory;
-                    let scoped_stream = ScopedStream {
-                        scope: Scope::from(scope_name),
-                        stream: Stream::from(index_stream_name),
-                    };
-                    let index_reader = client_factory.runtime_handle().block_on(client_factory.create_byte_reader(scoped_stream));
-                    let index_reader = SyncByteReader::new(index_reader, client_factory.runtime_handle());
-                    info!("Opened Pravega reader");
-
-                    let mut index_searcher = IndexSearcher::new(index_reader);
-                    let begin_index_record = index_searcher.search_timestamp_and_return_index_offset(
-                        begin_timestamp, SearchMethod::After)?;
-                    let end_index_record = index_searcher.search_timestamp_and_return_index_offset(
-                        end_timestamp, SearchMethod::After)?;
-                    // Determine whether we can possibly get more data in the future.
-                    // If the caller specified an end time and we already have an index record beyond this, then
-                    // future appends will not affect our result.
-                    // TODO: We can also guarantee this if the stream has been sealed.
-                    let have_all_data = end_index_record.0.timestamp >= end_timestamp;
-                    info!("begin_index_record={:?}, end_index_record={:?}, have_all_data={}",
-                            begin_index_record, end_index_record, have_all_data);
-                    let mut index_reader = index_searcher.into_inner();
-
-                    // Determine begin and end offsets of the index.
-                    let index_begin_offset = begin_index_record.1;
-                    let index_end_offset = end_index_record.1 + IndexRecord::RECORD_SIZE as u64;
-                    let index_size = index_end_offset - index_begin_offset;
-                    info!("index_begin_offset={}, index_end_offset={}, index_size={}", index_begin_offset, index_end_offset, index_size);
-
-                    // Position index reader at current beginning of the index.
-                    index_reader.seek(SeekFrom::Start(index_begin_offset)).unwrap();
-
-                    // Ensure EOF instead of waiting (potentially forever) for appends when we get to the current end.
-                    let mut index_reader = index_reader.take(index_size);
-
-                    // Media Sequence Number will always equal the index record number, even after truncation.
-                    let initial_media_sequence_number: u64 = index_begin_offset / IndexRecord::RECORD_SIZE as u64;
-                    info!("initial_media_sequence_number={}", initial_media_sequence_number);
-
-                    // Initial value for target duration. This will be updated with an exponential moving average, then rounded.
-                    let mut target_duration_seconds = 10.0;
-
-                    let mut playlist_body = String::new();
-                    let mut prev_index_record: Option<IndexRecord> = None;
-                    let mut next_segment_discont = false;
-
-                    loop {
-                        let mut index_record_reader = IndexRecordReader::new();
-                        let index_record = match index_record_reader.read(&mut index_reader) {
-                            Ok(n) => n,
-                            Err(e) if e.kind() == ErrorKind::UnexpectedEof && index_reader.limit() == 0 => {
-                                trace!("Reached requested end");
-                                break;
-                            },
-                            Err(e) => return Err(e),
-                        };
-                        trace!("index_record={:?}", index_record);
-                        if let Some(prev_index_record) = prev_index_record {
-                            // If index_record indicates a discontinuity, then assume there is a gap in the data
-                            // between the previous record and this one.
-                            // Any recorded content that falls in this gap may be corrupt so we will not display it.
-                            // Instead, we'll play a short media segment containing blue video and silent audio.
-                            // The length of this replacement content will be fixed, regardless of the timestamps.
-                            // The EXT-X-GAP tag should be used for this but it doesn't appear to be supported by hls.js.
-                            // It is possible that the duration of the gap in the index is very short or even 0.
-                            // However, we still need to count the gap so that the Media Sequence Numbers
-                            // correspond to the index offset.
-                            // h264parse would add discontinuity flag in each I frames in some conditions. Just simply ignore
-                            // discontinuity flag before figure out why.
-
-                            let mut discont = false;
-                           
-                            if let Some(timestamp_nanos) = index_record.timestamp.nanoseconds() {
-                                let prev_timestamp_nanos = prev_index_record.timestamp.nanoseconds().unwrap();
-                                if timestamp_nanos < prev_timestamp_nanos {
-                                    let rewind_seconds = (prev_timestamp_nanos - timestamp_nanos) as f64 * 1e-9;
-                                    warn!("Detected discontinuity; rewind of {:.3} seconds from {} to {}",
-                                    rewind_seconds, prev_index_record.timestamp, index_record.timestamp);
+            if begin_timestamp > end_timestamp {
+                return Err(ApiError::InvalidTimeRange("begin must not be after end".to_owned()));
+            }
+            let scoped_stream = self.get_scoped_stream(scope_name, stream_name).await?;
+            if opts.end.is_none() {
+                self.metrics.live_playlist_requested(format!("{}/{}", scoped_stream.scope.name, scoped_stream.stream.name));
+            }
+
+            let playlist = async {
+                let IndexRange { index_records, index_begin_offset, record_size, have_all_data } =
+                    self.get_index_range(&scoped_stream, begin_timestamp, end_timestamp).await?;
+
+                // Media Sequence Number will always equal the index record number, even after truncation.
+                let initial_media_sequence_number: u64 = index_begin_offset / record_size;
+                info!("initial_media_sequence_number={}", initial_media_sequence_number);
+
+                // With version 7 and higher, the initialization segment of fragmented MP4 is provided with EXT-X-MAP
+                // and is removed from each media segment.
+                let use_init_map = match index_records.first() {
+                    Some((first_index_record, _)) if use_gap_tag =>
+                        self.is_mp4_stream(&scoped_stream, first_index_record.offset).await?,
+                    _ => false,
+                };
+                // With EXT-X-BYTERANGE, segments are byte ranges of the payload resource, which includes the initialization segments.
+                let byte_range = opts.byte_range.unwrap_or(self.hls_byte_range);
+                let media_uri_suffix = if use_init_map && !byte_range { "&strip_init=true" } else { "" };
+                info!("version={}, use_init_map={}, byte_range={}", version, use_init_map, byte_range);
+                // Each window of index records has its own payload resource.
+                // Segments begin at every index record except the last.
+                let mut payload_maps: Vec<PayloadMap> = Vec::new();
+                if byte_range {
+                    let window_size = payload::WINDOW_RECORDS * record_size;
+                    let mut index_offset = index_begin_offset;
+                    let index_end_offset = index_records.last().map_or(index_begin_offset, |(_, index_offset)| *index_offset);
+                    while index_offset < index_end_offset {
+                        payload_maps.push(self.get_payload_map(&scoped_stream, index_offset).await?);
+                        index_offset = (index_offset / window_size + 1) * window_size;
+                    }
+                }
+
+                // Initial value for target duration. This will be updated with an exponential moving average, then rounded.
+                let mut target_duration_seconds = 10.0;
+                // Each EXTINF duration, rounded, must not exceed the target duration.
+                let mut max_segment_duration_seconds: f64 = 0.0;
+
+                let mut playlist_body = String::new();
+                let mut prev_index_record: Option<IndexRecord> = None;
+                let mut next_segment_discont = false;
+                let mut next_segment_needs_map = true;
+
+                for (index_record, _) in index_records {
+                    trace!("index_record={:?}", index_record);
+                    if let Some(prev_index_record) = prev_index_record {
+                        // If index_record indicates a discontinuity, then assume there is a gap in the data
+                        // between the previous record and this one.
+                        // Any recorded content that falls in this gap may be corrupt so we will not display it.
+                        // With version 7 and higher, the gap is identified with the EXT-X-GAP tag.
+                        // Otherwise, we'll play a short media segment containing blue video and silent audio.
+                        // The length of this replacement content will be fixed, regardless of the timestamps.
+                        // It is possible that the duration of the gap in the index is very short or even 0.
+                        // However, we still need to count the gap so that the Media Sequence Numbers
+                        // correspond to the index offset.
+                        // h264parse would add discontinuity flag in each I frames in some conditions. Just simply ignore
+                        // discontinuity flag before figure out why.
+
+                        let mut discont = false;
+                       
+                        if let (Some(prev_timestamp_nanos), Some(timestamp_nanos)) =
+                                (prev_index_record.timestamp.nanoseconds(), index_record.timestamp.nanoseconds()) {
+                            if timestamp_nanos < prev_timestamp_nanos {
+                                let rewind_seconds = (prev_timestamp_nanos - timestamp_nanos) as f64 * 1e-9;
+                                warn!("Detected discontinuity; rewind of {:.3} seconds from {} to {}",
+                                rewind_seconds, prev_index_record.timestamp, index_record.timestamp);
+                                discont = true;
+                            } else {
+                                let duration_seconds = (timestamp_nanos - prev_timestamp_nanos) as f64 * 1e-9;
+                                // If the timestamp increased by much more than the target duration,
+                                // then assume we have a discontinuity.
+                                if duration_seconds > target_duration_seconds + 1.0 {
+                                    warn!("Detected discontinuity; {:.3} second gap from {} to {}, target_duration_seconds={:.3}",
+                                        duration_seconds, prev_index_record.timestamp, index_record.timestamp, target_duration_seconds);
                                     discont = true;
                                 } else {
-                                    let duration_seconds = (timestamp_nanos - prev_timestamp_nanos) as f64 * 1e-9;
-                                    // If the timestamp increased by much more than the target duration,
-                                    // then assume we have a discontinuity.
-                                    if duration_seconds > target_duration_seconds + 1.0 {
-                                        warn!("Detected discontinuity; {:.3} second gap from {} to {}, target_duration_seconds={:.3}",
-                                            duration_seconds, prev_index_record.timestamp, index_record.timestamp, target_duration_seconds);
-                                        discont = true;
-                                    } else {
-                                        if next_segment_discont {
-                                            playlist_body.push_str("#EXT-X-DISCONTINUITY\n");
-                                            next_segment_discont = false;
-                                        }
-                                        let ema_alpha = 0.1;
-                                        target_duration_seconds = ema_alpha * duration_seconds + (1.0 - ema_alpha) * target_duration_seconds;
-                                        let begin_offset = prev_index_record.offset;
-                                        let end_offset = index_record.offset;
-                                        // "#EXTINF:10," where 10 is the duration of the segment in seconds
-                                        playlist_body.push_str(&format!("#EXTINF:{},\n", duration_seconds));
-                                        // "#EXT-X-PROGRAM-DATE-TIME:2010-02-19T14:54:23.123456789Z"
-                                        playlist_body.push_str(&format!("#EXT-X-PROGRAM-DATE-TIME:{}\n", prev_index_record.timestamp.to_iso_8601().unwrap()));
-                                        // "media?begin=0&end=204" where 0 and 204 are the begin and end byte offsets
-                                        playlist_body.push_str(&format!("media?begin={}&end={}\n", begin_offset, end_offset));
+                                    if next_segment_discont {
+                                        playlist_body.push_str("#EXT-X-DISCONTINUITY\n");
+                                        next_segment_discont = false;
+                                    }
+                                    let ema_alpha = 0.1;
+                                    target_duration_seconds = ema_alpha * duration_seconds + (1.0 - ema_alpha) * target_duration_seconds;
+                                    // Version 2 index records store the actual duration of the preceding segment.
+                                    let duration_seconds = match index_record.segment_duration.nanoseconds() {
+                                        Some(segment_duration) => segment_duration as f64 * 1e-9,
+                                        None => duration_seconds,
+                                    };
+                                    max_segment_duration_seconds = max_segment_duration_seconds.max(duration_seconds);
+                                    let begin_offset = prev_index_record.offset;
+                                    let end_offset = index_record.offset;
+                                    // The initialization segment is written by fragmp4pay before each key frame,
+                                    // so the first segment after a discontinuity can provide it.
+                                    if use_init_map && next_segment_needs_map {
+                                        playlist_body.push_str(&format!("#EXT-X-MAP:URI=\"init?begin={}\"\n", begin_offset));
+                                        next_segment_needs_map = false;
+                                    }
+                                    // "#EXTINF:10," where 10 is the duration of the segment in seconds
+                                    playlist_body.push_str(&format!("#EXTINF:{},\n", duration_seconds));
+                                    // "#EXT-X-PROGRAM-DATE-TIME:2010-02-19T14:54:23.123456789Z"
+                                    playlist_body.push_str(&format!("#EXT-X-PROGRAM-DATE-TIME:{}\n", prev_index_record.timestamp.to_iso_8601().unwrap()));
+                                    let payload_range = payload_maps.iter()
+                                        .rev()
+                                        .find(|payload_map| payload_map.anchor() <= begin_offset)
+                                        .and_then(|payload_map| Some((payload_map,
+                                            payload_map.payload_offset(begin_offset)?, payload_map.payload_offset(end_offset)?)));
+                                    match payload_range {
+                                        Some((payload_map, begin_payload_offset, end_payload_offset)) => {
+                                            // "#EXT-X-BYTERANGE:1000@0" where 1000 is the length and 0 is the offset in the payload resource
+                                            playlist_body.push_str(&format!("#EXT-X-BYTERANGE:{}@{}\n",
+                                                end_payload_offset - begin_payload_offset, begin_payload_offset));
+                                            playlist_body.push_str(&format!("payload/{}\n", payload_map.anchor()));
+                                        },
+                                        _ => {
+                                            // "media?begin=0&end=204" where 0 and 204 are the begin and end byte offsets
+                                            playlist_body.push_str(&format!("media?begin={}&end={}{}\n", begin_offset, end_offset, media_uri_suffix));
+                                        },
                                     }
                                 }
-                            } else {
-                                warn!("Detected discontinuity; missing timestamp in index at offset {}",
-                                    index_record.offset);
-                                discont = true;
                             }
-                            if discont {
-                                // warn!("Detected discontinuity; index_record={:?}", index_record);
-                                let gap_content_duration_seconds = 5;
-                                playlist_body.push_str("#EXT-X-DISCONTINUITY\n");
-                                playlist_body.push_str(&format!("#EXTINF:{},\n", gap_content_duration_seconds));
-                                playlist_body.push_str(&format!("/static/gap-{}s.mp4\n", gap_content_duration_seconds));
-                                next_segment_discont = true;
+                        } else {
+                            // Either record may be missing its timestamp.
+                            let offset = if index_record.timestamp.nanoseconds().is_none() {
+                                index_record.offset
+                            } else {
+                                prev_index_record.offset
+                            };
+                            warn!("Detected discontinuity; missing timestamp in index at offset {}", offset);
+                            discont = true;
+                        }
+                        if discont && use_gap_tag {
+                            // The gap segment has the actual duration of the gap, limited to the target duration.
+                            // Its URI will never be requested by the client.
+                            let gap_duration_seconds = match (prev_index_record.timestamp.nanoseconds(), index_record.timestamp.nanoseconds()) {
+                                (Some(prev_timestamp_nanos), Some(timestamp_nanos)) if timestamp_nanos > prev_timestamp_nanos =>
+                                    ((timestamp_nanos - prev_timestamp_nanos) as f64 * 1e-9).min(target_duration_seconds.round()),
+                                _ => target_duration_seconds.round(),
+                            };
+                            max_segment_duration_seconds = max_segment_duration_seconds.max(gap_duration_seconds);
+                            playlist_body.push_str(&format!("#EXTINF:{},\n", gap_duration_seconds));
+                            if let Some(timestamp) = prev_index_record.timestamp.to_iso_8601() {
+                                playlist_body.push_str(&format!("#EXT-X-PROGRAM-DATE-TIME:{}\n", timestamp));
                             }
+                            playlist_body.push_str("#EXT-X-GAP\n");
+                            playlist_body.push_str(&format!("media?begin={}&end={}\n", prev_index_record.offset, prev_index_record.offset));
+                            next_segment_discont = true;
+                            next_segment_needs_map = true;
+                        } else if discont {
+                            // warn!("Detected discontinuity; index_record={:?}", index_record);
+                            let gap_content_duration_seconds = 5;
+                            playlist_body.push_str("#EXT-X-DISCONTINUITY\n");
+                            playlist_body.push_str(&format!("#EXTINF:{},\n", gap_content_duration_seconds));
+                            playlist_body.push_str(&format!("/static/gap-{}s.mp4\n", gap_content_duration_seconds));
+                            next_segment_discont = true;
                         }
-                        prev_index_record = Some(index_record);
                     }
+                    prev_index_record = Some(index_record);
+                }
 
-                    let mut playlist = String::new();
-                    let target_duration_seconds = target_duration_seconds.round();
-                    info!("target_duration_seconds={}", target_duration_seconds);
+                let mut playlist = String::new();
+                let target_duration_seconds = if use_gap_tag {
+                    target_duration_seconds.round().max(max_segment_duration_seconds.round())
+                } else {
+                    target_duration_seconds.round()
+                };
+                info!("target_duration_seconds={}", target_duration_seconds);
+                if use_gap_tag {
+                    // EXT-X-ALLOW-CACHE was removed in version 7.
+                    playlist.push_str(&format!("#EXTM3U\n#EXT-X-VERSION:{}\n", version));
+                } else if byte_range {
+                    // Version 4 is required for EXT-X-BYTERANGE.
+                    playlist.push_str("#EXTM3U\n#EXT-X-VERSION:4\n#EXT-X-ALLOW-CACHE:NO\n");
+                } else {
                     playlist.push_str("#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-ALLOW-CACHE:NO\n");
-                    playlist.push_str(&format!("#EXT-X-MEDIA-SEQUENCE:{}\n", initial_media_sequence_number));
-                    playlist.push_str(&format!("#EXT-X-TARGETDURATION:{}\n", target_duration_seconds));
-                    playlist.push_str(&playlist_body);
-
-                    // Write ENDLIST if we have all data up to the requested end time.
-                    // This will prevent the browser from polling for updated playlists.
-                    if have_all_data {
-                        playlist.push_str("#EXT-X-ENDLIST\n");
-                    }
-                    info!("END");
-                    Ok(playlist)
-                })
-            })
-            .await??;
-            trace!("get_m3u8_playlist: spawn_blocking done");
+                }
+                playlist.push_str(&format!("#EXT-X-MEDIA-SEQUENCE:{}\n", initial_media_sequence_number));
+                playlist.push_str(&format!("#EXT-X-TARGETDURATION:{}\n", target_duration_seconds));
+                playlist.push_str(&playlist_body);
+
+                // Write ENDLIST if we have all data up to the requested end time.
+                // This will prevent the browser from polling for updated playlists.
+                if have_all_data {
+                    playlist.push_str("#EXT-X-ENDLIST\n");
+                }
+                Ok::<_, ApiError>(playlist)
+            }
+            .await?;
             trace!("get_m3u8_playlist: playlist={}", playlist);
             info!("get_m3u8_playlist: END");
             Ok(playlist)
         }
 
+        /// Returns the DASH MPD for the time range. Live MPDs are cached briefly, as for HLS playlists.
+        pub async fn get_mpd(
+            self,
+            scope_name: String,
+            stream_name: String,
+            opts: GetMpdOptions,
+        ) -> Result<String, ApiError> {
+            let key = format!("{}/{}/mpd?{:?}", scope_name, stream_name, opts);
+            let live = is_live(opts.end);
+            if live {
+                let cached_mpd = self.playlist_cache.get(&key);
+                self.metrics.cache_request("playlist", cached_mpd.is_some());
+                if let Some(mpd) = cached_mpd {
+                    info!("get_mpd: Found in cache: scope_name={}, stream_name={}", scope_name, stream_name);
+                    return Ok(mpd);
+                }
+            }
+            let mpd = self.build_mpd(scope_name, stream_name, opts).await?;
+            if live {
+                self.playlist_cache.insert(key, mpd.clone());
+            }
+            Ok(mpd)
+        }
+
+        /// Returns a DASH MPD for the time range.
+        /// The MPD is static if all data in the time range has been written. Otherwise, it is dynamic.
+        /// Returns NotFound if there are no segments in the time range.
+        async fn build_mpd(
+            &self,
+            scope_name: String,
+            stream_name: String,
+            opts: GetMpdOptions,
+        ) -> Result<String, ApiError> {
+            info!("get_mpd: BEGIN: scope_name={}, stream_name={}, begin={:?}, end={:?}", scope_name, stream_name, opts.begin, opts.end);
+            let begin_timestamp = PravegaTimestamp::from(opts.begin).or(PravegaTimestamp::MIN);
+            let end_timestamp = PravegaTimestamp::from(opts.end).or(PravegaTimestamp::MAX);
+            if begin_timestamp > end_timestamp {
+                return Err(ApiError::InvalidTimeRange("begin must not be after end".to_owned()));
+            }
+            let scoped_stream = self.get_scoped_stream(scope_name, stream_name).await?;
+            let IndexRange { index_records, have_all_data, .. } =
+                self.get_index_range(&scoped_stream, begin_timestamp, end_timestamp).await?;
+            let (is_mp4, codecs) = match index_records.first() {
+                Some((first_index_record, _)) if self.is_mp4_stream(&scoped_stream, first_index_record.offset).await? => {
+                    let init_segment = self.read_init_segment(scoped_stream.clone(), first_index_record.offset).await?;
+                    (true, init_segment.and_then(|init_segment| mp4::codecs(&init_segment)))
+                },
+                _ => (false, None),
+            };
+            let index_records: Vec<IndexRecord> = index_records.into_iter().map(|(index_record, _)| index_record).collect();
+            let config = MpdConfig {
+                dynamic: !have_all_data,
+                is_mp4,
+                codecs,
+                publish_time: PravegaTimestamp::now(),
+            };
+            info!("get_mpd: config={:?}", config);
+            let mpd = dash::build_mpd(&index_records, &config);
+            trace!("get_mpd: mpd={:?}", mpd);
+            info!("get_mpd: END");
+            mpd.ok_or_else(|| ApiError::NotFound("No segments found".to_owned()))
+        }
+
+        /// Returns a single MP4 file containing the time range, produced by a GStreamer pipeline.
+        /// The file begins at the key frame on or immediately before the begin timestamp and
+        /// ends at the key frame on or immediately after the end timestamp.
+        pub async fn get_export(
+            self,
+            scope_name: String,
+            stream_name: String,
+            opts: GetExportOptions,
+        ) -> Result<warp::reply::Response, ApiError> {
+            info!("get_export: scope_name={}, stream_name={}, begin={}, end={}", scope_name, stream_name, opts.begin, opts.end);
+            let exporter = match &self.exporter {
+                Some(exporter) => exporter,
+                None => return Err(ApiError::ServiceUnavailable("Export is not available".to_owned())),
+            };
+            if opts.begin >= opts.end {
+                return Err(ApiError::InvalidTimeRange("begin must be before end".to_owned()));
+            }
+            let max_duration = exporter.max_duration();
+            if (opts.end - opts.begin).to_std().map_or(true, |duration| duration > max_duration) {
+                return Err(ApiError::InvalidTimeRange(format!("the time range must not be longer than {} seconds", max_duration.as_secs())));
+            }
+            let scoped_stream = self.get_scoped_stream(scope_name.clone(), stream_name.clone()).await?;
+            // Find the key frames where pravegasrc will start and stop.
+            let (begin_index_record, end_index_record) = async {
+                let cached_index = self.index_cache.get(&scoped_stream)?;
+                let mut cached_index = cached_index.lock().await;
+                let (begin_index_record, _) = cached_index.search_timestamp_and_return_index_offset_async(
+                    PravegaTimestamp::from(Some(opts.begin)), SearchMethod::Before).await?;
+                let (end_index_record, _) = cached_index.search_timestamp_and_return_index_offset_async(
+                    PravegaTimestamp::from(Some(opts.end)), SearchMethod::After).await?;
+                Ok::<_, std::io::Error>((begin_index_record, end_index_record))
+            }.await.map_err(|err| ApiError::from_index_error(&scoped_stream, err))?;
+            info!("get_export: begin_index_record={:?}, end_index_record={:?}", begin_index_record, end_index_record);
+            if begin_index_record.offset >= end_index_record.offset {
+                return Err(ApiError::NotFound("No video in time range".to_owned()));
+            }
+            let seconds_format = chrono::SecondsFormat::Nanos;
+            let chunks = exporter.start(
+                format!("{}/{}", scope_name, stream_name),
+                opts.begin.to_rfc3339_opts(seconds_format, true),
+                opts.end.to_rfc3339_opts(seconds_format, true),
+                begin_index_record.timestamp.to_iso_8601())?;
+            let chunks = match chunks {
+                Some(chunks) => chunks,
+                None => return Err(ApiError::ServiceUnavailable("Too many exports in progress".to_owned())),
+            };
+            let file_name = format!("{}-{}.mp4", stream_name, opts.begin.format("%Y%m%dT%H%M%SZ"));
+            let response = warp::http::Response::builder()
+                .header("content-type", "video/mp4")
+                .header("content-disposition", format!("attachment; filename=\"{}\"", file_name))
+                .body(Body::wrap_stream(chunks))?;
+            Ok(response)
+        }
+
+        /// Receives an uploaded MP4 or MPEG TS file and writes its H.264 video to the stream, with the first frame at the start timestamp.
+        /// The scope must exist. The stream is created if it does not exist.
+        /// This returns when the file has been received. It is written to the stream in the background.
+        pub async fn post_ingest<S, B>(
+            self,
+            scope_name: String,
+            stream_name: String,
+            opts: PostIngestOptions,
+            body: S,
+        ) -> Result<IngestStatus, ApiError>
+        where
+            S: futures::Stream<Item = Result<B, warp::Error>>,
+            B: Buf,
+        {
+            info!("post_ingest: scope_name={}, stream_name={}, start_utc={}", scope_name, stream_name, opts.start_utc);
+            let ingester = match &self.ingester {
+                Some(ingester) => ingester,
+                None => return Err(ApiError::ServiceUnavailable("Ingest is not available".to_owned())),
+            };
+            // Check the scope and stream before receiving the file.
+            if !is_valid_stream_name(&stream_name) {
+                return Err(ApiError::BadRequest(format!("Invalid stream name {}", stream_name)));
+            }
+            match self.get_scoped_stream(scope_name.clone(), stream_name.clone()).await {
+                Ok(_) | Err(ApiError::StreamNotFound(_)) => {},
+                Err(err) => return Err(err),
+            }
+            let job = ingester.create_job(scope_name, stream_name)
+                .ok_or_else(|| ApiError::ServiceUnavailable("Too many ingests in progress".to_owned()))?;
+            if let Err(err) = receive_file(&job, body, ingester.max_file_size()).await {
+                job.fail(err.to_string());
+                return Err(err);
+            }
+            Ok(job.start(opts.start_utc.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true)))
+        }
+
+        /// Returns the status of an ingest job. The credentials must allow access to the stream of the job.
+        pub fn get_ingest(&self, id: u64, access: &Access) -> Result<IngestStatus, ApiError> {
+            let status = self.ingester.as_ref()
+                .and_then(|ingester| ingester.status(id))
+                .ok_or_else(|| ApiError::NotFound(format!("Ingest {} does not exist", id)))?;
+            access.check_stream(&status.scope_name, &status.stream_name)?;
+            Ok(status)
+        }
+
+        /// Starts WebRTC playback of the stream for the peer that sent the SDP offer.
+        /// Returns the session ID and the SDP answer.
+        pub async fn post_whep(
+            self,
+            scope_name: String,
+            stream_name: String,
+            opts: PostWhepOptions,
+            offer: Bytes,
+        ) -> Result<WhepAnswer, ApiError> {
+            info!("post_whep: scope_name={}, stream_name={}, opts={:?}", scope_name, stream_name, opts);
+            let whep_server = match &self.whep_server {
+                Some(whep_server) => whep_server,
+                None => return Err(ApiError::ServiceUnavailable("WebRTC playback is not available".to_owned())),
+            };
+            let offer = String::from_utf8(offer.to_vec())
+                .map_err(|_| ApiError::BadRequest("The SDP offer is not valid UTF-8".to_owned()))?;
+            let payload_type = whep::h264_payload_type(&offer)
+                .ok_or_else(|| ApiError::BadRequest("The SDP offer must receive H.264 video with packetization mode 1".to_owned()))?;
+            // Ensure that there is video where pravegasrc will start.
+            let scoped_stream = self.get_scoped_stream(scope_name.clone(), stream_name.clone()).await?;
+            let timestamp = PravegaTimestamp::from(opts.begin).or(PravegaTimestamp::MAX);
+            async {
+                let _timer = self.metrics.index_search_duration("whep_start").start_timer();
+                let cached_index = self.index_cache.get(&scoped_stream)?;
+                let mut cached_index = cached_index.lock().await;
+                cached_index.search_timestamp_and_return_index_offset_async(timestamp, SearchMethod::Before).await
+            }.await.map_err(|err| ApiError::from_index_error(&scoped_stream, err))?;
+            let begin_utc = opts.begin.map(|begin| begin.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true));
+            let answer = whep_server.start(scope_name, stream_name, begin_utc, offer, payload_type).await?;
+            answer.ok_or_else(|| ApiError::ServiceUnavailable("Too many WebRTC sessions in progress".to_owned()))
+        }
+
+        /// Stops a WebRTC playback session of the stream.
+        pub fn delete_whep(&self, scope_name: &str, stream_name: &str, id: u64) -> Result<(), ApiError> {
+            info!("delete_whep: scope_name={}, stream_name={}, id={}", scope_name, stream_name, id);
+            match &self.whep_server {
+                Some(whep_server) if whep_server.stop(scope_name, stream_name, id) => Ok(()),
+                _ => Err(ApiError::NotFound(format!("WebRTC session {} does not exist", id))),
+            }
+        }
+
+        /// Returns the key frame on or immediately before the timestamp.
+        /// Returns None if there is no key frame within max_age before the timestamp.
+        async fn find_key_frame(
+            &self,
+            scoped_stream: &ScopedStream,
+            timestamp: PravegaTimestamp,
+            max_age: Duration,
+        ) -> Result<Option<KeyFrame>, ApiError> {
+            self.read_key_frame(scoped_stream, timestamp, max_age).await
+                .map_err(|err| ApiError::from_index_error(scoped_stream, err))
+        }
+
+        async fn read_key_frame(
+            &self,
+            scoped_stream: &ScopedStream,
+            timestamp: PravegaTimestamp,
+            max_age: Duration,
+        ) -> Result<Option<KeyFrame>, std::io::Error> {
+            let cached_index = self.index_cache.get(scoped_stream)?;
+            let mut cached_index = cached_index.lock().await;
+            let (index_record, _) = cached_index.search_timestamp_and_return_index_offset_async(timestamp, SearchMethod::Before).await?;
+            let age_nanos = match (index_record.timestamp.nanoseconds(), timestamp.nanoseconds()) {
+                (Some(key_frame_nanos), Some(nanos)) if key_frame_nanos <= nanos => nanos - key_frame_nanos,
+                _ => return Ok(None),
+            };
+            if age_nanos as u128 > max_age.as_nanos() {
+                return Ok(None);
+            }
+            let next_index_record = cached_index.search_offset_after_async(index_record.offset).await?;
+            let key_frame = index_record.timestamp.to_iso_8601().map(|begin_utc| KeyFrame {
+                begin_utc,
+                end_utc: next_index_record.and_then(|(next_index_record, _)| next_index_record.timestamp.to_iso_8601()),
+            });
+            Ok(key_frame)
+        }
+
+        /// Returns the key frame on or immediately before the timestamp as a JPEG or PNG image.
+        pub async fn get_thumbnail(
+            self,
+            scope_name: String,
+            stream_name: String,
+            format: ImageFormat,
+            opts: GetThumbnailOptions,
+        ) -> Result<warp::reply::Response, ApiError> {
+            info!("get_thumbnail: scope_name={}, stream_name={}, format={:?}, opts={:?}", scope_name, stream_name, format, opts);
+            let thumbnailer = match &self.thumbnailer {
+                Some(thumbnailer) => thumbnailer,
+                None => return Err(ApiError::ServiceUnavailable("Thumbnails are not available".to_owned())),
+            };
+            let scoped_stream = self.get_scoped_stream(scope_name.clone(), stream_name.clone()).await?;
+            let key_frame = self.find_key_frame(&scoped_stream, PravegaTimestamp::from(Some(opts.timestamp)), MAX_KEY_FRAME_AGE).await?;
+            let key_frame = match key_frame {
+                Some(key_frame) => key_frame,
+                None => return Err(ApiError::NotFound("No video at timestamp".to_owned())),
+            };
+            // The thumbnail will not change once the key frame is followed by another index record.
+            let cache_control = if key_frame.end_utc.is_some() { "public, max-age=31536000, immutable" } else { "no-cache" };
+            let image = thumbnailer.snapshot(format!("{}/{}", scope_name, stream_name), key_frame, opts.width, opts.height, format).await?;
+            let response = match image {
+                Some(image) => warp::http::Response::builder()
+                    .header("content-type", format.content_type())
+                    .header("cache-control", cache_control)
+                    .body(Body::from(image))?,
+                None => return Err(ApiError::NotFound("No video frame at timestamp".to_owned())),
+            };
+            Ok(response)
+        }
+
+        /// Returns a WebVTT thumbnail track for the time range.
+        /// This does not decode any video. The sprite sheets are decoded when they are requested.
+        pub async fn get_thumbnail_track(
+            self,
+            scope_name: String,
+            stream_name: String,
+            opts: GetThumbnailTrackOptions,
+        ) -> Result<warp::reply::Response, ApiError> {
+            info!("get_thumbnail_track: scope_name={}, stream_name={}, opts={:?}", scope_name, stream_name, opts);
+            let track = ThumbnailTrack::new(opts.begin, opts.end, opts.interval, opts.width, opts.height)?;
+            self.get_scoped_stream(scope_name, stream_name).await?;
+            let seconds_format = chrono::SecondsFormat::AutoSi;
+            let sprite_uri = |sheet| format!("sprite.jpg?begin={}&end={}&interval={}&width={}&height={}&sheet={}",
+                opts.begin.to_rfc3339_opts(seconds_format, true),
+                opts.end.to_rfc3339_opts(seconds_format, true),
+                track.interval_nanos as f64 * 1e-9,
+                track.tile_width,
+                track.tile_height,
+                sheet);
+            let vtt = sprite::build_webvtt(track.duration_nanos, track.interval_nanos, track.tile_width, track.tile_height, sprite_uri);
+            let response = warp::http::Response::builder()
+                .header("content-type", "text/vtt")
+                .body(Body::from(vtt))?;
+            Ok(response)
+        }
+
+        /// Returns a sprite sheet of a thumbnail track as a JPEG image.
+        pub async fn get_sprite(
+            self,
+            scope_name: String,
+            stream_name: String,
+            opts: GetSpriteOptions,
+        ) -> Result<warp::reply::Response, ApiError> {
+            info!("get_sprite: scope_name={}, stream_name={}, opts={:?}", scope_name, stream_name, opts);
+            let thumbnailer = match &self.thumbnailer {
+                Some(thumbnailer) => thumbnailer,
+                None => return Err(ApiError::ServiceUnavailable("Thumbnails are not available".to_owned())),
+            };
+            let track = ThumbnailTrack::new(opts.begin, opts.end, opts.interval, opts.width, opts.height)?;
+            let tiles = sprite::sheet_tiles(opts.sheet, track.tile_count());
+            if tiles.is_empty() {
+                return Err(ApiError::NotFound("Sheet not found".to_owned()));
+            }
+            let scoped_stream = self.get_scoped_stream(scope_name.clone(), stream_name.clone()).await?;
+            // Each tile shows the key frame on or immediately before the beginning of its interval.
+            let max_age = MAX_KEY_FRAME_AGE.max(Duration::from_nanos(track.interval_nanos));
+            let mut key_frames = Vec::new();
+            let mut have_all_data = true;
+            for tile in tiles.clone() {
+                let timestamp = PravegaTimestamp::from_nanoseconds(
+                    track.begin_timestamp.nanoseconds().map(|begin_nanos| begin_nanos + tile as u64 * track.interval_nanos));
+                let key_frame = self.find_key_frame(&scoped_stream, timestamp, max_age).await?;
+                have_all_data = have_all_data && !matches!(&key_frame, Some(key_frame) if key_frame.end_utc.is_none());
+                key_frames.push(key_frame);
+            }
+            let layout = SpriteLayout {
+                tile_width: track.tile_width,
+                tile_height: track.tile_height,
+                tile_count: tiles.len(),
+            };
+            let image = thumbnailer.sprite(format!("{}/{}", scope_name, stream_name), key_frames, layout).await?;
+            // Tiles after the last key frame may change as the stream is written.
+            let have_all_data = have_all_data && PravegaTimestamp::from(Some(opts.end)) < PravegaTimestamp::now();
+            let cache_control = if have_all_data { "public, max-age=31536000, immutable" } else { "no-cache" };
+            let response = warp::http::Response::builder()
+                .header("content-type", ImageFormat::Jpeg.content_type())
+                .header("cache-control", cache_control)
+                .body(Body::from(image))?;
+            Ok(response)
+        }
+
+        /// Returns the timeline of a stream.
+        /// The index records on or immediately before the begin timestamp and on or immediately after the
+        /// end timestamp are included so that recorded intervals that span the time range are found.
+        async fn build_timeline(
+            &self,
+            scope_name: String,
+            stream_name: String,
+            range: &TimelineRange,
+        ) -> Result<TimelineResult, ApiError> {
+            info!("build_timeline: scope_name={}, stream_name={}", scope_name, stream_name);
+            let scoped_stream = self.get_scoped_stream(scope_name.clone(), stream_name.clone()).await?;
+            let index_records = async {
+                let cached_index = self.index_cache.get(&scoped_stream)?;
+                let mut cached_index = cached_index.lock().await;
+                let (_, index_begin_offset) = cached_index.search_timestamp_and_return_index_offset_async(
+                    range.begin_timestamp, SearchMethod::Before).await?;
+                let (_, end_offset) = cached_index.search_timestamp_and_return_index_offset_async(
+                    range.end_timestamp, SearchMethod::After).await?;
+                let index_end_offset = end_offset + cached_index.record_size_async().await?;
+                cached_index.get_index_records_in_range_async(index_begin_offset, index_end_offset).await
+            }.await.map_err(|err| ApiError::from_index_error(&scoped_stream, err))?;
+            let index_records: Vec<IndexRecord> = index_records.into_iter().map(|(index_record, _)| index_record).collect();
+            let begin_nanos = range.begin_timestamp.nanoseconds().unwrap();
+            let end_nanos = range.end_timestamp.nanoseconds().unwrap();
+            let timeline = timeline::build_timeline(&index_records, begin_nanos, end_nanos, range.resolution_nanos);
+            let to_interval = |interval: &Interval| TimelineInterval {
+                begin: timeline::to_iso_8601(interval.begin_nanos),
+                end: timeline::to_iso_8601(interval.end_nanos),
+            };
+            Ok(TimelineResult {
+                scope_name,
+                stream_name,
+                begin: timeline::to_iso_8601(begin_nanos),
+                end: timeline::to_iso_8601(end_nanos),
+                resolution_seconds: range.resolution_nanos as f64 * 1e-9,
+                intervals: timeline.intervals.iter().map(to_interval).collect(),
+                gaps: timeline.gaps.iter().map(to_interval).collect(),
+                discontinuities: timeline.discontinuities.iter().map(|discontinuity| TimelineDiscontinuity {
+                    timestamp: timeline::to_iso_8601(discontinuity.timestamp_nanos),
+                    reason: discontinuity.reason,
+                }).collect(),
+                error: None,
+            })
+        }
+
+        /// Returns the recorded intervals, gaps, and discontinuities of a stream as JSON.
+        pub async fn get_timeline(
+            self,
+            scope_name: String,
+            stream_name: String,
+            opts: GetTimelineOptions,
+        ) -> Result<TimelineResult, ApiError> {
+            info!("get_timeline: scope_name={}, stream_name={}, opts={:?}", scope_name, stream_name, opts);
+            let range = TimelineRange::new(opts.begin, opts.end, opts.resolution)?;
+            self.build_timeline(scope_name, stream_name, &range).await
+        }
+
+        /// Returns the timelines of multiple streams.
+        /// If the timeline of a stream cannot be determined, its error is included in the result.
+        pub async fn get_timelines(
+            self,
+            request: GetTimelinesRequest,
+        ) -> Result<TimelinesResult, ApiError> {
+            info!("get_timelines: streams={}, begin={}, end={}, resolution={:?}",
+                request.streams.len(), request.begin, request.end, request.resolution);
+            if request.streams.len() > MAX_TIMELINES_STREAMS {
+                return Err(ApiError::BadRequest(format!("A request can have at most {} streams", MAX_TIMELINES_STREAMS)));
+            }
+            let range = TimelineRange::new(request.begin, request.end, request.resolution)?;
+            let range = &range;
+            let timelines = futures::stream::iter(request.streams)
+                .map(|stream| {
+                    let db = self.clone();
+                    async move {
+                        let ListStreamsRecord { scope_name, stream_name } = stream;
+                        match db.build_timeline(scope_name.clone(), stream_name.clone(), range).await {
+                            Ok(timeline) => timeline,
+                            Err(err) => {
+                                warn!("get_timelines: scope_name={}, stream_name={}, err={}", scope_name, stream_name, err);
+                                TimelineResult {
+                                    scope_name,
+                                    stream_name,
+                                    begin: range.begin_timestamp.to_iso_8601().unwrap_or_default(),
+                                    end: range.end_timestamp.to_iso_8601().unwrap_or_default(),
+                                    resolution_seconds: range.resolution_nanos as f64 * 1e-9,
+                                    intervals: Vec::new(),
+                                    gaps: Vec::new(),
+                                    discontinuities: Vec::new(),
+                                    error: Some(err.to_string()),
+                                }
+                            },
+                        }
+                    }
+                })
+                .buffered(TIMELINES_CONCURRENCY)
+                .collect::<Vec<_>>()
+                .await;
+            Ok(TimelinesResult { timelines })
+        }
+
+        /// Returns the head and tail offsets of a byte stream.
+        async fn get_byte_stream_info(&self, scoped_stream: ScopedStream) -> Result<ByteStreamInfo, std::io::Error> {
+            let reader = self.client_factory.create_byte_reader(scoped_stream).await;
+            let mut reader = AsyncByteReader::new(reader);
+            let head_offset = current_head_async(&mut reader).await?;
+            let tail_offset = reader.seek(SeekFrom::End(0)).await?;
+            Ok(ByteStreamInfo { head_offset, tail_offset })
+        }
+
+        /// Returns the time range, size, and other information about a stream.
+        pub async fn get_stream_info(
+            self,
+            scope_name: String,
+            stream_name: String,
+        ) -> Result<StreamInfo, ApiError> {
+            let scoped_stream = self.get_scoped_stream(scope_name.clone(), stream_name.clone()).await?;
+            let index_scoped_stream = ScopedStream {
+                scope: Scope::from(scope_name.clone()),
+                stream: Stream::from(get_index_stream_name(&stream_name)),
+            };
+            let index_records: Vec<IndexRecord> = async {
+                let cached_index = self.index_cache.get(&scoped_stream)?;
+                let mut cached_index = cached_index.lock().await;
+                cached_index.get_index_records_async().await
+            }.await.map_err(|err| ApiError::from_index_error(&scoped_stream, err))?
+                .into_iter().map(|(index_record, _)| index_record).collect();
+            let summary = stream_info::summarize_index(&index_records);
+            let data = self.get_byte_stream_info(scoped_stream.clone()).await?;
+            let index = self.get_byte_stream_info(index_scoped_stream).await?;
+            let (content_type, codecs) = match index_records.first() {
+                Some(first_index_record) if self.is_mp4_stream(&scoped_stream, first_index_record.offset).await? => {
+                    let init_segment = self.read_init_segment(scoped_stream.clone(), first_index_record.offset).await?;
+                    (Some("video/mp4".to_owned()), init_segment.and_then(|init_segment| mp4::codecs(&init_segment)))
+                },
+                Some(_) => (Some("video/MP2T".to_owned()), None),
+                None => (None, None),
+            };
+            let controller_client = self.client_factory.controller_client();
+            let sealed = match controller_client.get_current_segments(&scoped_stream).await {
+                // A sealed stream has no current segments.
+                Ok(segments) => Some(segments.key_segment_map.is_empty()),
+                Err(err) => {
+                    warn!("get_stream_info: Unable to get current segments of {}: {:?}", scoped_stream, err);
+                    None
+                },
+            };
+            let stream_info = StreamInfo {
+                scope_name,
+                stream_name,
+                first_timestamp: summary.first_timestamp.to_iso_8601(),
+                last_timestamp: summary.last_timestamp.to_iso_8601(),
+                duration_seconds: summary.duration_seconds(),
+                retained_bytes: (data.tail_offset - data.head_offset) + (index.tail_offset - index.head_offset),
+                data,
+                index,
+                index_record_count: summary.record_count,
+                average_bitrate: summary.average_bitrate,
+                key_frame_interval_seconds: summary.key_frame_interval_seconds,
+                sealed,
+                content_type,
+                codecs,
+            };
+            info!("get_stream_info: stream_info={:?}", stream_info);
+            Ok(stream_info)
+        }
+
         pub async fn list_scopes(
             self
-        ) -> anyhow::Result<ListScopesResult> {
+        ) -> Result<ListScopesResult, ApiError> {
 
             info!("list_scopes");
             let controller_client = self.client_factory.controller_client();
             let mut scopes = Vec::new();
             let mut had_error = false;
 
+            let timer = self.metrics.controller_request_duration("list_scopes").start_timer();
             list_scopes(controller_client).for_each(|scope| {
                 if scope.is_ok() {
                     scopes.push(scope.unwrap())
@@ -559,9 +2987,10 @@ mod models {
 
                 future::ready(())
             }).await;
+            timer.observe_duration();
 
             if had_error {
-                anyhow::bail!("Error listing scopes");
+                return Err(ApiError::PravegaUnavailable("Error listing scopes".to_owned()));
             }
 
             let scopes: Vec<_> = scopes.into_iter().map(|scope| ListScopesRecord {
@@ -573,13 +3002,19 @@ mod models {
         pub async fn list_video_streams(
             self,
             scope_name: String,
-        ) -> anyhow::Result<ListStreamsResult> {
+        ) -> Result<ListStreamsResult, ApiError> {
 
             info!("list_video_streams: scope_name={}", scope_name.clone());
             let controller_client = self.client_factory.controller_client();
             let scope = Scope { name : scope_name.clone() };
+            let scope_exists = controller_client.check_scope_exists(&scope).await
+                .map_err(|err| ApiError::PravegaUnavailable(format!("Unable to check whether scope {} exists: {:?}", scope_name, err)))?;
+            if !scope_exists {
+                return Err(ApiError::ScopeNotFound(scope_name));
+            }
             let mut streams = Vec::new();
             let mut had_error = false;
+            let timer = self.metrics.controller_request_duration("list_streams").start_timer();
             list_streams_for_tag(scope, utils::get_video_tag_query(), controller_client).for_each(|stream| {
                 if stream.is_ok() {
                     streams.push(stream.unwrap());
@@ -588,9 +3023,10 @@ mod models {
                 }
                 future::ready(())
             }).await;
+            timer.observe_duration();
 
             if had_error {
-                anyhow::bail!("Error listing streams for scope={}", scope_name.clone());
+                return Err(ApiError::PravegaUnavailable(format!("Error listing streams for scope={}", scope_name.clone())));
             }
             let streams: Vec<_> = streams.into_iter().map(|scoped_stream| ListStreamsRecord {
                 scope_name: scope_name.clone(),
@@ -0,0 +1,255 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Prometheus metrics.
+//
+// Requests are counted and timed by route, which is the kind of resource such as "media" or "m3u8".
+// Scope and stream names are not used as labels since there can be any number of them.
+
+use futures::TryStreamExt;
+use hyper::body::{Body, HttpBody};
+use prometheus::{Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+#[allow(unused_imports)]
+use tracing::{debug, error, info, warn, trace};
+use warp::{Filter, Reply};
+use super::auth::{self, Auth};
+
+/// A stream has an active live playlist if one was requested within this duration.
+/// Players reload live playlists every target duration, which is usually a few seconds.
+const LIVE_PLAYLIST_ACTIVE_DURATION: Duration = Duration::from_secs(30);
+
+/// The resources of a stream, which are the last segment of paths such as /scopes/my_scope/streams/my_stream/media.
+const STREAM_ROUTES: &[&str] = &[
+    "media", "init", "part", "payload", "m3u8", "mpd", "export.mp4", "thumbnail.jpg", "thumbnail.png",
+    "thumbnails.vtt", "sprite.jpg", "timeline", "signed-url", "websocket", "ingest",
+    "whep",
+];
+
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    http_response_bytes: IntCounterVec,
+    pravega_read_duration: HistogramVec,
+    controller_request_duration: HistogramVec,
+    index_search_duration: HistogramVec,
+    cache_requests: IntCounterVec,
+    live_playlists: IntGauge,
+    websocket_connections: IntGauge,
+    /// The last time that a live playlist of each stream was requested.
+    live_playlist_requests: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("pravega_video_server".to_owned()), None).unwrap();
+        // Index searches and reads of cached events are usually much faster than HTTP requests.
+        let fast_buckets = prometheus::exponential_buckets(0.0001, 2.5, 12).unwrap();
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route and status code"),
+            &["route", "status"]).unwrap();
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "Time until the response headers are ready, by route"),
+            &["route"]).unwrap();
+        let http_response_bytes = IntCounterVec::new(
+            Opts::new("http_response_bytes_total", "Bytes of response bodies sent, by route"),
+            &["route"]).unwrap();
+        let pravega_read_duration = HistogramVec::new(
+            HistogramOpts::new("pravega_read_duration_seconds", "Time to open a Pravega reader or read a chunk of a media segment")
+                .buckets(fast_buckets.clone()),
+            &["operation"]).unwrap();
+        let controller_request_duration = HistogramVec::new(
+            HistogramOpts::new("pravega_controller_request_duration_seconds", "Time to list scopes or streams with the Pravega controller"),
+            &["operation"]).unwrap();
+        let index_search_duration = HistogramVec::new(
+            HistogramOpts::new("index_search_duration_seconds", "Time to search the index of a stream, including reading new index records")
+                .buckets(fast_buckets),
+            &["operation"]).unwrap();
+        let cache_requests = IntCounterVec::new(
+            Opts::new("cache_requests_total", "Lookups in the segment and playlist caches by result"),
+            &["cache", "result"]).unwrap();
+        let live_playlists = IntGauge::new(
+            "live_playlists_active",
+            "Streams with a live HLS playlist requested in the last 30 seconds").unwrap();
+        registry.register(Box::new(http_requests.clone())).unwrap();
+        registry.register(Box::new(http_request_duration.clone())).unwrap();
+        registry.register(Box::new(http_response_bytes.clone())).unwrap();
+        registry.register(Box::new(pravega_read_duration.clone())).unwrap();
+        registry.register(Box::new(controller_request_duration.clone())).unwrap();
+        registry.register(Box::new(index_search_duration.clone())).unwrap();
+        let websocket_connections = IntGauge::new(
+            "websocket_connections_active",
+            "Open WebSocket connections sending fragmented MP4").unwrap();
+        registry.register(Box::new(cache_requests.clone())).unwrap();
+        registry.register(Box::new(live_playlists.clone())).unwrap();
+        registry.register(Box::new(websocket_connections.clone())).unwrap();
+        Metrics {
+            registry,
+            http_requests,
+            http_request_duration,
+            http_response_bytes,
+            pravega_read_duration,
+            controller_request_duration,
+            index_search_duration,
+            cache_requests,
+            live_playlists,
+            websocket_connections,
+            live_playlist_requests: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The histogram of Pravega reads, such as "open" or "chunk".
+    pub fn pravega_read_duration(&self, operation: &str) -> Histogram {
+        self.pravega_read_duration.with_label_values(&[operation])
+    }
+
+    /// The histogram of Pravega controller requests, such as "list_scopes".
+    pub fn controller_request_duration(&self, operation: &str) -> Histogram {
+        self.controller_request_duration.with_label_values(&[operation])
+    }
+
+    /// The histogram of index searches, such as "time_range".
+    pub fn index_search_duration(&self, operation: &str) -> Histogram {
+        self.index_search_duration.with_label_values(&[operation])
+    }
+
+    /// Record a lookup in a cache, such as "segment" or "playlist".
+    pub fn cache_request(&self, cache: &str, hit: bool) {
+        self.cache_requests.with_label_values(&[cache, if hit { "hit" } else { "miss" }]).inc();
+    }
+
+    /// The gauge of open WebSocket connections.
+    pub fn websocket_connections(&self) -> IntGauge {
+        self.websocket_connections.clone()
+    }
+
+    /// Record that a live playlist of the stream was requested.
+    pub fn live_playlist_requested(&self, scoped_stream: String) {
+        self.live_playlist_requests.lock().unwrap().insert(scoped_stream, Instant::now());
+    }
+
+    /// Returns all metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        {
+            let mut live_playlist_requests = self.live_playlist_requests.lock().unwrap();
+            live_playlist_requests.retain(|_, requested| requested.elapsed() < LIVE_PLAYLIST_ACTIVE_DURATION);
+            self.live_playlists.set(live_playlist_requests.len() as i64);
+        }
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    /// Count and time the responses of the filter.
+    /// The bytes of a response body are counted as they are sent.
+    pub fn instrument<F, R>(&self, filter: F) -> impl Filter<Extract = (warp::reply::Response,), Error = warp::Rejection> + Clone
+    where
+        F: Filter<Extract = (R,), Error = warp::Rejection> + Clone + Send + Sync + 'static,
+        R: Reply,
+    {
+        let metrics = self.clone();
+        warp::path::full()
+            .and(warp::any().map(Instant::now))
+            .and(filter)
+            .map(move |path: warp::path::FullPath, start: Instant, reply: R| {
+                let route = route(path.as_str());
+                let response = reply.into_response();
+                metrics.http_requests.with_label_values(&[route, response.status().as_str()]).inc();
+                metrics.http_request_duration.with_label_values(&[route]).observe(start.elapsed().as_secs_f64());
+                let response_bytes = metrics.http_response_bytes.with_label_values(&[route]);
+                // Bodies with a known length are not wrapped so that the content-length header is still sent.
+                match response.body().size_hint().exact() {
+                    Some(len) => {
+                        response_bytes.inc_by(len);
+                        response
+                    },
+                    None => response.map(|body| {
+                        Body::wrap_stream(body.inspect_ok(move |chunk| response_bytes.inc_by(chunk.len() as u64)))
+                    }),
+                }
+            })
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// GET /metrics
+/// Returns the metrics in the Prometheus text format.
+/// When authentication is enabled, any valid credentials are required.
+pub fn get_metrics(metrics: Metrics, auth: Auth) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("metrics")
+        .and(warp::get())
+        .and(auth::require(auth))
+        .map(move || warp::reply::with_header(metrics.render(), "content-type", prometheus::TEXT_FORMAT))
+}
+
+/// Returns the route label of a request path.
+fn route(path: &str) -> &'static str {
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    let segments = match segments.as_slice() {
+        ["signed", _, segments @ ..] => segments,
+        segments => segments,
+    };
+    match segments {
+        ["scopes"] => "scopes",
+        ["scopes", _, "streams"] => "streams",
+        ["scopes", _, "streams", _] => "stream_info",
+        ["scopes", _, "streams", _, "payload", _] => "payload",
+        ["scopes", _, "streams", _, "whep", _] => "whep",
+        ["scopes", _, "streams", _, resource] => STREAM_ROUTES.iter().find(|route| *route == resource).copied().unwrap_or("other"),
+        ["timelines"] => "timelines",
+        ["ingests", _] => "ingests",
+        ["player"] => "player",
+        ["static", ..] => "static",
+        ["metrics"] => "metrics",
+        _ => "other",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_route() {
+        assert_eq!(route("/scopes"), "scopes");
+        assert_eq!(route("/scopes/my_scope/streams"), "streams");
+        assert_eq!(route("/scopes/my_scope/streams/my_stream"), "stream_info");
+        assert_eq!(route("/scopes/my_scope/streams/my_stream/media"), "media");
+        assert_eq!(route("/scopes/my_scope/streams/my_stream/payload/0"), "payload");
+        assert_eq!(route("/scopes/my_scope/streams/my_stream/websocket"), "websocket");
+        assert_eq!(route("/scopes/my_scope/streams/my_stream/whep/1"), "whep");
+        assert_eq!(route("/signed/123.abc/scopes/my_scope/streams/my_stream/m3u8"), "m3u8");
+        assert_eq!(route("/scopes/my_scope/streams/my_stream/unknown"), "other");
+        assert_eq!(route("/ingests/1"), "ingests");
+        assert_eq!(route("/static/hls-js.js"), "static");
+        assert_eq!(route("/favicon.ico"), "other");
+    }
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.live_playlist_requested("my_scope/my_stream".to_owned());
+        metrics.index_search_duration("time_range").observe(0.001);
+        metrics.cache_request("segment", true);
+        let text = metrics.render();
+        assert!(text.contains("pravega_video_server_live_playlists_active 1\n"));
+        assert!(text.contains("pravega_video_server_index_search_duration_seconds_count{operation=\"time_range\"} 1\n"));
+        assert!(text.contains("pravega_video_server_cache_requests_total{cache=\"segment\",result=\"hit\"} 1\n"));
+    }
+}
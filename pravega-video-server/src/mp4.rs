@@ -0,0 +1,394 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Parsing of top-level ISO BMFF (MP4) boxes in a byte stream that may be split at arbitrary positions.
+// fragmp4pay writes ftyp and moov boxes (the initialization segment) before each key frame,
+// followed by moof and mdat boxes (the media fragments).
+
+use std::convert::TryInto;
+use std::io::{Error, ErrorKind};
+
+pub type BoxType = [u8; 4];
+
+pub const FTYP: BoxType = *b"ftyp";
+pub const MOOV: BoxType = *b"moov";
+pub const MOOF: BoxType = *b"moof";
+pub const STYP: BoxType = *b"styp";
+
+/// Returns true if the box is part of an initialization segment.
+pub fn is_init_box(box_type: &BoxType) -> bool {
+    *box_type == FTYP || *box_type == MOOV
+}
+
+/// Returns true if the payload appears to begin with an MP4 box.
+/// MPEG transport streams begin with the sync byte 0x47 and will return false.
+pub fn is_mp4(payload: &[u8]) -> bool {
+    if payload.len() < 8 {
+        return false;
+    }
+    let box_type: BoxType = payload[4..8].try_into().unwrap();
+    [FTYP, MOOV, MOOF, STYP].contains(&box_type)
+}
+
+/// Returns the codecs parameter (RFC 6381) for the tracks in an initialization segment, such as "avc1.64001F,mp4a.40.2".
+/// Only H.264 video and AAC audio are identified. Returns None if no track is identified.
+/// Rather than parsing the entire moov box, this searches for the sample entry configuration boxes.
+pub fn codecs(init_segment: &[u8]) -> Option<String> {
+    let find = |box_type: &[u8]| init_segment.windows(4).position(|w| w == box_type).map(|pos| pos + 4);
+    let mut codecs = Vec::new();
+    if let Some(pos) = find(b"avcC") {
+        // AVCDecoderConfigurationRecord begins with configurationVersion, profile, compatibility, and level.
+        if let Some(config) = init_segment.get(pos..pos + 4) {
+            codecs.push(format!("avc1.{:02X}{:02X}{:02X}", config[1], config[2], config[3]));
+        }
+    }
+    if find(b"mp4a").is_some() {
+        // Assume AAC-LC.
+        codecs.push("mp4a.40.2".to_owned());
+    }
+    if codecs.is_empty() {
+        None
+    } else {
+        Some(codecs.join(","))
+    }
+}
+
+/// Splits a byte stream into top-level boxes.
+/// The input can be provided in pieces of any size.
+pub struct BoxReader {
+    /// Bytes of a box header that has not been completely received.
+    header: [u8; 16],
+    header_len: usize,
+    /// Type of the current box and the number of bytes remaining in it, including the header.
+    current: Option<(BoxType, u64)>,
+}
+
+impl BoxReader {
+    pub fn new() -> Self {
+        Self {
+            header: [0; 16],
+            header_len: 0,
+            current: None,
+        }
+    }
+
+    /// Returns the type of the box that the next byte belongs to, if known.
+    pub fn current_box_type(&self) -> Option<BoxType> {
+        self.current.map(|(box_type, _)| box_type)
+    }
+
+    /// Process the next piece of the stream.
+    /// The callback is called with the type of each box and a piece of that box, including the header.
+    /// A box may be passed to the callback in several pieces. A header is never split between calls.
+    pub fn push<F>(&mut self, mut input: &[u8], mut callback: F) -> Result<(), Error>
+    where
+        F: FnMut(BoxType, &[u8]),
+    {
+        while !input.is_empty() {
+            match self.current {
+                Some((box_type, remaining)) => {
+                    let len = remaining.min(input.len() as u64) as usize;
+                    callback(box_type, &input[..len]);
+                    input = &input[len..];
+                    let remaining = remaining - len as u64;
+                    self.current = if remaining == 0 { None } else { Some((box_type, remaining)) };
+                },
+                None => {
+                    // Accumulate the header. The 32-bit size is followed by the type and optionally a 64-bit size.
+                    let needed = if self.header_len >= 4 && self.header[0..4] == [0, 0, 0, 1] { 16 } else { 8 };
+                    let len = (needed - self.header_len).min(input.len());
+                    self.header[self.header_len..self.header_len + len].copy_from_slice(&input[..len]);
+                    self.header_len += len;
+                    input = &input[len..];
+                    if self.header_len < 8 || (self.header[0..4] == [0, 0, 0, 1] && self.header_len < 16) {
+                        continue;
+                    }
+                    let box_type: BoxType = self.header[4..8].try_into().unwrap();
+                    let size = match u32::from_be_bytes(self.header[0..4].try_into().unwrap()) {
+                        // The box extends to the end of the stream.
+                        0 => u64::MAX,
+                        1 => u64::from_be_bytes(self.header[8..16].try_into().unwrap()),
+                        size => size as u64,
+                    };
+                    let header_len = self.header_len;
+                    if size < header_len as u64 {
+                        return Err(Error::new(ErrorKind::InvalidData, format!("Invalid MP4 box size {}", size)));
+                    }
+                    callback(box_type, &self.header[..header_len]);
+                    self.header_len = 0;
+                    let remaining = size - header_len as u64;
+                    if remaining > 0 {
+                        self.current = Some((box_type, remaining));
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Removes the initialization segment (ftyp and moov boxes) from a stream of MP4 fragments.
+pub struct InitSegmentFilter {
+    box_reader: BoxReader,
+}
+
+impl InitSegmentFilter {
+    pub fn new() -> Self {
+        Self {
+            box_reader: BoxReader::new(),
+        }
+    }
+
+    /// Append all bytes of input, except those in ftyp and moov boxes, to output.
+    pub fn filter(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), Error> {
+        self.box_reader.push(input, |box_type, piece| {
+            if !is_init_box(&box_type) {
+                output.extend_from_slice(piece);
+            }
+        })
+    }
+}
+
+/// Extracts the initialization segment (ftyp and moov boxes) from the beginning of a stream of MP4 fragments.
+pub struct InitSegmentExtractor {
+    box_reader: BoxReader,
+    init_segment: Vec<u8>,
+    have_moov: bool,
+    /// Set when a box that is not part of the initialization segment is found.
+    finished: bool,
+}
+
+impl InitSegmentExtractor {
+    pub fn new() -> Self {
+        Self {
+            box_reader: BoxReader::new(),
+            init_segment: Vec::new(),
+            have_moov: false,
+            finished: false,
+        }
+    }
+
+    /// Process the next piece of the stream.
+    /// Returns true when no more input is needed.
+    pub fn push(&mut self, input: &[u8]) -> Result<bool, Error> {
+        if self.finished {
+            return Ok(true);
+        }
+        let init_segment = &mut self.init_segment;
+        let have_moov = &mut self.have_moov;
+        let finished = &mut self.finished;
+        self.box_reader.push(input, |box_type, piece| {
+            if *finished {
+            } else if is_init_box(&box_type) {
+                *have_moov |= box_type == MOOV;
+                init_segment.extend_from_slice(piece);
+            } else {
+                *finished = true;
+            }
+        })?;
+        // The moov box is the last box of the initialization segment.
+        if self.have_moov && self.box_reader.current_box_type().is_none() {
+            self.finished = true;
+        }
+        Ok(self.finished)
+    }
+
+    /// Returns the initialization segment if a complete moov box was found.
+    pub fn into_init_segment(self) -> Option<Vec<u8>> {
+        if self.have_moov && self.box_reader.current_box_type() != Some(MOOV) {
+            Some(self.init_segment)
+        } else {
+            None
+        }
+    }
+}
+
+/// A piece of a stream of MP4 fragments for a Media Source Extensions player.
+#[derive(Debug, PartialEq)]
+pub enum MseSegment {
+    /// A complete initialization segment (ftyp and moov boxes).
+    Init(Vec<u8>),
+    /// Media fragments (moof and mdat boxes).
+    Media(Vec<u8>),
+}
+
+/// Splits a stream of MP4 fragments into initialization segments and media.
+/// Since fragmp4pay repeats the initialization segment before each key frame,
+/// an initialization segment is returned only when it differs from the previous one.
+/// Media before the first initialization segment is dropped since a player cannot decode it.
+pub struct MseSegmenter {
+    box_reader: BoxReader,
+    /// The initialization segment being received.
+    init_segment: Vec<u8>,
+    /// The last initialization segment that was returned.
+    last_init_segment: Option<Vec<u8>>,
+}
+
+impl MseSegmenter {
+    pub fn new() -> Self {
+        Self {
+            box_reader: BoxReader::new(),
+            init_segment: Vec::new(),
+            last_init_segment: None,
+        }
+    }
+
+    /// Process the next piece of the stream and append the resulting segments to output.
+    /// Media is returned as soon as it is received, so a media segment may contain partial boxes.
+    pub fn push(&mut self, input: &[u8], output: &mut Vec<MseSegment>) -> Result<(), Error> {
+        let init_segment = &mut self.init_segment;
+        let last_init_segment = &mut self.last_init_segment;
+        let mut media = Vec::new();
+        self.box_reader.push(input, |box_type, piece| {
+            if is_init_box(&box_type) {
+                init_segment.extend_from_slice(piece);
+                return;
+            }
+            if !init_segment.is_empty() {
+                // The initialization segment is complete.
+                if last_init_segment.as_ref() != Some(init_segment) {
+                    if !media.is_empty() {
+                        output.push(MseSegment::Media(std::mem::take(&mut media)));
+                    }
+                    output.push(MseSegment::Init(init_segment.clone()));
+                    *last_init_segment = Some(init_segment.clone());
+                }
+                init_segment.clear();
+            }
+            if last_init_segment.is_some() {
+                media.extend_from_slice(piece);
+            }
+        })?;
+        if !media.is_empty() {
+            output.push(MseSegment::Media(media));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MDAT: BoxType = *b"mdat";
+
+    fn mp4_box(box_type: &BoxType, body_len: usize) -> Vec<u8> {
+        let mut b = ((body_len + 8) as u32).to_be_bytes().to_vec();
+        b.extend_from_slice(box_type);
+        b.resize(b.len() + body_len, box_type[0]);
+        b
+    }
+
+    fn fragment(with_init: bool) -> Vec<u8> {
+        let mut data = Vec::new();
+        if with_init {
+            data.extend(mp4_box(&FTYP, 20));
+            data.extend(mp4_box(&MOOV, 300));
+        }
+        data.extend(mp4_box(&MOOF, 100));
+        data.extend(mp4_box(&MDAT, 1000));
+        data
+    }
+
+    #[test]
+    fn test_init_segment_filter() {
+        let mut input = fragment(true);
+        input.extend(fragment(false));
+        input.extend(fragment(true));
+        let mut expected = fragment(false);
+        expected.extend(fragment(false));
+        expected.extend(fragment(false));
+        // The result must not depend on how the input is split.
+        for piece_size in [1, 3, 7, 188, 100000].iter() {
+            let mut filter = InitSegmentFilter::new();
+            let mut output = Vec::new();
+            for piece in input.chunks(*piece_size) {
+                filter.filter(piece, &mut output).unwrap();
+            }
+            assert_eq!(output, expected, "piece_size={}", piece_size);
+        }
+    }
+
+    #[test]
+    fn test_init_segment_extractor() {
+        let input = fragment(true);
+        let mut expected = mp4_box(&FTYP, 20);
+        expected.extend(mp4_box(&MOOV, 300));
+        for piece_size in [1, 5, 328, 100000].iter() {
+            let mut extractor = InitSegmentExtractor::new();
+            for piece in input.chunks(*piece_size) {
+                if extractor.push(piece).unwrap() {
+                    break;
+                }
+            }
+            assert_eq!(extractor.into_init_segment(), Some(expected.clone()), "piece_size={}", piece_size);
+        }
+        let mut extractor = InitSegmentExtractor::new();
+        assert!(extractor.push(&fragment(false)).unwrap());
+        assert_eq!(extractor.into_init_segment(), None);
+    }
+
+    #[test]
+    fn test_mse_segmenter() {
+        let mut other_init_segment = mp4_box(&FTYP, 20);
+        other_init_segment.extend(mp4_box(&MOOV, 400));
+        let mut input = fragment(false);
+        input.extend(fragment(true));
+        input.extend(fragment(false));
+        input.extend(fragment(true));
+        input.extend(other_init_segment.clone());
+        input.extend(fragment(false));
+        let mut init_segment = mp4_box(&FTYP, 20);
+        init_segment.extend(mp4_box(&MOOV, 300));
+        let mut media = fragment(false);
+        media.extend(fragment(false));
+        media.extend(fragment(false));
+        for piece_size in [1, 3, 7, 188, 100000].iter() {
+            let mut segmenter = MseSegmenter::new();
+            let mut output = Vec::new();
+            for piece in input.chunks(*piece_size) {
+                segmenter.push(piece, &mut output).unwrap();
+            }
+            // Concatenate consecutive media segments since they depend on how the input is split.
+            let mut segments: Vec<MseSegment> = Vec::new();
+            for segment in output {
+                match (segments.last_mut(), segment) {
+                    (Some(MseSegment::Media(last)), MseSegment::Media(m)) => last.extend(m),
+                    (_, segment) => segments.push(segment),
+                }
+            }
+            assert_eq!(segments, vec![
+                MseSegment::Init(init_segment.clone()),
+                MseSegment::Media(media.clone()),
+                MseSegment::Init(other_init_segment.clone()),
+                MseSegment::Media(fragment(false)),
+            ], "piece_size={}", piece_size);
+        }
+    }
+
+    #[test]
+    fn test_codecs() {
+        let mut init_segment = mp4_box(&FTYP, 20);
+        init_segment.extend_from_slice(&[0, 0, 0, 19]);
+        init_segment.extend_from_slice(b"avcC");
+        init_segment.extend_from_slice(&[1, 0x64, 0x00, 0x1F, 0xFF, 0xE1, 0, 0, 0, 0, 0]);
+        assert_eq!(codecs(&init_segment), Some("avc1.64001F".to_owned()));
+        init_segment.extend_from_slice(&[0, 0, 0, 8]);
+        init_segment.extend_from_slice(b"mp4a");
+        assert_eq!(codecs(&init_segment), Some("avc1.64001F,mp4a.40.2".to_owned()));
+        assert_eq!(codecs(&fragment(false)), None);
+    }
+
+    #[test]
+    fn test_is_mp4() {
+        assert!(is_mp4(&fragment(true)));
+        assert!(!is_mp4(&[0x47; 188]));
+    }
+}
@@ -0,0 +1,162 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Support for HTTP range requests on the payload byte space of a data stream.
+//
+// The payload byte space is the concatenation of the payloads of all events, without their headers,
+// beginning at an anchor. The anchor is the data stream offset of an index record.
+// Since data streams are append-only, a byte in the payload byte space never changes,
+// and the payload byte space can be served as a single cacheable resource for each anchor.
+// A new anchor is required only when the stream is truncated beyond the anchor.
+//
+// Each payload resource covers a fixed window of index records, so that mapping it reads a bounded
+// part of the data stream. The anchor is the first index record of the window that has not been truncated.
+
+/// Each payload resource covers the segments that begin at this many consecutive index records.
+pub const WINDOW_RECORDS: u64 = 100;
+
+/// Maps offsets in the payload byte space to offsets in the data stream.
+/// Only the offsets of index records are stored. Other offsets are found by reading events from the preceding index record.
+#[derive(Clone, Debug)]
+pub struct PayloadMap {
+    /// Sorted (data offset, payload offset) of index records.
+    points: Vec<(u64, u64)>,
+}
+
+impl PayloadMap {
+    pub fn new(anchor: u64) -> Self {
+        Self {
+            points: vec![(anchor, 0)],
+        }
+    }
+
+    /// The data stream offset at which the payload byte space begins.
+    pub fn anchor(&self) -> u64 {
+        self.points[0].0
+    }
+
+    /// The last mapped data stream offset and its payload offset.
+    /// The payload byte space is at least this long.
+    pub fn last_point(&self) -> (u64, u64) {
+        *self.points.last().unwrap()
+    }
+
+    /// Record the payload offset of an index record.
+    /// Points must be added in order.
+    pub fn add_point(&mut self, data_offset: u64, payload_offset: u64) {
+        let (last_data_offset, last_payload_offset) = self.last_point();
+        assert!(data_offset > last_data_offset && payload_offset >= last_payload_offset);
+        self.points.push((data_offset, payload_offset));
+    }
+
+    /// Returns the payload offset of a mapped data stream offset.
+    pub fn payload_offset(&self, data_offset: u64) -> Option<u64> {
+        self.points.binary_search_by_key(&data_offset, |(d, _)| *d).ok().map(|i| self.points[i].1)
+    }
+
+    /// Returns the last point with a payload offset at or before payload_offset.
+    /// Reading can begin at the returned data stream offset.
+    pub fn point_before(&self, payload_offset: u64) -> (u64, u64) {
+        // The first point has a payload offset of 0 so i is at least 1.
+        let i = self.points.partition_point(|(_, p)| *p <= payload_offset);
+        self.points[i - 1]
+    }
+
+    /// Returns the first point with a payload offset at or after payload_offset.
+    /// Reading can end at the returned data stream offset.
+    pub fn point_at_or_after(&self, payload_offset: u64) -> Option<(u64, u64)> {
+        let i = self.points.partition_point(|(_, p)| *p < payload_offset);
+        self.points.get(i).copied()
+    }
+}
+
+/// The result of parsing an HTTP Range header.
+#[derive(Debug, PartialEq)]
+pub enum RangeRequest {
+    /// Serve the bytes in the range [begin, end).
+    Satisfiable(u64, u64),
+    /// The range begins after the end of the resource.
+    NotSatisfiable,
+    /// The header is invalid or has multiple ranges. As allowed by RFC 7233, it will be ignored.
+    Ignored,
+}
+
+/// Parse an HTTP Range header with a single byte range, such as "bytes=0-499", "bytes=500-", or "bytes=-500".
+pub fn parse_range(value: &str, length: u64) -> RangeRequest {
+    let spec = match value.trim().strip_prefix("bytes=") {
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        _ => return RangeRequest::Ignored,
+    };
+    let (first, last) = match spec.split_once('-') {
+        Some(pair) => pair,
+        None => return RangeRequest::Ignored,
+    };
+    let parse = |s: &str| if s.is_empty() { Ok(None) } else { s.parse::<u64>().map(Some) };
+    match (parse(first), parse(last)) {
+        (Ok(Some(first)), Ok(last)) => {
+            if matches!(last, Some(last) if last < first) {
+                RangeRequest::Ignored
+            } else if first >= length {
+                RangeRequest::NotSatisfiable
+            } else {
+                let end = last.map_or(length, |last| (last + 1).min(length));
+                RangeRequest::Satisfiable(first, end)
+            }
+        },
+        // A suffix range requests the last bytes of the resource.
+        (Ok(None), Ok(Some(suffix_length))) => {
+            if suffix_length == 0 || length == 0 {
+                RangeRequest::NotSatisfiable
+            } else {
+                RangeRequest::Satisfiable(length - suffix_length.min(length), length)
+            }
+        },
+        _ => RangeRequest::Ignored,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_payload_map() {
+        let mut map = PayloadMap::new(1000);
+        map.add_point(2000, 900);
+        map.add_point(3000, 1800);
+        assert_eq!(map.anchor(), 1000);
+        assert_eq!(map.last_point(), (3000, 1800));
+        assert_eq!(map.payload_offset(2000), Some(900));
+        assert_eq!(map.payload_offset(2500), None);
+        assert_eq!(map.point_before(0), (1000, 0));
+        assert_eq!(map.point_before(899), (1000, 0));
+        assert_eq!(map.point_before(900), (2000, 900));
+        assert_eq!(map.point_before(5000), (3000, 1800));
+        assert_eq!(map.point_at_or_after(0), Some((1000, 0)));
+        assert_eq!(map.point_at_or_after(1), Some((2000, 900)));
+        assert_eq!(map.point_at_or_after(1800), Some((3000, 1800)));
+        assert_eq!(map.point_at_or_after(1801), None);
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-499", 1000), RangeRequest::Satisfiable(0, 500));
+        assert_eq!(parse_range("bytes=500-", 1000), RangeRequest::Satisfiable(500, 1000));
+        assert_eq!(parse_range("bytes=900-2000", 1000), RangeRequest::Satisfiable(900, 1000));
+        assert_eq!(parse_range("bytes=-300", 1000), RangeRequest::Satisfiable(700, 1000));
+        assert_eq!(parse_range("bytes=-3000", 1000), RangeRequest::Satisfiable(0, 1000));
+        assert_eq!(parse_range("bytes=1000-", 1000), RangeRequest::NotSatisfiable);
+        assert_eq!(parse_range("bytes=-0", 1000), RangeRequest::NotSatisfiable);
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), RangeRequest::Ignored);
+        assert_eq!(parse_range("bytes=5-1", 1000), RangeRequest::Ignored);
+        assert_eq!(parse_range("items=0-1", 1000), RangeRequest::Ignored);
+        assert_eq!(parse_range("bytes=a-b", 1000), RangeRequest::Ignored);
+    }
+}
@@ -0,0 +1,176 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Sprite sheets and WebVTT thumbnail tracks for scrub previews.
+//
+// A thumbnail track is a WebVTT file with one cue per interval of the time range.
+// The text of each cue is the URI of a sprite sheet with a media fragment (#xywh=x,y,w,h) that identifies the tile.
+// Each sprite sheet contains up to TILES_PER_SHEET tiles arranged in rows of up to COLUMNS tiles.
+
+/// Maximum number of tiles in a single sprite sheet.
+pub const TILES_PER_SHEET: usize = 100;
+/// Maximum number of tiles in each row of a sprite sheet.
+pub const COLUMNS: usize = 10;
+/// Tiles are RGBx.
+pub const BYTES_PER_PIXEL: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpriteLayout {
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub tile_count: usize,
+}
+
+impl SpriteLayout {
+    pub fn columns(&self) -> usize {
+        self.tile_count.clamp(1, COLUMNS)
+    }
+
+    pub fn rows(&self) -> usize {
+        div_ceil(self.tile_count, self.columns()).max(1)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.tile_width * self.columns() as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.tile_height * self.rows() as u32
+    }
+
+    /// Returns the x and y pixel coordinates of the top-left corner of a tile.
+    pub fn tile_position(&self, tile: usize) -> (u32, u32) {
+        let column = tile % self.columns();
+        let row = tile / self.columns();
+        (column as u32 * self.tile_width, row as u32 * self.tile_height)
+    }
+
+    /// Copy RGBx tiles into an RGBx sprite sheet. Missing tiles will be black.
+    pub fn compose(&self, tiles: &[Option<Vec<u8>>]) -> Vec<u8> {
+        let sheet_stride = self.width() as usize * BYTES_PER_PIXEL;
+        let tile_stride = self.tile_width as usize * BYTES_PER_PIXEL;
+        let mut sheet = vec![0; sheet_stride * self.height() as usize];
+        for (i, tile) in tiles.iter().enumerate().take(self.tile_count) {
+            let tile = match tile {
+                Some(tile) if tile.len() == tile_stride * self.tile_height as usize => tile,
+                _ => continue,
+            };
+            let (x, y) = self.tile_position(i);
+            for (row, tile_row) in tile.chunks_exact(tile_stride).enumerate() {
+                let begin = (y as usize + row) * sheet_stride + x as usize * BYTES_PER_PIXEL;
+                sheet[begin..begin + tile_stride].copy_from_slice(tile_row);
+            }
+        }
+        sheet
+    }
+}
+
+fn div_ceil(n: usize, d: usize) -> usize {
+    if n == 0 { 0 } else { (n - 1) / d + 1 }
+}
+
+/// Returns the number of tiles needed to cover a duration.
+pub fn tile_count(duration_nanos: u64, interval_nanos: u64) -> usize {
+    div_ceil(duration_nanos as usize, interval_nanos as usize)
+}
+
+/// Returns the number of sprite sheets needed for a number of tiles.
+pub fn sheet_count(tile_count: usize) -> usize {
+    div_ceil(tile_count, TILES_PER_SHEET)
+}
+
+/// Returns the indexes of the tiles in a sprite sheet.
+pub fn sheet_tiles(sheet: usize, tile_count: usize) -> std::ops::Range<usize> {
+    let begin = (sheet * TILES_PER_SHEET).min(tile_count);
+    let end = ((sheet + 1) * TILES_PER_SHEET).min(tile_count);
+    begin..end
+}
+
+/// Format a time in the WebVTT format, such as "01:02:03.456".
+fn format_vtt_time(nanos: u64) -> String {
+    let millis = nanos / 1_000_000;
+    format!("{:02}:{:02}:{:02}.{:03}", millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, millis % 1000)
+}
+
+/// Build a WebVTT thumbnail track. Cue times are relative to the beginning of the time range.
+/// sprite_uri returns the URI of a sprite sheet.
+pub fn build_webvtt(
+    duration_nanos: u64,
+    interval_nanos: u64,
+    tile_width: u32,
+    tile_height: u32,
+    sprite_uri: impl Fn(usize) -> String,
+) -> String {
+    let tile_count = tile_count(duration_nanos, interval_nanos);
+    let mut vtt = String::from("WEBVTT\n");
+    for sheet in 0..sheet_count(tile_count) {
+        let tiles = sheet_tiles(sheet, tile_count);
+        let layout = SpriteLayout { tile_width, tile_height, tile_count: tiles.len() };
+        let uri = sprite_uri(sheet);
+        for (i, tile) in tiles.enumerate() {
+            let cue_begin_nanos = tile as u64 * interval_nanos;
+            let cue_end_nanos = (cue_begin_nanos + interval_nanos).min(duration_nanos);
+            let (x, y) = layout.tile_position(i);
+            vtt.push_str(&format!("\n{} --> {}\n{}#xywh={},{},{},{}\n",
+                format_vtt_time(cue_begin_nanos), format_vtt_time(cue_end_nanos), uri, x, y, tile_width, tile_height));
+        }
+    }
+    vtt
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SECOND: u64 = 1_000_000_000;
+
+    #[test]
+    fn test_layout() {
+        let layout = SpriteLayout { tile_width: 160, tile_height: 90, tile_count: 25 };
+        assert_eq!(layout.columns(), 10);
+        assert_eq!(layout.rows(), 3);
+        assert_eq!((layout.width(), layout.height()), (1600, 270));
+        assert_eq!(layout.tile_position(0), (0, 0));
+        assert_eq!(layout.tile_position(12), (320, 90));
+        let layout = SpriteLayout { tile_width: 160, tile_height: 90, tile_count: 3 };
+        assert_eq!((layout.width(), layout.height()), (480, 90));
+    }
+
+    #[test]
+    fn test_compose() {
+        let layout = SpriteLayout { tile_width: 2, tile_height: 2, tile_count: 3 };
+        let tile = |value: u8| Some(vec![value; 2 * 2 * BYTES_PER_PIXEL]);
+        let sheet = layout.compose(&[tile(1), None, tile(3)]);
+        let stride = 6 * BYTES_PER_PIXEL;
+        assert_eq!(sheet.len(), stride * 2);
+        assert!(sheet[..2 * BYTES_PER_PIXEL].iter().all(|b| *b == 1));
+        assert!(sheet[stride..stride + 2 * BYTES_PER_PIXEL].iter().all(|b| *b == 1));
+        assert!(sheet[2 * BYTES_PER_PIXEL..4 * BYTES_PER_PIXEL].iter().all(|b| *b == 0));
+        assert!(sheet[stride + 4 * BYTES_PER_PIXEL..].iter().all(|b| *b == 3));
+    }
+
+    #[test]
+    fn test_sheets() {
+        assert_eq!(tile_count(95 * SECOND, 10 * SECOND), 10);
+        assert_eq!(tile_count(100 * SECOND, 10 * SECOND), 10);
+        assert_eq!(sheet_count(250), 3);
+        assert_eq!(sheet_tiles(2, 250), 200..250);
+        assert_eq!(sheet_tiles(3, 250), 250..250);
+    }
+
+    #[test]
+    fn test_build_webvtt() {
+        let vtt = build_webvtt(3605 * SECOND, 10 * SECOND, 160, 90, |sheet| format!("sprite.jpg?sheet={}", sheet));
+        assert!(vtt.starts_with("WEBVTT\n\n00:00:00.000 --> 00:00:10.000\nsprite.jpg?sheet=0#xywh=0,0,160,90\n"));
+        assert!(vtt.contains("\n00:16:50.000 --> 00:17:00.000\nsprite.jpg?sheet=1#xywh=160,0,160,90\n"));
+        assert!(vtt.ends_with("\n01:00:00.000 --> 01:00:05.000\nsprite.jpg?sheet=3#xywh=0,540,160,90\n"));
+        assert_eq!(vtt.matches(" --> ").count(), 361);
+    }
+}
@@ -0,0 +1,94 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Statistics of a video stream computed from its index records.
+
+use pravega_video::index::IndexRecord;
+use pravega_video::timestamp::PravegaTimestamp;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexSummary {
+    pub first_timestamp: PravegaTimestamp,
+    pub last_timestamp: PravegaTimestamp,
+    /// Number of index records.
+    pub record_count: usize,
+    /// Average bits per second of the data stream between the first and last index records.
+    pub average_bitrate: Option<f64>,
+    /// Average number of seconds between random-access index records.
+    pub key_frame_interval_seconds: Option<f64>,
+}
+
+impl IndexSummary {
+    pub fn duration_seconds(&self) -> Option<f64> {
+        match (self.first_timestamp.nanoseconds(), self.last_timestamp.nanoseconds()) {
+            (Some(first), Some(last)) if last >= first => Some((last - first) as f64 * 1e-9),
+            _ => None,
+        }
+    }
+}
+
+/// Summarize the index records of a stream.
+/// Gaps in the recording are included in the duration so the averages are lower than during recording.
+pub fn summarize_index(index_records: &[IndexRecord]) -> IndexSummary {
+    let first = index_records.first();
+    let last = index_records.last();
+    let mut summary = IndexSummary {
+        first_timestamp: first.map_or(PravegaTimestamp::NONE, |r| r.timestamp),
+        last_timestamp: last.map_or(PravegaTimestamp::NONE, |r| r.timestamp),
+        record_count: index_records.len(),
+        average_bitrate: None,
+        key_frame_interval_seconds: None,
+    };
+    let duration_seconds = match summary.duration_seconds() {
+        Some(duration_seconds) if duration_seconds > 0.0 => duration_seconds,
+        _ => return summary,
+    };
+    if let (Some(first), Some(last)) = (first, last) {
+        if last.offset >= first.offset {
+            summary.average_bitrate = Some((last.offset - first.offset) as f64 * 8.0 / duration_seconds);
+        }
+    }
+    let key_frame_count = index_records.iter().filter(|r| r.random_access).count();
+    if key_frame_count > 1 {
+        summary.key_frame_interval_seconds = Some(duration_seconds / (key_frame_count - 1) as f64);
+    }
+    summary
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SECOND: u64 = 1_000_000_000;
+    const T0: u64 = 1_600_000_000 * SECOND;
+
+    #[test]
+    fn test_summarize_index() {
+        // 1 Mbps with a key frame every 2 seconds and a non-random-access record between them.
+        let index_records: Vec<IndexRecord> = (0..11u64).map(|i| {
+            let timestamp = PravegaTimestamp::from_nanoseconds(Some(T0 + i * SECOND));
+            IndexRecord::new(timestamp, i * 125_000, i % 2 == 0, false)
+        }).collect();
+        let summary = summarize_index(&index_records);
+        assert_eq!(summary.record_count, 11);
+        assert_eq!(summary.duration_seconds(), Some(10.0));
+        assert_eq!(summary.average_bitrate, Some(1_000_000.0));
+        assert_eq!(summary.key_frame_interval_seconds, Some(2.0));
+
+        let summary = summarize_index(&index_records[..1]);
+        assert_eq!(summary.first_timestamp, summary.last_timestamp);
+        assert_eq!(summary.average_bitrate, None);
+        assert_eq!(summary.key_frame_interval_seconds, None);
+
+        let summary = summarize_index(&[]);
+        assert_eq!(summary.record_count, 0);
+        assert_eq!(summary.duration_seconds(), None);
+    }
+}
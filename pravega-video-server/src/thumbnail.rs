@@ -0,0 +1,230 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Thumbnails decoded from key frames.
+//
+// A GStreamer pipeline reads the events from a key frame to the next index record with pravegasrc,
+// decodes them in software with decodebin, and returns the first frame, scaled and encoded as JPEG or PNG.
+// Sprite sheets are composed from raw frames, then encoded as JPEG.
+
+use anyhow::anyhow;
+use gst::prelude::*;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+#[allow(unused_imports)]
+use tracing::{debug, error, info, warn, trace};
+use super::gst_util;
+use super::sprite::SpriteLayout;
+
+/// Decoding a key frame should take much less than this.
+const DECODE_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL_MS: u64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+}
+
+impl ImageFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Png => "image/png",
+        }
+    }
+
+    fn encoder(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "jpegenc",
+            ImageFormat::Png => "pngenc snapshot=false",
+        }
+    }
+}
+
+/// The events to read to decode a single key frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyFrame {
+    /// The timestamp of the index record of the key frame, in ISO 8601 format.
+    pub begin_utc: String,
+    /// The timestamp of the next index record, in ISO 8601 format.
+    /// If None, events will be read until the end of the stream when the pipeline starts.
+    pub end_utc: Option<String>,
+}
+
+/// Decodes thumbnails and limits the number of concurrent decoding pipelines.
+#[derive(Clone)]
+pub struct Thumbnailer {
+    controller: String,
+    keycloak_file: String,
+    semaphore: Arc<Semaphore>,
+}
+
+impl Thumbnailer {
+    pub fn new(controller: String, keycloak_file: String, max_concurrent_thumbnails: usize) -> Self {
+        Self {
+            controller,
+            keycloak_file,
+            semaphore: Arc::new(Semaphore::new(max_concurrent_thumbnails)),
+        }
+    }
+
+    /// Decode a key frame and return it as an image.
+    /// If only one of width or height is specified, the aspect ratio will be preserved.
+    /// Returns None if the events do not contain a video frame.
+    pub async fn snapshot(
+        &self,
+        stream: String,
+        key_frame: KeyFrame,
+        width: Option<u32>,
+        height: Option<u32>,
+        format: ImageFormat,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        let _permit = self.semaphore.acquire().await?;
+        let mut caps = "video/x-raw,pixel-aspect-ratio=1/1".to_owned();
+        if let Some(width) = width {
+            caps.push_str(&format!(",width={}", width));
+        }
+        if let Some(height) = height {
+            caps.push_str(&format!(",height={}", height));
+        }
+        let tail = format!("{} ! {}", caps, format.encoder());
+        let this = self.clone();
+        tokio::task::spawn_blocking(move || this.decode_key_frame(&stream, &key_frame, &tail)).await?
+    }
+
+    /// Decode key frames and return a sprite sheet as JPEG.
+    /// Tiles without a key frame will be black.
+    pub async fn sprite(
+        &self,
+        stream: String,
+        key_frames: Vec<Option<KeyFrame>>,
+        layout: SpriteLayout,
+    ) -> anyhow::Result<Vec<u8>> {
+        let _permit = self.semaphore.acquire().await?;
+        let this = self.clone();
+        tokio::task::spawn_blocking(move || {
+            // Tiles are letterboxed to preserve the aspect ratio of the video.
+            let tail = format!("video/x-raw,format=RGBx,width={},height={},pixel-aspect-ratio=1/1",
+                layout.tile_width, layout.tile_height);
+            let mut tiles: Vec<Option<Vec<u8>>> = Vec::new();
+            for (i, key_frame) in key_frames.iter().enumerate() {
+                // Consecutive tiles often have the same key frame when the interval is short.
+                let tile = match key_frame {
+                    Some(_) if i > 0 && key_frames[i - 1] == *key_frame => tiles[i - 1].clone(),
+                    Some(key_frame) => this.decode_key_frame(&stream, key_frame, &tail)?,
+                    None => None,
+                };
+                tiles.push(tile);
+            }
+            let sheet = layout.compose(&tiles);
+            encode_rgbx(sheet, layout.width(), layout.height(), ImageFormat::Jpeg)
+        }).await?
+    }
+
+    /// Run a pipeline that decodes a key frame and return the first buffer produced by tail.
+    /// This blocks until the buffer is available.
+    fn decode_key_frame(&self, stream: &str, key_frame: &KeyFrame, tail: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        debug!("decode_key_frame: stream={}, key_frame={:?}, tail={}", stream, key_frame, tail);
+        let end_mode = if key_frame.end_utc.is_some() { "timestamp" } else { "latest" };
+        let pipeline_description = format!(
+            "pravegasrc name=src start-mode=timestamp end-mode={} allow-create-scope=false \
+            ! decodebin name=decodebin \
+            videoconvert name=convert ! videoscale add-borders=true ! {} ! appsink name=sink sync=false",
+            end_mode, tail);
+        let pipeline = gst::parse_launch(&pipeline_description)?;
+        let pipeline = pipeline.dynamic_cast::<gst::Pipeline>().unwrap();
+
+        let pravegasrc = pipeline.by_name("src").unwrap();
+        pravegasrc.set_property("controller", &self.controller)?;
+        pravegasrc.set_property("keycloak-file", &self.keycloak_file)?;
+        pravegasrc.set_property("stream", &stream)?;
+        pravegasrc.set_property("start-utc", &key_frame.begin_utc)?;
+        if let Some(end_utc) = &key_frame.end_utc {
+            pravegasrc.set_property("end-utc", end_utc)?;
+        }
+
+        // Link the video stream from decodebin. Other streams are ignored.
+        gst_util::connect_pad_added(&pipeline, "decodebin", |pipeline, decodebin, src_pad| {
+            let is_video = gst_util::media_type(src_pad).map(|media_type| media_type.starts_with("video/")).unwrap_or_default();
+            if !is_video {
+                return;
+            }
+            let convert = pipeline.by_name("convert").unwrap();
+            let sink_pad = convert.static_pad("sink").unwrap();
+            if sink_pad.is_linked() {
+                warn!("decode_key_frame: Ignoring additional video stream from pad {}", src_pad.name());
+                return;
+            }
+            if let Err(err) = src_pad.link(&sink_pad) {
+                gst::element_error!(
+                    decodebin,
+                    gst::LibraryError::Failed,
+                    ("Failed to link video stream"),
+                    ["{:?}", err]
+                );
+            }
+        });
+
+        let appsink = pipeline.by_name("sink").unwrap().dynamic_cast::<gstreamer_app::AppSink>().unwrap();
+        let bus = pipeline.bus().unwrap();
+        pipeline.set_state(gst::State::Playing)?;
+        let start = Instant::now();
+        let result = loop {
+            if let Some(sample) = appsink.try_pull_sample(POLL_INTERVAL_MS * gst::MSECOND) {
+                let buffer = sample.buffer().ok_or_else(|| anyhow!("Sample has no buffer"))?;
+                let map = buffer.map_readable()?;
+                break Ok(Some(map.as_slice().to_vec()));
+            }
+            if appsink.is_eos() {
+                warn!("decode_key_frame: No video frame was decoded");
+                break Ok(None);
+            }
+            if let Some(err) = bus.pop_filtered(&[gst::MessageType::Error]).and_then(|msg| gst_util::message_error(&msg)) {
+                break Err(err);
+            }
+            if start.elapsed() > DECODE_TIMEOUT {
+                break Err(anyhow!("Timed out decoding key frame"));
+            }
+        };
+        pipeline.set_state(gst::State::Null)?;
+        result
+    }
+}
+
+/// Encode a raw RGBx image.
+fn encode_rgbx(image: Vec<u8>, width: u32, height: u32, format: ImageFormat) -> anyhow::Result<Vec<u8>> {
+    let pipeline_description = format!(
+        "appsrc name=src caps=video/x-raw,format=RGBx,width={},height={},framerate=0/1 \
+        ! videoconvert ! {} ! appsink name=sink sync=false",
+        width, height, format.encoder());
+    let pipeline = gst::parse_launch(&pipeline_description)?;
+    let pipeline = pipeline.dynamic_cast::<gst::Pipeline>().unwrap();
+    let appsrc = pipeline.by_name("src").unwrap().dynamic_cast::<gstreamer_app::AppSrc>().unwrap();
+    let appsink = pipeline.by_name("sink").unwrap().dynamic_cast::<gstreamer_app::AppSink>().unwrap();
+    pipeline.set_state(gst::State::Playing)?;
+    appsrc.push_buffer(gst::Buffer::from_mut_slice(image))?;
+    appsrc.end_of_stream()?;
+    let result = appsink.pull_sample()
+        .map_err(|_| anyhow!("Failed to encode image"))
+        .and_then(|sample| {
+            let buffer = sample.buffer().ok_or_else(|| anyhow!("Sample has no buffer"))?;
+            let map = buffer.map_readable()?;
+            Ok(map.as_slice().to_vec())
+        });
+    pipeline.set_state(gst::State::Null)?;
+    result
+}
+
+/// Initialize GStreamer. This must be called before decoding thumbnails.
+pub fn init() -> anyhow::Result<()> {
+    gst_util::init(&["pravegasrc", "decodebin", "videoconvert", "videoscale", "jpegenc", "pngenc", "appsrc", "appsink"])
+}
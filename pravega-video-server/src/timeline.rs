@@ -0,0 +1,162 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Recording timelines computed from index records.
+//
+// Each pair of consecutive index records is either recorded, a gap, or a discontinuity.
+// These are detected in the same way as for HLS playlists:
+// a gap is an increase in the timestamp of much more than the target duration, which is an exponential
+// moving average of the durations between index records, and a discontinuity is a timestamp that decreases or is missing.
+// Recorded intervals separated by less than the resolution are merged so that the size of the timeline is bounded.
+
+use pravega_video::index::IndexRecord;
+use pravega_video::timestamp::PravegaTimestamp;
+use serde_derive::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub begin_nanos: u64,
+    pub end_nanos: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscontinuityReason {
+    /// The timestamp decreased.
+    Rewind,
+    /// An index record has no timestamp.
+    MissingTimestamp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Discontinuity {
+    /// The timestamp of the index record before the discontinuity.
+    pub timestamp_nanos: u64,
+    pub reason: DiscontinuityReason,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Timeline {
+    /// Recorded intervals, in the order of the index records.
+    pub intervals: Vec<Interval>,
+    /// The intervals between consecutive recorded intervals. There is no gap at a rewind.
+    pub gaps: Vec<Interval>,
+    pub discontinuities: Vec<Discontinuity>,
+}
+
+/// Build a timeline from consecutive index records, limited to the time range [begin_nanos, end_nanos).
+/// Recorded intervals separated by less than resolution_nanos are merged,
+/// and only the first discontinuity within resolution_nanos is included.
+pub fn build_timeline(index_records: &[IndexRecord], begin_nanos: u64, end_nanos: u64, resolution_nanos: u64) -> Timeline {
+    // Initial value for target duration. This will be updated with an exponential moving average.
+    let mut target_duration_seconds = 10.0;
+    let mut timeline = Timeline::default();
+    for pair in index_records.windows(2) {
+        let (prev_index_record, index_record) = (&pair[0], &pair[1]);
+        let (prev_nanos, nanos) = match (prev_index_record.timestamp.nanoseconds(), index_record.timestamp.nanoseconds()) {
+            (Some(prev_nanos), Some(nanos)) => (prev_nanos, nanos),
+            (prev_nanos, _) => {
+                if let Some(prev_nanos) = prev_nanos {
+                    add_discontinuity(&mut timeline, prev_nanos, DiscontinuityReason::MissingTimestamp, resolution_nanos);
+                }
+                continue;
+            },
+        };
+        if nanos < prev_nanos {
+            add_discontinuity(&mut timeline, prev_nanos, DiscontinuityReason::Rewind, resolution_nanos);
+            continue;
+        }
+        let duration_seconds = (nanos - prev_nanos) as f64 * 1e-9;
+        if duration_seconds > target_duration_seconds + 1.0 {
+            // This is a gap.
+            continue;
+        }
+        let ema_alpha = 0.1;
+        target_duration_seconds = ema_alpha * duration_seconds + (1.0 - ema_alpha) * target_duration_seconds;
+        let begin = prev_nanos.max(begin_nanos);
+        let end = nanos.min(end_nanos);
+        if begin >= end {
+            continue;
+        }
+        match timeline.intervals.last_mut() {
+            Some(interval) if begin <= interval.end_nanos.saturating_add(resolution_nanos) && begin >= interval.begin_nanos => {
+                interval.end_nanos = interval.end_nanos.max(end);
+            },
+            _ => timeline.intervals.push(Interval { begin_nanos: begin, end_nanos: end }),
+        }
+    }
+    timeline.gaps = timeline.intervals.windows(2)
+        .filter(|pair| pair[1].begin_nanos > pair[0].end_nanos)
+        .map(|pair| Interval { begin_nanos: pair[0].end_nanos, end_nanos: pair[1].begin_nanos })
+        .collect();
+    timeline.discontinuities.retain(|d| begin_nanos <= d.timestamp_nanos && d.timestamp_nanos < end_nanos);
+    timeline
+}
+
+fn add_discontinuity(timeline: &mut Timeline, timestamp_nanos: u64, reason: DiscontinuityReason, resolution_nanos: u64) {
+    if let Some(last) = timeline.discontinuities.last() {
+        if timestamp_nanos >= last.timestamp_nanos && timestamp_nanos - last.timestamp_nanos < resolution_nanos {
+            return;
+        }
+    }
+    timeline.discontinuities.push(Discontinuity { timestamp_nanos, reason });
+}
+
+/// Format nanoseconds since the TAI epoch in ISO 8601 format.
+pub fn to_iso_8601(nanos: u64) -> String {
+    PravegaTimestamp::from_nanoseconds(Some(nanos)).to_iso_8601().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SECOND: u64 = 1_000_000_000;
+    const T0: u64 = 1_600_000_000 * SECOND;
+
+    fn make_records(times_seconds: &[Option<u64>]) -> Vec<IndexRecord> {
+        times_seconds.iter().enumerate().map(|(i, t)| {
+            let timestamp = PravegaTimestamp::from_nanoseconds(t.map(|t| T0 + t * SECOND));
+            IndexRecord::new(timestamp, 1000 * i as u64, true, false)
+        }).collect()
+    }
+
+    fn interval(begin_seconds: u64, end_seconds: u64) -> Interval {
+        Interval { begin_nanos: T0 + begin_seconds * SECOND, end_nanos: T0 + end_seconds * SECOND }
+    }
+
+    #[test]
+    fn test_build_timeline() {
+        // A 60 second gap after 6 seconds, a missing timestamp after 70 seconds, and a rewind after 80 seconds.
+        let records = make_records(&[
+            Some(0), Some(2), Some(4), Some(6), Some(66), Some(68), Some(70), None, Some(72), Some(74),
+            Some(76), Some(78), Some(80), Some(50), Some(52),
+        ]);
+        let timeline = build_timeline(&records, T0, T0 + 1000 * SECOND, 0);
+        assert_eq!(timeline.intervals, vec![interval(0, 6), interval(66, 70), interval(72, 80), interval(50, 52)]);
+        assert_eq!(timeline.gaps, vec![interval(6, 66), interval(70, 72)]);
+        assert_eq!(timeline.discontinuities, vec![
+            Discontinuity { timestamp_nanos: T0 + 70 * SECOND, reason: DiscontinuityReason::MissingTimestamp },
+            Discontinuity { timestamp_nanos: T0 + 80 * SECOND, reason: DiscontinuityReason::Rewind },
+        ]);
+    }
+
+    #[test]
+    fn test_build_timeline_resolution() {
+        let records = make_records(&[Some(0), Some(2), Some(4), Some(30), Some(32), Some(100), Some(102)]);
+        // Gaps of less than 60 seconds are merged.
+        let timeline = build_timeline(&records, T0, T0 + 1000 * SECOND, 60 * SECOND);
+        assert_eq!(timeline.intervals, vec![interval(0, 32), interval(100, 102)]);
+        assert_eq!(timeline.gaps, vec![interval(32, 100)]);
+        // Intervals are limited to the time range.
+        let timeline = build_timeline(&records, T0 + SECOND, T0 + 31 * SECOND, 0);
+        assert_eq!(timeline.intervals, vec![interval(1, 4), interval(30, 31)]);
+    }
+}
@@ -0,0 +1,211 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// HTTPS with a certificate and private key that are reloaded when their files change.
+//
+// Warp's built-in TLS loads the certificate only at startup, so connections are accepted here and served by Hyper.
+// Each TLS handshake uses the certificate that was most recently loaded.
+// This allows certificates to be renewed, such as by cert-manager updating a Kubernetes secret, without a restart.
+
+use anyhow::{anyhow, Context};
+use hyper::server::conn::Http;
+use std::fs::File;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::{ClientHello, NoClientAuth, PrivateKey, ResolvesServerCert, ServerConfig};
+use tokio_rustls::rustls::internal::pemfile;
+use tokio_rustls::rustls::sign::{self, CertifiedKey};
+#[allow(unused_imports)]
+use tracing::{debug, error, info, warn, trace};
+use warp::{Filter, Reply};
+
+/// How often the certificate and key files are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// PEM file with the certificate chain, beginning with the server certificate.
+    pub cert_file: PathBuf,
+    /// PEM file with a PKCS#8 or RSA private key.
+    pub key_file: PathBuf,
+}
+
+impl TlsConfig {
+    fn load(&self) -> anyhow::Result<CertifiedKey> {
+        let certs = pemfile::certs(&mut self.open(&self.cert_file)?)
+            .map_err(|_| anyhow!("Unable to parse certificates in {}", self.cert_file.display()))?;
+        if certs.is_empty() {
+            anyhow::bail!("No certificates found in {}", self.cert_file.display());
+        }
+        let key = self.load_key()?;
+        let key = sign::any_supported_type(&key)
+            .map_err(|_| anyhow!("Unsupported private key type in {}", self.key_file.display()))?;
+        Ok(CertifiedKey::new(certs, Arc::new(key)))
+    }
+
+    fn load_key(&self) -> anyhow::Result<PrivateKey> {
+        let parse_error = || anyhow!("Unable to parse private key in {}", self.key_file.display());
+        let mut keys = pemfile::pkcs8_private_keys(&mut self.open(&self.key_file)?).map_err(|_| parse_error())?;
+        if keys.is_empty() {
+            keys = pemfile::rsa_private_keys(&mut self.open(&self.key_file)?).map_err(|_| parse_error())?;
+        }
+        keys.into_iter().next().ok_or_else(|| anyhow!("No private key found in {}", self.key_file.display()))
+    }
+
+    fn open(&self, path: &Path) -> anyhow::Result<BufReader<File>> {
+        let file = File::open(path).with_context(|| format!("Unable to open {}", path.display()))?;
+        Ok(BufReader::new(file))
+    }
+
+    /// Returns the modification times of the certificate and key files.
+    /// Metadata follows symbolic links so replacing the target of a link is detected.
+    fn modified(&self) -> Option<(SystemTime, SystemTime)> {
+        let modified = |path: &Path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        Some((modified(&self.cert_file)?, modified(&self.key_file)?))
+    }
+}
+
+/// Provides the most recently loaded certificate to each TLS handshake.
+struct ReloadingCertResolver {
+    certified_key: RwLock<CertifiedKey>,
+}
+
+impl ResolvesServerCert for ReloadingCertResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<CertifiedKey> {
+        Some(self.certified_key.read().unwrap().clone())
+    }
+}
+
+/// Reload the certificate and key when either file is modified.
+/// If they cannot be loaded, such as when only one of the files has been replaced so far,
+/// the previous certificate continues to be used and loading is retried after the next interval.
+async fn reload_periodically(config: TlsConfig, resolver: Arc<ReloadingCertResolver>) {
+    let mut loaded_modified = config.modified();
+    loop {
+        tokio::time::sleep(RELOAD_INTERVAL).await;
+        let modified = config.modified();
+        if modified == loaded_modified {
+            continue;
+        }
+        match config.load() {
+            Ok(certified_key) => {
+                *resolver.certified_key.write().unwrap() = certified_key;
+                loaded_modified = modified;
+                info!("Reloaded TLS certificate from {}", config.cert_file.display());
+            },
+            Err(err) => warn!("Unable to reload TLS certificate; the previous certificate will be used: {:#}", err),
+        }
+    }
+}
+
+/// Serve HTTPS on the address. This returns only if the listener fails.
+/// The certificate and key must be valid when this is called.
+pub async fn serve<F, R>(filter: F, addr: SocketAddr, config: TlsConfig) -> anyhow::Result<()>
+where
+    F: Filter<Extract = (R,), Error = warp::Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    let certified_key = config.load()?;
+    info!("Loaded TLS certificate from {}", config.cert_file.display());
+    let resolver = Arc::new(ReloadingCertResolver {
+        certified_key: RwLock::new(certified_key),
+    });
+    tokio::spawn(reload_periodically(config, resolver.clone()));
+
+    let mut server_config = ServerConfig::new(NoClientAuth::new());
+    server_config.cert_resolver = resolver;
+    server_config.set_protocols(&[b"h2".to_vec(), b"http/1.1".to_vec()]);
+    let acceptor = TlsAcceptor::from(Arc::new(server_config));
+
+    let service = warp::service(filter);
+    let listener = TcpListener::bind(addr).await.with_context(|| format!("Unable to listen on {}", addr))?;
+    info!("Listening for HTTPS on {}", addr);
+    loop {
+        let (stream, remote_addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                // Errors such as too many open files are temporary. Do not retry immediately.
+                warn!("Unable to accept connection: {}", err);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            },
+        };
+        let acceptor = acceptor.clone();
+        let service = service.clone();
+        // The handshake is performed in a separate task so that a slow client does not delay other connections.
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    debug!("TLS handshake with {} failed: {}", remote_addr, err);
+                    return;
+                },
+            };
+            if let Err(err) = Http::new().serve_connection(stream, service).await {
+                debug!("Connection with {} failed: {}", remote_addr, err);
+            }
+        });
+    }
+}
+
+/// Returns the HTTPS URI that a request for the path and query to the host should be redirected to.
+/// The port in the host header is replaced with the HTTPS port.
+pub fn https_location(host: &str, https_port: u16, path_and_query: &str) -> String {
+    // Remove the port but not the colons of an IPv6 address such as "[::1]:80".
+    let host_name = match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => &host[..i],
+        _ => host,
+    };
+    if https_port == 443 {
+        format!("https://{}{}", host_name, path_and_query)
+    } else {
+        format!("https://{}:{}{}", host_name, https_port, path_and_query)
+    }
+}
+
+/// Redirects all requests to the same path and query on the HTTPS port.
+/// Requests without a host header are rejected.
+pub fn https_redirect(https_port: u16) -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::header::<String>("host")
+        .and(warp::path::full())
+        .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+        .map(move |host: String, path: warp::path::FullPath, query: String| {
+            let path_and_query = if query.is_empty() {
+                path.as_str().to_owned()
+            } else {
+                format!("{}?{}", path.as_str(), query)
+            };
+            let location = https_location(&host, https_port, &path_and_query);
+            warp::http::Response::builder()
+                .status(warp::http::StatusCode::MOVED_PERMANENTLY)
+                .header("location", location)
+                .body(hyper::Body::empty())
+                .unwrap()
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_https_location() {
+        assert_eq!(https_location("example.com", 443, "/player?scope=a&stream=b"), "https://example.com/player?scope=a&stream=b");
+        assert_eq!(https_location("example.com:80", 443, "/"), "https://example.com/");
+        assert_eq!(https_location("example.com:8080", 3443, "/scopes"), "https://example.com:3443/scopes");
+        assert_eq!(https_location("[::1]:80", 3443, "/"), "https://[::1]:3443/");
+        assert_eq!(https_location("[::1]", 443, "/"), "https://[::1]/");
+    }
+}
@@ -0,0 +1,378 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Low-latency playback with WebRTC, negotiated with the WebRTC-HTTP Egress Protocol (WHEP).
+//
+// The client sends an SDP offer and receives an SDP answer. A GStreamer pipeline reads the stream with pravegasrc,
+// demuxes it with parsebin, and packetizes the H.264 video with rtph264pay for webrtcbin.
+// There is no decoding or encoding.
+// ICE candidates are not trickled. The answer is returned when ICE gathering is complete so that it contains all
+// candidates. No STUN or TURN server is used, so only host candidates are gathered.
+// A session ends when the client deletes it, the peer connection fails or closes, the peer does not connect in time,
+// or the pipeline stops.
+
+use anyhow::anyhow;
+use gst::prelude::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+#[allow(unused_imports)]
+use tracing::{debug, error, info, warn, trace};
+use super::gst_util;
+
+/// Gathering host candidates should take much less than this.
+const ICE_GATHERING_TIMEOUT: Duration = Duration::from_secs(5);
+/// A session is stopped if the peer does not connect within this time after the answer is returned.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL_MS: u64 = 10;
+/// The name of the application message that stops the pipeline of a session.
+const STOP_MESSAGE: &str = "whep-stop";
+
+#[derive(Debug, Clone)]
+struct WhepSettings {
+    /// Pravega controller in format "tcp://127.0.0.1:9090"
+    controller: String,
+    keycloak_file: String,
+    /// Stream in format "my_scope/my_stream"
+    stream: String,
+    /// Begin timestamp in RFC 3339 format. If None, playback starts at the most recent key frame.
+    begin_utc: Option<String>,
+    /// The RTP payload type of H.264 in the offer.
+    payload_type: u32,
+}
+
+/// A session that is playing.
+struct WhepSession {
+    scope_name: String,
+    stream_name: String,
+    /// The bus of the pipeline, used to stop it.
+    bus: gst::Bus,
+}
+
+/// The result of a successful negotiation.
+#[derive(Debug, Clone)]
+pub struct WhepAnswer {
+    /// The session ID, used to delete the session.
+    pub id: u64,
+    /// The SDP answer, including all ICE candidates.
+    pub sdp: String,
+}
+
+/// Starts WebRTC sessions and limits the number of concurrent sessions.
+#[derive(Clone)]
+pub struct WhepServer {
+    controller: String,
+    keycloak_file: String,
+    semaphore: Arc<Semaphore>,
+    next_id: Arc<AtomicU64>,
+    sessions: Arc<Mutex<HashMap<u64, WhepSession>>>,
+}
+
+impl WhepServer {
+    pub fn new(controller: String, keycloak_file: String, max_concurrent_sessions: usize) -> Self {
+        Self {
+            controller,
+            keycloak_file,
+            semaphore: Arc::new(Semaphore::new(max_concurrent_sessions)),
+            next_id: Arc::new(AtomicU64::new(1)),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Start a pipeline that sends the stream to the peer that made the SDP offer.
+    /// The payload type must be an H.264 payload type of the offer, from h264_payload_type.
+    /// Returns the SDP answer, or None if the maximum number of sessions are in progress.
+    pub async fn start(
+        &self,
+        scope_name: String,
+        stream_name: String,
+        begin_utc: Option<String>,
+        offer: String,
+        payload_type: u32,
+    ) -> anyhow::Result<Option<WhepAnswer>> {
+        let permit = match self.semaphore.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => return Ok(None),
+        };
+        let settings = WhepSettings {
+            controller: self.controller.clone(),
+            keycloak_file: self.keycloak_file.clone(),
+            stream: format!("{}/{}", scope_name, stream_name),
+            begin_utc,
+            payload_type,
+        };
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (pipeline, sdp, connected) = tokio::task::spawn_blocking(move || start_pipeline(id, settings, &offer)).await??;
+        let bus = pipeline.bus().unwrap();
+        self.sessions.lock().unwrap().insert(id, WhepSession { scope_name, stream_name, bus: bus.clone() });
+
+        // A client that never connects would otherwise hold the pipeline and the permit forever.
+        let sessions = self.sessions.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(CONNECT_TIMEOUT).await;
+            if !connected.load(Ordering::Relaxed) {
+                if let Some(session) = sessions.lock().unwrap().get(&id) {
+                    warn!("whep: id={}: The peer did not connect within {:?}", id, CONNECT_TIMEOUT);
+                    post_stop_message(&session.bus);
+                }
+            }
+        });
+
+        // Wait for the pipeline to finish in a separate thread because iterating the bus blocks.
+        // The permit is released when the pipeline stops.
+        let sessions = self.sessions.clone();
+        std::thread::spawn(move || {
+            let _permit: OwnedSemaphorePermit = permit;
+            match gst_util::wait_for_eos(&bus, Some(STOP_MESSAGE)) {
+                Ok(()) => info!("whep: id={}: Stopping pipeline", id),
+                Err(err) => error!("whep: id={}: {}", id, err),
+            }
+            sessions.lock().unwrap().remove(&id);
+            if let Err(err) = pipeline.set_state(gst::State::Null) {
+                error!("whep: id={}: Unable to set the pipeline to the Null state: {}", id, err);
+            }
+            info!("whep: id={}: END", id);
+        });
+        Ok(Some(WhepAnswer { id, sdp }))
+    }
+
+    /// Stop a session of the stream. Returns false if there is no such session.
+    pub fn stop(&self, scope_name: &str, stream_name: &str, id: u64) -> bool {
+        match self.sessions.lock().unwrap().get(&id) {
+            Some(session) if session.scope_name == scope_name && session.stream_name == stream_name => {
+                post_stop_message(&session.bus);
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+/// Initialize GStreamer. This must be called before starting sessions.
+pub fn init() -> anyhow::Result<()> {
+    gst_util::init(&["pravegasrc", "parsebin", "queue", "identity", "rtph264pay", "webrtcbin", "nicesink"])
+}
+
+/// Returns the RTP payload type of H.264 in the video media description of an SDP offer.
+/// Browsers offer several H.264 payload types. The first one in order of preference that
+/// uses packetization mode 1 is chosen because rtph264pay fragments large NAL units.
+pub fn h264_payload_type(offer: &str) -> Option<u32> {
+    let mut formats: Vec<u32> = Vec::new();
+    let mut h264_formats: Vec<u32> = Vec::new();
+    let mut mode_1_formats: Vec<u32> = Vec::new();
+    let mut in_video = false;
+    for line in offer.lines().map(|line| line.trim_end()) {
+        if let Some(media) = line.strip_prefix("m=") {
+            // Only the first video media description is used.
+            if in_video {
+                break;
+            }
+            in_video = media.starts_with("video ");
+            if in_video {
+                // m=video 9 UDP/TLS/RTP/SAVPF 96 97 102
+                formats = media.split_whitespace().skip(3).filter_map(|format| format.parse().ok()).collect();
+            }
+        } else if !in_video {
+            continue;
+        } else if let Some(rtpmap) = line.strip_prefix("a=rtpmap:") {
+            // a=rtpmap:102 H264/90000
+            let mut parts = rtpmap.split_whitespace();
+            if let (Some(format), Some(encoding)) = (parts.next().and_then(|format| format.parse().ok()), parts.next()) {
+                if encoding.to_ascii_uppercase().starts_with("H264/") {
+                    h264_formats.push(format);
+                }
+            }
+        } else if let Some(fmtp) = line.strip_prefix("a=fmtp:") {
+            // a=fmtp:102 level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42001f
+            let mut parts = fmtp.splitn(2, ' ');
+            if let (Some(format), Some(parameters)) = (parts.next().and_then(|format| format.parse().ok()), parts.next()) {
+                if parameters.split(';').any(|parameter| parameter.trim() == "packetization-mode=1") {
+                    mode_1_formats.push(format);
+                }
+            }
+        }
+    }
+    formats.into_iter().find(|format| h264_formats.contains(format) && mode_1_formats.contains(format))
+}
+
+fn post_stop_message(bus: &gst::Bus) {
+    if let Err(err) = bus.post(&gst::message::Application::new(gst::Structure::new_empty(STOP_MESSAGE))) {
+        warn!("whep: Unable to stop pipeline: {}", err);
+    }
+}
+
+/// Start a pipeline and negotiate with the SDP offer. This blocks until ICE gathering is complete.
+/// Returns the pipeline, the SDP answer, and a flag that is set when the peer connects.
+fn start_pipeline(id: u64, settings: WhepSettings, offer: &str) -> anyhow::Result<(gst::Pipeline, String, Arc<AtomicBool>)> {
+    info!("whep: id={}: settings={:?}", id, settings);
+    let start_mode = if settings.begin_utc.is_some() { "timestamp" } else { "latest" };
+    // Live playback sends buffers as soon as they are read, for the lowest latency.
+    // Historical playback must be paced in real time because pravegasrc reads much faster.
+    let sync = settings.begin_utc.is_some();
+    let pipeline_description = format!(
+        "pravegasrc name=src start-mode={} allow-create-scope=false ! parsebin name=parsebin", start_mode)
+        + &format!(" queue name=queue ! identity sync={}", sync)
+        + &format!(" ! rtph264pay config-interval=-1 pt={}", settings.payload_type)
+        + &format!(" ! application/x-rtp,media=video,encoding-name=H264,clock-rate=90000,payload={}", settings.payload_type)
+        + " ! webrtcbin name=webrtc bundle-policy=max-bundle";
+    info!("whep: id={}: Launch Pipeline: {}", id, pipeline_description);
+    let pipeline = gst::parse_launch(&pipeline_description)?;
+    let pipeline = pipeline.dynamic_cast::<gst::Pipeline>().unwrap();
+
+    let pravegasrc = pipeline.by_name("src").unwrap();
+    pravegasrc.set_property("controller", &settings.controller)?;
+    pravegasrc.set_property("keycloak-file", &settings.keycloak_file)?;
+    pravegasrc.set_property("stream", &settings.stream)?;
+    if let Some(begin_utc) = &settings.begin_utc {
+        pravegasrc.set_property("start-utc", begin_utc)?;
+    }
+
+    // The H.264 stream from parsebin is linked to the queue. Other streams are ignored.
+    gst_util::link_h264_video(&pipeline, "parsebin", "queue", format!("whep: id={}", id));
+
+    // The session is stopped when the browser closes the connection or it cannot be established.
+    let webrtc = pipeline.by_name("webrtc").unwrap();
+    let bus = pipeline.bus().unwrap();
+    let connected = Arc::new(AtomicBool::new(false));
+    let notify_connected = connected.clone();
+    webrtc.connect_notify(Some("connection-state"), move |webrtc, _| {
+        let state = webrtc.property("connection-state").ok()
+            .and_then(|state| state.get::<gst_webrtc::WebRTCPeerConnectionState>().ok());
+        info!("whep: id={}: connection-state={:?}", id, state);
+        if state == Some(gst_webrtc::WebRTCPeerConnectionState::Connected) {
+            notify_connected.store(true, Ordering::Relaxed);
+        }
+        if matches!(state, Some(gst_webrtc::WebRTCPeerConnectionState::Failed) | Some(gst_webrtc::WebRTCPeerConnectionState::Closed)) {
+            post_stop_message(&bus);
+        }
+    });
+
+    pipeline.set_state(gst::State::Playing)?;
+    match negotiate(id, &webrtc, offer) {
+        Ok(answer) => Ok((pipeline, answer, connected)),
+        Err(err) => {
+            if let Err(err) = pipeline.set_state(gst::State::Null) {
+                error!("whep: id={}: Unable to set the pipeline to the Null state: {}", id, err);
+            }
+            Err(err)
+        },
+    }
+}
+
+/// Apply the SDP offer, create the answer, and wait for ICE gathering to complete.
+/// Returns the local description, which includes the ICE candidates.
+fn negotiate(id: u64, webrtc: &gst::Element, offer: &str) -> anyhow::Result<String> {
+    // The video is only sent.
+    let transceiver = webrtc.emit_by_name("get-transceiver", &[&0i32])?
+        .ok_or_else(|| anyhow!("webrtcbin has no transceiver"))?
+        .get::<gst_webrtc::WebRTCRTPTransceiver>()?;
+    transceiver.set_property("direction", &gst_webrtc::WebRTCRTPTransceiverDirection::Sendonly)?;
+
+    let offer = gst_sdp::SDPMessage::parse_buffer(offer.as_bytes())
+        .map_err(|_| anyhow!("Unable to parse SDP offer"))?;
+    let offer = gst_webrtc::WebRTCSessionDescription::new(gst_webrtc::WebRTCSDPType::Offer, offer);
+    let promise = gst::Promise::new();
+    webrtc.emit_by_name("set-remote-description", &[&offer, &promise])?;
+    wait_for_promise(&promise, "set-remote-description")?;
+
+    let promise = gst::Promise::new();
+    webrtc.emit_by_name("create-answer", &[&None::<gst::Structure>, &promise])?;
+    wait_for_promise(&promise, "create-answer")?;
+    let answer = promise.get_reply()
+        .ok_or_else(|| anyhow!("create-answer did not reply"))?
+        .get::<gst_webrtc::WebRTCSessionDescription>("answer")?;
+
+    let promise = gst::Promise::new();
+    webrtc.emit_by_name("set-local-description", &[&answer, &promise])?;
+    wait_for_promise(&promise, "set-local-description")?;
+
+    let start = Instant::now();
+    loop {
+        let state = webrtc.property("ice-gathering-state")?.get::<gst_webrtc::WebRTCICEGatheringState>()?;
+        if state == gst_webrtc::WebRTCICEGatheringState::Complete {
+            break;
+        }
+        if start.elapsed() > ICE_GATHERING_TIMEOUT {
+            return Err(anyhow!("Timeout waiting for ICE gathering to complete"));
+        }
+        std::thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+    }
+    debug!("whep: id={}: ICE gathering completed in {:?}", id, start.elapsed());
+
+    let answer = webrtc.property("local-description")?.get::<gst_webrtc::WebRTCSessionDescription>()?;
+    let sdp = answer.sdp().as_text()?;
+    trace!("whep: id={}: answer={}", id, sdp);
+    Ok(sdp)
+}
+
+fn wait_for_promise(promise: &gst::Promise, signal_name: &str) -> anyhow::Result<()> {
+    match promise.wait() {
+        gst::PromiseResult::Replied => {},
+        result => return Err(anyhow!("{} failed: {:?}", signal_name, result)),
+    }
+    // webrtcbin replies with an error field if the description could not be applied.
+    if let Some(reply) = promise.get_reply() {
+        if let Ok(err) = reply.get::<gst::glib::Error>("error") {
+            return Err(anyhow!("{} failed: {}", signal_name, err));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_h264_payload_type() {
+        let offer = "v=0\r\n\
+            o=- 4611731400430051336 2 IN IP4 127.0.0.1\r\n\
+            s=-\r\n\
+            t=0 0\r\n\
+            a=group:BUNDLE 0 1\r\n\
+            m=audio 9 UDP/TLS/RTP/SAVPF 111\r\n\
+            a=mid:0\r\n\
+            a=recvonly\r\n\
+            a=rtpmap:111 opus/48000/2\r\n\
+            m=video 9 UDP/TLS/RTP/SAVPF 96 97 102 103 127\r\n\
+            a=mid:1\r\n\
+            a=recvonly\r\n\
+            a=rtpmap:96 VP8/90000\r\n\
+            a=rtpmap:97 rtx/90000\r\n\
+            a=fmtp:97 apt=96\r\n\
+            a=rtpmap:102 H264/90000\r\n\
+            a=fmtp:102 level-asymmetry-allowed=1;packetization-mode=0;profile-level-id=42001f\r\n\
+            a=rtpmap:127 H264/90000\r\n\
+            a=fmtp:127 level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f\r\n\
+            a=rtpmap:103 rtx/90000\r\n\
+            a=fmtp:103 apt=102\r\n";
+        assert_eq!(h264_payload_type(offer), Some(127));
+        // The order of the formats in the media description is the order of preference.
+        let offer = offer.replace("96 97 102 103 127", "96 127 97 102 103")
+            .replace("packetization-mode=0", "packetization-mode=1");
+        assert_eq!(h264_payload_type(&offer), Some(127));
+        let offer = offer.replace("96 127 97 102 103", "96 97 102 103 127");
+        assert_eq!(h264_payload_type(&offer), Some(102));
+    }
+
+    #[test]
+    fn test_h264_payload_type_missing() {
+        let offer = "v=0\n\
+            m=audio 9 UDP/TLS/RTP/SAVPF 102\n\
+            a=rtpmap:102 H264/90000\n\
+            a=fmtp:102 packetization-mode=1\n\
+            m=video 9 UDP/TLS/RTP/SAVPF 96\n\
+            a=rtpmap:96 VP8/90000\n";
+        assert_eq!(h264_payload_type(offer), None);
+        assert_eq!(h264_payload_type(""), None);
+    }
+}
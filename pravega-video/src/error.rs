@@ -0,0 +1,62 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Error types for the high-level video stream API.
+
+use crate::timestamp::PravegaTimestamp;
+use std::fmt;
+use std::io::ErrorKind;
+
+#[derive(Debug)]
+pub enum VideoStreamError {
+    /// The Pravega client configuration is invalid.
+    Config(String),
+    /// The index has no records so it is not possible to seek by time.
+    EmptyIndex,
+    /// An exact seek was requested but no index record has this timestamp.
+    TimestampNotFound(PravegaTimestamp),
+    /// The data or index stream contains data that could not be decoded.
+    InvalidData(String),
+    /// The operation is not allowed in the current state.
+    InvalidState(String),
+    /// Any other I/O error from Pravega.
+    Io(std::io::Error),
+}
+
+impl fmt::Display for VideoStreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VideoStreamError::Config(msg) => write!(f, "Invalid Pravega client configuration: {}", msg),
+            VideoStreamError::EmptyIndex => write!(f, "Index has no records"),
+            VideoStreamError::TimestampNotFound(timestamp) => write!(f, "No index record has timestamp {}", timestamp),
+            VideoStreamError::InvalidData(msg) => write!(f, "Invalid data: {}", msg),
+            VideoStreamError::InvalidState(msg) => write!(f, "Invalid state: {}", msg),
+            VideoStreamError::Io(error) => write!(f, "I/O error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for VideoStreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VideoStreamError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for VideoStreamError {
+    fn from(error: std::io::Error) -> Self {
+        match error.kind() {
+            ErrorKind::InvalidData => VideoStreamError::InvalidData(error.to_string()),
+            _ => VideoStreamError::Io(error),
+        }
+    }
+}
@@ -13,6 +13,7 @@
 use std::convert::TryInto;
 use std::io::{Error, ErrorKind, Read, Write};
 use enumflags2::BitFlags;
+use tokio::io::{AsyncRead, AsyncReadExt};
 use crate::timestamp::PravegaTimestamp;
 
 #[derive(BitFlags, Copy, Clone, Debug, PartialEq)]
@@ -116,17 +117,34 @@ pub struct EventWithHeader<'a> {
 */
 /// ```
 pub struct EventWriter {
+    // The encoded event. This is reused so that memory is allocated only when a larger event is written.
+    scratch: Vec<u8>,
 }
 
 impl EventWriter {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            scratch: Vec::new(),
+        }
     }
 
+    /// Writes the event header and payload with a single call to write_all.
+    /// Each event must be a single atomic append, so the header and payload are never written separately.
+    /// When the writer is a BufWriter, an event is either appended to its buffer or written to the inner writer
+    /// in one call, so an event never spans two appends.
     pub fn write<'a, W>(&mut self, event: &EventWithHeader<'a>, writer: &mut W) -> Result<(), Error>
     where
         W: Write,
     {
+        let header = self.encode_header(event)?;
+        self.scratch.clear();
+        self.scratch.extend_from_slice(&header[..]);
+        self.scratch.extend_from_slice(event.payload);
+        writer.write_all(&self.scratch[..])
+    }
+
+    /// Returns the 20 bytes that precede the payload.
+    pub fn encode_header<'a>(&self, event: &EventWithHeader<'a>) -> Result<[u8; 20], Error> {
         let mut flags = BitFlags::<EventHeaderFlags>::empty();
         if event.header.include_in_index {
             flags |= EventHeaderFlags::IncludeInIndex;
@@ -142,15 +160,12 @@ impl EventWriter {
             return Err(Error::new(ErrorKind::InvalidInput, format!("Payload of {} bytes exceeds {} bytes",
                 payload_length, EventWithHeader::MAX_PAYLOAD_SIZE)));
         }
-        let event_length: u32 = (payload_length + 12).try_into().unwrap();
-        let write_length = payload_length + 20;
-        let mut bytes_to_write: Vec<u8> = vec![0; write_length];
-        bytes_to_write[4..8].copy_from_slice(&event_length.to_be_bytes()[..]);
-        bytes_to_write[11..12].copy_from_slice(&flags.bits().to_be_bytes()[..]);
-        bytes_to_write[12..20].copy_from_slice(&event.header.timestamp.nanoseconds().unwrap_or_default().to_be_bytes()[..]);
-        bytes_to_write[20..20+payload_length].copy_from_slice(&event.payload[..]);
-        writer.write_all(&bytes_to_write).unwrap();
-        Ok(())
+        let event_length = (payload_length + 12) as u32;
+        let mut header = [0; 20];
+        header[4..8].copy_from_slice(&event_length.to_be_bytes()[..]);
+        header[11..12].copy_from_slice(&flags.bits().to_be_bytes()[..]);
+        header[12..20].copy_from_slice(&event.header.timestamp.nanoseconds().unwrap_or_default().to_be_bytes()[..]);
+        Ok(header)
     }
 }
 
@@ -181,6 +196,19 @@ impl EventReader {
         R: Read,
     {
         rdr.read_exact(&mut self.event_length_bytes[0..8])?;
+        self.parse_event_length()
+    }
+
+    // Same as read_required_buffer_length() but reads asynchronously.
+    pub async fn read_required_buffer_length_async<R>(&mut self, rdr: &mut R) -> Result<usize, Error>
+    where
+        R: AsyncRead + Unpin,
+    {
+        rdr.read_exact(&mut self.event_length_bytes[0..8]).await?;
+        self.parse_event_length()
+    }
+
+    fn parse_event_length(&mut self) -> Result<usize, Error> {
         let event_length_bytes: [u8; 4] = self.event_length_bytes[4..8].try_into().unwrap();
         self.event_length = u32::from_be_bytes(event_length_bytes) as usize;
         // Event length must be between 12 and MAX_ATOMIC_WRITE_SIZE - 8.
@@ -203,6 +231,22 @@ impl EventReader {
         }
         //  Note that bytes 0..8 of buffer are unused. However, this keeps the byte ranges consistent with the writer.
         rdr.read_exact(&mut buffer[8..self.required_buffer_length])?;
+        self.parse_event(buffer)
+    }
+
+    // Same as read_event() but reads asynchronously.
+    pub async fn read_event_async<'a, R>(&mut self, rdr: &mut R, buffer: &'a mut [u8]) -> Result<EventWithHeader<'a>, Error>
+    where
+        R: AsyncRead + Unpin,
+    {
+        if buffer.len() < self.required_buffer_length {
+            return Err(Error::new(ErrorKind::InvalidInput, "Buffer too small"))
+        }
+        rdr.read_exact(&mut buffer[8..self.required_buffer_length]).await?;
+        self.parse_event(buffer)
+    }
+
+    fn parse_event<'a>(&self, buffer: &'a [u8]) -> Result<EventWithHeader<'a>, Error> {
         let flags = BitFlags::<EventHeaderFlags>::from_bits(buffer[11]).unwrap();
         let include_in_index = flags.contains(EventHeaderFlags::IncludeInIndex);
         let random_access = flags.contains(EventHeaderFlags::RandomAccessIndicator);
@@ -242,6 +286,11 @@ impl<'a> EventWithHeader<'a> {
         }
     }
 
+    /// Returns the number of bytes that EventWriter will write for this event.
+    pub fn encoded_length(&self) -> usize {
+        self.payload.len() + 20
+    }
+
     pub fn max_payload_size() -> usize {
         EventWithHeader::MAX_PAYLOAD_SIZE
     }
@@ -254,7 +303,7 @@ mod test {
     use tracing::{info, trace};
     use rand::{RngCore, SeedableRng};
     use rand_chacha::ChaCha8Rng;
-    use std::io::{Cursor, ErrorKind};
+    use std::io::{BufWriter, Cursor, Error, ErrorKind, Write};
 
     #[test]
     fn test_event_writer_reader() {
@@ -291,4 +340,127 @@ mod test {
             }
         }
     }
+
+    #[tokio::test]
+    async fn test_event_reader_async() {
+        let payload = vec![1, 2, 3, 4, 5];
+        let event = EventWithHeader::new(
+            &payload[..],
+            PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000)),
+            true, true, false);
+        let mut serialized_bytes_cursor = Cursor::new(Vec::new());
+        EventWriter::new().write(&event, &mut serialized_bytes_cursor).unwrap();
+        serialized_bytes_cursor.set_position(0);
+        let mut event_reader = EventReader::new();
+        let required_buffer_length = event_reader.read_required_buffer_length_async(&mut serialized_bytes_cursor).await.unwrap();
+        assert_eq!(required_buffer_length, 20 + payload.len());
+        let mut read_buffer: Vec<u8> = vec![0; required_buffer_length];
+        let deserialized_event = event_reader.read_event_async(&mut serialized_bytes_cursor, &mut read_buffer[..]).await.unwrap();
+        assert_eq!(event, deserialized_event);
+        // Reading at the end of the stream should return UnexpectedEof.
+        let err = event_reader.read_required_buffer_length_async(&mut serialized_bytes_cursor).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    /// A writer that accepts at most 7 bytes per call and then fails after a limit.
+    struct LimitedWriter {
+        data: Vec<u8>,
+        limit: usize,
+    }
+
+    impl Write for LimitedWriter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            if self.data.len() >= self.limit {
+                return Err(Error::new(ErrorKind::Other, "Limit reached"));
+            }
+            let n = buf.len().min(7).min(self.limit - self.data.len());
+            self.data.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_event_writer_partial_writes() {
+        let payload: Vec<u8> = (0..188).map(|i| i as u8).collect();
+        let event = EventWithHeader::new(
+            &payload[..],
+            PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000)),
+            true, false, true);
+        let mut writer = LimitedWriter { data: Vec::new(), limit: usize::MAX };
+        EventWriter::new().write(&event, &mut writer).unwrap();
+        let mut expected = EventWriter::new().encode_header(&event).unwrap().to_vec();
+        expected.extend_from_slice(&payload[..]);
+        assert_eq!(writer.data, expected);
+        let mut serialized_bytes_cursor = Cursor::new(writer.data);
+        let mut event_reader = EventReader::new();
+        let required_buffer_length = event_reader.read_required_buffer_length(&mut serialized_bytes_cursor).unwrap();
+        let mut read_buffer: Vec<u8> = vec![0; required_buffer_length];
+        let deserialized_event = event_reader.read_event(&mut serialized_bytes_cursor, &mut read_buffer[..]).unwrap();
+        assert_eq!(event, deserialized_event);
+    }
+
+    #[test]
+    fn test_event_writer_error() {
+        // A write error must be returned instead of causing a panic.
+        let payload = vec![0; 188];
+        let event = EventWithHeader::new(&payload[..], PravegaTimestamp::NONE, false, false, false);
+        let mut writer = LimitedWriter { data: Vec::new(), limit: 100 };
+        let err = EventWriter::new().write(&event, &mut writer).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Other);
+        let mut buffer = [0; 10];
+        let err = EventWriter::new().write(&event, &mut &mut buffer[..]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WriteZero);
+    }
+
+    /// Records each call to write as a separate append.
+    /// Like SyncByteWriter, it does not implement write_vectored, and each write is appended atomically.
+    struct AppendRecorder {
+        appends: Vec<Vec<u8>>,
+    }
+
+    impl Write for AppendRecorder {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            self.appends.push(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_event_writer_one_append_per_event() {
+        // Events smaller than, equal to, and larger than the buffer, some of which cross the end of the buffer.
+        let buffer_size = 1000;
+        let payload_lengths = [188, 188, 188, 188, 188, 600, 980, 188, 1000, 188, 2500, 188, 188];
+        let mut writer = BufWriter::with_capacity(buffer_size, AppendRecorder { appends: Vec::new() });
+        let mut event_writer = EventWriter::new();
+        for (i, payload_length) in payload_lengths.iter().enumerate() {
+            let payload = vec![i as u8; *payload_length];
+            let event = EventWithHeader::new(&payload[..], PravegaTimestamp::from_nanoseconds(Some(i as u64 + 1)), false, false, false);
+            event_writer.write(&event, &mut writer).unwrap();
+        }
+        writer.flush().unwrap();
+        let appends = writer.into_inner().ok().unwrap().appends;
+        // Each append must contain only whole events.
+        let mut num_events = 0;
+        for append in appends.iter() {
+            let mut cursor = Cursor::new(&append[..]);
+            while (cursor.position() as usize) < append.len() {
+                let mut event_reader = EventReader::new();
+                let required_buffer_length = event_reader.read_required_buffer_length(&mut cursor).unwrap();
+                let mut read_buffer: Vec<u8> = vec![0; required_buffer_length];
+                let event = event_reader.read_event(&mut cursor, &mut read_buffer[..]).unwrap();
+                assert_eq!(event.payload.len(), payload_lengths[num_events]);
+                assert!(event.payload.iter().all(|b| *b == num_events as u8));
+                num_events += 1;
+            }
+        }
+        assert_eq!(num_events, payload_lengths.len());
+    }
 }
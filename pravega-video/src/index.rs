@@ -11,17 +11,84 @@
 // Module for writing and reading an index in a Pravega stream.
 
 use crate::event_serde::EventHeaderFlags;
-use crate::timestamp::PravegaTimestamp;
-use crate::utils::CurrentHead;
+use crate::timestamp::{PravegaTimestamp, TimeDelta};
+use crate::utils::{AsyncCurrentHead, CurrentHead, current_head_async};
 use enumflags2::BitFlags;
 use std::convert::TryInto;
 use std::io::{BufReader, Error, ErrorKind, Read, Write, Seek, SeekFrom};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
 use tracing::{debug, trace};
 
 pub fn get_index_stream_name(stream_name: &str) -> String {
     format!("{}-index", stream_name)
 }
 
+/// The version of the index format.
+/// All records in an index stream must use the same version.
+/// The version is detected from the header of the first record in the index.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum IndexVersion {
+    /// 20-byte records containing the timestamp, offset and flags.
+    V1,
+    /// 48-byte records that also contain the duration and length of the preceding segment and a session id.
+    V2,
+}
+
+impl IndexVersion {
+    /// Number of bytes at the beginning of each record that identify the version and flags.
+    pub const HEADER_SIZE: usize = 4;
+
+    pub fn record_size(&self) -> usize {
+        match self {
+            IndexVersion::V1 => IndexRecord::RECORD_SIZE,
+            IndexVersion::V2 => IndexRecord::RECORD_SIZE_V2,
+        }
+    }
+
+    /// Returns the value written in the version field of the record header.
+    /// Version 1 records have this field set to 0 because it was originally reserved.
+    fn to_header_byte(&self) -> u8 {
+        match self {
+            IndexVersion::V1 => 0,
+            IndexVersion::V2 => 2,
+        }
+    }
+
+    fn from_header_byte(b: u8) -> Result<Self, Error> {
+        match b {
+            0 => Ok(IndexVersion::V1),
+            2 => Ok(IndexVersion::V2),
+            _ => Err(Error::new(ErrorKind::InvalidData, format!("Unsupported index version {}", b))),
+        }
+    }
+
+    /// Returns the version from the header of a serialized index record.
+    pub fn from_header(header: &[u8]) -> Result<Self, Error> {
+        if header.len() < IndexVersion::HEADER_SIZE {
+            return Err(Error::new(ErrorKind::InvalidInput, "Index record header is too short"));
+        }
+        IndexVersion::from_header_byte(header[0])
+    }
+}
+
+impl Default for IndexVersion {
+    fn default() -> Self {
+        IndexVersion::V1
+    }
+}
+
+impl std::convert::TryFrom<u32> for IndexVersion {
+    type Error = Error;
+
+    fn try_from(v: u32) -> Result<Self, Self::Error> {
+        match v {
+            1 => Ok(IndexVersion::V1),
+            2 => Ok(IndexVersion::V2),
+            _ => Err(Error::new(ErrorKind::InvalidInput, format!("Unsupported index version {}", v))),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct IndexRecord {
     pub timestamp: PravegaTimestamp,
@@ -29,10 +96,20 @@ pub struct IndexRecord {
     pub offset: u64,
     pub random_access: bool,
     pub discontinuity: bool,
+    /// Duration of the media between the previous index record and this one, if known.
+    /// This is only stored in version 2 indexes.
+    pub segment_duration: TimeDelta,
+    /// Number of bytes in the data stream between the previous index record and this one, if known.
+    /// This is only stored in version 2 indexes.
+    pub segment_length: Option<u64>,
+    /// Identifies the writer session that wrote this record.
+    /// This is only stored in version 2 indexes.
+    pub session_id: Option<u64>,
 }
 
 impl IndexRecord {
     pub const RECORD_SIZE: usize = 20;
+    pub const RECORD_SIZE_V2: usize = 48;
 
     pub fn new(timestamp: PravegaTimestamp, offset: u64,
                random_access: bool, discontinuity: bool) -> Self {
@@ -41,6 +118,19 @@ impl IndexRecord {
             offset,
             random_access,
             discontinuity,
+            segment_duration: TimeDelta::none(),
+            segment_length: None,
+            session_id: None,
+        }
+    }
+
+    /// Returns a copy of this record with the fields that are only stored in version 2 indexes.
+    pub fn with_segment(self, segment_duration: TimeDelta, segment_length: Option<u64>, session_id: Option<u64>) -> Self {
+        Self {
+            segment_duration,
+            segment_length,
+            session_id,
+            ..self
         }
     }
 }
@@ -49,7 +139,7 @@ impl IndexRecord {
 /**
    A struct to serialize an IndexRecord for writing to a Pravega byte stream.
 
-   The following encoding is used:
+   Version 1 uses the following encoding:
 
     0                   1                   2                   3
     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
@@ -75,8 +165,45 @@ impl IndexRecord {
    |                                                               |
    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 
+   Version 2 uses the following encoding:
+
+    0                   1                   2                   3
+    0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+   |  version (2)  |           reserved (set to 0)           |D|R|R|
+   |               |                                         |I|A|E|
+   |               |                                         |S|N|S|
+   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+   |                timestamp (64-bit BE unsigned int)             |
+   +                    same as version 1                          +
+   |                                                               |
+   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+   |                  offset (64-bit BE unsigned int)              |
+   +                    same as version 1                          +
+   |                                                               |
+   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+   |            segment duration (64-bit BE unsigned int)          |
+   +         nanoseconds from the previous record to this one      +
+   |                          0 if unknown                         |
+   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+   |             segment length (64-bit BE unsigned int)           |
+   +            bytes from the previous record to this one         +
+   |                          0 if unknown                         |
+   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+   |                session id (64-bit BE unsigned int)            |
+   +                          0 if unknown                         +
+   |                                                               |
+   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+   |                      reserved (set to 0)                      |
+   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
    One tick mark represents one bit position.
 
+   version:
+      In version 1, this byte is reserved and set to 0.
+      In version 2, this byte is set to 2.
+      All records in an index must have the same version.
+      Readers determine the version from the first record in the index.
    reserved, RES:
       All reserved bits must be 0.
       These may be utilized in the future for other purposes.
@@ -84,6 +211,15 @@ impl IndexRecord {
    RAN - random access indicator
    timestamp:
       A timestamp value of 0 is not allowed in the index.
+   segment duration:
+      The duration of the media written between the previous index record and this record
+      by the same writer session. This is 0 if this is the first record written by the session.
+      Unlike the difference between timestamps, this does not include any gap caused by a discontinuity.
+   segment length:
+      The number of bytes in the data stream between the previous index record and this record.
+      This is 0 if this is the first record written by the session.
+   session id:
+      A value that identifies the writer session (e.g. a pravegasink instance) that wrote the record.
 
    See event_serde.rs for definitions of common fields.
 
@@ -107,11 +243,23 @@ impl IndexRecord {
 */
 /// ```
 pub struct IndexRecordWriter {
+    version: IndexVersion,
 }
 
 impl IndexRecordWriter {
+    /// Create a writer for version 1 records.
     pub fn new() -> Self {
-        Self {}
+        Self::with_version(IndexVersion::V1)
+    }
+
+    pub fn with_version(version: IndexVersion) -> Self {
+        Self {
+            version,
+        }
+    }
+
+    pub fn version(&self) -> IndexVersion {
+        self.version
     }
 
     pub fn write<W>(&mut self, record: &IndexRecord, writer: &mut W) -> Result<(), Error>
@@ -129,11 +277,18 @@ impl IndexRecordWriter {
         if timestamp_nanos == 0 {
             return Err(Error::new(ErrorKind::InvalidInput, "Timestamp is none or 0"));
         }
-        let mut bytes_to_write: Vec<u8> = vec![0; IndexRecord::RECORD_SIZE];
+        let mut bytes_to_write: Vec<u8> = vec![0; self.version.record_size()];
+        bytes_to_write[0] = self.version.to_header_byte();
         bytes_to_write[3..4].copy_from_slice(&flags.bits().to_be_bytes()[..]);
         bytes_to_write[4..12].copy_from_slice(&timestamp_nanos.to_be_bytes()[..]);
         bytes_to_write[12..20].copy_from_slice(&record.offset.to_be_bytes()[..]);
-        writer.write(&bytes_to_write).unwrap();
+        if self.version == IndexVersion::V2 {
+            let segment_duration = record.segment_duration.nanoseconds().unwrap_or_default().max(0) as u64;
+            bytes_to_write[20..28].copy_from_slice(&segment_duration.to_be_bytes()[..]);
+            bytes_to_write[28..36].copy_from_slice(&record.segment_length.unwrap_or_default().to_be_bytes()[..]);
+            bytes_to_write[36..44].copy_from_slice(&record.session_id.unwrap_or_default().to_be_bytes()[..]);
+        }
+        writer.write_all(&bytes_to_write)?;
         Ok(())
     }
 }
@@ -142,6 +297,7 @@ pub struct IndexRecordReader {
 }
 
 // A struct to deserialize an IndexRecord that was written to a Pravega byte stream.
+// Both version 1 and version 2 records can be read. The version of each record is determined from its header.
 impl IndexRecordReader {
     pub fn new() -> Self {
         Self {}
@@ -151,29 +307,61 @@ impl IndexRecordReader {
     where
         R: Read,
     {
-        let mut buffer: Vec<u8> = vec![0; IndexRecord::RECORD_SIZE];
-        rdr.read_exact(&mut buffer[..])?;
-        let flags = BitFlags::<EventHeaderFlags>::from_bits(buffer[3]).unwrap();
+        let mut buffer: Vec<u8> = vec![0; IndexRecord::RECORD_SIZE_V2];
+        rdr.read_exact(&mut buffer[0..IndexVersion::HEADER_SIZE])?;
+        let version = IndexVersion::from_header(&buffer[..])?;
+        rdr.read_exact(&mut buffer[IndexVersion::HEADER_SIZE..version.record_size()])?;
+        Self::parse(&buffer[..], version)
+    }
+
+    // Same as read() but reads asynchronously.
+    pub async fn read_async<R>(&mut self, rdr: &mut R) -> Result<IndexRecord, Error>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut buffer: Vec<u8> = vec![0; IndexRecord::RECORD_SIZE_V2];
+        rdr.read_exact(&mut buffer[0..IndexVersion::HEADER_SIZE]).await?;
+        let version = IndexVersion::from_header(&buffer[..])?;
+        rdr.read_exact(&mut buffer[IndexVersion::HEADER_SIZE..version.record_size()]).await?;
+        Self::parse(&buffer[..], version)
+    }
+
+    fn parse(buffer: &[u8], version: IndexVersion) -> Result<IndexRecord, Error> {
+        let flags = BitFlags::<EventHeaderFlags>::from_bits(buffer[3]).map_err(|_| {
+            Error::new(ErrorKind::InvalidData, format!("Invalid index record flags {}", buffer[3]))
+        })?;
         let random_access = flags.contains(EventHeaderFlags::RandomAccessIndicator);
         let discontinuity = flags.contains(EventHeaderFlags::DiscontinuityIndicator);
         let timestamp = u64::from_be_bytes(buffer[4..12].try_into().unwrap());
         // A timestamp of 0 is not allowed but if is read, it will be converted to None.
         let timestamp = if timestamp == 0 { None } else { Some(timestamp) };
         let offset = u64::from_be_bytes(buffer[12..20].try_into().unwrap());
-        Ok(IndexRecord {
-            timestamp: PravegaTimestamp::from_nanoseconds(timestamp),
-            offset,
-            random_access,
-            discontinuity,
-        })
+        let record = IndexRecord::new(PravegaTimestamp::from_nanoseconds(timestamp), offset, random_access, discontinuity);
+        match version {
+            IndexVersion::V1 => Ok(record),
+            IndexVersion::V2 => {
+                // Fields with a value of 0 are unknown.
+                let non_zero = |x: u64| if x == 0 { None } else { Some(x) };
+                let segment_duration = u64::from_be_bytes(buffer[20..28].try_into().unwrap());
+                let segment_length = u64::from_be_bytes(buffer[28..36].try_into().unwrap());
+                let session_id = u64::from_be_bytes(buffer[36..44].try_into().unwrap());
+                Ok(record.with_segment(
+                    TimeDelta(non_zero(segment_duration).map(|t| t as i128)),
+                    non_zero(segment_length),
+                    non_zero(session_id)))
+            },
+        }
     }
 }
 
 // A struct for searching an index.
 // The index can be stored in any object that implements Read and Seek, including a Pravega stream.
+// Both version 1 and version 2 indexes can be searched.
 pub struct IndexSearcher<R: Read + Seek + CurrentHead> {
     // We currently use a BufReader to improve the performance of the sequential read through the index when searching.
     reader: BufReader<R>,
+    // The version of the index. This is determined from the first record when it is first needed.
+    version: Option<IndexVersion>,
 }
 
 #[derive(Debug)]
@@ -188,6 +376,36 @@ impl<R: Read + Seek + CurrentHead> IndexSearcher<R> {
     pub fn new(reader: R) -> Self {
         Self {
             reader: BufReader::with_capacity(8*1024, reader),
+            version: None,
+        }
+    }
+
+    /// Returns the version of the index, determined from the header of the first record.
+    /// Returns None if the index has no records.
+    pub fn index_version(&mut self) -> Result<Option<IndexVersion>, Error> {
+        if let Some(version) = self.version {
+            return Ok(Some(version));
+        }
+        let head_offset = self.reader.get_ref().current_head()?;
+        let tail_offset = self.reader.seek(SeekFrom::End(0))?;
+        if tail_offset < head_offset + IndexVersion::HEADER_SIZE as u64 {
+            return Ok(None);
+        }
+        self.reader.seek(SeekFrom::Start(head_offset))?;
+        let mut header = [0; IndexVersion::HEADER_SIZE];
+        self.reader.read_exact(&mut header)?;
+        let version = IndexVersion::from_header(&header)?;
+        debug!("IndexSearcher::index_version: version={:?}", version);
+        self.version = Some(version);
+        Ok(self.version)
+    }
+
+    /// Returns the size of each record in the index.
+    /// If the index has no records, returns an UnexpectedEof error.
+    pub fn record_size(&mut self) -> Result<u64, Error> {
+        match self.index_version()? {
+            Some(version) => Ok(version.record_size() as u64),
+            None => Err(Error::new(ErrorKind::UnexpectedEof, "Index has no records")),
         }
     }
 
@@ -205,14 +423,15 @@ impl<R: Read + Seek + CurrentHead> IndexSearcher<R> {
 
         let result = (|| {
             let mut index_record_reader = IndexRecordReader::new();
+            let record_size = self.record_size()?;
 
             let first_index_offset = self.reader.get_ref().current_head()?;
             let tail_offset = self.reader.seek(SeekFrom::End(0))?;
-            if tail_offset < first_index_offset + IndexRecord::RECORD_SIZE as u64 {
+            if tail_offset < first_index_offset + record_size {
                 return Err(Error::new(ErrorKind::UnexpectedEof, "Index has no records"));
             }
 
-            let mut last_index_offset = self.reader.seek(SeekFrom::Start(tail_offset - IndexRecord::RECORD_SIZE as u64))?;
+            let mut last_index_offset = self.reader.seek(SeekFrom::Start(tail_offset - record_size))?;
             // TODO: Below may fail due to https://github.com/pravega/pravega-client-rust/issues/163.
             let tail_index_record = index_record_reader.read(&mut self.reader)?;
 
@@ -227,14 +446,14 @@ impl<R: Read + Seek + CurrentHead> IndexSearcher<R> {
 
             // Use binary search algorithm
             loop {
-                let middle_index = (last_index_offset + first_index_offset) / 2 / IndexRecord::RECORD_SIZE as u64;
-                let middle_index_offset = self.reader.seek(SeekFrom::Start(middle_index * IndexRecord::RECORD_SIZE as u64))?;
+                let middle_index = (last_index_offset + first_index_offset) / 2 / record_size;
+                let middle_index_offset = self.reader.seek(SeekFrom::Start(middle_index * record_size))?;
                 let middle_index_record = index_record_reader.read(&mut self.reader)?;
                 trace!("IndexSearcher::search_timestamp_and_return_index_offset: index_record={:?}", middle_index_record);
                 if size_bytes > tail_index_record.offset - middle_index_record.offset {
-                    last_index_offset = middle_index_offset - IndexRecord::RECORD_SIZE as u64;
+                    last_index_offset = middle_index_offset - record_size;
                 } else if size_bytes < tail_index_record.offset - middle_index_record.offset {
-                    first_index_offset = middle_index_offset + IndexRecord::RECORD_SIZE as u64;
+                    first_index_offset = middle_index_offset + record_size;
                 } else {
                     return Ok((middle_index_record, middle_index_offset));
                 }
@@ -274,15 +493,16 @@ impl<R: Read + Seek + CurrentHead> IndexSearcher<R> {
 
         let result = (|| {
             let mut index_record_reader = IndexRecordReader::new();
+            let record_size = self.record_size()?;
 
             let first_index_offset = self.reader.get_ref().current_head()?;
             let tail_offset = self.reader.seek(SeekFrom::End(0))?;
-            if tail_offset < first_index_offset + IndexRecord::RECORD_SIZE as u64 {
+            if tail_offset < first_index_offset + record_size {
                 return Err(Error::new(ErrorKind::UnexpectedEof, "Index has no records"));
             }
 
             // Get last record.
-            let mut last_index_offset = self.reader.seek(SeekFrom::Start(tail_offset - IndexRecord::RECORD_SIZE as u64))?;
+            let mut last_index_offset = self.reader.seek(SeekFrom::Start(tail_offset - record_size))?;
             // TODO: Below may fail due to https://github.com/pravega/pravega-client-rust/issues/163.
             let mut last_index_record = index_record_reader.read(&mut self.reader)?;
             // Return last record if desired timestamp is after or equal to it.
@@ -300,14 +520,14 @@ impl<R: Read + Seek + CurrentHead> IndexSearcher<R> {
 
             // Use binary search algorithm
             loop {
-                let middle_index = (last_index_offset + first_index_offset) / 2 / IndexRecord::RECORD_SIZE as u64;
-                let middle_index_offset = self.reader.seek(SeekFrom::Start(middle_index * IndexRecord::RECORD_SIZE as u64))?;
+                let middle_index = (last_index_offset + first_index_offset) / 2 / record_size;
+                let middle_index_offset = self.reader.seek(SeekFrom::Start(middle_index * record_size))?;
                 let middle_index_record = index_record_reader.read(&mut self.reader)?;
                 trace!("IndexSearcher::search_timestamp_and_return_index_offset: index_record={:?}", middle_index_record);
                 if timestamp < middle_index_record.timestamp {
-                    last_index_offset = middle_index_offset - IndexRecord::RECORD_SIZE as u64;
+                    last_index_offset = middle_index_offset - record_size;
                 } else if timestamp > middle_index_record.timestamp {
-                    first_index_offset = middle_index_offset + IndexRecord::RECORD_SIZE as u64;
+                    first_index_offset = middle_index_offset + record_size;
                 } else {
                     return Ok((middle_index_record, middle_index_offset));
                 }
@@ -372,6 +592,10 @@ impl<R: Read + Seek + CurrentHead> IndexSearcher<R> {
     /// This should only be used for debugging and testing.
     pub fn get_index_records(&mut self) -> Result<Vec<(IndexRecord, u64)>, Error> {
         let mut records = Vec::new();
+        let record_size = match self.index_version()? {
+            Some(version) => version.record_size() as u64,
+            None => return Ok(records),
+        };
         let index_begin_offset = self.reader.get_ref().current_head()?;
         let index_end_offset = self.reader.seek(SeekFrom::End(0))?;
         self.reader.seek(SeekFrom::Start(index_begin_offset))?;
@@ -380,7 +604,204 @@ impl<R: Read + Seek + CurrentHead> IndexSearcher<R> {
         while index_offset < index_end_offset {
             let index_record = index_record_reader.read(&mut self.reader)?;
             records.push((index_record, index_offset));
-            index_offset += IndexRecord::RECORD_SIZE as u64;
+            index_offset += record_size;
+        }
+        Ok(records)
+    }
+}
+
+// The async equivalent of IndexSearcher.
+// The index can be stored in any object that implements AsyncRead and AsyncSeek,
+// including a Pravega stream wrapped in an AsyncByteReader.
+pub struct AsyncIndexSearcher<R: AsyncRead + AsyncSeek + AsyncCurrentHead + Unpin> {
+    reader: tokio::io::BufReader<R>,
+    // The version of the index. This is determined from the first record when it is first needed.
+    version: Option<IndexVersion>,
+}
+
+impl<R: AsyncRead + AsyncSeek + AsyncCurrentHead + Unpin> AsyncIndexSearcher<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: tokio::io::BufReader::with_capacity(8*1024, reader),
+            version: None,
+        }
+    }
+
+    /// Same as IndexSearcher::index_version.
+    pub async fn index_version(&mut self) -> Result<Option<IndexVersion>, Error> {
+        if let Some(version) = self.version {
+            return Ok(Some(version));
+        }
+        let head_offset = current_head_async(&mut self.reader).await?;
+        let tail_offset = self.reader.seek(SeekFrom::End(0)).await?;
+        if tail_offset < head_offset + IndexVersion::HEADER_SIZE as u64 {
+            return Ok(None);
+        }
+        self.reader.seek(SeekFrom::Start(head_offset)).await?;
+        let mut header = [0; IndexVersion::HEADER_SIZE];
+        self.reader.read_exact(&mut header).await?;
+        let version = IndexVersion::from_header(&header)?;
+        debug!("AsyncIndexSearcher::index_version: version={:?}", version);
+        self.version = Some(version);
+        Ok(self.version)
+    }
+
+    /// Same as IndexSearcher::record_size.
+    pub async fn record_size(&mut self) -> Result<u64, Error> {
+        match self.index_version().await? {
+            Some(version) => Ok(version.record_size() as u64),
+            None => Err(Error::new(ErrorKind::UnexpectedEof, "Index has no records")),
+        }
+    }
+
+    async fn read_record_at(&mut self, index_offset: u64) -> Result<IndexRecord, Error> {
+        self.reader.seek(SeekFrom::Start(index_offset)).await?;
+        IndexRecordReader::new().read_async(&mut self.reader).await
+    }
+
+    /// Returns the offsets of the first and last records in the index.
+    async fn get_first_and_last_index_offsets(&mut self) -> Result<(u64, u64), Error> {
+        let record_size = self.record_size().await?;
+        let first_index_offset = current_head_async(&mut self.reader).await?;
+        let tail_offset = self.reader.seek(SeekFrom::End(0)).await?;
+        if tail_offset < first_index_offset + record_size {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Index has no records"));
+        }
+        Ok((first_index_offset, tail_offset - record_size))
+    }
+
+    /// Same as IndexSearcher::search_size_and_return_index_offset.
+    pub async fn search_size_and_return_index_offset(&mut self, size_bytes: u64, method: SearchMethod)
+            -> Result<(IndexRecord, u64), Error> {
+        let result = self.search_size_and_return_index_offset_impl(size_bytes, &method).await;
+        debug!("AsyncIndexSearcher::search_size_and_return_index_offset({}, {:?}) = {:?}", size_bytes, method, result);
+        result
+    }
+
+    async fn search_size_and_return_index_offset_impl(&mut self, size_bytes: u64, method: &SearchMethod)
+            -> Result<(IndexRecord, u64), Error> {
+        let record_size = self.record_size().await?;
+        let (mut first_index_offset, mut last_index_offset) = self.get_first_and_last_index_offsets().await?;
+        let tail_index_record = self.read_record_at(last_index_offset).await?;
+        let first_index_record = self.read_record_at(first_index_offset).await?;
+
+        // Return first record if desired size is larger or equal to it.
+        if tail_index_record.offset - first_index_record.offset <= size_bytes {
+            return Ok((first_index_record, first_index_offset));
+        }
+
+        // Use binary search algorithm
+        loop {
+            let middle_index_offset = (last_index_offset + first_index_offset) / 2 / record_size * record_size;
+            let middle_index_record = self.read_record_at(middle_index_offset).await?;
+            trace!("AsyncIndexSearcher::search_size_and_return_index_offset: index_record={:?}", middle_index_record);
+            if size_bytes > tail_index_record.offset - middle_index_record.offset {
+                last_index_offset = middle_index_offset - record_size;
+            } else if size_bytes < tail_index_record.offset - middle_index_record.offset {
+                first_index_offset = middle_index_offset + record_size;
+            } else {
+                return Ok((middle_index_record, middle_index_offset));
+            }
+            if first_index_offset > last_index_offset {
+                break;
+            }
+        }
+
+        let index_offset = match method {
+            SearchMethod::Before => last_index_offset,
+            SearchMethod::After => first_index_offset,
+        };
+        Ok((self.read_record_at(index_offset).await?, index_offset))
+    }
+
+    /// Same as IndexSearcher::search_timestamp_and_return_index_offset.
+    pub async fn search_timestamp_and_return_index_offset(&mut self, timestamp: PravegaTimestamp, method: SearchMethod)
+            -> Result<(IndexRecord, u64), Error> {
+        let result = self.search_timestamp_and_return_index_offset_impl(timestamp, &method).await;
+        debug!("AsyncIndexSearcher::search_timestamp_and_return_index_offset({}, {:?}) = {:?}", timestamp, method, result);
+        result
+    }
+
+    async fn search_timestamp_and_return_index_offset_impl(&mut self, timestamp: PravegaTimestamp, method: &SearchMethod)
+            -> Result<(IndexRecord, u64), Error> {
+        let record_size = self.record_size().await?;
+        let (mut first_index_offset, mut last_index_offset) = self.get_first_and_last_index_offsets().await?;
+
+        // Return last record if desired timestamp is after or equal to it.
+        let last_index_record = self.read_record_at(last_index_offset).await?;
+        if last_index_record.timestamp <= timestamp {
+            return Ok((last_index_record, last_index_offset));
+        }
+
+        // Return first record if desired timestamp is before or equal to it.
+        let first_index_record = self.read_record_at(first_index_offset).await?;
+        if timestamp <= first_index_record.timestamp {
+            return Ok((first_index_record, first_index_offset));
+        }
+
+        // Use binary search algorithm
+        loop {
+            let middle_index_offset = (last_index_offset + first_index_offset) / 2 / record_size * record_size;
+            let middle_index_record = self.read_record_at(middle_index_offset).await?;
+            trace!("AsyncIndexSearcher::search_timestamp_and_return_index_offset: index_record={:?}", middle_index_record);
+            if timestamp < middle_index_record.timestamp {
+                last_index_offset = middle_index_offset - record_size;
+            } else if timestamp > middle_index_record.timestamp {
+                first_index_offset = middle_index_offset + record_size;
+            } else {
+                return Ok((middle_index_record, middle_index_offset));
+            }
+            if first_index_offset > last_index_offset {
+                break;
+            }
+        }
+
+        let index_offset = match method {
+            SearchMethod::Before => last_index_offset,
+            SearchMethod::After => first_index_offset,
+        };
+        Ok((self.read_record_at(index_offset).await?, index_offset))
+    }
+
+    /// Same as IndexSearcher::search_timestamp.
+    pub async fn search_timestamp(&mut self, timestamp: PravegaTimestamp) -> Result<IndexRecord, Error> {
+        self.search_timestamp_and_return_index_offset(timestamp, SearchMethod::Before).await.map(|x| x.0)
+    }
+
+    /// Same as IndexSearcher::search_timestamp_after.
+    pub async fn search_timestamp_after(&mut self, timestamp: PravegaTimestamp) -> Result<IndexRecord, Error> {
+        self.search_timestamp_and_return_index_offset(timestamp, SearchMethod::After).await.map(|x| x.0)
+    }
+
+    pub async fn get_first_record(&mut self) -> Result<IndexRecord, Error> {
+        self.search_timestamp(PravegaTimestamp::MIN).await
+    }
+
+    pub async fn get_last_record(&mut self) -> Result<IndexRecord, Error> {
+        self.search_timestamp(PravegaTimestamp::MAX).await
+    }
+
+    /// Unwraps this `AsyncIndexSearcher<R>`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader.into_inner()
+    }
+
+    /// Returns a list of all index records.
+    pub async fn get_index_records(&mut self) -> Result<Vec<(IndexRecord, u64)>, Error> {
+        let mut records = Vec::new();
+        let record_size = match self.index_version().await? {
+            Some(version) => version.record_size() as u64,
+            None => return Ok(records),
+        };
+        let index_begin_offset = current_head_async(&mut self.reader).await?;
+        let index_end_offset = self.reader.seek(SeekFrom::End(0)).await?;
+        self.reader.seek(SeekFrom::Start(index_begin_offset)).await?;
+        let mut index_record_reader = IndexRecordReader::new();
+        let mut index_offset = index_begin_offset;
+        while index_offset < index_end_offset {
+            let index_record = index_record_reader.read_async(&mut self.reader).await?;
+            records.push((index_record, index_offset));
+            index_offset += record_size;
         }
         Ok(records)
     }
@@ -388,8 +809,8 @@ impl<R: Read + Seek + CurrentHead> IndexSearcher<R> {
 
 #[cfg(test)]
 mod test {
-    use crate::index::{IndexRecord, IndexRecordWriter, IndexRecordReader, IndexSearcher, SearchMethod};
-    use crate::timestamp::PravegaTimestamp;
+    use crate::index::{AsyncIndexSearcher, IndexRecord, IndexRecordWriter, IndexRecordReader, IndexSearcher, IndexVersion, SearchMethod};
+    use crate::timestamp::{PravegaTimestamp, TimeDelta};
     use tracing::info;
     use std::io::Cursor;
 
@@ -411,14 +832,65 @@ mod test {
         assert_eq!(index_record, deserialized_index_record);
     }
 
+    #[test]
+    fn test_index_writer_reader_v2() {
+        let index_record = IndexRecord::new(
+            PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000)),
+            300, true, false)
+            .with_segment(TimeDelta(Some(2_000_000_000)), Some(12345), Some(7));
+        info!("index_record={:?}", index_record);
+        let mut serialized_bytes_cursor = Cursor::new(vec![0 as u8; IndexRecord::RECORD_SIZE_V2]);
+        let mut index_record_writer = IndexRecordWriter::with_version(IndexVersion::V2);
+        index_record_writer.write(&index_record, &mut serialized_bytes_cursor).unwrap();
+        assert_eq!(serialized_bytes_cursor.position(), IndexRecord::RECORD_SIZE_V2 as u64);
+        serialized_bytes_cursor.set_position(0);
+
+        let mut index_record_reader = IndexRecordReader::new();
+        let deserialized_index_record = index_record_reader.read(&mut serialized_bytes_cursor).unwrap();
+        info!("deserialized_index_record={:?}", deserialized_index_record);
+        assert_eq!(index_record, deserialized_index_record);
+
+        // A version 1 writer does not store the additional fields.
+        let mut serialized_bytes_cursor = Cursor::new(vec![0 as u8; IndexRecord::RECORD_SIZE]);
+        IndexRecordWriter::new().write(&index_record, &mut serialized_bytes_cursor).unwrap();
+        assert_eq!(serialized_bytes_cursor.position(), IndexRecord::RECORD_SIZE as u64);
+        serialized_bytes_cursor.set_position(0);
+        let deserialized_index_record = index_record_reader.read(&mut serialized_bytes_cursor).unwrap();
+        assert_eq!(deserialized_index_record, IndexRecord::new(index_record.timestamp, 300, true, false));
+    }
+
+    #[test]
+    fn test_index_version() {
+        let mut index_searcher = IndexSearcher::new(Cursor::new(Vec::<u8>::new()));
+        assert_eq!(index_searcher.index_version().unwrap(), None);
+        assert!(index_searcher.get_first_record().is_err());
+
+        let mut cursor = Cursor::new(Vec::new());
+        let rec = IndexRecord::new(PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000)), 0, true, true);
+        IndexRecordWriter::with_version(IndexVersion::V2).write(&rec, &mut cursor).unwrap();
+        let mut index_searcher = IndexSearcher::new(cursor);
+        assert_eq!(index_searcher.index_version().unwrap(), Some(IndexVersion::V2));
+        assert_eq!(index_searcher.record_size().unwrap(), IndexRecord::RECORD_SIZE_V2 as u64);
+        assert_eq!(index_searcher.get_index_records().unwrap(), vec![(rec, 0)]);
+    }
+
     #[test]
     fn test_index_searcher() {
+        check_index_searcher(IndexVersion::V1);
+    }
+
+    #[test]
+    fn test_index_searcher_v2() {
+        check_index_searcher(IndexVersion::V2);
+    }
+
+    fn check_index_searcher(version: IndexVersion) {
         // env_logger::init();
         // Create index in memory.
         let num_recs = 100;
         let mut index_records: Vec<IndexRecord> = Vec::new();
-        let mut memory_index_cursor = Cursor::new(vec![0 as u8; num_recs * IndexRecord::RECORD_SIZE]);
-        let mut index_record_writer = IndexRecordWriter::new();
+        let mut memory_index_cursor = Cursor::new(vec![0 as u8; num_recs * version.record_size()]);
+        let mut index_record_writer = IndexRecordWriter::with_version(version);
         let first_record = IndexRecord::new(
             PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000)),
             300, true, true);
@@ -431,6 +903,9 @@ mod test {
             rec = IndexRecord::new(
                 timestamp, rec.offset + 100 + 2 * i as u64,
                 true, false);
+            if version == IndexVersion::V2 {
+                rec = rec.with_segment(timestamp - index_records[i].timestamp, Some(rec.offset - index_records[i].offset), Some(1));
+            }
         }
         info!("index_records={:?}", index_records);
         let last_record = index_records.last().unwrap().to_owned();
@@ -466,7 +941,7 @@ mod test {
                     search_timestamp, SearchMethod::After).unwrap();
                 info!("search_timestamp={}, found_record={:?}", search_timestamp, found_record);
                 assert_eq!(found_record.0, *rec);
-                assert_eq!(found_record.1, (i * IndexRecord::RECORD_SIZE) as u64);
+                assert_eq!(found_record.1, (i * version.record_size()) as u64);
             }
 
             // Search for timestamps after and equal to the index record.
@@ -477,7 +952,45 @@ mod test {
                     search_timestamp, SearchMethod::Before).unwrap();
                 info!("search_timestamp={}, found_record={:?}", search_timestamp, found_record);
                 assert_eq!(found_record.0, *rec);
-                assert_eq!(found_record.1, (i * IndexRecord::RECORD_SIZE) as u64);
+                assert_eq!(found_record.1, (i * version.record_size()) as u64);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_async_index_searcher() {
+        for version in [IndexVersion::V1, IndexVersion::V2].iter() {
+            let mut cursor = Cursor::new(Vec::new());
+            let mut index_record_writer = IndexRecordWriter::with_version(*version);
+            for i in 0..50u64 {
+                let rec = IndexRecord::new(
+                    PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000 + 1000 * i + i * i)),
+                    300 + 100 * i + i * i, true, i == 0);
+                index_record_writer.write(&rec, &mut cursor).unwrap();
+            }
+            let bytes = cursor.into_inner();
+            let mut index_searcher = IndexSearcher::new(Cursor::new(bytes.clone()));
+            let mut async_index_searcher = AsyncIndexSearcher::new(Cursor::new(bytes));
+            assert_eq!(async_index_searcher.index_version().await.unwrap(), Some(*version));
+            assert_eq!(async_index_searcher.get_index_records().await.unwrap(), index_searcher.get_index_records().unwrap());
+            assert_eq!(async_index_searcher.get_first_record().await.unwrap(), index_searcher.get_first_record().unwrap());
+            assert_eq!(async_index_searcher.get_last_record().await.unwrap(), index_searcher.get_last_record().unwrap());
+            for t in (1_600_000_000_000_000_000 - 10..1_600_000_000_000_053_000).step_by(17) {
+                let t = PravegaTimestamp::from_nanoseconds(Some(t));
+                assert_eq!(
+                    async_index_searcher.search_timestamp_and_return_index_offset(t, SearchMethod::Before).await.unwrap(),
+                    index_searcher.search_timestamp_and_return_index_offset(t, SearchMethod::Before).unwrap());
+                assert_eq!(
+                    async_index_searcher.search_timestamp_and_return_index_offset(t, SearchMethod::After).await.unwrap(),
+                    index_searcher.search_timestamp_and_return_index_offset(t, SearchMethod::After).unwrap());
+            }
+            for size in (0..8000).step_by(13) {
+                assert_eq!(
+                    async_index_searcher.search_size_and_return_index_offset(size, SearchMethod::Before).await.unwrap(),
+                    index_searcher.search_size_and_return_index_offset(size, SearchMethod::Before).unwrap());
+                assert_eq!(
+                    async_index_searcher.search_size_and_return_index_offset(size, SearchMethod::After).await.unwrap(),
+                    index_searcher.search_size_and_return_index_offset(size, SearchMethod::After).unwrap());
             }
         }
     }
@@ -0,0 +1,1022 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Module for caching index records in memory.
+
+use crate::index::{AsyncIndexSearcher, IndexRecord, IndexRecordReader, IndexSearcher, IndexVersion, SearchMethod, get_index_stream_name};
+use crate::storage::{StorageBackend, StreamReader};
+use crate::timestamp::PravegaTimestamp;
+use crate::utils::{AsyncByteReader, AsyncCurrentHead, CurrentHead, current_head_async};
+use pravega_client::client_factory::ClientFactoryAsync;
+use pravega_client_shared::{ScopedStream, Stream};
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+use tracing::{debug, trace};
+
+/// When reading new records, read at most this many bytes at a time.
+/// This is a multiple of the record size of all index versions.
+const READ_CHUNK_SIZE: u64 = 240 * 4096;
+
+/// An in-memory copy of the most recent records in a single index stream.
+/// Before each search, new records appended to the tail of the index are read
+/// and records that have been truncated from the head of the index are discarded.
+/// At most max_records records are cached. Searches for older records read them from the index stream.
+/// It provides the same search methods as IndexSearcher.
+/// If the reader implements AsyncRead and AsyncSeek, the async methods can be used instead.
+pub struct CachedIndex<R> {
+    reader: R,
+    version: Option<IndexVersion>,
+    /// Cached index records and their offsets in the index, in index order.
+    records: Vec<(IndexRecord, u64)>,
+    /// The first record in the index and its offset, if it is before the cached records.
+    head_record: Option<(IndexRecord, u64)>,
+    /// The index offset of the first record that has not been truncated.
+    head_offset: u64,
+    /// The index offset immediately after the last cached record.
+    next_offset: u64,
+    max_records: usize,
+    /// The index will not be read more often than this.
+    refresh_interval: Duration,
+    last_refresh: Option<Instant>,
+}
+
+impl<R> CachedIndex<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_refresh_interval(reader, Duration::from_secs(0))
+    }
+
+    /// Searches will use the cached records, without checking for appends or truncation,
+    /// if the index was read less than refresh_interval ago.
+    pub fn with_refresh_interval(reader: R, refresh_interval: Duration) -> Self {
+        Self {
+            reader,
+            version: None,
+            records: Vec::new(),
+            head_record: None,
+            head_offset: 0,
+            next_offset: 0,
+            max_records: usize::MAX,
+            refresh_interval,
+            last_refresh: None,
+        }
+    }
+
+    /// Cache at most max_records of the most recent records. It must be at least 1.
+    pub fn with_max_records(mut self, max_records: usize) -> Self {
+        self.max_records = usize::max(1, max_records);
+        self
+    }
+
+    /// Unwraps this `CachedIndex<R>`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn is_fresh(&self) -> bool {
+        match self.last_refresh {
+            Some(last_refresh) => last_refresh.elapsed() < self.refresh_interval,
+            None => false,
+        }
+    }
+
+    /// Returns the index offset of the first cached record.
+    fn window_begin(&self) -> u64 {
+        self.records.first().map_or(self.next_offset, |(_, index_offset)| *index_offset)
+    }
+
+    /// Returns true if every record in the index is cached.
+    fn is_complete(&self) -> bool {
+        self.window_begin() <= self.head_offset
+    }
+
+    /// Discard records before head_offset.
+    /// Returns the offset at which reading of new records should begin.
+    fn discard_truncated(&mut self, head_offset: u64) -> u64 {
+        let num_truncated = self.records.iter().take_while(|(_, index_offset)| *index_offset < head_offset).count();
+        if num_truncated > 0 {
+            debug!("CachedIndex::refresh: discarding {} truncated records before offset {}", num_truncated, head_offset);
+            self.records.drain(..num_truncated);
+        }
+        if self.next_offset < head_offset {
+            self.next_offset = head_offset;
+        }
+        if self.head_offset != head_offset {
+            self.head_offset = head_offset;
+            self.head_record = None;
+        }
+        self.next_offset
+    }
+
+    /// If there are more than max_records records between next_offset and tail_offset,
+    /// skip the older ones and discard all cached records.
+    /// Returns the offset at which reading of new records should begin.
+    fn skip_to_window(&mut self, tail_offset: u64) -> u64 {
+        if let Some(version) = self.version {
+            let record_size = version.record_size() as u64;
+            let window_size = (self.max_records as u64).saturating_mul(record_size);
+            if tail_offset - self.next_offset > window_size {
+                let next_offset = (tail_offset - window_size) / record_size * record_size;
+                debug!("CachedIndex::refresh: skipping records from offset {} to {}", self.next_offset, next_offset);
+                self.records.clear();
+                self.next_offset = next_offset;
+            }
+        }
+        self.next_offset
+    }
+
+    /// Parse whole records from buffer, which begins at buffer_offset.
+    /// Returns the records and the number of bytes consumed.
+    fn parse_records(&mut self, buffer: &[u8], buffer_offset: u64) -> Result<(Vec<(IndexRecord, u64)>, usize), Error> {
+        let mut index_record_reader = IndexRecordReader::new();
+        let mut records = Vec::new();
+        let mut pos = 0;
+        while pos + IndexVersion::HEADER_SIZE <= buffer.len() {
+            let version = IndexVersion::from_header(&buffer[pos..])?;
+            match self.version {
+                Some(v) if v != version => {
+                    return Err(Error::new(ErrorKind::InvalidData,
+                        format!("Index record at offset {} has version {:?} but index has version {:?}",
+                        buffer_offset + pos as u64, version, v)));
+                },
+                Some(_) => {},
+                None => self.version = Some(version),
+            }
+            let record_size = version.record_size();
+            if pos + record_size > buffer.len() {
+                break;
+            }
+            let index_record = index_record_reader.read(&mut &buffer[pos..pos + record_size])?;
+            records.push((index_record, buffer_offset + pos as u64));
+            pos += record_size;
+        }
+        Ok((records, pos))
+    }
+
+    /// Parse whole records from buffer, which begins at next_offset, and cache them.
+    /// The oldest records are discarded so that at most max_records are cached.
+    /// Returns the number of bytes consumed.
+    fn add_records(&mut self, buffer: &[u8]) -> Result<usize, Error> {
+        let (records, pos) = self.parse_records(buffer, self.next_offset)?;
+        self.records.extend(records);
+        if self.records.len() > self.max_records {
+            let num_discarded = self.records.len() - self.max_records;
+            self.records.drain(..num_discarded);
+        }
+        self.next_offset += pos as u64;
+        Ok(pos)
+    }
+
+    /// Returns true if the records before the cached records have not been cached
+    /// and the first of them has not been read.
+    fn needs_head_record(&self) -> bool {
+        !self.is_complete() && self.head_record.is_none()
+    }
+
+    /// Returns the range of index offsets in [begin_index_offset, end_index_offset) that are not cached
+    /// and have not been truncated, or None if there are no such records.
+    fn uncached_range(&self, begin_index_offset: u64, end_index_offset: u64) -> Option<(u64, u64)> {
+        let begin = u64::max(begin_index_offset, self.head_offset);
+        let end = u64::min(end_index_offset, self.window_begin());
+        if begin >= end {
+            return None;
+        }
+        // Round up to the next record.
+        let record_size = self.version.map_or(1, |version| version.record_size() as u64);
+        let begin = (begin + record_size - 1) / record_size * record_size;
+        if begin < end {
+            Some((begin, end))
+        } else {
+            None
+        }
+    }
+
+    fn cached_record_size(&self) -> Result<u64, Error> {
+        match self.version {
+            Some(version) => Ok(version.record_size() as u64),
+            None => Err(Error::new(ErrorKind::UnexpectedEof, "Index has no records")),
+        }
+    }
+
+    /// Returns the first record in the index, or None if it is not cached and has not been read.
+    fn first_record(&self) -> Option<(IndexRecord, u64)> {
+        if self.is_complete() {
+            self.records.first().copied()
+        } else {
+            self.head_record
+        }
+    }
+
+    /// Returns None if the result may be a record that is not cached.
+    fn cached_search_size(&self, size_bytes: u64, method: &SearchMethod) -> Option<Result<(IndexRecord, u64), Error>> {
+        let result = (|| {
+            let records = &self.records;
+            let (first, last) = match (self.first_record(), records.last()) {
+                (Some(first), Some(last)) => (first, last),
+                _ if self.is_complete() => return Some(Err(Error::new(ErrorKind::UnexpectedEof, "Index has no records"))),
+                _ => return None,
+            };
+            let tail_offset = last.0.offset;
+            // Return first record if desired size is larger or equal to it.
+            if tail_offset - first.0.offset <= size_bytes {
+                return Some(Ok(first));
+            }
+            // The result is before the cached records.
+            if tail_offset - records[0].0.offset <= size_bytes {
+                return None;
+            }
+            // The first cached record has a size greater than size_bytes and the last record has a size of 0,
+            // so i will be between 1 and the number of records - 1.
+            let i = records.partition_point(|(r, _)| tail_offset - r.offset > size_bytes);
+            if tail_offset - records[i].0.offset == size_bytes {
+                return Some(Ok(records[i]));
+            }
+            match method {
+                SearchMethod::Before => Some(Ok(records[i - 1])),
+                SearchMethod::After => Some(Ok(records[i])),
+            }
+        })();
+        if let Some(result) = &result {
+            debug!("CachedIndex::search_size_and_return_index_offset({}, {:?}) = {:?}", size_bytes, method, result);
+        }
+        result
+    }
+
+    /// Returns None if the result may be a record that is not cached.
+    fn cached_search_timestamp(&self, timestamp: PravegaTimestamp, method: &SearchMethod) -> Option<Result<(IndexRecord, u64), Error>> {
+        let result = (|| {
+            let records = &self.records;
+            let (first, last) = match (self.first_record(), records.last()) {
+                (Some(first), Some(last)) => (first, last),
+                _ if self.is_complete() => return Some(Err(Error::new(ErrorKind::UnexpectedEof, "Index has no records"))),
+                _ => return None,
+            };
+            // Return last record if desired timestamp is after or equal to it.
+            if last.0.timestamp <= timestamp {
+                return Some(Ok(*last));
+            }
+            // Return first record if desired timestamp is before or equal to it.
+            if timestamp <= first.0.timestamp {
+                return Some(Ok(first));
+            }
+            // The result is before the cached records.
+            if timestamp <= records[0].0.timestamp {
+                return None;
+            }
+            // i will be between 1 and the number of records - 1.
+            let i = records.partition_point(|(r, _)| r.timestamp < timestamp);
+            if records[i].0.timestamp == timestamp {
+                return Some(Ok(records[i]));
+            }
+            match method {
+                SearchMethod::Before => Some(Ok(records[i - 1])),
+                SearchMethod::After => Some(Ok(records[i])),
+            }
+        })();
+        if let Some(result) = &result {
+            debug!("CachedIndex::search_timestamp_and_return_index_offset({}, {:?}) = {:?}", timestamp, method, result);
+        }
+        result
+    }
+
+    /// Returns None if the result may be a record that is not cached.
+    fn cached_search_offset_after(&self, offset: u64) -> Option<Option<(IndexRecord, u64)>> {
+        match self.records.first() {
+            Some((first, _)) if first.offset > offset && !self.is_complete() => None,
+            None if !self.is_complete() => None,
+            _ => {
+                let i = self.records.partition_point(|(r, _)| r.offset <= offset);
+                Some(self.records.get(i).copied())
+            },
+        }
+    }
+
+    fn cached_records_in_range(&self, begin_index_offset: u64, end_index_offset: u64) -> Vec<(IndexRecord, u64)> {
+        let begin = self.records.partition_point(|(_, o)| *o < begin_index_offset);
+        let end = self.records.partition_point(|(_, o)| *o < end_index_offset);
+        self.records[begin..end.max(begin)].to_vec()
+    }
+}
+
+impl<R: Read + Seek + CurrentHead> CachedIndex<R> {
+    /// Read any new records from the tail of the index and discard records that have been truncated.
+    /// This does nothing if the index was read less than refresh_interval ago.
+    pub fn refresh(&mut self) -> Result<(), Error> {
+        if self.is_fresh() {
+            return Ok(());
+        }
+        self.refresh_now()?;
+        self.last_refresh = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Read any new records from the tail of the index and discard records that have been truncated.
+    pub fn refresh_now(&mut self) -> Result<(), Error> {
+        let head_offset = self.reader.current_head()?;
+        let mut next_offset = self.discard_truncated(head_offset);
+        let tail_offset = self.reader.seek(SeekFrom::End(0))?;
+        while next_offset < tail_offset {
+            next_offset = self.skip_to_window(tail_offset);
+            let length = u64::min(tail_offset - next_offset, READ_CHUNK_SIZE);
+            self.reader.seek(SeekFrom::Start(next_offset))?;
+            let mut buffer = vec![0; length as usize];
+            self.reader.read_exact(&mut buffer[..])?;
+            if self.add_records(&buffer[..])? == 0 {
+                // The tail contains an incomplete record. This should not happen because records are written atomically.
+                break;
+            }
+            next_offset = self.next_offset;
+        }
+        if self.needs_head_record() {
+            let head_record = self.read_record_at(head_offset)?;
+            self.head_record = Some((head_record, head_offset));
+        }
+        trace!("CachedIndex::refresh: head_offset={}, tail_offset={}, num_records={}", head_offset, tail_offset, self.records.len());
+        Ok(())
+    }
+
+    fn read_record_at(&mut self, index_offset: u64) -> Result<IndexRecord, Error> {
+        self.reader.seek(SeekFrom::Start(index_offset))?;
+        IndexRecordReader::new().read(&mut self.reader)
+    }
+
+    /// Read records in the range [begin_index_offset, end_index_offset) from the index stream.
+    fn read_records(&mut self, begin_index_offset: u64, end_index_offset: u64) -> Result<Vec<(IndexRecord, u64)>, Error> {
+        let mut records = Vec::new();
+        let mut offset = begin_index_offset;
+        while offset < end_index_offset {
+            let length = u64::min(end_index_offset - offset, READ_CHUNK_SIZE);
+            self.reader.seek(SeekFrom::Start(offset))?;
+            let mut buffer = vec![0; length as usize];
+            self.reader.read_exact(&mut buffer[..])?;
+            let (chunk_records, num_bytes) = self.parse_records(&buffer[..], offset)?;
+            if num_bytes == 0 {
+                break;
+            }
+            records.extend(chunk_records);
+            offset += num_bytes as u64;
+        }
+        Ok(records)
+    }
+
+    /// Same as search_offset_after() but searches the records before the cached records in the index stream.
+    fn uncached_search_offset_after(&mut self, offset: u64) -> Result<Option<(IndexRecord, u64)>, Error> {
+        let record_size = self.cached_record_size()?;
+        let mut low = self.head_offset / record_size;
+        let mut high = self.window_begin() / record_size;
+        let mut result = self.records.first().copied();
+        while low < high {
+            let middle = (low + high) / 2;
+            let index_record = self.read_record_at(middle * record_size)?;
+            if index_record.offset <= offset {
+                low = middle + 1;
+            } else {
+                high = middle;
+                result = Some((index_record, middle * record_size));
+            }
+        }
+        Ok(result)
+    }
+
+    /// Returns the version of the index, determined from the header of the first record.
+    /// Returns None if the index has no records.
+    pub fn index_version(&mut self) -> Result<Option<IndexVersion>, Error> {
+        self.refresh()?;
+        Ok(self.version)
+    }
+
+    /// Returns the size of each record in the index.
+    /// If the index has no records, returns an UnexpectedEof error.
+    pub fn record_size(&mut self) -> Result<u64, Error> {
+        self.refresh()?;
+        self.cached_record_size()
+    }
+
+    /// Same as IndexSearcher::search_size_and_return_index_offset.
+    pub fn search_size_and_return_index_offset(&mut self, size_bytes: u64, method: SearchMethod)
+            -> Result<(IndexRecord, u64), Error> {
+        self.refresh()?;
+        match self.cached_search_size(size_bytes, &method) {
+            Some(result) => result,
+            None => IndexSearcher::new(&mut self.reader).search_size_and_return_index_offset(size_bytes, method),
+        }
+    }
+
+    /// Same as IndexSearcher::search_timestamp_and_return_index_offset.
+    pub fn search_timestamp_and_return_index_offset(&mut self, timestamp: PravegaTimestamp, method: SearchMethod)
+            -> Result<(IndexRecord, u64), Error> {
+        self.refresh()?;
+        match self.cached_search_timestamp(timestamp, &method) {
+            Some(result) => result,
+            None => IndexSearcher::new(&mut self.reader).search_timestamp_and_return_index_offset(timestamp, method),
+        }
+    }
+
+    /// Same as IndexSearcher::search_timestamp.
+    pub fn search_timestamp(&mut self, timestamp: PravegaTimestamp) -> Result<IndexRecord, Error> {
+        self.search_timestamp_and_return_index_offset(timestamp, SearchMethod::Before).map(|x| x.0)
+    }
+
+    /// Same as IndexSearcher::search_timestamp_after.
+    pub fn search_timestamp_after(&mut self, timestamp: PravegaTimestamp) -> Result<IndexRecord, Error> {
+        self.search_timestamp_and_return_index_offset(timestamp, SearchMethod::After).map(|x| x.0)
+    }
+
+    pub fn get_first_record(&mut self) -> Result<IndexRecord, Error> {
+        self.search_timestamp(PravegaTimestamp::MIN)
+    }
+
+    pub fn get_last_record(&mut self) -> Result<IndexRecord, Error> {
+        self.search_timestamp(PravegaTimestamp::MAX)
+    }
+
+    /// Returns a list of all index records.
+    /// Records that are not cached are read from the index stream.
+    pub fn get_index_records(&mut self) -> Result<Vec<(IndexRecord, u64)>, Error> {
+        self.get_index_records_in_range(0, u64::MAX)
+    }
+
+    /// Returns index records with an index offset in the range [begin_index_offset, end_index_offset).
+    /// Records that are not cached are read from the index stream.
+    pub fn get_index_records_in_range(&mut self, begin_index_offset: u64, end_index_offset: u64) -> Result<Vec<(IndexRecord, u64)>, Error> {
+        self.refresh()?;
+        let mut records = match self.uncached_range(begin_index_offset, end_index_offset) {
+            Some((begin, end)) => self.read_records(begin, end)?,
+            None => Vec::new(),
+        };
+        records.extend(self.cached_records_in_range(begin_index_offset, end_index_offset));
+        Ok(records)
+    }
+
+    /// Returns the first index record with a data stream offset greater than offset, and its index offset.
+    /// Returns None if there is no such record.
+    pub fn search_offset_after(&mut self, offset: u64) -> Result<Option<(IndexRecord, u64)>, Error> {
+        self.refresh()?;
+        match self.cached_search_offset_after(offset) {
+            Some(result) => Ok(result),
+            None => self.uncached_search_offset_after(offset),
+        }
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + AsyncCurrentHead + Unpin> CachedIndex<R> {
+    /// Same as refresh() but reads asynchronously.
+    pub async fn refresh_async(&mut self) -> Result<(), Error> {
+        if self.is_fresh() {
+            return Ok(());
+        }
+        self.refresh_now_async().await?;
+        self.last_refresh = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Same as refresh_now() but reads asynchronously.
+    pub async fn refresh_now_async(&mut self) -> Result<(), Error> {
+        let head_offset = current_head_async(&mut self.reader).await?;
+        let mut next_offset = self.discard_truncated(head_offset);
+        let tail_offset = self.reader.seek(SeekFrom::End(0)).await?;
+        while next_offset < tail_offset {
+            next_offset = self.skip_to_window(tail_offset);
+            let length = u64::min(tail_offset - next_offset, READ_CHUNK_SIZE);
+            self.reader.seek(SeekFrom::Start(next_offset)).await?;
+            let mut buffer = vec![0; length as usize];
+            self.reader.read_exact(&mut buffer[..]).await?;
+            if self.add_records(&buffer[..])? == 0 {
+                break;
+            }
+            next_offset = self.next_offset;
+        }
+        if self.needs_head_record() {
+            let head_record = self.read_record_at_async(head_offset).await?;
+            self.head_record = Some((head_record, head_offset));
+        }
+        trace!("CachedIndex::refresh_async: head_offset={}, tail_offset={}, num_records={}", head_offset, tail_offset, self.records.len());
+        Ok(())
+    }
+
+    async fn read_record_at_async(&mut self, index_offset: u64) -> Result<IndexRecord, Error> {
+        self.reader.seek(SeekFrom::Start(index_offset)).await?;
+        IndexRecordReader::new().read_async(&mut self.reader).await
+    }
+
+    /// Same as read_records() but reads asynchronously.
+    async fn read_records_async(&mut self, begin_index_offset: u64, end_index_offset: u64) -> Result<Vec<(IndexRecord, u64)>, Error> {
+        let mut records = Vec::new();
+        let mut offset = begin_index_offset;
+        while offset < end_index_offset {
+            let length = u64::min(end_index_offset - offset, READ_CHUNK_SIZE);
+            self.reader.seek(SeekFrom::Start(offset)).await?;
+            let mut buffer = vec![0; length as usize];
+            self.reader.read_exact(&mut buffer[..]).await?;
+            let (chunk_records, num_bytes) = self.parse_records(&buffer[..], offset)?;
+            if num_bytes == 0 {
+                break;
+            }
+            records.extend(chunk_records);
+            offset += num_bytes as u64;
+        }
+        Ok(records)
+    }
+
+    /// Same as uncached_search_offset_after() but reads asynchronously.
+    async fn uncached_search_offset_after_async(&mut self, offset: u64) -> Result<Option<(IndexRecord, u64)>, Error> {
+        let record_size = self.cached_record_size()?;
+        let mut low = self.head_offset / record_size;
+        let mut high = self.window_begin() / record_size;
+        let mut result = self.records.first().copied();
+        while low < high {
+            let middle = (low + high) / 2;
+            let index_record = self.read_record_at_async(middle * record_size).await?;
+            if index_record.offset <= offset {
+                low = middle + 1;
+            } else {
+                high = middle;
+                result = Some((index_record, middle * record_size));
+            }
+        }
+        Ok(result)
+    }
+
+    /// Same as index_version() but reads asynchronously.
+    pub async fn index_version_async(&mut self) -> Result<Option<IndexVersion>, Error> {
+        self.refresh_async().await?;
+        Ok(self.version)
+    }
+
+    /// Same as record_size() but reads asynchronously.
+    pub async fn record_size_async(&mut self) -> Result<u64, Error> {
+        self.refresh_async().await?;
+        self.cached_record_size()
+    }
+
+    /// Same as search_size_and_return_index_offset() but reads asynchronously.
+    pub async fn search_size_and_return_index_offset_async(&mut self, size_bytes: u64, method: SearchMethod)
+            -> Result<(IndexRecord, u64), Error> {
+        self.refresh_async().await?;
+        match self.cached_search_size(size_bytes, &method) {
+            Some(result) => result,
+            None => AsyncIndexSearcher::new(&mut self.reader).search_size_and_return_index_offset(size_bytes, method).await,
+        }
+    }
+
+    /// Same as search_timestamp_and_return_index_offset() but reads asynchronously.
+    pub async fn search_timestamp_and_return_index_offset_async(&mut self, timestamp: PravegaTimestamp, method: SearchMethod)
+            -> Result<(IndexRecord, u64), Error> {
+        self.refresh_async().await?;
+        match self.cached_search_timestamp(timestamp, &method) {
+            Some(result) => result,
+            None => AsyncIndexSearcher::new(&mut self.reader).search_timestamp_and_return_index_offset(timestamp, method).await,
+        }
+    }
+
+    pub async fn get_first_record_async(&mut self) -> Result<IndexRecord, Error> {
+        self.search_timestamp_and_return_index_offset_async(PravegaTimestamp::MIN, SearchMethod::Before).await.map(|x| x.0)
+    }
+
+    pub async fn get_last_record_async(&mut self) -> Result<IndexRecord, Error> {
+        self.search_timestamp_and_return_index_offset_async(PravegaTimestamp::MAX, SearchMethod::Before).await.map(|x| x.0)
+    }
+
+    /// Same as get_index_records() but reads asynchronously.
+    pub async fn get_index_records_async(&mut self) -> Result<Vec<(IndexRecord, u64)>, Error> {
+        self.get_index_records_in_range_async(0, u64::MAX).await
+    }
+
+    /// Same as get_index_records_in_range() but reads asynchronously.
+    pub async fn get_index_records_in_range_async(&mut self, begin_index_offset: u64, end_index_offset: u64) -> Result<Vec<(IndexRecord, u64)>, Error> {
+        self.refresh_async().await?;
+        let mut records = match self.uncached_range(begin_index_offset, end_index_offset) {
+            Some((begin, end)) => self.read_records_async(begin, end).await?,
+            None => Vec::new(),
+        };
+        records.extend(self.cached_records_in_range(begin_index_offset, end_index_offset));
+        Ok(records)
+    }
+
+    /// Same as search_offset_after() but reads asynchronously.
+    pub async fn search_offset_after_async(&mut self, offset: u64) -> Result<Option<(IndexRecord, u64)>, Error> {
+        self.refresh_async().await?;
+        match self.cached_search_offset_after(offset) {
+            Some(result) => Ok(result),
+            None => self.uncached_search_offset_after_async(offset).await,
+        }
+    }
+}
+
+struct IndexCacheEntry<R> {
+    index: Arc<tokio::sync::Mutex<CachedIndex<R>>>,
+    last_used: Instant,
+}
+
+/// A cache of index records for many streams.
+/// This can be cloned and shared between threads.
+/// Each index is read from the Pravega stream only once, after which only new records are read.
+/// Each cached index is protected by an async mutex so that it can be held across await points.
+/// Synchronous code should use blocking_lock().
+/// When more than capacity streams are cached, the least recently used index is evicted.
+/// Each index caches at most max_records records, so the cache holds at most capacity * max_records records.
+pub struct IndexCache<R> {
+    opener: Arc<dyn Fn(&ScopedStream) -> Result<R, Error> + Send + Sync>,
+    indexes: Arc<Mutex<HashMap<ScopedStream, IndexCacheEntry<R>>>>,
+    refresh_interval: Duration,
+    capacity: usize,
+    max_records: usize,
+}
+
+impl<R> Clone for IndexCache<R> {
+    fn clone(&self) -> Self {
+        Self {
+            opener: self.opener.clone(),
+            indexes: self.indexes.clone(),
+            refresh_interval: self.refresh_interval,
+            capacity: self.capacity,
+            max_records: self.max_records,
+        }
+    }
+}
+
+impl<R> IndexCache<R> {
+    /// Create a cache that uses opener to open a reader for an index stream.
+    /// At most capacity indexes will be cached. It must be at least 1.
+    pub fn new<F>(opener: F, refresh_interval: Duration, capacity: usize) -> Self
+    where
+        F: Fn(&ScopedStream) -> Result<R, Error> + Send + Sync + 'static,
+    {
+        Self {
+            opener: Arc::new(opener),
+            indexes: Arc::new(Mutex::new(HashMap::new())),
+            refresh_interval,
+            capacity: usize::max(1, capacity),
+            max_records: usize::MAX,
+        }
+    }
+
+    /// Cache at most max_records of the most recent records of each index. It must be at least 1.
+    pub fn with_max_records(mut self, max_records: usize) -> Self {
+        self.max_records = usize::max(1, max_records);
+        self
+    }
+
+    /// Returns the cached index for a video stream, opening the index stream if needed.
+    /// The scoped_stream parameter is the data stream, not the index stream.
+    /// An evicted index remains usable by callers that hold it.
+    pub fn get(&self, scoped_stream: &ScopedStream) -> Result<Arc<tokio::sync::Mutex<CachedIndex<R>>>, Error> {
+        if let Some(entry) = self.indexes.lock().unwrap().get_mut(scoped_stream) {
+            entry.last_used = Instant::now();
+            return Ok(entry.index.clone());
+        }
+        // Open the reader without holding the lock.
+        let index_scoped_stream = ScopedStream {
+            scope: scoped_stream.scope.clone(),
+            stream: Stream::from(get_index_stream_name(&scoped_stream.stream.name)),
+        };
+        let reader = (self.opener)(&index_scoped_stream)?;
+        let index = CachedIndex::with_refresh_interval(reader, self.refresh_interval).with_max_records(self.max_records);
+        let index = Arc::new(tokio::sync::Mutex::new(index));
+        let mut indexes = self.indexes.lock().unwrap();
+        let index = indexes.entry(scoped_stream.clone())
+            .or_insert(IndexCacheEntry { index, last_used: Instant::now() })
+            .index.clone();
+        while indexes.len() > self.capacity {
+            let least_recently_used = indexes.iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(key) = least_recently_used {
+                debug!("IndexCache::get: evicting index for {}", key);
+                indexes.remove(&key);
+            }
+        }
+        Ok(index)
+    }
+
+    /// Remove the cached index for a video stream.
+    pub fn remove(&self, scoped_stream: &ScopedStream) {
+        self.indexes.lock().unwrap().remove(scoped_stream);
+    }
+
+    /// Returns the number of streams in the cache.
+    pub fn len(&self) -> usize {
+        self.indexes.lock().unwrap().len()
+    }
+}
+
+impl IndexCache<Box<dyn StreamReader>> {
+    /// Create a cache that reads index streams from a storage backend.
+    /// Methods must not be called from an async context because the readers may block.
+    pub fn with_storage_backend(storage: Arc<dyn StorageBackend>, refresh_interval: Duration, capacity: usize) -> Self {
+        Self::new(move |index_scoped_stream: &ScopedStream| {
+            storage.create_reader(index_scoped_stream)
+        }, refresh_interval, capacity)
+    }
+}
+
+impl IndexCache<AsyncByteReader> {
+    /// Create a cache that reads index streams using the Pravega client.
+    /// Use the async methods of CachedIndex with this cache.
+    pub fn with_client_factory_async(client_factory: ClientFactoryAsync, refresh_interval: Duration, capacity: usize) -> Self {
+        Self::new(move |index_scoped_stream: &ScopedStream| {
+            Ok(AsyncByteReader::open(client_factory.clone(), index_scoped_stream.clone()))
+        }, refresh_interval, capacity)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::index::{IndexRecordWriter, IndexSearcher};
+    use std::io::Cursor;
+
+    /// An in-memory byte stream that can be appended to and truncated while it is being read.
+    #[derive(Clone)]
+    struct SharedStream {
+        data: Arc<Mutex<(Vec<u8>, u64)>>,
+        position: u64,
+    }
+
+    impl SharedStream {
+        fn new() -> Self {
+            Self { data: Arc::new(Mutex::new((Vec::new(), 0))), position: 0 }
+        }
+
+        fn append(&self, bytes: &[u8]) {
+            self.data.lock().unwrap().0.extend_from_slice(bytes);
+        }
+
+        fn truncate(&self, head: u64) {
+            self.data.lock().unwrap().1 = head;
+        }
+    }
+
+    impl Read for SharedStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let data = self.data.lock().unwrap();
+            let mut cursor = Cursor::new(&data.0[..]);
+            cursor.set_position(self.position);
+            let n = Read::read(&mut cursor, buf)?;
+            self.position += n as u64;
+            Ok(n)
+        }
+    }
+
+    impl Seek for SharedStream {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            let data = self.data.lock().unwrap();
+            let mut cursor = Cursor::new(&data.0[..]);
+            cursor.set_position(self.position);
+            self.position = Seek::seek(&mut cursor, pos)?;
+            Ok(self.position)
+        }
+    }
+
+    impl CurrentHead for SharedStream {
+        fn current_head(&self) -> std::io::Result<u64> {
+            Ok(self.data.lock().unwrap().1)
+        }
+    }
+
+    fn make_records(num_recs: usize, first_timestamp: u64, first_offset: u64) -> Vec<IndexRecord> {
+        (0..num_recs).map(|i| {
+            IndexRecord::new(
+                PravegaTimestamp::from_nanoseconds(Some(first_timestamp + 1000 * i as u64 + (i * i) as u64)),
+                first_offset + 100 * i as u64 + (i * i) as u64,
+                true, i == 0)
+        }).collect()
+    }
+
+    fn serialize(records: &[IndexRecord], version: IndexVersion) -> Vec<u8> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut writer = IndexRecordWriter::with_version(version);
+        for rec in records {
+            writer.write(rec, &mut cursor).unwrap();
+        }
+        cursor.into_inner()
+    }
+
+    #[test]
+    fn test_cached_index_matches_searcher() {
+        for version in [IndexVersion::V1, IndexVersion::V2].iter() {
+            let records = make_records(50, 1_600_000_000_000_000_000, 300);
+            let bytes = serialize(&records, *version);
+            let mut index_searcher = IndexSearcher::new(Cursor::new(bytes.clone()));
+            let mut cached_index = CachedIndex::new(Cursor::new(bytes));
+            assert_eq!(cached_index.get_index_records().unwrap(), index_searcher.get_index_records().unwrap());
+            assert_eq!(cached_index.get_first_record().unwrap(), index_searcher.get_first_record().unwrap());
+            assert_eq!(cached_index.get_last_record().unwrap(), index_searcher.get_last_record().unwrap());
+            let first = records.first().unwrap().timestamp.nanoseconds().unwrap();
+            let last = records.last().unwrap().timestamp.nanoseconds().unwrap();
+            for t in (first - 10..last + 10).step_by(7) {
+                let t = PravegaTimestamp::from_nanoseconds(Some(t));
+                assert_eq!(
+                    cached_index.search_timestamp_and_return_index_offset(t, SearchMethod::Before).unwrap(),
+                    index_searcher.search_timestamp_and_return_index_offset(t, SearchMethod::Before).unwrap());
+                assert_eq!(
+                    cached_index.search_timestamp_and_return_index_offset(t, SearchMethod::After).unwrap(),
+                    index_searcher.search_timestamp_and_return_index_offset(t, SearchMethod::After).unwrap());
+            }
+            let total_size = records.last().unwrap().offset - records.first().unwrap().offset;
+            for size in (0..total_size + 10).step_by(13) {
+                assert_eq!(
+                    cached_index.search_size_and_return_index_offset(size, SearchMethod::Before).unwrap(),
+                    index_searcher.search_size_and_return_index_offset(size, SearchMethod::Before).unwrap());
+                assert_eq!(
+                    cached_index.search_size_and_return_index_offset(size, SearchMethod::After).unwrap(),
+                    index_searcher.search_size_and_return_index_offset(size, SearchMethod::After).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_cached_index_tail_and_truncation() {
+        let stream = SharedStream::new();
+        let mut cached_index = CachedIndex::new(stream.clone());
+        let err = cached_index.get_first_record().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+        // Follow appends at the tail.
+        let records = make_records(20, 1_600_000_000_000_000_000, 0);
+        stream.append(&serialize(&records[0..10], IndexVersion::V1));
+        assert_eq!(cached_index.get_last_record().unwrap(), records[9]);
+        stream.append(&serialize(&records[10..20], IndexVersion::V1));
+        assert_eq!(cached_index.get_last_record().unwrap(), records[19]);
+        assert_eq!(cached_index.get_index_records().unwrap().len(), 20);
+        assert_eq!(cached_index.get_index_records_in_range(40, 100).unwrap(),
+            vec![(records[2], 40), (records[3], 60), (records[4], 80)]);
+        assert_eq!(cached_index.search_offset_after(records[2].offset - 1).unwrap(), Some((records[2], 40)));
+        assert_eq!(cached_index.search_offset_after(records[2].offset).unwrap(), Some((records[3], 60)));
+        assert_eq!(cached_index.search_offset_after(records[19].offset).unwrap(), None);
+
+        // Discard truncated records at the head.
+        stream.truncate(5 * IndexRecord::RECORD_SIZE as u64);
+        assert_eq!(cached_index.get_first_record().unwrap(), records[5]);
+        assert_eq!(cached_index.get_index_records().unwrap().len(), 15);
+
+        // Truncation beyond the cached records.
+        let more_records = make_records(5, 1_700_000_000_000_000_000, 10000);
+        stream.append(&serialize(&more_records, IndexVersion::V1));
+        stream.truncate(22 * IndexRecord::RECORD_SIZE as u64);
+        assert_eq!(cached_index.get_index_records().unwrap(),
+            vec![(more_records[2], 440), (more_records[3], 460), (more_records[4], 480)]);
+    }
+
+    /// Compare every search of a CachedIndex with an IndexSearcher that reads the same stream.
+    fn check_matches_searcher(cached_index: &mut CachedIndex<SharedStream>, stream: &SharedStream) {
+        let mut stream = stream.clone();
+        stream.position = 0;
+        let mut index_searcher = IndexSearcher::new(stream);
+        let index_records = index_searcher.get_index_records().unwrap();
+        assert_eq!(cached_index.get_index_records().unwrap(), index_records);
+        assert_eq!(cached_index.get_first_record().unwrap(), index_searcher.get_first_record().unwrap());
+        assert_eq!(cached_index.get_last_record().unwrap(), index_searcher.get_last_record().unwrap());
+        let (first, _) = index_records.first().unwrap();
+        let (last, _) = index_records.last().unwrap();
+        let first_timestamp = first.timestamp.nanoseconds().unwrap();
+        let last_timestamp = last.timestamp.nanoseconds().unwrap();
+        for t in (first_timestamp - 10..last_timestamp + 10).step_by(97) {
+            let t = PravegaTimestamp::from_nanoseconds(Some(t));
+            assert_eq!(
+                cached_index.search_timestamp_and_return_index_offset(t, SearchMethod::Before).unwrap(),
+                index_searcher.search_timestamp_and_return_index_offset(t, SearchMethod::Before).unwrap());
+            assert_eq!(
+                cached_index.search_timestamp_and_return_index_offset(t, SearchMethod::After).unwrap(),
+                index_searcher.search_timestamp_and_return_index_offset(t, SearchMethod::After).unwrap());
+        }
+        for size in (0..last.offset - first.offset + 10).step_by(53) {
+            assert_eq!(
+                cached_index.search_size_and_return_index_offset(size, SearchMethod::Before).unwrap(),
+                index_searcher.search_size_and_return_index_offset(size, SearchMethod::Before).unwrap());
+            assert_eq!(
+                cached_index.search_size_and_return_index_offset(size, SearchMethod::After).unwrap(),
+                index_searcher.search_size_and_return_index_offset(size, SearchMethod::After).unwrap());
+        }
+        for offset in (first.offset.saturating_sub(10)..last.offset + 10).step_by(41) {
+            let expected = index_records.iter().find(|(r, _)| r.offset > offset).copied();
+            assert_eq!(cached_index.search_offset_after(offset).unwrap(), expected);
+        }
+        for (_, begin) in index_records.iter().step_by(7) {
+            let end = begin + 9 * IndexRecord::RECORD_SIZE as u64;
+            let expected: Vec<_> = index_records.iter().filter(|(_, o)| begin <= o && *o < end).copied().collect();
+            assert_eq!(cached_index.get_index_records_in_range(*begin, end).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_cached_index_max_records() {
+        let stream = SharedStream::new();
+        let mut cached_index = CachedIndex::new(stream.clone()).with_max_records(10);
+        let records = make_records(100, 1_600_000_000_000_000_000, 0);
+        stream.append(&serialize(&records[0..5], IndexVersion::V1));
+        check_matches_searcher(&mut cached_index, &stream);
+        assert_eq!(cached_index.records.len(), 5);
+
+        // Only the last 10 records are cached. Older records are read from the stream.
+        stream.append(&serialize(&records[5..30], IndexVersion::V1));
+        check_matches_searcher(&mut cached_index, &stream);
+        assert_eq!(cached_index.records.len(), 10);
+        assert_eq!(cached_index.records[0], (records[20], 400));
+        assert_eq!(cached_index.head_record, Some((records[0], 0)));
+
+        // Records that were never cached are skipped when reading the tail.
+        stream.append(&serialize(&records[30..100], IndexVersion::V1));
+        check_matches_searcher(&mut cached_index, &stream);
+        assert_eq!(cached_index.records.len(), 10);
+        assert_eq!(cached_index.records[0], (records[90], 1800));
+
+        // Truncation before and into the cached records.
+        stream.truncate(50 * IndexRecord::RECORD_SIZE as u64);
+        check_matches_searcher(&mut cached_index, &stream);
+        assert_eq!(cached_index.head_record, Some((records[50], 1000)));
+        stream.truncate(95 * IndexRecord::RECORD_SIZE as u64);
+        check_matches_searcher(&mut cached_index, &stream);
+        assert_eq!(cached_index.records.len(), 5);
+        assert_eq!(cached_index.head_record, None);
+    }
+
+    #[test]
+    fn test_index_cache() {
+        let stream = SharedStream::new();
+        let records = make_records(10, 1_600_000_000_000_000_000, 0);
+        stream.append(&serialize(&records, IndexVersion::V2));
+        let opened = Arc::new(Mutex::new(Vec::new()));
+        let index_cache = {
+            let opened = opened.clone();
+            let stream = stream.clone();
+            IndexCache::new(move |index_scoped_stream: &ScopedStream| {
+                opened.lock().unwrap().push(index_scoped_stream.stream.name.clone());
+                Ok(stream.clone())
+            }, Duration::from_secs(0), 2)
+        };
+        let scoped_stream = ScopedStream::from("scope1/stream1");
+        let index1 = index_cache.get(&scoped_stream).unwrap();
+        let index2 = index_cache.clone().get(&scoped_stream).unwrap();
+        assert!(Arc::ptr_eq(&index1, &index2));
+        assert_eq!(*opened.lock().unwrap(), vec!["stream1-index".to_owned()]);
+        assert_eq!(index1.blocking_lock().get_last_record().unwrap(), records[9]);
+        assert_eq!(index1.blocking_lock().index_version().unwrap(), Some(IndexVersion::V2));
+
+        // The least recently used index is evicted when the capacity is exceeded.
+        let scoped_stream2 = ScopedStream::from("scope1/stream2");
+        let scoped_stream3 = ScopedStream::from("scope1/stream3");
+        index_cache.get(&scoped_stream2).unwrap();
+        std::thread::sleep(Duration::from_millis(2));
+        index_cache.get(&scoped_stream).unwrap();
+        std::thread::sleep(Duration::from_millis(2));
+        index_cache.get(&scoped_stream3).unwrap();
+        assert_eq!(index_cache.len(), 2);
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(Arc::ptr_eq(&index1, &index_cache.get(&scoped_stream).unwrap()));
+        index_cache.get(&scoped_stream2).unwrap();
+        assert_eq!(*opened.lock().unwrap(), vec!["stream1-index", "stream2-index", "stream3-index", "stream2-index"]);
+
+        index_cache.remove(&scoped_stream);
+        index_cache.remove(&scoped_stream2);
+        assert_eq!(index_cache.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_cached_index_async() {
+        let records = make_records(30, 1_600_000_000_000_000_000, 0);
+        let bytes = serialize(&records, IndexVersion::V2);
+        let mut cached_index = CachedIndex::new(Cursor::new(bytes.clone()));
+        let mut async_cached_index = CachedIndex::new(Cursor::new(bytes));
+        assert_eq!(async_cached_index.index_version_async().await.unwrap(), Some(IndexVersion::V2));
+        assert_eq!(async_cached_index.get_index_records_async().await.unwrap(), cached_index.get_index_records().unwrap());
+        assert_eq!(async_cached_index.get_last_record_async().await.unwrap(), records[29]);
+        let timestamp = PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_010_500));
+        assert_eq!(
+            async_cached_index.search_timestamp_and_return_index_offset_async(timestamp, SearchMethod::After).await.unwrap(),
+            cached_index.search_timestamp_and_return_index_offset(timestamp, SearchMethod::After).unwrap());
+        assert_eq!(
+            async_cached_index.search_size_and_return_index_offset_async(1000, SearchMethod::Before).await.unwrap(),
+            cached_index.search_size_and_return_index_offset(1000, SearchMethod::Before).unwrap());
+        assert_eq!(
+            async_cached_index.search_offset_after_async(1000).await.unwrap(),
+            cached_index.search_offset_after(1000).unwrap());
+
+        // Searches outside of the cached records read the stream.
+        let mut async_cached_index = CachedIndex::new(Cursor::new(serialize(&records, IndexVersion::V2))).with_max_records(5);
+        assert_eq!(async_cached_index.get_index_records_async().await.unwrap(), cached_index.get_index_records().unwrap());
+        assert_eq!(async_cached_index.records.len(), 5);
+        assert_eq!(async_cached_index.get_first_record_async().await.unwrap(), records[0]);
+        assert_eq!(
+            async_cached_index.search_timestamp_and_return_index_offset_async(timestamp, SearchMethod::After).await.unwrap(),
+            cached_index.search_timestamp_and_return_index_offset(timestamp, SearchMethod::After).unwrap());
+        assert_eq!(
+            async_cached_index.search_size_and_return_index_offset_async(10000, SearchMethod::Before).await.unwrap(),
+            cached_index.search_size_and_return_index_offset(10000, SearchMethod::Before).unwrap());
+        assert_eq!(
+            async_cached_index.search_offset_after_async(1000).await.unwrap(),
+            cached_index.search_offset_after(1000).unwrap());
+        assert_eq!(
+            async_cached_index.get_index_records_in_range_async(96, 480).await.unwrap(),
+            cached_index.get_index_records_in_range(96, 480).unwrap());
+    }
+}
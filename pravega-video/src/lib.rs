@@ -8,8 +8,14 @@
 // http://www.apache.org/licenses/LICENSE-2.0
 //
 
+pub mod error;
 pub mod event_serde;
 pub mod index;
+pub mod index_cache;
+pub mod storage;
+pub mod stream_verifier;
 pub mod timestamp;
 pub mod tracing;
 pub mod utils;
+pub mod video_stream_reader;
+pub mod video_stream_writer;
@@ -0,0 +1,726 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Storage backends for byte streams.
+// Besides Pravega, streams can be stored in a local directory or in memory.
+// This allows development, testing, and offline demos without a Pravega cluster.
+
+use crate::error::VideoStreamError;
+use crate::utils::{CurrentHead, SyncByteReader, SyncByteWriter, create_client_config};
+use once_cell::sync::Lazy;
+use pravega_client::client_factory::ClientFactory;
+use pravega_client_config::ClientConfig;
+use pravega_client_shared::{Scaling, ScaleType, Scope, ScopedStream, StreamConfiguration};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+pub const FILE_URI_PREFIX: &str = "file://";
+pub const MEMORY_URI_PREFIX: &str = "memory://";
+
+/// Reads a byte stream.
+/// When reading at the tail of a stream that is not sealed, read blocks until data is available.
+/// When reading at the tail of a sealed stream, read returns 0.
+pub trait StreamReader: Read + Seek + CurrentHead + Send {}
+
+impl<T: Read + Seek + CurrentHead + Send> StreamReader for T {}
+
+/// Appends to a byte stream.
+/// Seek only supports SeekFrom::Current(0), which returns the current write offset.
+pub trait StreamWriter: Write + Seek + Send {
+    /// Prevent any further writes to the stream.
+    fn seal(&mut self) -> Result<()>;
+    /// Delete all data before the offset. Reads before this offset will fail.
+    fn truncate_data_before(&mut self, offset: u64) -> Result<()>;
+}
+
+impl<T: StreamWriter + ?Sized> StreamWriter for Box<T> {
+    fn seal(&mut self) -> Result<()> {
+        (**self).seal()
+    }
+
+    fn truncate_data_before(&mut self, offset: u64) -> Result<()> {
+        (**self).truncate_data_before(offset)
+    }
+}
+
+/// Creates streams and opens readers and writers for them.
+pub trait StorageBackend: Send + Sync {
+    /// Create a scope. This may fail if the scope already exists.
+    fn create_scope(&self, scope: &Scope) -> Result<()>;
+    /// Create a stream if it does not exist.
+    fn create_stream(&self, scoped_stream: &ScopedStream, tags: Option<Vec<String>>) -> Result<()>;
+    /// Open a reader positioned at offset 0.
+    fn create_reader(&self, scoped_stream: &ScopedStream) -> Result<Box<dyn StreamReader>>;
+    /// Open a writer positioned at the tail of the stream.
+    fn create_writer(&self, scoped_stream: &ScopedStream) -> Result<Box<dyn StreamWriter>>;
+}
+
+/// Create a storage backend from a controller URI.
+///   - `file:///path` stores streams in a local directory.
+///   - `memory://name` stores streams in memory. Backends with the same name in the same process share streams.
+///   - Anything else is a Pravega controller URI such as `tcp://127.0.0.1:9090`.
+pub fn create_storage_backend(controller: String, keycloak_file: Option<String>) -> std::result::Result<Arc<dyn StorageBackend>, VideoStreamError> {
+    if let Some(path) = controller.strip_prefix(FILE_URI_PREFIX) {
+        info!("create_storage_backend: Using local directory {}", path);
+        Ok(Arc::new(LocalDirStorageBackend::new(path)))
+    } else if let Some(name) = controller.strip_prefix(MEMORY_URI_PREFIX) {
+        info!("create_storage_backend: Using in-memory storage {}", name);
+        Ok(Arc::new(MemoryStorageBackend::named(name)))
+    } else {
+        let config = create_client_config(controller, keycloak_file).map_err(VideoStreamError::Config)?;
+        Ok(Arc::new(PravegaStorageBackend::new(config)))
+    }
+}
+
+fn other_error<E: std::fmt::Debug>(error: E) -> Error {
+    Error::new(ErrorKind::Other, format!("{:?}", error))
+}
+
+fn truncated_error(offset: u64, head: u64) -> Error {
+    Error::new(ErrorKind::Other, format!("Offset {} has been truncated; the current head is {}", offset, head))
+}
+
+fn sealed_error() -> Error {
+    Error::new(ErrorKind::PermissionDenied, "Stream is sealed")
+}
+
+/// Resolve a seek position against the current position and tail of a stream.
+fn resolve_seek(pos: SeekFrom, position: u64, tail: u64) -> Result<u64> {
+    let new_position = match pos {
+        SeekFrom::Start(offset) => Some(offset),
+        SeekFrom::Current(delta) => add_signed(position, delta),
+        SeekFrom::End(delta) => add_signed(tail, delta),
+    };
+    new_position.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Invalid seek to a negative or overflowing position"))
+}
+
+fn add_signed(base: u64, delta: i64) -> Option<u64> {
+    if delta >= 0 {
+        base.checked_add(delta as u64)
+    } else {
+        base.checked_sub(delta.wrapping_neg() as u64)
+    }
+}
+
+//
+// Pravega
+//
+
+/// Streams stored in Pravega.
+/// Readers and writers use the Tokio runtime owned by this backend so it must outlive them.
+pub struct PravegaStorageBackend {
+    client_factory: ClientFactory,
+}
+
+impl PravegaStorageBackend {
+    pub fn new(client_config: ClientConfig) -> Self {
+        Self {
+            client_factory: ClientFactory::new(client_config),
+        }
+    }
+
+    pub fn client_factory(&self) -> &ClientFactory {
+        &self.client_factory
+    }
+}
+
+impl StorageBackend for PravegaStorageBackend {
+    fn create_scope(&self, scope: &Scope) -> Result<()> {
+        let controller_client = self.client_factory.controller_client();
+        self.client_factory.runtime().block_on(controller_client.create_scope(scope)).map_err(other_error)?;
+        Ok(())
+    }
+
+    fn create_stream(&self, scoped_stream: &ScopedStream, tags: Option<Vec<String>>) -> Result<()> {
+        let stream_config = StreamConfiguration {
+            scoped_stream: scoped_stream.clone(),
+            scaling: Scaling {
+                scale_type: ScaleType::FixedNumSegments,
+                min_num_segments: 1,
+                ..Default::default()
+            },
+            retention: Default::default(),
+            tags,
+        };
+        let controller_client = self.client_factory.controller_client();
+        self.client_factory.runtime().block_on(controller_client.create_stream(&stream_config)).map_err(other_error)?;
+        Ok(())
+    }
+
+    fn create_reader(&self, scoped_stream: &ScopedStream) -> Result<Box<dyn StreamReader>> {
+        let reader = self.client_factory.runtime().block_on(self.client_factory.create_byte_reader(scoped_stream.clone()));
+        Ok(Box::new(SyncByteReader::new(reader, self.client_factory.runtime_handle())))
+    }
+
+    fn create_writer(&self, scoped_stream: &ScopedStream) -> Result<Box<dyn StreamWriter>> {
+        let writer = self.client_factory.runtime().block_on(self.client_factory.create_byte_writer(scoped_stream.clone()));
+        let mut writer = SyncByteWriter::new(writer, self.client_factory.runtime_handle());
+        writer.seek_to_tail();
+        Ok(Box::new(writer))
+    }
+}
+
+impl StreamWriter for SyncByteWriter {
+    fn seal(&mut self) -> Result<()> {
+        SyncByteWriter::seal(self)
+    }
+
+    fn truncate_data_before(&mut self, offset: u64) -> Result<()> {
+        SyncByteWriter::truncate_data_before(self, offset)
+    }
+}
+
+//
+// Local directory
+//
+
+/// Streams stored in a local directory.
+/// Each stream is a directory `scope/stream` containing the file `data`.
+/// The truncation offset is stored in the file `head` and the file `sealed` marks a sealed stream.
+/// On Linux, truncated data is deallocated by punching a hole in the data file so that offsets do not change.
+/// Readers and writers may be in different processes. A writer finds that another writer sealed the stream within a second.
+pub struct LocalDirStorageBackend {
+    root: PathBuf,
+}
+
+impl LocalDirStorageBackend {
+    const DATA_FILE_NAME: &'static str = "data";
+    const HEAD_FILE_NAME: &'static str = "head";
+    const SEALED_FILE_NAME: &'static str = "sealed";
+    const TAIL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+    // Readers cache the head and seal state. They refresh them at the tail, on seek, and at least this often.
+    // Writers check whether another writer has sealed the stream at most this often.
+    const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: root.into(),
+        }
+    }
+
+    fn stream_dir(&self, scoped_stream: &ScopedStream) -> PathBuf {
+        self.root.join(&scoped_stream.scope.name).join(&scoped_stream.stream.name)
+    }
+
+    fn read_head(dir: &Path) -> Result<u64> {
+        match fs::read_to_string(dir.join(Self::HEAD_FILE_NAME)) {
+            Ok(s) => s.trim().parse().map_err(|_| Error::new(ErrorKind::InvalidData, format!("Invalid head file in {}", dir.display()))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn is_sealed(dir: &Path) -> bool {
+        dir.join(Self::SEALED_FILE_NAME).exists()
+    }
+}
+
+impl StorageBackend for LocalDirStorageBackend {
+    fn create_scope(&self, scope: &Scope) -> Result<()> {
+        fs::create_dir_all(self.root.join(&scope.name))
+    }
+
+    fn create_stream(&self, scoped_stream: &ScopedStream, _tags: Option<Vec<String>>) -> Result<()> {
+        let dir = self.stream_dir(scoped_stream);
+        fs::create_dir_all(&dir)?;
+        OpenOptions::new().create(true).append(true).open(dir.join(Self::DATA_FILE_NAME))?;
+        Ok(())
+    }
+
+    fn create_reader(&self, scoped_stream: &ScopedStream) -> Result<Box<dyn StreamReader>> {
+        let dir = self.stream_dir(scoped_stream);
+        let file = File::open(dir.join(Self::DATA_FILE_NAME))?;
+        let mut reader = FileStreamReader {
+            file,
+            dir,
+            position: 0,
+            head: 0,
+            sealed: false,
+            refresh_time: Instant::now(),
+        };
+        reader.refresh()?;
+        Ok(Box::new(reader))
+    }
+
+    fn create_writer(&self, scoped_stream: &ScopedStream) -> Result<Box<dyn StreamWriter>> {
+        let dir = self.stream_dir(scoped_stream);
+        let file = OpenOptions::new().append(true).open(dir.join(Self::DATA_FILE_NAME))?;
+        let offset = file.metadata()?.len();
+        let sealed = Self::is_sealed(&dir);
+        Ok(Box::new(FileStreamWriter {
+            file,
+            dir,
+            offset,
+            sealed,
+            seal_check_time: Instant::now(),
+        }))
+    }
+}
+
+struct FileStreamReader {
+    file: File,
+    dir: PathBuf,
+    position: u64,
+    // Cached contents of the head file.
+    head: u64,
+    // Cached existence of the sealed file. A stream cannot be unsealed.
+    sealed: bool,
+    refresh_time: Instant,
+}
+
+impl FileStreamReader {
+    fn refresh(&mut self) -> Result<()> {
+        self.head = LocalDirStorageBackend::read_head(&self.dir)?;
+        self.sealed = self.sealed || LocalDirStorageBackend::is_sealed(&self.dir);
+        self.refresh_time = Instant::now();
+        Ok(())
+    }
+}
+
+impl Read for FileStreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.refresh_time.elapsed() >= LocalDirStorageBackend::REFRESH_INTERVAL {
+            self.refresh()?;
+        }
+        loop {
+            if self.position < self.head {
+                return Err(truncated_error(self.position, self.head));
+            }
+            let n = self.file.read(buf)?;
+            if buf[..n].contains(&0) {
+                // The data may have been truncated while it was read, in which case the hole reads as zeros.
+                // The head file is written before the hole is punched, so the new head is visible now.
+                self.head = LocalDirStorageBackend::read_head(&self.dir)?;
+                if self.position < self.head {
+                    self.file.seek(SeekFrom::Start(self.position))?;
+                    return Err(truncated_error(self.position, self.head));
+                }
+            }
+            if n > 0 || buf.is_empty() || self.sealed {
+                self.position += n as u64;
+                return Ok(n);
+            }
+            // At the tail. If the stream has been sealed, read again so that we do not miss data written just before sealing.
+            self.refresh()?;
+            if !self.sealed {
+                std::thread::sleep(LocalDirStorageBackend::TAIL_POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+impl Seek for FileStreamReader {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        self.refresh()?;
+        let tail = self.file.metadata()?.len();
+        let position = resolve_seek(pos, self.position, tail)?;
+        self.file.seek(SeekFrom::Start(position))?;
+        self.position = position;
+        Ok(position)
+    }
+}
+
+impl CurrentHead for FileStreamReader {
+    fn current_head(&self) -> Result<u64> {
+        LocalDirStorageBackend::read_head(&self.dir)
+    }
+}
+
+struct FileStreamWriter {
+    file: File,
+    dir: PathBuf,
+    offset: u64,
+    // True once the stream is found to be sealed, possibly by another writer.
+    sealed: bool,
+    seal_check_time: Instant,
+}
+
+impl Write for FileStreamWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if !self.sealed && self.seal_check_time.elapsed() >= LocalDirStorageBackend::REFRESH_INTERVAL {
+            self.sealed = LocalDirStorageBackend::is_sealed(&self.dir);
+            self.seal_check_time = Instant::now();
+        }
+        if self.sealed {
+            return Err(sealed_error());
+        }
+        let n = self.file.write(buf)?;
+        self.offset += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for FileStreamWriter {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        match pos {
+            SeekFrom::Current(0) => Ok(self.offset),
+            _ => Err(Error::new(ErrorKind::InvalidInput, "Seek is not allowed")),
+        }
+    }
+}
+
+impl StreamWriter for FileStreamWriter {
+    fn seal(&mut self) -> Result<()> {
+        File::create(self.dir.join(LocalDirStorageBackend::SEALED_FILE_NAME))?;
+        self.sealed = true;
+        Ok(())
+    }
+
+    fn truncate_data_before(&mut self, offset: u64) -> Result<()> {
+        let tail = self.file.metadata()?.len();
+        if offset > tail {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Truncation offset {} is beyond the tail {}", offset, tail)));
+        }
+        if offset > LocalDirStorageBackend::read_head(&self.dir)? {
+            // Write to a temporary file and rename so that readers never see a partial head file.
+            let temp_path = self.dir.join(format!("{}.tmp", LocalDirStorageBackend::HEAD_FILE_NAME));
+            fs::write(&temp_path, offset.to_string())?;
+            fs::rename(&temp_path, self.dir.join(LocalDirStorageBackend::HEAD_FILE_NAME))?;
+            // The head file is written first so that readers that read the zeros in the hole will find the new head and fail.
+            punch_hole(&self.file, offset)?;
+        }
+        Ok(())
+    }
+}
+
+/// Deallocate the disk space used by the file before the offset.
+/// The length of the file and the offsets of later data do not change.
+#[cfg(target_os = "linux")]
+fn punch_hole(file: &File, offset: u64) -> Result<()> {
+    use std::os::unix::io::AsRawFd;
+    let result = unsafe {
+        libc::fallocate(file.as_raw_fd(), libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE, 0, offset as libc::off_t)
+    };
+    if result == 0 {
+        return Ok(());
+    }
+    let error = Error::last_os_error();
+    if error.raw_os_error() == Some(libc::EOPNOTSUPP) {
+        // Truncated data cannot be read because of the head file but its space will not be reclaimed.
+        warn!("punch_hole: File system does not support punching holes; truncated data will not be deallocated");
+        Ok(())
+    } else {
+        Err(error)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn punch_hole(_file: &File, _offset: u64) -> Result<()> {
+    Ok(())
+}
+
+//
+// Memory
+//
+
+static NAMED_MEMORY_BACKENDS: Lazy<Mutex<HashMap<String, MemoryStorageBackend>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Streams stored in memory.
+/// Clones share the same streams.
+#[derive(Clone, Default)]
+pub struct MemoryStorageBackend {
+    streams: Arc<Mutex<HashMap<ScopedStream, Arc<MemoryStream>>>>,
+}
+
+impl MemoryStorageBackend {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the backend with this name, creating it if needed.
+    /// This allows elements in the same process to share streams.
+    pub fn named(name: &str) -> Self {
+        NAMED_MEMORY_BACKENDS.lock().unwrap().entry(name.to_owned()).or_default().clone()
+    }
+
+    fn get_stream(&self, scoped_stream: &ScopedStream) -> Result<Arc<MemoryStream>> {
+        self.streams.lock().unwrap().get(scoped_stream).cloned().ok_or_else(|| {
+            Error::new(ErrorKind::NotFound, format!("Stream {} does not exist", scoped_stream))
+        })
+    }
+}
+
+impl StorageBackend for MemoryStorageBackend {
+    fn create_scope(&self, _scope: &Scope) -> Result<()> {
+        Ok(())
+    }
+
+    fn create_stream(&self, scoped_stream: &ScopedStream, _tags: Option<Vec<String>>) -> Result<()> {
+        self.streams.lock().unwrap().entry(scoped_stream.clone()).or_default();
+        Ok(())
+    }
+
+    fn create_reader(&self, scoped_stream: &ScopedStream) -> Result<Box<dyn StreamReader>> {
+        Ok(Box::new(MemoryStreamReader {
+            stream: self.get_stream(scoped_stream)?,
+            position: 0,
+        }))
+    }
+
+    fn create_writer(&self, scoped_stream: &ScopedStream) -> Result<Box<dyn StreamWriter>> {
+        Ok(Box::new(MemoryStreamWriter {
+            stream: self.get_stream(scoped_stream)?,
+        }))
+    }
+}
+
+#[derive(Default)]
+struct MemoryStream {
+    state: Mutex<MemoryStreamState>,
+    // Notified when data is appended or the stream is sealed.
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct MemoryStreamState {
+    // Data from the head to the tail.
+    data: Vec<u8>,
+    head: u64,
+    sealed: bool,
+}
+
+impl MemoryStreamState {
+    fn tail(&self) -> u64 {
+        self.head + self.data.len() as u64
+    }
+}
+
+struct MemoryStreamReader {
+    stream: Arc<MemoryStream>,
+    position: u64,
+}
+
+impl Read for MemoryStreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut state = self.stream.state.lock().unwrap();
+        loop {
+            if self.position < state.head {
+                return Err(truncated_error(self.position, state.head));
+            }
+            if self.position < state.tail() {
+                let start = (self.position - state.head) as usize;
+                let n = usize::min(buf.len(), state.data.len() - start);
+                buf[..n].copy_from_slice(&state.data[start..start + n]);
+                self.position += n as u64;
+                return Ok(n);
+            }
+            if buf.is_empty() || state.sealed {
+                return Ok(0);
+            }
+            state = self.stream.changed.wait(state).unwrap();
+        }
+    }
+}
+
+impl Seek for MemoryStreamReader {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let tail = self.stream.state.lock().unwrap().tail();
+        self.position = resolve_seek(pos, self.position, tail)?;
+        Ok(self.position)
+    }
+}
+
+impl CurrentHead for MemoryStreamReader {
+    fn current_head(&self) -> Result<u64> {
+        Ok(self.stream.state.lock().unwrap().head)
+    }
+}
+
+struct MemoryStreamWriter {
+    stream: Arc<MemoryStream>,
+}
+
+impl Write for MemoryStreamWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let mut state = self.stream.state.lock().unwrap();
+        if state.sealed {
+            return Err(sealed_error());
+        }
+        state.data.extend_from_slice(buf);
+        self.stream.changed.notify_all();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemoryStreamWriter {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        match pos {
+            SeekFrom::Current(0) => Ok(self.stream.state.lock().unwrap().tail()),
+            _ => Err(Error::new(ErrorKind::InvalidInput, "Seek is not allowed")),
+        }
+    }
+}
+
+impl StreamWriter for MemoryStreamWriter {
+    fn seal(&mut self) -> Result<()> {
+        self.stream.state.lock().unwrap().sealed = true;
+        self.stream.changed.notify_all();
+        Ok(())
+    }
+
+    fn truncate_data_before(&mut self, offset: u64) -> Result<()> {
+        let mut state = self.stream.state.lock().unwrap();
+        if offset > state.tail() {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Truncation offset {} is beyond the tail {}", offset, state.tail())));
+        }
+        if offset > state.head {
+            let len = (offset - state.head) as usize;
+            state.data.drain(..len);
+            state.head = offset;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pravega_client_shared::Stream;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// Writers that were opened before the stream was sealed must fail to write after seal_delay.
+    fn check_backend(backend: &dyn StorageBackend, seal_delay: Duration) {
+        let scoped_stream = ScopedStream {
+            scope: Scope::from("scope1".to_owned()),
+            stream: Stream::from("stream1".to_owned()),
+        };
+        assert!(backend.create_reader(&scoped_stream).is_err());
+        backend.create_scope(&scoped_stream.scope).unwrap();
+        backend.create_stream(&scoped_stream, None).unwrap();
+
+        // Append.
+        let mut writer = backend.create_writer(&scoped_stream).unwrap();
+        assert_eq!(writer.seek(SeekFrom::Current(0)).unwrap(), 0);
+        writer.write_all(b"0123456789").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.seek(SeekFrom::Current(0)).unwrap(), 10);
+        // Creating a stream that exists does not change it.
+        backend.create_stream(&scoped_stream, None).unwrap();
+        let mut writer2 = backend.create_writer(&scoped_stream).unwrap();
+        assert_eq!(writer2.seek(SeekFrom::Current(0)).unwrap(), 10);
+
+        // Read.
+        let mut reader = backend.create_reader(&scoped_stream).unwrap();
+        assert_eq!(reader.current_head().unwrap(), 0);
+        assert_eq!(reader.seek(SeekFrom::End(0)).unwrap(), 10);
+        assert_eq!(reader.seek(SeekFrom::Start(4)).unwrap(), 4);
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"4567");
+        assert_eq!(reader.seek(SeekFrom::Current(-2)).unwrap(), 6);
+
+        // Tail read blocks until data is written.
+        reader.seek(SeekFrom::Start(10)).unwrap();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            writer2.write_all(b"abc").unwrap();
+            writer2.flush().unwrap();
+            writer2
+        });
+        let mut buf = [0; 3];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"abc");
+        let mut writer2 = handle.join().unwrap();
+
+        // Truncate.
+        writer.truncate_data_before(5).unwrap();
+        assert_eq!(reader.current_head().unwrap(), 5);
+        reader.seek(SeekFrom::Start(2)).unwrap();
+        assert!(reader.read(&mut buf).is_err());
+        reader.seek(SeekFrom::Start(5)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"567");
+        // Truncating before the head is ignored.
+        writer.truncate_data_before(3).unwrap();
+        assert_eq!(reader.current_head().unwrap(), 5);
+        assert!(writer.truncate_data_before(100).is_err());
+
+        // Seal.
+        writer.seal().unwrap();
+        assert!(writer.write_all(b"x").is_err());
+        // Other writers must not be able to write after the stream is sealed.
+        let mut writer3 = backend.create_writer(&scoped_stream).unwrap();
+        assert!(writer3.write_all(b"x").is_err());
+        std::thread::sleep(seal_delay);
+        assert!(writer2.write_all(b"x").is_err());
+        reader.seek(SeekFrom::Start(10)).unwrap();
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(&rest[..], b"abc");
+    }
+
+    #[test]
+    fn test_memory_storage_backend() {
+        check_backend(&MemoryStorageBackend::new(), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn test_memory_storage_backend_named() {
+        let scoped_stream = ScopedStream::from("scope1/stream1");
+        MemoryStorageBackend::named("test_named").create_stream(&scoped_stream, None).unwrap();
+        assert!(MemoryStorageBackend::named("test_named").create_reader(&scoped_stream).is_ok());
+        assert!(MemoryStorageBackend::named("test_other").create_reader(&scoped_stream).is_err());
+    }
+
+    #[test]
+    fn test_local_dir_storage_backend() {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let root = std::env::temp_dir().join(format!("pravega-video-test-{}", nanos));
+        check_backend(&LocalDirStorageBackend::new(&root), LocalDirStorageBackend::REFRESH_INTERVAL);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_local_dir_storage_backend_reclaims_truncated_data() {
+        use std::os::unix::fs::MetadataExt;
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let root = std::env::temp_dir().join(format!("pravega-video-test-{}", nanos));
+        let backend = LocalDirStorageBackend::new(&root);
+        let scoped_stream = ScopedStream::from("scope1/stream1");
+        backend.create_stream(&scoped_stream, None).unwrap();
+        let mut writer = backend.create_writer(&scoped_stream).unwrap();
+        let length = 4 * 1024 * 1024;
+        writer.write_all(&vec![1; length]).unwrap();
+        writer.write_all(b"abc").unwrap();
+        let data_path = backend.stream_dir(&scoped_stream).join(LocalDirStorageBackend::DATA_FILE_NAME);
+        File::open(&data_path).unwrap().sync_all().unwrap();
+        let blocks_before = fs::metadata(&data_path).unwrap().blocks();
+        let mut old_reader = backend.create_reader(&scoped_stream).unwrap();
+
+        writer.truncate_data_before(length as u64).unwrap();
+        // A reader that has not refreshed its head must not return the zeros in the hole.
+        let mut buf = [0; 1024];
+        let err = old_reader.read(&mut buf).unwrap_err();
+        assert!(err.to_string().contains("has been truncated"), "{}", err);
+        let metadata = fs::metadata(&data_path).unwrap();
+        assert_eq!(metadata.len(), length as u64 + 3);
+        assert!(metadata.blocks() < blocks_before, "blocks_before={}, blocks_after={}", blocks_before, metadata.blocks());
+        let mut reader = backend.create_reader(&scoped_stream).unwrap();
+        reader.seek(SeekFrom::Start(length as u64)).unwrap();
+        let mut buf = [0; 3];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"abc");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,727 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// Verifies the integrity of a video stream and its index stream.
+// The data stream is walked event by event and compared with the index records
+// to check the encoding rules and constraints documented in event_serde.rs and index.rs.
+
+use crate::error::VideoStreamError;
+use crate::event_serde::EventWithHeader;
+use crate::index::{IndexRecord, IndexRecordReader, IndexVersion, get_index_stream_name};
+use crate::storage::StorageBackend;
+use crate::timestamp::{PravegaTimestamp, TimeDelta, SECOND};
+use crate::utils::CurrentHead;
+use pravega_client_shared::{ScopedStream, Stream};
+use std::convert::TryInto;
+use std::fmt;
+use std::io::{BufReader, Cursor, Error, ErrorKind, Read, Seek, SeekFrom};
+use tracing::{debug, info};
+
+/// Flag bits that may be set in byte 11 of an event.
+const EVENT_FLAGS_MASK: u8 = 0b00000111;
+/// Flag bits that may be set in byte 3 of an index record.
+const INDEX_FLAGS_MASK: u8 = 0b00000110;
+
+#[derive(Debug, Clone)]
+pub struct StreamVerifierConfig {
+    /// A gap in the media longer than this will be reported.
+    pub gap_threshold: TimeDelta,
+    /// Stop recording issues after this many. Issues are still counted.
+    pub max_issues: usize,
+}
+
+impl Default for StreamVerifierConfig {
+    fn default() -> Self {
+        Self {
+            gap_threshold: SECOND,
+            max_issues: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Informational, such as a discontinuity written by a new writer session.
+    Info,
+    /// Unusual but allowed, such as a gap or an incomplete event at the tail of a stream that is being written.
+    Warning,
+    /// A violation of the encoding rules or index constraints.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Info => f.write_str("INFO"),
+            Severity::Warning => f.write_str("WARNING"),
+            Severity::Error => f.write_str("ERROR"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamKind {
+    Data,
+    Index,
+}
+
+impl fmt::Display for StreamKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StreamKind::Data => f.write_str("data"),
+            StreamKind::Index => f.write_str("index"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueKind {
+    /// The type code or event length of an event is invalid.
+    InvalidEventFraming,
+    /// The last event extends beyond the tail of the data stream.
+    IncompleteEvent,
+    /// A reserved bit is set in an event or index record.
+    ReservedBitsSet,
+    /// An index record has an unsupported version, a different version than the first record, or a timestamp of 0.
+    InvalidIndexRecord,
+    /// The last index record extends beyond the tail of the index stream.
+    IncompleteIndexRecord,
+    IndexTimestampDecreased,
+    IndexOffsetDecreased,
+    /// An index record points to the middle of an event.
+    IndexOffsetNotOnEventBoundary,
+    /// An index record points to data that has been truncated.
+    IndexOffsetBeforeHead,
+    /// An index record points beyond the tail of the data stream.
+    IndexOffsetBeyondData,
+    /// The flags in an index record do not match the flags of the event it points to.
+    IndexFlagsMismatch,
+    /// An event has the include-in-index flag but there is no index record for it.
+    IndexRecordMissing,
+    /// The segment length of a version 2 index record does not match the offsets of the records.
+    IndexSegmentLengthMismatch,
+    /// An event between the first and last index records has a timestamp outside of the range of the index.
+    TimestampOutOfRange,
+    Gap,
+    Discontinuity,
+    /// Data after the last index record.
+    UnindexedData,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerificationIssue {
+    pub severity: Severity,
+    pub kind: IssueKind,
+    pub stream: StreamKind,
+    /// Offset in the data or index stream.
+    pub offset: u64,
+    pub message: String,
+}
+
+impl fmt::Display for VerificationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:?} at {} offset {}: {}", self.severity, self.kind, self.stream, self.offset, self.message)
+    }
+}
+
+/// The result of verifying a video stream.
+#[derive(Debug, Clone, Default)]
+pub struct VerificationReport {
+    pub data_head: u64,
+    pub data_tail: u64,
+    pub index_head: u64,
+    pub index_tail: u64,
+    pub index_version: Option<IndexVersion>,
+    pub event_count: u64,
+    pub index_record_count: u64,
+    /// Timestamps of the first and last events with a timestamp.
+    pub first_timestamp: PravegaTimestamp,
+    pub last_timestamp: PravegaTimestamp,
+    pub discontinuity_count: u64,
+    pub gap_count: u64,
+    pub error_count: u64,
+    pub warning_count: u64,
+    /// Issues in the order that they were found, limited to StreamVerifierConfig::max_issues.
+    pub issues: Vec<VerificationIssue>,
+}
+
+impl VerificationReport {
+    /// Returns true if no errors were found. Warnings and informational issues are allowed.
+    pub fn is_ok(&self) -> bool {
+        self.error_count == 0
+    }
+
+    pub fn issues_of_kind(&self, kind: IssueKind) -> impl Iterator<Item = &VerificationIssue> {
+        self.issues.iter().filter(move |issue| issue.kind == kind)
+    }
+}
+
+impl fmt::Display for VerificationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "data stream:     head={}, tail={}, events={}", self.data_head, self.data_tail, self.event_count)?;
+        writeln!(f, "index stream:    head={}, tail={}, records={}, version={:?}",
+            self.index_head, self.index_tail, self.index_record_count, self.index_version)?;
+        writeln!(f, "timestamps:      first={}, last={}", self.first_timestamp, self.last_timestamp)?;
+        writeln!(f, "discontinuities: {}, gaps: {}", self.discontinuity_count, self.gap_count)?;
+        for issue in self.issues.iter() {
+            writeln!(f, "{}", issue)?;
+        }
+        let omitted = self.error_count + self.warning_count
+            - self.issues.iter().filter(|issue| issue.severity >= Severity::Warning).count() as u64;
+        if omitted > 0 {
+            writeln!(f, "({} additional issues were not recorded)", omitted)?;
+        }
+        write!(f, "result:          {} ({} errors, {} warnings)",
+            if self.is_ok() { "OK" } else { "FAILED" }, self.error_count, self.warning_count)
+    }
+}
+
+/// Verify a video stream and its index stream in any storage backend.
+pub fn verify_stream_with_storage(storage: &dyn StorageBackend, scoped_stream: &ScopedStream,
+        config: &StreamVerifierConfig) -> Result<VerificationReport, VideoStreamError> {
+    let index_scoped_stream = ScopedStream {
+        scope: scoped_stream.scope.clone(),
+        stream: Stream::from(get_index_stream_name(&scoped_stream.stream.name)),
+    };
+    let data_reader = storage.create_reader(scoped_stream)?;
+    let index_reader = storage.create_reader(&index_scoped_stream)?;
+    verify_stream(data_reader, index_reader, config)
+}
+
+/// Verify a data stream and its index.
+/// Only the bytes between the head and the tail at the time of the call are verified.
+/// An error is returned only if the streams could not be read. Problems with the content are reported in the VerificationReport.
+pub fn verify_stream<D, I>(data_reader: D, index_reader: I, config: &StreamVerifierConfig)
+        -> Result<VerificationReport, VideoStreamError>
+where
+    D: Read + Seek + CurrentHead,
+    I: Read + Seek + CurrentHead,
+{
+    let mut verifier = Verifier {
+        config,
+        report: VerificationReport::default(),
+    };
+    // The index is read before the data so that any data referenced by the index will be readable.
+    let records = verifier.read_index(index_reader)?;
+    verifier.check_index(&records);
+    verifier.check_data(data_reader, &records)?;
+    info!("verify_stream: events={}, index_records={}, errors={}, warnings={}",
+        verifier.report.event_count, verifier.report.index_record_count,
+        verifier.report.error_count, verifier.report.warning_count);
+    Ok(verifier.report)
+}
+
+struct Verifier<'a> {
+    config: &'a StreamVerifierConfig,
+    report: VerificationReport,
+}
+
+/// An index record and its offset in the index stream.
+struct LocatedIndexRecord {
+    index_offset: u64,
+    record: IndexRecord,
+}
+
+/// The fields of an event header that are checked.
+struct RawEvent {
+    length: u64,
+    timestamp: PravegaTimestamp,
+    include_in_index: bool,
+    random_access: bool,
+    discontinuity: bool,
+    reserved_bits_set: bool,
+}
+
+enum ReadEventResult {
+    Event(RawEvent),
+    End,
+    Incomplete(String),
+    Invalid(String),
+}
+
+impl<'a> Verifier<'a> {
+    fn add_issue(&mut self, severity: Severity, kind: IssueKind, stream: StreamKind, offset: u64, message: String) {
+        debug!("Verifier: {:?} {:?} at {} offset {}: {}", severity, kind, stream, offset, message);
+        match severity {
+            Severity::Error => self.report.error_count += 1,
+            Severity::Warning => self.report.warning_count += 1,
+            Severity::Info => {},
+        }
+        if self.report.issues.len() < self.config.max_issues {
+            self.report.issues.push(VerificationIssue { severity, kind, stream, offset, message });
+        }
+    }
+
+    /// Read all index records between the head and tail of the index stream.
+    /// Records that cannot be parsed are reported and skipped.
+    fn read_index<I: Read + Seek + CurrentHead>(&mut self, index_reader: I) -> Result<Vec<LocatedIndexRecord>, VideoStreamError> {
+        let head = index_reader.current_head()?;
+        let mut index_reader = BufReader::new(index_reader);
+        let tail = index_reader.seek(SeekFrom::End(0))?;
+        index_reader.seek(SeekFrom::Start(head))?;
+        self.report.index_head = head;
+        self.report.index_tail = tail;
+        let mut records = Vec::new();
+        if tail - head < IndexVersion::HEADER_SIZE as u64 {
+            if tail > head {
+                self.add_issue(Severity::Warning, IssueKind::IncompleteIndexRecord, StreamKind::Index, head,
+                    format!("{} bytes is too short for an index record", tail - head));
+            }
+            return Ok(records);
+        }
+        let mut buffer = vec![0; IndexRecord::RECORD_SIZE_V2];
+        index_reader.read_exact(&mut buffer[0..IndexVersion::HEADER_SIZE])?;
+        index_reader.seek(SeekFrom::Start(head))?;
+        let version = match IndexVersion::from_header(&buffer[..]) {
+            Ok(version) => version,
+            Err(e) => {
+                self.add_issue(Severity::Error, IssueKind::InvalidIndexRecord, StreamKind::Index, head, e.to_string());
+                return Ok(records);
+            },
+        };
+        self.report.index_version = Some(version);
+        let record_size = version.record_size() as u64;
+        if head % record_size != 0 {
+            self.add_issue(Severity::Error, IssueKind::InvalidIndexRecord, StreamKind::Index, head,
+                format!("Head is not a multiple of the record size {}", record_size));
+        }
+        let mut index_record_reader = IndexRecordReader::new();
+        let mut index_offset = head;
+        while index_offset + record_size <= tail {
+            let buffer = &mut buffer[0..record_size as usize];
+            index_reader.read_exact(buffer)?;
+            if let Some(record) = self.parse_index_record(buffer, version, index_offset, &mut index_record_reader) {
+                records.push(LocatedIndexRecord { index_offset, record });
+            }
+            index_offset += record_size;
+        }
+        if index_offset < tail {
+            self.add_issue(Severity::Warning, IssueKind::IncompleteIndexRecord, StreamKind::Index, index_offset,
+                format!("{} bytes is too short for an index record", tail - index_offset));
+        }
+        self.report.index_record_count = records.len() as u64;
+        Ok(records)
+    }
+
+    fn parse_index_record(&mut self, buffer: &mut [u8], version: IndexVersion, index_offset: u64,
+            index_record_reader: &mut IndexRecordReader) -> Option<IndexRecord> {
+        match IndexVersion::from_header(buffer) {
+            Ok(v) if v == version => {},
+            Ok(v) => {
+                self.add_issue(Severity::Error, IssueKind::InvalidIndexRecord, StreamKind::Index, index_offset,
+                    format!("Record has version {:?} but the index has version {:?}", v, version));
+                return None;
+            },
+            Err(e) => {
+                self.add_issue(Severity::Error, IssueKind::InvalidIndexRecord, StreamKind::Index, index_offset, e.to_string());
+                return None;
+            },
+        }
+        let reserved_bits_set = buffer[1] != 0 || buffer[2] != 0 || buffer[3] & !INDEX_FLAGS_MASK != 0
+            || (version == IndexVersion::V2 && buffer[44..48].iter().any(|b| *b != 0));
+        if reserved_bits_set {
+            self.add_issue(Severity::Error, IssueKind::ReservedBitsSet, StreamKind::Index, index_offset,
+                "Reserved bits are set in index record".to_owned());
+            // Clear the reserved flags so that the rest of the record can be checked.
+            buffer[3] &= INDEX_FLAGS_MASK;
+        }
+        let record = match index_record_reader.read(&mut Cursor::new(&buffer[..])) {
+            Ok(record) => record,
+            Err(e) => {
+                self.add_issue(Severity::Error, IssueKind::InvalidIndexRecord, StreamKind::Index, index_offset, e.to_string());
+                return None;
+            },
+        };
+        if record.timestamp.is_none() {
+            self.add_issue(Severity::Error, IssueKind::InvalidIndexRecord, StreamKind::Index, index_offset,
+                "Index record has a timestamp of 0".to_owned());
+        }
+        Some(record)
+    }
+
+    /// Check the index records in the order that they were written.
+    fn check_index(&mut self, records: &[LocatedIndexRecord]) {
+        for (i, located) in records.iter().enumerate() {
+            let record = &located.record;
+            if i == 0 {
+                continue;
+            }
+            let prev = &records[i - 1].record;
+            if record.timestamp.is_some() && prev.timestamp.is_some() && record.timestamp < prev.timestamp {
+                self.add_issue(Severity::Error, IssueKind::IndexTimestampDecreased, StreamKind::Index, located.index_offset,
+                    format!("Timestamp {} is less than the previous timestamp {}", record.timestamp, prev.timestamp));
+            }
+            if record.offset < prev.offset {
+                self.add_issue(Severity::Error, IssueKind::IndexOffsetDecreased, StreamKind::Index, located.index_offset,
+                    format!("Offset {} is less than the previous offset {}", record.offset, prev.offset));
+            }
+            // The segment length is the number of bytes since the previous record written by the same session.
+            if let (Some(segment_length), Some(session_id)) = (record.segment_length, record.session_id) {
+                if prev.session_id == Some(session_id) && record.offset.checked_sub(prev.offset) != Some(segment_length) {
+                    self.add_issue(Severity::Error, IssueKind::IndexSegmentLengthMismatch, StreamKind::Index, located.index_offset,
+                        format!("Segment length {} does not match the offset difference from {} to {}",
+                            segment_length, prev.offset, record.offset));
+                }
+            }
+            if record.discontinuity {
+                self.report.discontinuity_count += 1;
+                self.add_issue(Severity::Info, IssueKind::Discontinuity, StreamKind::Index, located.index_offset,
+                    format!("Discontinuity at timestamp {}, data offset {}", record.timestamp, record.offset));
+            }
+            // If the segment duration is known, the gap is the time between records that was not covered by media.
+            // Otherwise, the gap can only be determined at a discontinuity.
+            let elapsed = record.timestamp - prev.timestamp;
+            let gap = match (elapsed.nanoseconds(), record.segment_duration.nanoseconds()) {
+                (Some(elapsed), Some(duration)) => Some(elapsed - duration),
+                (Some(elapsed), None) if record.discontinuity => Some(elapsed),
+                _ => None,
+            };
+            if let (Some(gap), Some(threshold)) = (gap, self.config.gap_threshold.nanoseconds()) {
+                if gap > threshold {
+                    self.report.gap_count += 1;
+                    self.add_issue(Severity::Warning, IssueKind::Gap, StreamKind::Index, located.index_offset,
+                        format!("Gap of {} before timestamp {}", TimeDelta(Some(gap)), record.timestamp));
+                }
+            }
+        }
+    }
+
+    /// Walk the data stream event by event and compare it with the index records.
+    fn check_data<D: Read + Seek + CurrentHead>(&mut self, data_reader: D, records: &[LocatedIndexRecord]) -> Result<(), VideoStreamError> {
+        let head = data_reader.current_head()?;
+        let mut data_reader = BufReader::with_capacity(128 * 1024, data_reader);
+        let tail = data_reader.seek(SeekFrom::End(0))?;
+        data_reader.seek(SeekFrom::Start(head))?;
+        self.report.data_head = head;
+        self.report.data_tail = tail;
+
+        // Per the constraints in index.rs, events between the first and last index records (O1 and ON)
+        // must have timestamps between the first and last index timestamps (T1 inclusive, TN exclusive).
+        let indexed_range = match (records.first(), records.last()) {
+            (Some(first), Some(last)) if records.len() >= 2 => Some((first.record, last.record)),
+            _ => None,
+        };
+
+        // Records are visited in the order of their data offsets.
+        let mut by_offset: Vec<&LocatedIndexRecord> = records.iter().collect();
+        by_offset.sort_by_key(|located| located.record.offset);
+        let mut next_record = 0;
+
+        let mut offset = head;
+        loop {
+            // Any records before this event were not on an event boundary.
+            while next_record < by_offset.len() && by_offset[next_record].record.offset < offset {
+                let located = by_offset[next_record];
+                if located.record.offset < head {
+                    self.add_issue(Severity::Warning, IssueKind::IndexOffsetBeforeHead, StreamKind::Index, located.index_offset,
+                        format!("Data offset {} is before the data head {}", located.record.offset, head));
+                } else {
+                    self.add_issue(Severity::Error, IssueKind::IndexOffsetNotOnEventBoundary, StreamKind::Index, located.index_offset,
+                        format!("Data offset {} is not at the beginning of an event", located.record.offset));
+                }
+                next_record += 1;
+            }
+
+            let event = match read_event(&mut data_reader, tail - offset)? {
+                ReadEventResult::Event(event) => event,
+                ReadEventResult::End => break,
+                ReadEventResult::Incomplete(message) => {
+                    self.add_issue(Severity::Warning, IssueKind::IncompleteEvent, StreamKind::Data, offset, message);
+                    break;
+                },
+                ReadEventResult::Invalid(message) => {
+                    self.add_issue(Severity::Error, IssueKind::InvalidEventFraming, StreamKind::Data, offset, message);
+                    // Attempt to resume at the next index record.
+                    match by_offset[next_record..].iter().find(|located| located.record.offset > offset) {
+                        Some(located) if located.record.offset < tail => {
+                            offset = located.record.offset;
+                            data_reader.seek(SeekFrom::Start(offset))?;
+                            continue;
+                        },
+                        _ => break,
+                    }
+                },
+            };
+            self.report.event_count += 1;
+            if event.reserved_bits_set {
+                self.add_issue(Severity::Error, IssueKind::ReservedBitsSet, StreamKind::Data, offset,
+                    "Reserved bits are set in event header".to_owned());
+            }
+            if event.timestamp.is_some() {
+                if self.report.first_timestamp.is_none() {
+                    self.report.first_timestamp = event.timestamp;
+                }
+                self.report.last_timestamp = event.timestamp;
+            }
+
+            let mut indexed = false;
+            while next_record < by_offset.len() && by_offset[next_record].record.offset == offset {
+                let located = by_offset[next_record];
+                let record = &located.record;
+                if event.include_in_index && record.random_access == event.random_access && record.discontinuity == event.discontinuity {
+                    indexed = true;
+                } else if !record.random_access && !record.discontinuity && event.discontinuity {
+                    // This is the final record written by the previous session, pointing at the first event of the next session.
+                } else {
+                    self.add_issue(Severity::Error, IssueKind::IndexFlagsMismatch, StreamKind::Index, located.index_offset,
+                        format!("Index record (RAN={}, DIS={}) does not match event at data offset {} (IND={}, RAN={}, DIS={})",
+                            record.random_access, record.discontinuity, offset,
+                            event.include_in_index, event.random_access, event.discontinuity));
+                }
+                next_record += 1;
+            }
+
+            if let Some((first, last)) = indexed_range {
+                if first.offset <= offset && offset < last.offset {
+                    if event.include_in_index && !indexed {
+                        self.add_issue(Severity::Warning, IssueKind::IndexRecordMissing, StreamKind::Data, offset,
+                            "Event should be included in the index but there is no index record for it".to_owned());
+                    }
+                    if event.timestamp.is_some() && (event.timestamp < first.timestamp || event.timestamp >= last.timestamp) {
+                        self.add_issue(Severity::Error, IssueKind::TimestampOutOfRange, StreamKind::Data, offset,
+                            format!("Timestamp {} is not in the index range from {} inclusive to {} exclusive",
+                                event.timestamp, first.timestamp, last.timestamp));
+                    }
+                }
+            }
+            offset += event.length;
+        }
+
+        // Remaining records point at or beyond the position where the walk ended.
+        // A record at the tail is allowed because the index record is written before the event it points to.
+        for located in by_offset[next_record..].iter() {
+            if located.record.offset > tail {
+                self.add_issue(Severity::Error, IssueKind::IndexOffsetBeyondData, StreamKind::Index, located.index_offset,
+                    format!("Data offset {} is beyond the data tail {}", located.record.offset, tail));
+            }
+        }
+
+        if let Some(last) = records.last() {
+            if last.record.offset < offset {
+                self.add_issue(Severity::Info, IssueKind::UnindexedData, StreamKind::Data, last.record.offset,
+                    format!("{} bytes after the last index record", offset - last.record.offset));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Read an event header and skip the payload.
+/// No more than `remaining` bytes will be read.
+fn read_event<R: Read>(reader: &mut R, remaining: u64) -> Result<ReadEventResult, Error> {
+    if remaining == 0 {
+        return Ok(ReadEventResult::End);
+    }
+    if remaining < 8 {
+        return Ok(ReadEventResult::Incomplete(format!("{} bytes is too short for an event header", remaining)));
+    }
+    let mut header = [0; 20];
+    reader.read_exact(&mut header[0..8])?;
+    let type_code = u32::from_be_bytes(header[0..4].try_into().unwrap());
+    if type_code != 0 {
+        return Ok(ReadEventResult::Invalid(format!("Invalid type code {}", type_code)));
+    }
+    // Event length must be between 12 and MAX_ATOMIC_WRITE_SIZE - 8.
+    let event_length = u32::from_be_bytes(header[4..8].try_into().unwrap()) as u64;
+    if event_length < 12 || event_length > EventWithHeader::max_payload_size() as u64 + 12 {
+        return Ok(ReadEventResult::Invalid(format!("Invalid event length {}", event_length)));
+    }
+    if 8 + event_length > remaining {
+        return Ok(ReadEventResult::Incomplete(format!("Event length {} extends beyond the tail", event_length)));
+    }
+    reader.read_exact(&mut header[8..20])?;
+    let payload_length = event_length - 12;
+    let skipped = std::io::copy(&mut reader.take(payload_length), &mut std::io::sink())?;
+    if skipped != payload_length {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "Unable to read event payload"));
+    }
+    let flags = header[11];
+    let timestamp = u64::from_be_bytes(header[12..20].try_into().unwrap());
+    Ok(ReadEventResult::Event(RawEvent {
+        length: 8 + event_length,
+        timestamp: PravegaTimestamp::from_nanoseconds(if timestamp == 0 { None } else { Some(timestamp) }),
+        include_in_index: flags & 0b001 != 0,
+        random_access: flags & 0b010 != 0,
+        discontinuity: flags & 0b100 != 0,
+        reserved_bits_set: header[8..11].iter().any(|b| *b != 0) || flags & !EVENT_FLAGS_MASK != 0,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_serde::EventWriter;
+    use crate::index::IndexRecordWriter;
+    use crate::video_stream_writer::{VideoFrame, VideoStreamWriter, VideoStreamWriterConfig};
+
+    const NANOS_PER_SECOND: u64 = 1_000_000_000;
+    const EVENT_SIZE: u64 = 120;
+
+    fn ts(nanos: u64) -> PravegaTimestamp {
+        PravegaTimestamp::from_nanoseconds(Some(1_600_000_000 * NANOS_PER_SECOND + nanos))
+    }
+
+    /// Write 30 frames, 100 ms apart, with key frames every 10 frames.
+    fn write_stream(index_version: IndexVersion) -> (Vec<u8>, Vec<u8>) {
+        let mut data = Cursor::new(Vec::new());
+        let mut index = Cursor::new(Vec::new());
+        {
+            let config = VideoStreamWriterConfig { index_version, ..Default::default() };
+            let mut writer = VideoStreamWriter::from_writers(&mut data, &mut index, config).unwrap();
+            let payload = vec![0; 100];
+            for i in 0..30 {
+                let mut frame = VideoFrame::new(&payload[..], ts(i * NANOS_PER_SECOND / 10), i % 10 == 0);
+                frame.duration = Some(NANOS_PER_SECOND / 10);
+                writer.write(&frame).unwrap();
+            }
+            writer.close().unwrap();
+        }
+        (data.into_inner(), index.into_inner())
+    }
+
+    fn verify(data: Vec<u8>, index: Vec<u8>) -> VerificationReport {
+        let report = verify_stream(Cursor::new(data), Cursor::new(index), &Default::default()).unwrap();
+        println!("{}", report);
+        report
+    }
+
+    fn kinds(report: &VerificationReport) -> Vec<IssueKind> {
+        report.issues.iter().map(|issue| issue.kind).collect()
+    }
+
+    #[test]
+    fn test_verify_valid_stream() {
+        for index_version in [IndexVersion::V1, IndexVersion::V2].iter() {
+            let (data, index) = write_stream(*index_version);
+            let report = verify(data, index);
+            assert!(report.is_ok());
+            assert_eq!(report.warning_count, 0);
+            assert_eq!(report.event_count, 30);
+            assert_eq!(report.index_record_count, 4);
+            assert_eq!(report.index_version, Some(*index_version));
+            assert_eq!(report.first_timestamp, ts(0));
+            assert_eq!(report.last_timestamp, ts(29 * NANOS_PER_SECOND / 10));
+            assert!(report.issues.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_verify_reserved_bits() {
+        let (mut data, index) = write_stream(IndexVersion::V2);
+        data[EVENT_SIZE as usize + 9] = 1;
+        let report = verify(data, index);
+        assert!(!report.is_ok());
+        assert_eq!(kinds(&report), vec![IssueKind::ReservedBitsSet]);
+        assert_eq!(report.issues[0].offset, EVENT_SIZE);
+    }
+
+    #[test]
+    fn test_verify_invalid_framing() {
+        // Corrupting the event length will cause the verifier to resume at the next index record.
+        let (mut data, index) = write_stream(IndexVersion::V1);
+        data[2 * EVENT_SIZE as usize + 4] = 0xff;
+        let report = verify(data, index);
+        assert_eq!(kinds(&report), vec![IssueKind::InvalidEventFraming]);
+        assert_eq!(report.event_count, 22);
+    }
+
+    #[test]
+    fn test_verify_index_offset_not_on_boundary() {
+        let (data, index) = write_stream(IndexVersion::V1);
+        let mut records = read_records(index);
+        records[1].offset += 1;
+        let report = verify(data, write_records(&records, IndexVersion::V1));
+        assert_eq!(kinds(&report), vec![IssueKind::IndexRecordMissing, IssueKind::IndexOffsetNotOnEventBoundary]);
+    }
+
+    #[test]
+    fn test_verify_index_timestamp_decreased() {
+        let (data, index) = write_stream(IndexVersion::V1);
+        let mut records = read_records(index);
+        records[2].timestamp = ts(500_000_000);
+        let report = verify(data, write_records(&records, IndexVersion::V1));
+        assert!(!report.is_ok());
+        assert!(report.issues_of_kind(IssueKind::IndexTimestampDecreased).count() == 1);
+    }
+
+    #[test]
+    fn test_verify_index_flags_mismatch() {
+        let (data, index) = write_stream(IndexVersion::V2);
+        let mut records = read_records(index);
+        records[1].random_access = false;
+        let report = verify(data, write_records(&records, IndexVersion::V2));
+        assert_eq!(kinds(&report), vec![IssueKind::IndexFlagsMismatch, IssueKind::IndexRecordMissing]);
+    }
+
+    #[test]
+    fn test_verify_final_record_rule() {
+        // The final record must have a timestamp greater than all events.
+        let (data, index) = write_stream(IndexVersion::V1);
+        let mut records = read_records(index);
+        records[3].timestamp = ts(29 * NANOS_PER_SECOND / 10);
+        let report = verify(data, write_records(&records, IndexVersion::V1));
+        assert_eq!(kinds(&report), vec![IssueKind::TimestampOutOfRange]);
+        assert_eq!(report.issues[0].offset, 29 * EVENT_SIZE);
+    }
+
+    #[test]
+    fn test_verify_gap_and_discontinuity() {
+        let mut data = Cursor::new(Vec::new());
+        let mut index = Cursor::new(Vec::new());
+        let payload = vec![0; 100];
+        for session in 0..2 {
+            let config = VideoStreamWriterConfig { index_version: IndexVersion::V2, ..Default::default() };
+            let mut writer = VideoStreamWriter::from_writers(&mut data, &mut index, config).unwrap();
+            for i in 0..10 {
+                let mut frame = VideoFrame::new(&payload[..], ts(session * 60 * NANOS_PER_SECOND + i * NANOS_PER_SECOND / 10), i == 0);
+                frame.duration = Some(NANOS_PER_SECOND / 10);
+                writer.write(&frame).unwrap();
+            }
+            writer.close().unwrap();
+        }
+        let report = verify(data.into_inner(), index.into_inner());
+        assert!(report.is_ok());
+        assert_eq!(report.discontinuity_count, 1);
+        assert_eq!(report.gap_count, 1);
+        assert_eq!(kinds(&report), vec![IssueKind::Discontinuity, IssueKind::Gap]);
+    }
+
+    #[test]
+    fn test_verify_incomplete_tail() {
+        let (mut data, index) = write_stream(IndexVersion::V1);
+        let mut event_writer = EventWriter::new();
+        let payload = vec![0; 100];
+        let mut extra = Cursor::new(Vec::new());
+        event_writer.write(&EventWithHeader::new(&payload[..], ts(3 * NANOS_PER_SECOND), false, false, false), &mut extra).unwrap();
+        data.extend_from_slice(&extra.into_inner()[0..50]);
+        let report = verify(data, index);
+        assert!(report.is_ok());
+        assert_eq!(kinds(&report), vec![IssueKind::IncompleteEvent]);
+    }
+
+    fn read_records(index: Vec<u8>) -> Vec<IndexRecord> {
+        let mut reader = Cursor::new(index);
+        let mut index_record_reader = IndexRecordReader::new();
+        let mut records = Vec::new();
+        while (reader.position() as usize) < reader.get_ref().len() {
+            records.push(index_record_reader.read(&mut reader).unwrap());
+        }
+        records
+    }
+
+    fn write_records(records: &[IndexRecord], version: IndexVersion) -> Vec<u8> {
+        let mut index = Cursor::new(Vec::new());
+        let mut index_record_writer = IndexRecordWriter::with_version(version);
+        for record in records.iter() {
+            index_record_writer.write(record, &mut index).unwrap();
+        }
+        index.into_inner()
+    }
+}
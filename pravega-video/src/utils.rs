@@ -10,14 +10,20 @@
 
 // Pravega utility functions.
 
+use std::future::Future;
 use std::net::{SocketAddr, AddrParseError};
+use std::pin::Pin;
+use std::task::{Context, Poll};
 use std::time::{Duration, UNIX_EPOCH};
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
 
-use pravega_client::byte::ByteReader;
+use pravega_client::byte::{ByteReader, ByteWriter};
+use pravega_client::client_factory::ClientFactoryAsync;
 use pravega_client_config::{ClientConfig, ClientConfigBuilder};
 use pravega_client_config::credentials::Credentials;
+use pravega_client_shared::ScopedStream;
 
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
 use tokio::runtime::Handle;
 
 pub const DEFAULT_PRAVEGA_CONTROLLER_URI: &str = "tcp://127.0.0.1:9090";
@@ -50,6 +56,58 @@ impl Seek for SyncByteReader {
     }
 }
 
+/// A wrapper for ByteWriter that implements std::io::Write.
+/// Seek only supports SeekFrom::Current(0), which returns the current write offset.
+pub struct SyncByteWriter {
+    byte_writer: ByteWriter,
+    runtime_handle: Handle,
+}
+
+impl SyncByteWriter {
+    pub fn new(byte_writer: ByteWriter, runtime_handle: Handle) -> Self {
+        Self {
+            byte_writer,
+            runtime_handle,
+        }
+    }
+
+    /// Gets a mutable reference to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut ByteWriter {
+        &mut self.byte_writer
+    }
+
+    pub fn seal(&mut self) -> std::io::Result<()> {
+        self.runtime_handle.block_on(self.byte_writer.seal()).map_err(|err| Error::new(ErrorKind::Other, err.to_string()))
+    }
+
+    pub fn seek_to_tail(&mut self) {
+        self.runtime_handle.block_on(self.byte_writer.seek_to_tail())
+    }
+
+    pub fn truncate_data_before(&mut self, offset: u64) -> std::io::Result<()> {
+        self.runtime_handle.block_on(self.byte_writer.truncate_data_before(offset as i64)).map_err(|err| Error::new(ErrorKind::Other, err.to_string()))
+    }
+}
+
+impl Write for SyncByteWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.runtime_handle.block_on(self.byte_writer.write(buf)).map_err(|err| Error::new(ErrorKind::Other, err.to_string()))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.runtime_handle.block_on(self.byte_writer.flush()).map_err(|err| Error::new(ErrorKind::Other, err.to_string()))
+    }
+}
+
+impl Seek for SyncByteWriter {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match pos {
+            SeekFrom::Current(0) => Ok(self.byte_writer.current_offset() as u64),
+            _ => Err(Error::new(ErrorKind::InvalidInput, "Seek is not allowed")),
+        }
+    }
+}
+
 /// A trait that allows retrieval of the current head of a Pravega byte stream.
 /// The default implementation returns 0 to indicate that no data has been truncated.
 pub trait CurrentHead {
@@ -66,6 +124,255 @@ impl CurrentHead for SyncByteReader {
 
 impl<T> CurrentHead for std::io::Cursor<T> {}
 
+impl<T: CurrentHead + ?Sized> CurrentHead for Box<T> {
+    fn current_head(&self) -> std::io::Result<u64> {
+        (**self).current_head()
+    }
+}
+
+impl<T: CurrentHead + ?Sized> CurrentHead for &mut T {
+    fn current_head(&self) -> std::io::Result<u64> {
+        (**self).current_head()
+    }
+}
+
+/// The async equivalent of CurrentHead.
+pub trait AsyncCurrentHead {
+    fn poll_current_head(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>>;
+}
+
+impl<T: Unpin> AsyncCurrentHead for std::io::Cursor<T> {
+    fn poll_current_head(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        Poll::Ready(Ok(0))
+    }
+}
+
+impl<T: AsyncCurrentHead + Unpin + ?Sized> AsyncCurrentHead for &mut T {
+    fn poll_current_head(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        Pin::new(&mut **self.get_mut()).poll_current_head(cx)
+    }
+}
+
+impl<R: AsyncRead + AsyncCurrentHead + Unpin> AsyncCurrentHead for tokio::io::BufReader<R> {
+    fn poll_current_head(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        Pin::new(self.get_mut().get_mut()).poll_current_head(cx)
+    }
+}
+
+/// Future returned by current_head_async.
+pub struct CurrentHeadFuture<'a, R: ?Sized> {
+    reader: &'a mut R,
+}
+
+impl<R: AsyncCurrentHead + Unpin + ?Sized> Future for CurrentHeadFuture<'_, R> {
+    type Output = std::io::Result<u64>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut *self.reader).poll_current_head(cx)
+    }
+}
+
+/// Returns the offset of the first byte that has not been truncated.
+pub fn current_head_async<R: AsyncCurrentHead + Unpin + ?Sized>(reader: &mut R) -> CurrentHeadFuture<'_, R> {
+    CurrentHeadFuture { reader }
+}
+
+/// Same as futures::ready.
+macro_rules! futures_ready {
+    ($e:expr) => {
+        match $e {
+            Poll::Ready(t) => t,
+            Poll::Pending => return Poll::Pending,
+        }
+    };
+}
+
+type ByteReaderFuture<T> = Pin<Box<dyn Future<Output = (ByteReader, std::io::Result<T>)> + Send>>;
+
+enum AsyncByteReaderState {
+    Opening(Pin<Box<dyn Future<Output = ByteReader> + Send>>),
+    Idle(ByteReader),
+    Reading(ByteReaderFuture<Vec<u8>>),
+    Seeking(ByteReaderFuture<u64>),
+    CurrentHead(ByteReaderFuture<u64>),
+    Invalid,
+}
+
+/// An adapter that implements AsyncRead, AsyncSeek and AsyncCurrentHead for a Pravega ByteReader.
+/// Unlike SyncByteReader, this never blocks the thread.
+/// Only one operation can be in progress at a time.
+pub struct AsyncByteReader {
+    state: AsyncByteReaderState,
+    /// The offset of the next byte that will be returned by poll_read.
+    position: u64,
+    /// Bytes that have been read from the ByteReader but not yet returned by poll_read.
+    /// This happens when poll_read is called with a smaller buffer than a pending read.
+    leftover: Vec<u8>,
+    leftover_pos: usize,
+}
+
+impl AsyncByteReader {
+    pub fn new(byte_reader: ByteReader) -> Self {
+        Self::with_state(AsyncByteReaderState::Idle(byte_reader))
+    }
+
+    /// Open a byte reader for a stream.
+    /// This returns immediately. The stream will be opened when the reader is first used.
+    pub fn open(client_factory: ClientFactoryAsync, scoped_stream: ScopedStream) -> Self {
+        Self::with_state(AsyncByteReaderState::Opening(Box::pin(async move {
+            client_factory.create_byte_reader(scoped_stream).await
+        })))
+    }
+
+    fn with_state(state: AsyncByteReaderState) -> Self {
+        Self {
+            state,
+            // A new ByteReader is positioned at offset 0.
+            position: 0,
+            leftover: Vec::new(),
+            leftover_pos: 0,
+        }
+    }
+
+    fn leftover_len(&self) -> usize {
+        self.leftover.len() - self.leftover_pos
+    }
+
+    /// Wait for any operation in progress to complete.
+    fn poll_idle(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        loop {
+            match &mut self.state {
+                AsyncByteReaderState::Opening(future) => {
+                    let byte_reader = futures_ready!(future.as_mut().poll(cx));
+                    self.state = AsyncByteReaderState::Idle(byte_reader);
+                },
+                AsyncByteReaderState::Idle(_) => break,
+                AsyncByteReaderState::Reading(future) => {
+                    let (byte_reader, result) = futures_ready!(future.as_mut().poll(cx));
+                    self.state = AsyncByteReaderState::Idle(byte_reader);
+                    // Keep the bytes so that they can be returned by the next call to poll_read.
+                    let bytes = result?;
+                    self.leftover = bytes;
+                    self.leftover_pos = 0;
+                },
+                AsyncByteReaderState::Seeking(future) => {
+                    let (byte_reader, result) = futures_ready!(future.as_mut().poll(cx));
+                    self.state = AsyncByteReaderState::Idle(byte_reader);
+                    self.position = result?;
+                },
+                AsyncByteReaderState::CurrentHead(future) => {
+                    let (byte_reader, _) = futures_ready!(future.as_mut().poll(cx));
+                    self.state = AsyncByteReaderState::Idle(byte_reader);
+                },
+                AsyncByteReaderState::Invalid => {
+                    return Poll::Ready(Err(Error::new(ErrorKind::Other, "AsyncByteReader is in an invalid state")));
+                },
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn take_byte_reader(&mut self) -> ByteReader {
+        match std::mem::replace(&mut self.state, AsyncByteReaderState::Invalid) {
+            AsyncByteReaderState::Idle(byte_reader) => byte_reader,
+            _ => panic!("AsyncByteReader is not idle"),
+        }
+    }
+}
+
+impl AsyncRead for AsyncByteReader {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        if this.leftover_len() == 0 {
+            if !matches!(this.state, AsyncByteReaderState::Reading(_)) {
+                // Wait for any other operation to complete, then start a read.
+                futures_ready!(this.poll_idle(cx))?;
+                if buf.remaining() == 0 {
+                    return Poll::Ready(Ok(()));
+                }
+                let mut byte_reader = this.take_byte_reader();
+                let mut bytes = vec![0; buf.remaining()];
+                this.state = AsyncByteReaderState::Reading(Box::pin(async move {
+                    let result = byte_reader.read(&mut bytes[..]).await.map(|n| {
+                        bytes.truncate(n);
+                        bytes
+                    });
+                    (byte_reader, result)
+                }));
+            }
+            // This completes the read, which will fill leftover.
+            futures_ready!(this.poll_idle(cx))?;
+        }
+        // If leftover is still empty, we have reached the end of the stream.
+        let n = usize::min(this.leftover_len(), buf.remaining());
+        buf.put_slice(&this.leftover[this.leftover_pos..this.leftover_pos + n]);
+        this.leftover_pos += n;
+        this.position += n as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncSeek for AsyncByteReader {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+        let this = self.get_mut();
+        if !matches!(this.state, AsyncByteReaderState::Idle(_)) {
+            return Err(Error::new(ErrorKind::Other, "Another operation is pending"));
+        }
+        // The ByteReader is positioned after any leftover bytes so relative seeks must use our position.
+        let position = match position {
+            SeekFrom::Current(n) => {
+                let new_position = this.position as i64 + n;
+                if new_position < 0 {
+                    return Err(Error::new(ErrorKind::InvalidInput, "Invalid seek to a negative position"));
+                }
+                SeekFrom::Start(new_position as u64)
+            },
+            p => p,
+        };
+        this.leftover.clear();
+        this.leftover_pos = 0;
+        let mut byte_reader = this.take_byte_reader();
+        this.state = AsyncByteReaderState::Seeking(Box::pin(async move {
+            let result = byte_reader.seek(position).await;
+            (byte_reader, result)
+        }));
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        let this = self.get_mut();
+        if let AsyncByteReaderState::Seeking(future) = &mut this.state {
+            let (byte_reader, result) = futures_ready!(future.as_mut().poll(cx));
+            this.state = AsyncByteReaderState::Idle(byte_reader);
+            this.position = result?;
+            return Poll::Ready(Ok(this.position));
+        }
+        futures_ready!(this.poll_idle(cx))?;
+        Poll::Ready(Ok(this.position))
+    }
+}
+
+impl AsyncCurrentHead for AsyncByteReader {
+    fn poll_current_head(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        let this = self.get_mut();
+        if !matches!(this.state, AsyncByteReaderState::CurrentHead(_)) {
+            // Leftover bytes are preserved because this does not change the position.
+            futures_ready!(this.poll_idle(cx))?;
+            let byte_reader = this.take_byte_reader();
+            this.state = AsyncByteReaderState::CurrentHead(Box::pin(async move {
+                let result = byte_reader.current_head().await;
+                (byte_reader, result)
+            }));
+        }
+        if let AsyncByteReaderState::CurrentHead(future) = &mut this.state {
+            let (byte_reader, result) = futures_ready!(future.as_mut().poll(cx));
+            this.state = AsyncByteReaderState::Idle(byte_reader);
+            return Poll::Ready(result);
+        }
+        unreachable!()
+    }
+}
+
 pub fn parse_controller_uri(controller: String) -> Result<SocketAddr, AddrParseError> {
     controller.parse::<SocketAddr>()
 }
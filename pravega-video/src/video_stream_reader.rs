@@ -0,0 +1,401 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// A high-level API for reading events from a video stream by time.
+
+use crate::error::VideoStreamError;
+use crate::event_serde::{EventHeader, EventReader};
+use crate::index::{IndexRecord, IndexSearcher, SearchMethod, get_index_stream_name};
+use crate::timestamp::PravegaTimestamp;
+use crate::storage::{PravegaStorageBackend, StorageBackend, StreamReader};
+use crate::utils::CurrentHead;
+use pravega_client_config::ClientConfig;
+use pravega_client_shared::{ScopedStream, Stream};
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, trace};
+
+/// Determines the index record used when seeking to a timestamp.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeekMode {
+    /// Seek to the index record at or immediately before the timestamp.
+    /// This is normally used to begin decoding at a random access point.
+    Before,
+    /// Seek to the index record at or immediately after the timestamp.
+    After,
+    /// Seek to an index record with exactly this timestamp.
+    /// Fails with VideoStreamError::TimestampNotFound if there is no such record.
+    Exact,
+}
+
+/// An event read from a video stream.
+#[derive(Debug, PartialEq)]
+pub struct VideoEvent {
+    /// The byte offset of this event in the data stream.
+    pub offset: u64,
+    pub header: EventHeader,
+    pub payload: Vec<u8>,
+}
+
+/// Reads events from a video stream written by pravegasink.
+/// It can seek by timestamp using the index, read a bounded time range, and follow the tail of the stream.
+///
+/// ```ignore
+/// let mut reader = VideoStreamReader::open(client_config, ScopedStream::from("scope1/stream1"))?;
+/// reader.set_range(begin_timestamp, end_timestamp)?;
+/// for event in reader {
+///     let event = event?;
+/// }
+/// ```
+pub struct VideoStreamReader<R: Read + Seek + CurrentHead = Box<dyn StreamReader>> {
+    data_reader: BufReader<R>,
+    index_searcher: IndexSearcher<R>,
+    /// The offset of the next event to read.
+    offset: u64,
+    /// When not following the tail, reading stops at this offset.
+    tail_offset: u64,
+    /// Reading stops at this offset. This is determined from the index when the end timestamp is set.
+    end_offset: Option<u64>,
+    /// Reading stops at the first event with a timestamp at or after this.
+    end_timestamp: PravegaTimestamp,
+    follow_tail: bool,
+    tail_poll_interval: Duration,
+    finished: bool,
+    // The storage backend may own resources used by the readers, such as a Tokio runtime, so it must be dropped last.
+    _storage: Option<Arc<dyn StorageBackend>>,
+}
+
+impl VideoStreamReader<Box<dyn StreamReader>> {
+    /// Open a video stream and its index stream in Pravega.
+    /// The reader will be positioned at the first event that has not been truncated.
+    pub fn open(client_config: ClientConfig, scoped_stream: ScopedStream) -> Result<Self, VideoStreamError> {
+        Self::open_with_storage(Arc::new(PravegaStorageBackend::new(client_config)), scoped_stream)
+    }
+
+    /// Open a video stream and its index stream in any storage backend.
+    /// The reader will be positioned at the first event that has not been truncated.
+    pub fn open_with_storage(storage: Arc<dyn StorageBackend>, scoped_stream: ScopedStream) -> Result<Self, VideoStreamError> {
+        debug!("VideoStreamReader::open_with_storage: scoped_stream={}", scoped_stream);
+        let index_scoped_stream = ScopedStream {
+            scope: scoped_stream.scope.clone(),
+            stream: Stream::from(get_index_stream_name(&scoped_stream.stream.name)),
+        };
+        let data_reader = storage.create_reader(&scoped_stream)?;
+        let index_reader = storage.create_reader(&index_scoped_stream)?;
+        let mut reader = Self::from_readers(data_reader, index_reader)?;
+        reader._storage = Some(storage);
+        Ok(reader)
+    }
+}
+
+impl<R: Read + Seek + CurrentHead> VideoStreamReader<R> {
+    /// Create a reader from any data and index readers.
+    /// The reader will be positioned at the first event that has not been truncated.
+    pub fn from_readers(data_reader: R, index_reader: R) -> Result<Self, VideoStreamError> {
+        let mut reader = Self {
+            data_reader: BufReader::with_capacity(128 * 1024, data_reader),
+            index_searcher: IndexSearcher::new(index_reader),
+            offset: 0,
+            tail_offset: 0,
+            end_offset: None,
+            end_timestamp: PravegaTimestamp::NONE,
+            follow_tail: false,
+            tail_poll_interval: Duration::from_millis(100),
+            finished: false,
+            _storage: None,
+        };
+        reader.seek_to_head()?;
+        Ok(reader)
+    }
+
+    /// If true, reading will wait for new events when the end of the stream is reached.
+    /// Otherwise, reading stops at the end of the stream as of the most recent seek.
+    pub fn with_follow_tail(mut self, follow_tail: bool) -> Self {
+        self.follow_tail = follow_tail;
+        self
+    }
+
+    /// When following the tail, this is how long to wait before checking for new events.
+    pub fn with_tail_poll_interval(mut self, tail_poll_interval: Duration) -> Self {
+        self.tail_poll_interval = tail_poll_interval;
+        self
+    }
+
+    /// Returns the offset of the next event to read.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Position the reader at the first event that has not been truncated.
+    pub fn seek_to_head(&mut self) -> Result<(), VideoStreamError> {
+        let head_offset = self.data_reader.get_ref().current_head()?;
+        self.seek_to_offset(head_offset)
+    }
+
+    /// Position the reader at the end of the stream.
+    /// This is normally used with follow_tail to read only new events.
+    pub fn seek_to_tail(&mut self) -> Result<(), VideoStreamError> {
+        let tail_offset = self.data_reader.seek(SeekFrom::End(0))?;
+        self.seek_to_offset(tail_offset)
+    }
+
+    /// Position the reader at a byte offset in the data stream.
+    /// The offset must be the beginning of an event, such as the offset of an IndexRecord or VideoEvent.
+    pub fn seek_to_offset(&mut self, offset: u64) -> Result<(), VideoStreamError> {
+        if !self.follow_tail {
+            self.tail_offset = self.data_reader.seek(SeekFrom::End(0))?;
+        }
+        self.data_reader.seek(SeekFrom::Start(offset))?;
+        self.offset = offset;
+        self.finished = false;
+        debug!("VideoStreamReader::seek_to_offset: offset={}, tail_offset={}", offset, self.tail_offset);
+        Ok(())
+    }
+
+    /// Position the reader at an event using the index.
+    /// Returns the index record that was used.
+    pub fn seek(&mut self, timestamp: PravegaTimestamp, mode: SeekMode) -> Result<IndexRecord, VideoStreamError> {
+        let method = match mode {
+            SeekMode::After => SearchMethod::After,
+            SeekMode::Before | SeekMode::Exact => SearchMethod::Before,
+        };
+        let (index_record, _) = self.index_searcher.search_timestamp_and_return_index_offset(timestamp, method)
+            .map_err(index_error)?;
+        if mode == SeekMode::Exact && index_record.timestamp != timestamp {
+            return Err(VideoStreamError::TimestampNotFound(timestamp));
+        }
+        debug!("VideoStreamReader::seek: timestamp={}, mode={:?}, index_record={:?}", timestamp, mode, index_record);
+        self.seek_to_offset(index_record.offset)?;
+        Ok(index_record)
+    }
+
+    /// Stop reading at the first event at or after end_timestamp.
+    /// Use PravegaTimestamp::NONE to read without an end time.
+    pub fn set_end_timestamp(&mut self, end_timestamp: PravegaTimestamp) -> Result<(), VideoStreamError> {
+        self.end_timestamp = end_timestamp;
+        self.end_offset = None;
+        if end_timestamp.is_some() {
+            // If the end timestamp has been indexed, we can stop at the index record even if
+            // timestamps of events in between are not in order.
+            match self.index_searcher.search_timestamp_and_return_index_offset(end_timestamp, SearchMethod::After) {
+                Ok((index_record, _)) if index_record.timestamp >= end_timestamp => {
+                    self.end_offset = Some(index_record.offset);
+                },
+                Ok(_) => {},
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => {},
+                Err(e) => return Err(e.into()),
+            }
+        }
+        self.finished = false;
+        debug!("VideoStreamReader::set_end_timestamp: end_timestamp={}, end_offset={:?}", end_timestamp, self.end_offset);
+        Ok(())
+    }
+
+    /// Read events in the time range [begin_timestamp, end_timestamp).
+    /// Reading will begin at the random access point at or before begin_timestamp.
+    pub fn set_range(&mut self, begin_timestamp: PravegaTimestamp, end_timestamp: PravegaTimestamp) -> Result<IndexRecord, VideoStreamError> {
+        let index_record = self.seek(begin_timestamp, SeekMode::Before)?;
+        self.set_end_timestamp(end_timestamp)?;
+        Ok(index_record)
+    }
+
+    /// Read the next event.
+    /// Returns None when the end of the range or the end of the stream has been reached.
+    /// When following the tail, this blocks until the next event is available.
+    pub fn read_event(&mut self) -> Result<Option<VideoEvent>, VideoStreamError> {
+        loop {
+            if self.finished {
+                return Ok(None);
+            }
+            let limit_offset = match (self.end_offset, self.follow_tail) {
+                (Some(end_offset), true) => Some(end_offset),
+                (Some(end_offset), false) => Some(end_offset.min(self.tail_offset)),
+                (None, true) => None,
+                (None, false) => Some(self.tail_offset),
+            };
+            if let Some(limit_offset) = limit_offset {
+                if self.offset >= limit_offset {
+                    debug!("VideoStreamReader::read_event: reached end offset {}", limit_offset);
+                    self.finished = true;
+                    return Ok(None);
+                }
+            }
+            match self.read_next_event() {
+                Ok(event) => {
+                    if self.end_timestamp.is_some() && event.header.timestamp.is_some() && event.header.timestamp >= self.end_timestamp {
+                        debug!("VideoStreamReader::read_event: reached end timestamp {}", self.end_timestamp);
+                        // Leave the reader positioned at this event in case the range is extended.
+                        self.seek_to_offset(event.offset)?;
+                        self.finished = true;
+                        return Ok(None);
+                    }
+                    return Ok(Some(event));
+                },
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof && self.follow_tail => {
+                    // The next event has not been written yet.
+                    trace!("VideoStreamReader::read_event: waiting for event at offset {}", self.offset);
+                    self.data_reader.seek(SeekFrom::Start(self.offset))?;
+                    std::thread::sleep(self.tail_poll_interval);
+                },
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn read_next_event(&mut self) -> std::io::Result<VideoEvent> {
+        let mut event_reader = EventReader::new();
+        let required_buffer_length = event_reader.read_required_buffer_length(&mut self.data_reader)?;
+        let mut buffer: Vec<u8> = vec![0; required_buffer_length];
+        let event = event_reader.read_event(&mut self.data_reader, &mut buffer[..])?;
+        let payload_length = event.payload.len();
+        let header = event.header;
+        // Remove the header so that the buffer can be returned as the payload without allocating another buffer.
+        // This moves the payload to the start of the buffer, which is much cheaper than reading it.
+        buffer.drain(..required_buffer_length - payload_length);
+        let video_event = VideoEvent {
+            offset: self.offset,
+            header,
+            payload: buffer,
+        };
+        self.offset += required_buffer_length as u64;
+        trace!("VideoStreamReader::read_next_event: offset={}, header={:?}", video_event.offset, video_event.header);
+        Ok(video_event)
+    }
+}
+
+impl<R: Read + Seek + CurrentHead> Iterator for VideoStreamReader<R> {
+    type Item = Result<VideoEvent, VideoStreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.read_event().transpose();
+        if let Some(Err(_)) = result {
+            // Avoid returning the same error forever.
+            self.finished = true;
+        }
+        result
+    }
+}
+
+fn index_error(error: std::io::Error) -> VideoStreamError {
+    match error.kind() {
+        ErrorKind::UnexpectedEof => VideoStreamError::EmptyIndex,
+        _ => error.into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_serde::{EventWithHeader, EventWriter};
+    use crate::index::IndexRecordWriter;
+    use std::io::Cursor;
+
+    /// Returns data and index streams with 10 events per second for 10 seconds and a random access point every second.
+    fn make_streams() -> (Vec<u8>, Vec<u8>) {
+        let mut data = Cursor::new(Vec::new());
+        let mut index = Cursor::new(Vec::new());
+        let mut event_writer = EventWriter::new();
+        let mut index_record_writer = IndexRecordWriter::new();
+        for i in 0..100u64 {
+            let timestamp = PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000 + i * 100_000_000));
+            let random_access = i % 10 == 0;
+            if random_access {
+                let index_record = IndexRecord::new(timestamp, data.position(), true, i == 0);
+                index_record_writer.write(&index_record, &mut index).unwrap();
+            }
+            let payload = vec![i as u8; 10 + i as usize];
+            let event = EventWithHeader::new(&payload[..], timestamp, random_access, random_access, i == 0);
+            event_writer.write(&event, &mut data).unwrap();
+        }
+        (data.into_inner(), index.into_inner())
+    }
+
+    fn ts(i: u64) -> PravegaTimestamp {
+        PravegaTimestamp::from_nanoseconds(Some(1_600_000_000_000_000_000 + i * 100_000_000))
+    }
+
+    fn payload_ids(reader: VideoStreamReader<Cursor<Vec<u8>>>) -> Vec<u8> {
+        reader.map(|event| event.unwrap().payload[0]).collect()
+    }
+
+    #[test]
+    fn test_video_stream_reader_read_all() {
+        let (data, index) = make_streams();
+        let reader = VideoStreamReader::from_readers(Cursor::new(data), Cursor::new(index)).unwrap();
+        let events: Vec<VideoEvent> = reader.map(|event| event.unwrap()).collect();
+        assert_eq!(events.len(), 100);
+        assert_eq!(events[0].offset, 0);
+        assert!(events[0].header.discontinuity);
+        assert!(events[10].header.random_access);
+        assert!(!events[11].header.random_access);
+        assert_eq!(events[11].header.timestamp, ts(11));
+        assert_eq!(events[11].payload, vec![11; 21]);
+        assert_eq!(events[12].offset, events[11].offset + 20 + 21);
+    }
+
+    #[test]
+    fn test_video_stream_reader_seek() {
+        let (data, index) = make_streams();
+        let mut reader = VideoStreamReader::from_readers(Cursor::new(data), Cursor::new(index)).unwrap();
+        let index_record = reader.seek(ts(25), SeekMode::Before).unwrap();
+        assert_eq!(index_record.timestamp, ts(20));
+        assert_eq!(reader.read_event().unwrap().unwrap().header.timestamp, ts(20));
+        let index_record = reader.seek(ts(25), SeekMode::After).unwrap();
+        assert_eq!(index_record.timestamp, ts(30));
+        assert_eq!(reader.read_event().unwrap().unwrap().header.timestamp, ts(30));
+        reader.seek(ts(40), SeekMode::Exact).unwrap();
+        assert_eq!(reader.read_event().unwrap().unwrap().header.timestamp, ts(40));
+        match reader.seek(ts(41), SeekMode::Exact) {
+            Err(VideoStreamError::TimestampNotFound(t)) => assert_eq!(t, ts(41)),
+            other => panic!("unexpected result {:?}", other),
+        }
+        // A failed seek does not change the position.
+        assert_eq!(payload_ids(reader), (41..100).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn test_video_stream_reader_range() {
+        let (data, index) = make_streams();
+        let mut reader = VideoStreamReader::from_readers(Cursor::new(data.clone()), Cursor::new(index.clone())).unwrap();
+        reader.set_range(ts(25), ts(47)).unwrap();
+        assert_eq!(payload_ids(reader), (20..47).collect::<Vec<u8>>());
+
+        // End timestamp beyond the index.
+        let mut reader = VideoStreamReader::from_readers(Cursor::new(data), Cursor::new(index)).unwrap();
+        reader.set_range(ts(95), ts(200)).unwrap();
+        assert_eq!(payload_ids(reader), (90..100).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn test_video_stream_reader_empty_index() {
+        let (data, _) = make_streams();
+        let mut reader = VideoStreamReader::from_readers(Cursor::new(data), Cursor::new(Vec::new())).unwrap();
+        match reader.seek(ts(0), SeekMode::Before) {
+            Err(VideoStreamError::EmptyIndex) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+        // Reading without the index still works.
+        assert_eq!(reader.count(), 100);
+    }
+
+    #[test]
+    fn test_video_stream_reader_invalid_data() {
+        let (mut data, index) = make_streams();
+        // Corrupt the event length of the first event.
+        data[4..8].copy_from_slice(&[0, 0, 0, 1]);
+        let mut reader = VideoStreamReader::from_readers(Cursor::new(data), Cursor::new(index)).unwrap();
+        match reader.next() {
+            Some(Err(VideoStreamError::InvalidData(_))) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(reader.next().is_none());
+    }
+}
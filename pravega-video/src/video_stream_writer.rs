@@ -0,0 +1,620 @@
+//
+// Copyright (c) Dell Inc., or its subsidiaries. All Rights Reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+
+// A high-level API for writing video streams and their indexes.
+// This implements the same indexing rules as pravegasink so that streams
+// written by other applications can be read by pravegasrc and the HLS server.
+
+use crate::error::VideoStreamError;
+use crate::event_serde::{EventWithHeader, EventWriter};
+use crate::index::{IndexRecord, IndexRecordWriter, IndexSearcher, IndexVersion, get_index_stream_name};
+use crate::timestamp::PravegaTimestamp;
+use crate::storage::{PravegaStorageBackend, StorageBackend, StreamWriter};
+use crate::utils::get_video_tags;
+use pravega_client_config::ClientConfig;
+use pravega_client_shared::{ScopedStream, Stream};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::sync::Arc;
+use tracing::{debug, info, trace, warn};
+
+pub const DEFAULT_BUFFER_SIZE: usize = 128*1024;
+pub const DEFAULT_INDEX_MIN_NANOS: u64 = 500_000_000;
+pub const DEFAULT_INDEX_MAX_NANOS: u64 = 10_000_000_000;
+
+#[derive(Debug, Clone)]
+pub struct VideoStreamWriterConfig {
+    /// Size of the buffer used for the data stream.
+    pub buffer_size: usize,
+    /// An index record will not be written for a key frame less than this many nanoseconds after the previous index record.
+    pub index_min_nanos: u64,
+    /// An index record will be written for a delta frame if no index record has been written for this many nanoseconds.
+    pub index_max_nanos: u64,
+    /// The version of index records to write to a new index.
+    /// If the index already has records, the existing version is used.
+    pub index_version: IndexVersion,
+    /// If true, attempt to create the scope when opening.
+    pub allow_create_scope: bool,
+}
+
+impl Default for VideoStreamWriterConfig {
+    fn default() -> Self {
+        Self {
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            index_min_nanos: DEFAULT_INDEX_MIN_NANOS,
+            index_max_nanos: DEFAULT_INDEX_MAX_NANOS,
+            index_version: IndexVersion::default(),
+            allow_create_scope: true,
+        }
+    }
+}
+
+/// A video frame, or any other buffer, to write to a video stream.
+#[derive(Debug, Clone)]
+pub struct VideoFrame<'a> {
+    pub payload: &'a [u8],
+    pub timestamp: PravegaTimestamp,
+    /// Duration in nanoseconds, if known.
+    pub duration: Option<u64>,
+    /// True if decoding can begin at this frame (a key frame).
+    pub random_access: bool,
+    /// True if upstream has indicated a discontinuity.
+    pub discontinuity: bool,
+    /// If true, both streams will be flushed after writing this frame.
+    pub sync_after: bool,
+}
+
+impl<'a> VideoFrame<'a> {
+    pub fn new(payload: &'a [u8], timestamp: PravegaTimestamp, random_access: bool) -> Self {
+        Self {
+            payload,
+            timestamp,
+            duration: None,
+            random_access,
+            discontinuity: false,
+            sync_after: false,
+        }
+    }
+}
+
+/// Whether an index record was written for a frame and why.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexDecision {
+    /// The frame has no timestamp so it was not indexed.
+    NoTimestamp,
+    /// The frame is a delta frame so it was not indexed.
+    DeltaUnit,
+    /// The frame is a key frame and it was indexed.
+    /// The interval is the time since the previous index record written by this writer, if any.
+    KeyFrame { interval_nanos: Option<u64> },
+    /// The frame is a key frame but it was not indexed because an index record was written less than index_min_nanos ago.
+    SkipKeyFrame { interval_nanos: u64 },
+    /// The frame is a delta frame but it was indexed because no key frame has been received for index_max_nanos.
+    /// If first is true, this writer had not written any index records.
+    ForceDeltaUnit { interval_nanos: u64, first: bool },
+}
+
+impl IndexDecision {
+    pub fn include_in_index(&self) -> bool {
+        matches!(self, IndexDecision::KeyFrame { .. } | IndexDecision::ForceDeltaUnit { .. })
+    }
+}
+
+/// Describes how a frame was written by VideoStreamWriter::write.
+#[derive(Debug, Clone)]
+pub struct FrameWriteResult {
+    pub index_decision: IndexDecision,
+    /// The index record written before the frame, if any.
+    pub index_record: Option<IndexRecord>,
+    /// True if the frame was marked as a discontinuity.
+    pub discontinuity: bool,
+    /// Data stream offset of the frame.
+    pub offset: u64,
+    /// Data stream offset after the frame.
+    pub end_offset: u64,
+    /// Number of events written. This is greater than 1 if the frame exceeded the atomic write size.
+    pub fragments: usize,
+}
+
+/// Writes video frames to a data stream and index records to an index stream.
+///
+/// Index records are written according to these rules:
+///   - An index record is written at a key frame unless an index record was written less than index_min_nanos ago.
+///   - An index record is written at a delta frame if no index record has been written for index_max_nanos.
+///   - Frames without a timestamp are never indexed.
+///   - The first frame and the first index record written by this writer are marked as a discontinuity.
+///   - Before an index record is written, all prior data is flushed so that readers of the index never block on data.
+///   - The index record is written before the frame.
+///   - On close, a final index record is written with the timestamp and offset at the end of the last frame.
+///
+/// Dropping the writer without calling close() will not write the final index record.
+pub struct VideoStreamWriter<W: Write + Seek = Box<dyn StreamWriter>, I: Write = Box<dyn StreamWriter>> {
+    data_writer: BufWriter<W>,
+    index_writer: I,
+    index_record_writer: IndexRecordWriter,
+    event_writer: EventWriter,
+    index_min_nanos: u64,
+    index_max_nanos: u64,
+    // Identifies this writer session in version 2 index records.
+    session_id: u64,
+    // Data stream offset of the next frame.
+    offset: u64,
+    // First received timestamp that is not None.
+    first_valid_time: PravegaTimestamp,
+    // Timestamp of last written index record.
+    last_index_time: PravegaTimestamp,
+    // Data stream offset of last written index record.
+    last_index_offset: Option<u64>,
+    // The timestamp that will be written to the index on close.
+    final_timestamp: PravegaTimestamp,
+    // The offset that will be written to the index on close.
+    final_offset: Option<u64>,
+    frames_written: u64,
+    closed: bool,
+    // The storage backend may own resources used by the writers, such as a Tokio runtime, so it must be dropped last.
+    _storage: Option<Arc<dyn StorageBackend>>,
+}
+
+impl VideoStreamWriter<Box<dyn StreamWriter>, Box<dyn StreamWriter>> {
+    /// Create the data and index streams in Pravega if needed and open them for appending.
+    pub fn open(client_config: ClientConfig, scoped_stream: ScopedStream, config: VideoStreamWriterConfig) -> Result<Self, VideoStreamError> {
+        Self::open_with_storage(Arc::new(PravegaStorageBackend::new(client_config)), scoped_stream, config)
+    }
+
+    /// Create the data and index streams in any storage backend if needed and open them for appending.
+    pub fn open_with_storage(storage: Arc<dyn StorageBackend>, scoped_stream: ScopedStream, config: VideoStreamWriterConfig) -> Result<Self, VideoStreamError> {
+        info!("VideoStreamWriter::open_with_storage: scoped_stream={}, config={:?}", scoped_stream, config);
+        let index_scoped_stream = ScopedStream {
+            scope: scoped_stream.scope.clone(),
+            stream: Stream::from(get_index_stream_name(&scoped_stream.stream.name)),
+        };
+
+        if config.allow_create_scope {
+            // This is expected to fail in some environments, even if the scope already exists.
+            if let Err(error) = storage.create_scope(&scoped_stream.scope) {
+                debug!("Failed to create scope. This is normal if the scope already exists: {}", error);
+            }
+        }
+        storage.create_stream(&scoped_stream, get_video_tags())?;
+        storage.create_stream(&index_scoped_stream, None)?;
+        let data_writer = storage.create_writer(&scoped_stream)?;
+        let index_writer = storage.create_writer(&index_scoped_stream)?;
+
+        // All records in an index must have the same version.
+        // If the index already has records, continue to use the existing version.
+        let mut index_searcher = IndexSearcher::new(storage.create_reader(&index_scoped_stream)?);
+        let mut config = config;
+        if let Some(existing_index_version) = index_searcher.index_version()? {
+            if existing_index_version != config.index_version {
+                warn!("VideoStreamWriter::open_with_storage: Index stream has version {:?} records; ignoring requested version {:?}",
+                    existing_index_version, config.index_version);
+            }
+            config.index_version = existing_index_version;
+        }
+
+        let mut writer = Self::from_writers(data_writer, index_writer, config)?;
+        writer._storage = Some(storage);
+        Ok(writer)
+    }
+}
+
+impl<W: StreamWriter, I: StreamWriter> VideoStreamWriter<W, I> {
+    /// Seal the data and index streams so that no more data can be written to them.
+    /// This must be called after close().
+    pub fn seal(&mut self) -> Result<(), VideoStreamError> {
+        if !self.closed {
+            return Err(VideoStreamError::InvalidState("Writer must be closed before sealing".to_owned()));
+        }
+        self.data_writer.get_mut().seal()?;
+        self.index_writer.seal()?;
+        info!("VideoStreamWriter::seal: Streams sealed");
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek, I: Write> VideoStreamWriter<W, I> {
+    /// Create a writer from any data and index writers.
+    /// The data writer must be positioned at the end of the data stream.
+    /// The index version in config is used as is.
+    pub fn from_writers(mut data_writer: W, index_writer: I, config: VideoStreamWriterConfig) -> Result<Self, VideoStreamError> {
+        if config.index_min_nanos > config.index_max_nanos {
+            return Err(VideoStreamError::Config("index_min_nanos must be <= index_max_nanos".to_owned()));
+        }
+        let offset = data_writer.seek(SeekFrom::Current(0))?;
+        let session_id = PravegaTimestamp::now().nanoseconds().unwrap_or_default();
+        debug!("VideoStreamWriter::from_writers: offset={}, session_id={}, config={:?}", offset, session_id, config);
+        Ok(Self {
+            data_writer: BufWriter::with_capacity(config.buffer_size, data_writer),
+            index_writer,
+            index_record_writer: IndexRecordWriter::with_version(config.index_version),
+            event_writer: EventWriter::new(),
+            index_min_nanos: config.index_min_nanos,
+            index_max_nanos: config.index_max_nanos,
+            session_id,
+            offset,
+            first_valid_time: PravegaTimestamp::NONE,
+            last_index_time: PravegaTimestamp::NONE,
+            last_index_offset: None,
+            final_timestamp: PravegaTimestamp::NONE,
+            final_offset: None,
+            frames_written: 0,
+            closed: false,
+            _storage: None,
+        })
+    }
+
+    /// Returns the data stream offset at which the next frame will be written.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn index_version(&self) -> IndexVersion {
+        self.index_record_writer.version()
+    }
+
+    /// Returns whether an index record should be written for this frame and why.
+    fn index_decision(&self, frame: &VideoFrame) -> IndexDecision {
+        let timestamp = match frame.timestamp.nanoseconds() {
+            Some(timestamp) => timestamp,
+            // Frame has an invalid timestamp. Never index.
+            None => return IndexDecision::NoTimestamp,
+        };
+        match self.last_index_time.nanoseconds() {
+            Some(last_index_time) => {
+                let interval_nanos = timestamp.saturating_sub(last_index_time);
+                if frame.random_access {
+                    if timestamp < last_index_time + self.index_min_nanos {
+                        IndexDecision::SkipKeyFrame { interval_nanos }
+                    } else {
+                        IndexDecision::KeyFrame { interval_nanos: Some(interval_nanos) }
+                    }
+                } else if timestamp > last_index_time + self.index_max_nanos {
+                    // This is required for encoders such as nvv4l2h264enc that identify all buffers as delta units.
+                    IndexDecision::ForceDeltaUnit { interval_nanos, first: false }
+                } else {
+                    IndexDecision::DeltaUnit
+                }
+            },
+            None => {
+                // An index record has not been written by this writer yet.
+                if frame.random_access {
+                    IndexDecision::KeyFrame { interval_nanos: None }
+                } else {
+                    match self.first_valid_time.nanoseconds() {
+                        Some(first_valid_time) if timestamp > first_valid_time + self.index_max_nanos => {
+                            IndexDecision::ForceDeltaUnit { interval_nanos: timestamp - first_valid_time, first: true }
+                        },
+                        _ => IndexDecision::DeltaUnit,
+                    }
+                }
+            },
+        }
+    }
+
+    /// Write a frame to the data stream, preceded by an index record if required.
+    /// Returns the index decision, the index record that was written, if any, and where the frame was written.
+    /// This writer does not log these details so that the caller can log them in its own way.
+    pub fn write(&mut self, frame: &VideoFrame) -> Result<FrameWriteResult, VideoStreamError> {
+        if self.closed {
+            return Err(VideoStreamError::InvalidState("Writer is closed".to_owned()));
+        }
+        if self.first_valid_time.is_none() {
+            self.first_valid_time = frame.timestamp;
+        }
+        let writer_offset = self.offset;
+        trace!("VideoStreamWriter::write: timestamp={}, duration={:?}, size={}, writer_offset={}",
+            frame.timestamp, frame.duration, frame.payload.len(), writer_offset);
+
+        let index_decision = self.index_decision(frame);
+        let include_in_index = index_decision.include_in_index();
+
+        // Per the index constraints defined in index.rs, if we are writing an index record now,
+        // we must flush any data writes prior to this frame, so that reads do not block waiting on this writer.
+        // In order to detect any stalls writing the index stream, also flush the index stream.
+        if include_in_index {
+            self.data_writer.flush()?;
+            self.index_writer.flush()?;
+        }
+
+        // Record a discontinuity if any of the following are true:
+        //   1) the frame is marked as a discontinuity
+        //   2) this will be the first frame written to the data stream by this writer
+        //   3) this will be the first index record written by this writer
+        let discontinuity =
+               frame.discontinuity
+            || self.frames_written == 0
+            || (include_in_index && self.last_index_time.is_none());
+
+        // We write the index record before the frame so that any readers blocked on reading the
+        // index will unblock as soon as possible.
+        let index_record = if include_in_index {
+            // The segment duration and length describe the data written by this session since the previous index record.
+            let index_record = IndexRecord::new(frame.timestamp, writer_offset, frame.random_access, discontinuity)
+                .with_segment(
+                    self.final_timestamp - self.last_index_time,
+                    self.last_index_offset.map(|o| writer_offset - o),
+                    Some(self.session_id));
+            self.index_record_writer.write(&index_record, &mut self.index_writer)?;
+            self.last_index_time = frame.timestamp;
+            self.last_index_offset = Some(writer_offset);
+            Some(index_record)
+        } else {
+            None
+        };
+
+        // If the payload is greater than ~8 MiB, it will be fragmented into multiple atomic writes, each with an EventHeader.
+        // Additional fragments must not be indexed and must not be marked as a discontinuity as that would reset the demuxer.
+        let mut pos_to_write = 0;
+        let mut fragments = 0;
+        loop {
+            let length_to_write = usize::min(frame.payload.len() - pos_to_write, EventWithHeader::max_payload_size());
+            if length_to_write == 0 { break };
+            let payload = &frame.payload[pos_to_write..pos_to_write+length_to_write];
+            let event = if pos_to_write == 0 {
+                EventWithHeader::new(payload, frame.timestamp, include_in_index, frame.random_access, discontinuity)
+            } else {
+                EventWithHeader::new(payload, frame.timestamp, false, false, false)
+            };
+            self.event_writer.write(&event, &mut self.data_writer)?;
+            self.offset += event.encoded_length() as u64;
+            pos_to_write += length_to_write;
+            fragments += 1;
+        }
+        self.frames_written += 1;
+
+        if frame.sync_after {
+            self.flush()?;
+        }
+
+        // Maintain values that will be written to the index on close.
+        // Per the index constraints defined in index.rs, the timestamp in the index record must
+        // be strictly greater than the timestamp in the data stream.
+        if frame.timestamp.is_some() {
+            // If the duration is reported as 0 or is unknown, we record it as if it had a 1 nanosecond duration.
+            let duration = std::cmp::max(1, frame.duration.unwrap_or_default());
+            self.final_timestamp = PravegaTimestamp::from_nanoseconds(frame.timestamp.nanoseconds().map(|t| t + duration));
+        }
+        self.final_offset = Some(self.offset);
+
+        Ok(FrameWriteResult {
+            index_decision,
+            index_record,
+            discontinuity,
+            offset: writer_offset,
+            end_offset: self.offset,
+            fragments,
+        })
+    }
+
+    /// Flush the data stream and then the index stream.
+    pub fn flush(&mut self) -> Result<(), VideoStreamError> {
+        self.data_writer.flush()?;
+        self.index_writer.flush()?;
+        Ok(())
+    }
+
+    /// Flush all data and write the final index record.
+    /// The final index record has the timestamp at the end of the last frame and the offset at the end of the data stream.
+    /// Returns the final index record, if any.
+    pub fn close(&mut self) -> Result<Option<IndexRecord>, VideoStreamError> {
+        if self.closed {
+            return Err(VideoStreamError::InvalidState("Writer is closed".to_owned()));
+        }
+        self.data_writer.flush()?;
+        let index_record = match self.final_offset {
+            Some(final_offset) if self.final_timestamp.is_some() => {
+                let index_record = IndexRecord::new(self.final_timestamp, final_offset, false, false)
+                    .with_segment(
+                        self.final_timestamp - self.last_index_time,
+                        self.last_index_offset.map(|o| final_offset - o),
+                        Some(self.session_id));
+                self.index_record_writer.write(&index_record, &mut self.index_writer)?;
+                info!("VideoStreamWriter::close: Wrote final index record {:?}", index_record);
+                Some(index_record)
+            },
+            _ => None,
+        };
+        self.index_writer.flush()?;
+        self.closed = true;
+        Ok(index_record)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_serde::EventReader;
+    use crate::index::IndexRecordReader;
+    use crate::storage::MemoryStorageBackend;
+    use crate::video_stream_reader::{SeekMode, VideoStreamReader};
+    use std::io::Cursor;
+
+    const SECOND: u64 = 1_000_000_000;
+
+    fn ts(nanos: u64) -> PravegaTimestamp {
+        PravegaTimestamp::from_nanoseconds(Some(1_600_000_000 * SECOND + nanos))
+    }
+
+    fn read_index(index: Vec<u8>) -> Vec<IndexRecord> {
+        let mut reader = Cursor::new(index);
+        let mut index_record_reader = IndexRecordReader::new();
+        let mut records = Vec::new();
+        while (reader.position() as usize) < reader.get_ref().len() {
+            records.push(index_record_reader.read(&mut reader).unwrap());
+        }
+        records
+    }
+
+    fn read_flags(data: Vec<u8>) -> Vec<(u64, bool, bool, bool)> {
+        let mut reader = Cursor::new(data);
+        let mut events = Vec::new();
+        while (reader.position() as usize) < reader.get_ref().len() {
+            let offset = reader.position();
+            let mut event_reader = EventReader::new();
+            let length = event_reader.read_required_buffer_length(&mut reader).unwrap();
+            let mut buffer = vec![0; length];
+            let event = event_reader.read_event(&mut reader, &mut buffer[..]).unwrap();
+            events.push((offset, event.header.include_in_index, event.header.random_access, event.header.discontinuity));
+        }
+        events
+    }
+
+    #[test]
+    fn test_video_stream_writer_indexing() {
+        let mut data = Cursor::new(Vec::new());
+        let mut index = Cursor::new(Vec::new());
+        let config = VideoStreamWriterConfig {
+            index_min_nanos: SECOND,
+            index_max_nanos: 3 * SECOND,
+            ..Default::default()
+        };
+        {
+            let mut writer = VideoStreamWriter::from_writers(&mut data, &mut index, config).unwrap();
+            let payload = vec![0; 100];
+            // Frames every 100 ms. Key frames at 0, 0.5, 2.0 sec. Delta frames only after that.
+            for i in 0..60 {
+                let random_access = i == 0 || i == 5 || i == 20;
+                let mut frame = VideoFrame::new(&payload[..], ts(i * SECOND / 10), random_access);
+                frame.duration = Some(SECOND / 10);
+                let result = writer.write(&frame).unwrap();
+                assert_eq!(result.index_record.is_some(), i == 0 || i == 20 || i == 51, "i={}", i);
+                assert_eq!(result.offset, i * 120);
+                assert_eq!(result.end_offset, (i + 1) * 120);
+                let expected_decision = match i {
+                    0 => IndexDecision::KeyFrame { interval_nanos: None },
+                    5 => IndexDecision::SkipKeyFrame { interval_nanos: SECOND / 2 },
+                    20 => IndexDecision::KeyFrame { interval_nanos: Some(2 * SECOND) },
+                    51 => IndexDecision::ForceDeltaUnit { interval_nanos: 3_100_000_000, first: false },
+                    _ => IndexDecision::DeltaUnit,
+                };
+                assert_eq!(result.index_decision, expected_decision, "i={}", i);
+            }
+            let final_record = writer.close().unwrap().unwrap();
+            assert_eq!(final_record.timestamp, ts(6 * SECOND));
+            assert_eq!(final_record.offset, 60 * 120);
+            assert!(writer.write(&VideoFrame::new(&payload[..], ts(0), true)).is_err());
+        }
+        let records = read_index(index.into_inner());
+        let timestamps: Vec<_> = records.iter().map(|r| r.timestamp).collect();
+        assert_eq!(timestamps, vec![ts(0), ts(2 * SECOND), ts(5_100_000_000), ts(6 * SECOND)]);
+        assert!(records[0].discontinuity && records[0].random_access);
+        assert!(!records[1].discontinuity && records[1].random_access);
+        assert!(!records[2].random_access);
+        assert_eq!(records[1].offset, 20 * 120);
+
+        let events = read_flags(data.into_inner());
+        assert_eq!(events.len(), 60);
+        assert_eq!(events[0], (0, true, true, true));
+        assert_eq!(events[5], (5 * 120, false, true, false));
+        assert_eq!(events[20], (20 * 120, true, true, false));
+        assert_eq!(events[51], (51 * 120, true, false, false));
+    }
+
+    #[test]
+    fn test_video_stream_writer_append() {
+        // Writing to an existing stream must mark the first frame as a discontinuity and use the existing offset.
+        let mut data = Cursor::new(vec![0; 1000]);
+        data.set_position(1000);
+        let mut index = Cursor::new(Vec::new());
+        {
+            let mut writer = VideoStreamWriter::from_writers(&mut data, &mut index, Default::default()).unwrap();
+            let payload = vec![0; 10];
+            // The first frame is a delta frame so it is not indexed but it is a discontinuity.
+            let result = writer.write(&VideoFrame::new(&payload[..], ts(0), false)).unwrap();
+            assert!(result.index_record.is_none());
+            assert!(result.discontinuity);
+            assert_eq!(result.offset, 1000);
+            let index_record = writer.write(&VideoFrame::new(&payload[..], ts(SECOND), true)).unwrap().index_record.unwrap();
+            assert_eq!(index_record.offset, 1030);
+            assert!(index_record.discontinuity);
+            writer.close().unwrap();
+        }
+        let mut data = data.into_inner();
+        let events = read_flags(data.split_off(1000));
+        assert_eq!(events, vec![(0, false, false, true), (30, true, true, true)]);
+        let records = read_index(index.into_inner());
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].timestamp, ts(SECOND + 1));
+        assert_eq!(records[1].offset, 1060);
+    }
+
+    #[test]
+    fn test_video_stream_writer_no_timestamp() {
+        let mut data = Cursor::new(Vec::new());
+        let mut index = Cursor::new(Vec::new());
+        {
+            let mut writer = VideoStreamWriter::from_writers(&mut data, &mut index, Default::default()).unwrap();
+            let payload = vec![0; 10];
+            let result = writer.write(&VideoFrame::new(&payload[..], PravegaTimestamp::NONE, true)).unwrap();
+            assert!(result.index_record.is_none());
+            assert_eq!(result.index_decision, IndexDecision::NoTimestamp);
+            assert!(writer.close().unwrap().is_none());
+        }
+        assert!(index.into_inner().is_empty());
+        assert_eq!(read_flags(data.into_inner()), vec![(0, false, true, true)]);
+    }
+
+    #[test]
+    fn test_video_stream_writer_force_first_index_record() {
+        // An encoder that marks all frames as delta units must still be indexed.
+        let mut data = Cursor::new(Vec::new());
+        let mut index = Cursor::new(Vec::new());
+        let config = VideoStreamWriterConfig {
+            index_min_nanos: SECOND,
+            index_max_nanos: 3 * SECOND,
+            ..Default::default()
+        };
+        let mut writer = VideoStreamWriter::from_writers(&mut data, &mut index, config).unwrap();
+        let payload = vec![0; 10];
+        let decisions: Vec<_> = (0..5)
+            .map(|i| writer.write(&VideoFrame::new(&payload[..], ts(i * SECOND), false)).unwrap().index_decision)
+            .collect();
+        assert_eq!(decisions, vec![
+            IndexDecision::DeltaUnit,
+            IndexDecision::DeltaUnit,
+            IndexDecision::DeltaUnit,
+            IndexDecision::DeltaUnit,
+            IndexDecision::ForceDeltaUnit { interval_nanos: 4 * SECOND, first: true },
+        ]);
+    }
+
+    #[test]
+    fn test_video_stream_writer_memory_storage() {
+        let storage: Arc<dyn StorageBackend> = Arc::new(MemoryStorageBackend::new());
+        let scoped_stream = ScopedStream::from("scope1/stream1");
+        let payload = vec![0; 10];
+        for session in 0..2 {
+            let mut writer = VideoStreamWriter::open_with_storage(storage.clone(), scoped_stream.clone(), Default::default()).unwrap();
+            for i in 0..10 {
+                writer.write(&VideoFrame::new(&payload[..], ts((10 * session + i) * SECOND), true)).unwrap();
+            }
+            writer.close().unwrap();
+        }
+        let mut reader = VideoStreamReader::open_with_storage(storage, scoped_stream).unwrap();
+        let index_record = reader.seek(ts(10 * SECOND), SeekMode::Exact).unwrap();
+        assert!(index_record.discontinuity);
+        let events: Vec<_> = reader.map(|event| event.unwrap()).collect();
+        assert_eq!(events.len(), 10);
+        assert!(events[0].header.discontinuity);
+        assert_eq!(events[9].header.timestamp, ts(19 * SECOND));
+    }
+
+    #[test]
+    fn test_video_stream_writer_invalid_config() {
+        let config = VideoStreamWriterConfig {
+            index_min_nanos: 2 * SECOND,
+            index_max_nanos: SECOND,
+            ..Default::default()
+        };
+        match VideoStreamWriter::from_writers(Cursor::new(Vec::new()), Cursor::new(Vec::new()), config) {
+            Err(VideoStreamError::Config(_)) => {},
+            _ => panic!("expected config error"),
+        }
+    }
+}